@@ -1,17 +1,27 @@
-use crate::Error;
+use crate::{Error, MAX_HOST_TRAP_CODE};
 use serde_json::{self, Map, Value};
 use std::collections::{hash_map::Entry, HashMap};
 use std::fs;
 use std::path::Path;
 
+/// The host symbols that imports are bound to.
+///
+/// In JSON, each import is bound either to a symbol, `"name": "symbol"`, or to a symbol along with
+/// the host-defined trap code the function may trap with, `"name": { "symbol": "symbol",
+/// "trap_code": 7 }`. When a host function bound with a trap code asks the guest to trap, with
+/// lucet-runtime's `Vmctx::host_trap`, the guest traps with that code as a `TrapCode::Host`.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Bindings {
     bindings: HashMap<String, HashMap<String, String>>,
+    trap_codes: HashMap<String, HashMap<String, u16>>,
 }
 
 impl Bindings {
     pub fn new(bindings: HashMap<String, HashMap<String, String>>) -> Bindings {
-        Self { bindings }
+        Self {
+            bindings,
+            trap_codes: HashMap::new(),
+        }
     }
 
     pub fn env(env: HashMap<String, String>) -> Bindings {
@@ -41,6 +51,24 @@ impl Bindings {
         Ok(Self::from_str(&contents)?)
     }
 
+    /// Mark the import `module::symbol` as one that may trap with the host-defined `code`, which
+    /// must be at most `MAX_HOST_TRAP_CODE`.
+    pub fn set_trap_code(&mut self, module: &str, symbol: &str, code: u16) {
+        self.trap_codes
+            .entry(module.to_owned())
+            .or_default()
+            .insert(symbol.to_owned(), code);
+    }
+
+    /// The host-defined trap code the import `module::symbol` may trap with, if it is marked as
+    /// one that traps.
+    pub fn trap_code(&self, module: &str, symbol: &str) -> Option<u16> {
+        self.trap_codes
+            .get(module)
+            .and_then(|m| m.get(symbol))
+            .copied()
+    }
+
     pub fn extend(&mut self, other: &Bindings) -> Result<(), Error> {
         for (modname, othermodbindings) in other.bindings.iter() {
             match self.bindings.entry(modname.clone()) {
@@ -68,6 +96,20 @@ impl Bindings {
                 }
             }
         }
+        for (modname, othercodes) in other.trap_codes.iter() {
+            for (bindname, code) in othercodes {
+                match self.trap_code(modname, bindname) {
+                    Some(existing) if existing != *code => {
+                        return Err(Error::RebindError {
+                            key: bindname.to_owned(),
+                            binding: format!("trap code {}", code),
+                            attempt: format!("trap code {}", existing),
+                        });
+                    }
+                    _ => self.set_trap_code(modname, bindname, *code),
+                }
+            }
+        }
         Ok(())
     }
 
@@ -89,11 +131,15 @@ impl Bindings {
 
     fn parse_modules_json_obj(m: &Map<String, Value>) -> Result<Self, Error> {
         let mut res = HashMap::new();
+        let mut trap_codes = HashMap::new();
         for (modulename, values) in m {
             match values.as_object() {
                 Some(methods) => {
-                    let methodmap = Self::parse_methods_json_obj(methods)?;
+                    let (methodmap, codes) = Self::parse_methods_json_obj(methods)?;
                     res.insert(modulename.to_owned(), methodmap);
+                    if !codes.is_empty() {
+                        trap_codes.insert(modulename.to_owned(), codes);
+                    }
                 }
                 None => {
                     return Err(Error::ParseError {
@@ -103,15 +149,39 @@ impl Bindings {
                 }
             }
         }
-        Ok(Self::new(res))
+        Ok(Self {
+            bindings: res,
+            trap_codes,
+        })
     }
 
-    fn parse_methods_json_obj(m: &Map<String, Value>) -> Result<HashMap<String, String>, Error> {
+    fn parse_methods_json_obj(
+        m: &Map<String, Value>,
+    ) -> Result<(HashMap<String, String>, HashMap<String, u16>), Error> {
         let mut res = HashMap::new();
+        let mut codes = HashMap::new();
         for (method, i) in m {
-            match i.as_str() {
+            let symbol = i
+                .as_str()
+                .or_else(|| i.get("symbol").and_then(|symbol| symbol.as_str()));
+            let code = match i.get("trap_code") {
+                None => None,
+                Some(code) => match code.as_u64() {
+                    Some(code) if code <= MAX_HOST_TRAP_CODE as u64 => Some(code as u16),
+                    _ => {
+                        return Err(Error::ParseError {
+                            key: method.to_owned(),
+                            value: i.to_string(),
+                        });
+                    }
+                },
+            };
+            match symbol {
                 Some(importbinding) => {
                     res.insert(method.to_owned(), importbinding.to_owned());
+                    if let Some(code) = code {
+                        codes.insert(method.to_owned(), code);
+                    }
                 }
                 None => {
                     return Err(Error::ParseError {
@@ -121,7 +191,7 @@ impl Bindings {
                 }
             }
         }
-        Ok(res)
+        Ok((res, codes))
     }
 
     pub fn to_string(&self) -> Result<String, Error> {
@@ -138,16 +208,29 @@ impl Bindings {
         for (modulename, values) in self.bindings.iter() {
             m.insert(
                 modulename.to_owned(),
-                Value::from(Self::serialize_methods_json_obj(values)),
+                Value::from(self.serialize_methods_json_obj(modulename, values)),
             );
         }
         m
     }
 
-    fn serialize_methods_json_obj(methods: &HashMap<String, String>) -> Map<String, Value> {
+    fn serialize_methods_json_obj(
+        &self,
+        modulename: &str,
+        methods: &HashMap<String, String>,
+    ) -> Map<String, Value> {
         let mut m = Map::new();
         for (methodname, symbol) in methods.iter() {
-            m.insert(methodname.to_owned(), Value::from(symbol.to_owned()));
+            let binding = match self.trap_code(modulename, methodname) {
+                Some(code) => {
+                    let mut binding = Map::new();
+                    binding.insert("symbol".to_owned(), Value::from(symbol.to_owned()));
+                    binding.insert("trap_code".to_owned(), Value::from(code));
+                    Value::from(binding)
+                }
+                None => Value::from(symbol.to_owned()),
+            };
+            m.insert(methodname.to_owned(), binding);
         }
         m
     }
@@ -1,4 +1,4 @@
-use crate::traps::{TrapManifest, TrapSite};
+use crate::traps::{trap_table_header_size, trap_table_version, TrapManifest, TrapSite};
 use cranelift_entity::entity_impl;
 use serde::{Deserialize, Serialize};
 
@@ -151,6 +151,7 @@ pub struct FunctionSpec {
     code_len: u32,
     traps_addr: u64,
     traps_len: u64,
+    compile_nanos: u64,
 }
 
 impl FunctionSpec {
@@ -160,6 +161,16 @@ impl FunctionSpec {
             code_len,
             traps_addr,
             traps_len,
+            compile_nanos: 0,
+        }
+    }
+    /// How long lucetc took to translate and compile the function, in nanoseconds, or `None` if
+    /// that wasn't recorded.
+    pub fn compile_nanos(&self) -> Option<u64> {
+        if self.compile_nanos == 0 {
+            None
+        } else {
+            Some(self.compile_nanos)
         }
     }
     pub fn ptr(&self) -> FunctionPointer {
@@ -168,6 +179,9 @@ impl FunctionSpec {
     pub fn code_len(&self) -> u32 {
         self.code_len
     }
+    pub fn traps_addr(&self) -> u64 {
+        self.traps_addr
+    }
     pub fn traps_len(&self) -> u64 {
         self.traps_len
     }
@@ -186,13 +200,23 @@ impl FunctionSpec {
 
         None
     }
+    /// The function's trap table, or `None` if it has none or the table is in a format this
+    /// version can't read.
+    ///
+    /// The table may be unversioned or start with a header, depending on the version of lucetc
+    /// that wrote it and how it was configured. `traps_len` counts sites, not the header.
     pub fn traps(&self) -> Option<TrapManifest<'_>> {
-        let traps_ptr = self.traps_addr as *const TrapSite;
-        if !traps_ptr.is_null() {
-            let traps_slice = unsafe { from_raw_parts(traps_ptr, self.traps_len as usize) };
-            Some(TrapManifest::new(traps_slice))
-        } else {
-            None
+        let mut traps_ptr = self.traps_addr as *const TrapSite;
+        if traps_ptr.is_null() {
+            return None;
+        }
+        if self.traps_len > 0 {
+            // A table with any sites is at least one site long, whether or not it has a header.
+            let first = unsafe { *(traps_ptr as *const [u8; 8]) };
+            let header_size = trap_table_header_size(trap_table_version(first))?;
+            traps_ptr = unsafe { (traps_ptr as *const u8).add(header_size) as *const TrapSite };
         }
+        let traps_slice = unsafe { from_raw_parts(traps_ptr, self.traps_len as usize) };
+        Some(TrapManifest::new(traps_slice))
     }
 }
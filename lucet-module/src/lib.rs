@@ -27,13 +27,19 @@ pub use crate::functions::{
 pub use crate::globals::{Global, GlobalDef, GlobalSpec, GlobalValue};
 pub use crate::linear_memory::{HeapSpec, LinearMemorySpec, SparseData};
 pub use crate::module::{Module, SerializedModule, LUCET_MODULE_SYM};
-pub use crate::module_data::{ModuleData, ModuleFeatures, MODULE_DATA_SYM};
+pub use crate::module_data::{
+    module_data_sym, ModuleData, ModuleFeatures, RequiredFeatures, WasmProposals, MODULE_DATA_SYM,
+};
 pub use crate::runtime::InstanceRuntimeData;
 pub use crate::signature::{ModuleSignature, PublicKey};
 pub use crate::tables::TableElement;
-pub use crate::traps::{TrapCode, TrapManifest, TrapSite};
+pub use crate::traps::{
+    trap_table_header_size, trap_table_version, TrapCode, TrapManifest, TrapSite,
+    MAX_HOST_TRAP_CODE, TRAP_TABLE_VERSION, TRAP_TABLE_VERSION_HEADER,
+    TRAP_TABLE_VERSION_UNVERSIONED,
+};
 pub use crate::types::{Signature, ValueType};
-pub use crate::version_info::VersionInfo;
+pub use crate::version_info::{VersionInfo, ABI_REVISION};
 
 /// Owned variants of the module data types, useful for serialization and testing.
 pub mod owned {
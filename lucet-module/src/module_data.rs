@@ -14,6 +14,13 @@ use serde_big_array::big_array;
 
 pub const MODULE_DATA_SYM: &str = "lucet_module_data";
 
+/// The symbol of the module data belonging to the module found through `module_symbol`.
+///
+/// This is `MODULE_DATA_SYM` for a module found through `LUCET_MODULE_SYM`.
+pub fn module_data_sym(module_symbol: &str) -> String {
+    format!("{}_data", module_symbol)
+}
+
 big_array! {
     BigArray;
     SignatureBones::BYTES,
@@ -46,6 +53,9 @@ pub struct ModuleData<'a> {
     module_signature: [u8; SignatureBones::BYTES],
     features: ModuleFeatures,
     start_function: Option<FunctionIndex>,
+    /// The width, in bits, of a pointer on the target the module was compiled for.
+    pointer_width: u8,
+    required_features: RequiredFeatures,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -81,6 +91,38 @@ impl ModuleFeatures {
     }
 }
 
+/// The WebAssembly proposals, beyond the MVP, that a module uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WasmProposals {
+    pub simd: bool,
+    pub threads: bool,
+    pub bulk_memory: bool,
+    pub reference_types: bool,
+    pub multi_value: bool,
+    pub multi_memory: bool,
+    pub tail_call: bool,
+}
+
+/// What a module needs from the runtime and the host to run: the WebAssembly proposals it uses,
+/// and the CPU features its code uses.
+///
+/// Unlike `ModuleFeatures`, which lists every CPU feature the module was compiled to allow,
+/// `cpu` only lists the features its code is expected to actually use.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RequiredFeatures {
+    pub wasm: WasmProposals,
+    pub cpu: ModuleFeatures,
+}
+
+impl RequiredFeatures {
+    pub fn none() -> Self {
+        Self {
+            wasm: WasmProposals::default(),
+            cpu: ModuleFeatures::none(),
+        }
+    }
+}
+
 impl<'a> ModuleData<'a> {
     pub fn new(
         linear_memory: Option<LinearMemorySpec<'a>>,
@@ -102,9 +144,24 @@ impl<'a> ModuleData<'a> {
             module_signature: [0u8; SignatureBones::BYTES],
             features,
             start_function,
+            pointer_width: (std::mem::size_of::<usize>() * 8) as u8,
+            required_features: RequiredFeatures::none(),
         }
     }
 
+    /// Record that the module was compiled for a target with `pointer_width`-bit pointers. A
+    /// `ModuleData` is created with the host's pointer width.
+    pub fn with_pointer_width(mut self, pointer_width: u8) -> Self {
+        self.pointer_width = pointer_width;
+        self
+    }
+
+    /// Record what the module needs to run. A `ModuleData` is created requiring nothing.
+    pub fn with_required_features(mut self, required_features: RequiredFeatures) -> Self {
+        self.required_features = required_features;
+        self
+    }
+
     pub fn heap_spec(&self) -> Option<&HeapSpec> {
         if let Some(ref linear_memory) = self.linear_memory {
             Some(&linear_memory.heap)
@@ -169,6 +226,16 @@ impl<'a> ModuleData<'a> {
         &self.features
     }
 
+    /// The width, in bits, of a pointer on the target the module was compiled for.
+    pub fn pointer_width(&self) -> u8 {
+        self.pointer_width
+    }
+
+    /// What the module needs from the runtime and the host to run.
+    pub fn required_features(&self) -> &RequiredFeatures {
+        &self.required_features
+    }
+
     pub fn patch_module_signature(
         module_data_bin: &'a [u8],
         module_signature: &[u8],
@@ -1,5 +1,9 @@
+use std::sync::atomic::AtomicU64;
+
 /// This struct describes the handful of fields that Lucet-compiled programs may directly interact with, but
 /// are provided through VMContext.
+///
+/// Any change to this layout must bump `ABI_REVISION`.
 #[repr(C)]
 #[repr(align(8))]
 pub struct InstanceRuntimeData {
@@ -18,4 +22,15 @@ pub struct InstanceRuntimeData {
     pub instruction_count_adj: i64,
     pub instruction_count_bound: i64,
     pub stack_limit: u64,
+    /// Nonzero when the host has asked the guest to stop. Guests compiled with interrupt checks
+    /// load this at loop back-edges, and trap with `TrapCode::Interrupt` if it is set.
+    ///
+    /// The host may set this from another thread while the guest runs, so it is only written
+    /// atomically. `AtomicU64` has the same layout as `u64`, and the guest's aligned 8-byte load
+    /// of it is atomic on every target lucetc supports.
+    pub interrupt_flag: AtomicU64,
+    /// Nonzero when a hostcall has asked the guest to trap once it returns. After calling an
+    /// import bound with a `trap_code`, the guest checks this, and traps with that code as a
+    /// `TrapCode::Host` if it is set. Other imports leave it set until the instance is reset.
+    pub host_trap: u64,
 }
@@ -1,6 +1,6 @@
 use crate::error::Error::{self, IOError, ModuleSignatureError};
 use crate::module::{SerializedModule, LUCET_MODULE_SYM};
-use crate::module_data::MODULE_DATA_SYM;
+use crate::module_data::module_data_sym;
 use crate::ModuleData;
 use byteorder::{ByteOrder, LittleEndian};
 use memoffset::offset_of;
@@ -18,13 +18,25 @@ impl ModuleSignature {
         so_path: P,
         pk: &PublicKey,
         module_data: &ModuleData<'_>,
+    ) -> Result<(), Error> {
+        Self::verify_with_symbol(so_path, pk, module_data, LUCET_MODULE_SYM)
+    }
+
+    /// Like `verify`, for a module found through the symbol `module_symbol` rather than
+    /// `lucet_module`.
+    pub fn verify_with_symbol<P: AsRef<Path>>(
+        so_path: P,
+        pk: &PublicKey,
+        module_data: &ModuleData<'_>,
+        module_symbol: &str,
     ) -> Result<(), Error> {
         let signature_box: SignatureBox =
             SignatureBones::from_bytes(&module_data.get_module_signature())
                 .map_err(ModuleSignatureError)?
                 .into();
 
-        let mut raw_module_and_data = RawModuleAndData::from_file(&so_path).map_err(IOError)?;
+        let mut raw_module_and_data =
+            RawModuleAndData::from_file(&so_path, module_symbol).map_err(IOError)?;
         let cleared_module_data_bin =
             ModuleData::clear_module_signature(raw_module_and_data.module_data_bin())?;
         raw_module_and_data.patch_module_data(&cleared_module_data_bin);
@@ -40,7 +52,18 @@ impl ModuleSignature {
     }
 
     pub fn sign<P: AsRef<Path>>(path: P, sk: &SecretKey) -> Result<(), Error> {
-        let raw_module_and_data = RawModuleAndData::from_file(&path).map_err(IOError)?;
+        Self::sign_with_symbol(path, sk, LUCET_MODULE_SYM)
+    }
+
+    /// Like `sign`, for a module found through the symbol `module_symbol` rather than
+    /// `lucet_module`.
+    pub fn sign_with_symbol<P: AsRef<Path>>(
+        path: P,
+        sk: &SecretKey,
+        module_symbol: &str,
+    ) -> Result<(), Error> {
+        let raw_module_and_data =
+            RawModuleAndData::from_file(&path, module_symbol).map_err(IOError)?;
         let signature_box = minisign::sign(
             None,
             sk,
@@ -75,23 +98,24 @@ struct RawModuleAndData {
 }
 
 impl RawModuleAndData {
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+    pub fn from_file<P: AsRef<Path>>(path: P, module_symbol: &str) -> Result<Self, io::Error> {
         let mut obj_bin: Vec<u8> = Vec::new();
         File::open(&path)?.read_to_end(&mut obj_bin)?;
 
         let native_data_symbol_data =
-            Self::symbol_data(&obj_bin, LUCET_MODULE_SYM, true)?.ok_or(io::Error::new(
+            Self::symbol_data(&obj_bin, module_symbol, true)?.ok_or(io::Error::new(
                 io::ErrorKind::InvalidInput,
-                format!("`{}` symbol not present", LUCET_MODULE_SYM),
+                format!("`{}` symbol not present", module_symbol),
             ))?;
 
         // While `module_data` is the first field of the `SerializedModule` that `lucet_module` points
         // to, it is a virtual address, not a file offset. The translation is somewhat tricky at
         // the moment, so just look at the corresponding `lucet_module_data` symbol for now.
-        let module_data_symbol_data =
-            Self::symbol_data(&obj_bin, MODULE_DATA_SYM, true)?.ok_or(io::Error::new(
+        let module_data_symbol = module_data_sym(module_symbol);
+        let module_data_symbol_data = Self::symbol_data(&obj_bin, &module_data_symbol, true)?
+            .ok_or(io::Error::new(
                 io::ErrorKind::InvalidInput,
-                format!("`{}` symbol not present", MODULE_DATA_SYM),
+                format!("`{}` symbol not present", module_data_symbol),
             ))?;
 
         let module_data_len = LittleEndian::read_u64(
@@ -1,6 +1,10 @@
 /// The type of a WebAssembly
 /// [trap](http://webassembly.github.io/spec/core/intro/overview.html#trap).
-#[repr(u32)]
+///
+/// In a trap table, a code is stored as a `u32` with the kind of trap in the low 16 bits and, for
+/// `Host`, the host-defined code in the high 16 bits. Because of `repr(u16)`, that is also how a
+/// code is laid out in memory on little-endian targets, so trap tables can be read in place.
+#[repr(u16)]
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum TrapCode {
     StackOverflow,
@@ -14,6 +18,85 @@ pub enum TrapCode {
     TableOutOfBounds,
     Unreachable,
     HeapMisaligned,
+    // The variants are declared in the order of their packed kinds, as trap tables are read in
+    // place: each variant's discriminant must be its kind in `to_u32`.
+    /// A trap on behalf of a host function, with a code defined by the host. Bindings declare
+    /// which code an imported function traps with when the host function asks the guest to trap.
+    Host(u16),
+    /// `memory.grow` would have grown memory past the limit set with lucetc's
+    /// `CompilerBuilder::hard_memory_limit`.
+    MemoryLimitExceeded,
+    /// A call to an import that lucetc's `CompilerBuilder::weak_imports` declared weak, which the
+    /// host did not provide.
+    MissingImport,
+}
+
+/// The largest host-defined trap code. lucetc sets aside the codes above it for traps of its own.
+pub const MAX_HOST_TRAP_CODE: u16 = u16::MAX - 2;
+
+impl TrapCode {
+    /// The packed representation of this code in a trap table.
+    pub fn to_u32(self) -> u32 {
+        let (ty, subtype) = match self {
+            TrapCode::StackOverflow => (0, 0),
+            TrapCode::HeapOutOfBounds => (1, 0),
+            TrapCode::IndirectCallToNull => (2, 0),
+            TrapCode::BadSignature => (3, 0),
+            TrapCode::IntegerOverflow => (4, 0),
+            TrapCode::IntegerDivByZero => (5, 0),
+            TrapCode::BadConversionToInteger => (6, 0),
+            TrapCode::Interrupt => (7, 0),
+            TrapCode::TableOutOfBounds => (8, 0),
+            TrapCode::Unreachable => (9, 0),
+            TrapCode::HeapMisaligned => (10, 0),
+            TrapCode::Host(code) => (11, code),
+            TrapCode::MemoryLimitExceeded => (12, 0),
+            TrapCode::MissingImport => (13, 0),
+        };
+        (subtype as u32) << 16 | ty
+    }
+
+    /// The oldest trap table format that can hold this code.
+    ///
+    /// Runtimes that read formats before `TRAP_TABLE_VERSION` read each code in place as one of
+    /// the codes they know, with no subtype, so newer codes must not appear in those formats.
+    pub fn min_trap_table_version(self) -> u8 {
+        match self {
+            TrapCode::Host(_) | TrapCode::MemoryLimitExceeded | TrapCode::MissingImport => {
+                TRAP_TABLE_VERSION
+            }
+            _ => TRAP_TABLE_VERSION_UNVERSIONED,
+        }
+    }
+
+    /// Unpack a code from its representation in a trap table, or `None` if it is not a valid
+    /// code.
+    pub fn from_u32(packed: u32) -> Option<TrapCode> {
+        let subtype = (packed >> 16) as u16;
+        let code = match packed & 0xffff {
+            0 => TrapCode::StackOverflow,
+            1 => TrapCode::HeapOutOfBounds,
+            2 => TrapCode::IndirectCallToNull,
+            3 => TrapCode::BadSignature,
+            4 => TrapCode::IntegerOverflow,
+            5 => TrapCode::IntegerDivByZero,
+            6 => TrapCode::BadConversionToInteger,
+            7 => TrapCode::Interrupt,
+            8 => TrapCode::TableOutOfBounds,
+            9 => TrapCode::Unreachable,
+            10 => TrapCode::HeapMisaligned,
+            11 => return Some(TrapCode::Host(subtype)),
+            12 => TrapCode::MemoryLimitExceeded,
+            13 => TrapCode::MissingImport,
+            _ => return None,
+        };
+        // Only host codes have a subtype.
+        if subtype == 0 {
+            Some(code)
+        } else {
+            None
+        }
+    }
 }
 
 /// Trap information for an address in a compiled function
@@ -29,6 +112,50 @@ pub struct TrapSite {
     pub code: TrapCode,
 }
 
+/// The trap table format written by `TrapSite::serialize`: the sites alone, with no header.
+/// Runtimes that predate versioned trap tables can only read this format.
+pub const TRAP_TABLE_VERSION_UNVERSIONED: u8 = 0;
+
+/// The first versioned trap table format: an 8-byte header holding the version, followed by the
+/// sites as `TrapSite::serialize` lays them out, with only the codes an unversioned table can
+/// hold.
+///
+/// Runtimes before ABI revision 2 would read the header as a trap site, so modules with
+/// versioned trap tables carry at least that revision, and those runtimes refuse them.
+pub const TRAP_TABLE_VERSION_HEADER: u8 = 1;
+
+/// The current trap table format: laid out like `TRAP_TABLE_VERSION_HEADER`, but codes may also
+/// be host-defined codes, packed with their subtype, `MemoryLimitExceeded`, or `MissingImport`.
+pub const TRAP_TABLE_VERSION: u8 = 2;
+
+/// The code word of a trap table header. The header is laid out like a trap site, to keep the
+/// sites after it aligned, and no `TrapCode` packs to this word, so a header can't be mistaken
+/// for the first site of an unversioned table.
+const TRAP_TABLE_HEADER_MARK: u32 = 0xffff_ffff;
+
+/// The format version of a trap table that starts with `first`, the table's first 8 bytes.
+///
+/// Unversioned tables have no header, so this is `TRAP_TABLE_VERSION_UNVERSIONED` unless `first`
+/// is a header. A table without any sites may be empty, and has no version to read.
+pub fn trap_table_version(first: [u8; 8]) -> u8 {
+    let mark = u32::from_le_bytes([first[4], first[5], first[6], first[7]]);
+    if mark == TRAP_TABLE_HEADER_MARK {
+        first[0]
+    } else {
+        TRAP_TABLE_VERSION_UNVERSIONED
+    }
+}
+
+/// The size of the header before the sites of a trap table in format `version`, or `None` if
+/// this version of lucet-module can't read or write that format.
+pub fn trap_table_header_size(version: u8) -> Option<usize> {
+    match version {
+        TRAP_TABLE_VERSION_UNVERSIONED => Some(0),
+        TRAP_TABLE_VERSION_HEADER | TRAP_TABLE_VERSION => Some(std::mem::size_of::<TrapSite>()),
+        _ => None,
+    }
+}
+
 /// A collection of trap sites, typically obtained from a
 /// single function (see [`FunctionSpec::traps`])
 #[repr(C)]
@@ -37,10 +164,76 @@ pub struct TrapManifest<'a> {
     pub traps: &'a [TrapSite],
 }
 
+impl TrapSite {
+    /// Serialize trap sites into the trap table format read by `TrapManifest::from_bytes`: each
+    /// site is a little-endian `u32` offset followed by the packed `u32` code.
+    pub fn serialize(sites: &[TrapSite]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(sites.len() * std::mem::size_of::<TrapSite>());
+        for site in sites {
+            bytes.extend_from_slice(&site.offset.to_le_bytes());
+            bytes.extend_from_slice(&site.code.to_u32().to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Serialize trap sites into trap table format `version`, or `None` if this version of
+    /// lucet-module can't write that format, or a site's code can't be held in it.
+    ///
+    /// Writing `TRAP_TABLE_VERSION_UNVERSIONED` is the same as `serialize`, for runtimes that
+    /// can't read a header.
+    pub fn serialize_versioned(sites: &[TrapSite], version: u8) -> Option<Vec<u8>> {
+        let header_size = trap_table_header_size(version)?;
+        if sites
+            .iter()
+            .any(|site| site.code.min_trap_table_version() > version)
+        {
+            return None;
+        }
+        let mut bytes =
+            Vec::with_capacity(header_size + sites.len() * std::mem::size_of::<TrapSite>());
+        if header_size > 0 {
+            bytes.extend_from_slice(&(version as u32).to_le_bytes());
+            bytes.extend_from_slice(&TRAP_TABLE_HEADER_MARK.to_le_bytes());
+        }
+        bytes.extend_from_slice(&Self::serialize(sites));
+        Some(bytes)
+    }
+}
+
 impl<'a> TrapManifest<'a> {
     pub fn new(traps: &'a [TrapSite]) -> TrapManifest<'_> {
         TrapManifest { traps }
     }
+
+    /// Read a trap table written by `TrapSite::serialize` or `TrapSite::serialize_versioned`
+    /// without copying it. Returns `None` if `bytes` is misaligned, is not a whole number of
+    /// trap sites, holds an invalid code, or is in a format this version can't read.
+    pub fn from_bytes(bytes: &'a [u8]) -> Option<TrapManifest<'a>> {
+        let site_size = std::mem::size_of::<TrapSite>();
+        let bytes = match bytes.get(0..site_size) {
+            Some(first) => {
+                let mut header = [0; 8];
+                header.copy_from_slice(first);
+                &bytes[trap_table_header_size(trap_table_version(header))?..]
+            }
+            None => bytes,
+        };
+        if bytes.len() % site_size != 0
+            || bytes.as_ptr() as usize % std::mem::align_of::<TrapSite>() != 0
+        {
+            return None;
+        }
+        for site in bytes.chunks(site_size) {
+            let mut code = [0; 4];
+            code.copy_from_slice(&site[4..8]);
+            TrapCode::from_u32(u32::from_le_bytes(code))?;
+        }
+        // Safety: the bytes are aligned for `TrapSite`, and each site holds a valid code.
+        let traps = unsafe {
+            std::slice::from_raw_parts(bytes.as_ptr() as *const TrapSite, bytes.len() / site_size)
+        };
+        Some(TrapManifest { traps })
+    }
     pub fn lookup_addr(&self, addr: u32) -> Option<TrapCode> {
         // predicate to find the trapsite for the addr via binary search
         let f = |ts: &TrapSite| ts.offset.cmp(&addr);
@@ -3,6 +3,22 @@ use std::cmp::min;
 use std::fmt;
 use std::io;
 
+/// The layout revision of the runtime structures that compiled code accesses directly, such as
+/// `InstanceRuntimeData` at negative offsets from the vmctx, and of the data the runtime reads
+/// from a module, such as its trap tables. Bump this whenever either layout changes, so that the
+/// runtime refuses modules compiled against a different layout rather than letting them read and
+/// write the wrong fields.
+///
+/// Revision 2 is the first whose runtimes read versioned trap tables; see
+/// `TRAP_TABLE_VERSION_HEADER`. Revision 3 adds the compile time to `FunctionSpec`.
+///
+/// This is stored in the low 15 bits of `VersionInfo::reserved`, so it must stay below `0x8000`.
+pub const ABI_REVISION: u16 = 3;
+
+/// The bit in `VersionInfo::reserved` marking version information as present. See
+/// `VersionInfo::current` for why this is the high bit.
+const VALID_BIT: u16 = 0x8000;
+
 /// VersionInfo is information about a Lucet module to allow the Lucet runtime to determine if or
 /// how the module can be loaded, if so requested. The information here describes implementation
 /// details in runtime support for `lucetc`-produced modules, and nothing higher level.
@@ -44,7 +60,7 @@ impl VersionInfo {
             major,
             minor,
             patch,
-            reserved: 0x8000,
+            reserved: VALID_BIT | ABI_REVISION,
             version_hash,
         }
     }
@@ -100,7 +116,13 @@ impl VersionInfo {
     }
 
     pub fn valid(&self) -> bool {
-        self.reserved == 0x8000
+        self.reserved & VALID_BIT != 0
+    }
+
+    /// The revision of the runtime structure layout this version was built against. Modules
+    /// predating the revision number report revision 0.
+    pub fn abi_revision(&self) -> u16 {
+        self.reserved & !VALID_BIT
     }
 
     pub fn current(current_hash: &'static [u8]) -> Self {
@@ -52,3 +52,28 @@ fn explicit_from_file() {
         "bindings from file returned value for non-existent symbol"
     );
 }
+
+#[test]
+fn trap_codes() {
+    let map = Bindings::from_str(
+        r#"{ "env": { "hello": "hello_sym", "fail": { "symbol": "fail_sym", "trap_code": 7 } } }"#,
+    )
+    .expect("load bindings with trap codes");
+    assert_eq!(map.translate("env", "fail").unwrap(), "fail_sym");
+    assert_eq!(map.trap_code("env", "fail"), Some(7));
+    assert_eq!(map.trap_code("env", "hello"), None);
+
+    let round_trip = Bindings::from_str(&map.to_string().unwrap()).unwrap();
+    assert_eq!(round_trip, map);
+
+    let out_of_range = Bindings::from_str(
+        r#"{ "env": { "fail": { "symbol": "fail_sym", "trap_code": 65536 } } }"#,
+    );
+    assert!(out_of_range.is_err());
+
+    // Codes above `MAX_HOST_TRAP_CODE` are reserved for lucetc.
+    let reserved = Bindings::from_str(
+        r#"{ "env": { "fail": { "symbol": "fail_sym", "trap_code": 65535 } } }"#,
+    );
+    assert!(reserved.is_err());
+}
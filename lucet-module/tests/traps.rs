@@ -0,0 +1,138 @@
+use lucet_module::{
+    trap_table_version, FunctionSpec, TrapCode, TrapManifest, TrapSite, TRAP_TABLE_VERSION,
+    TRAP_TABLE_VERSION_HEADER, TRAP_TABLE_VERSION_UNVERSIONED,
+};
+
+/// Copy `bytes` into a buffer aligned like `TrapSite`, as trap tables are read in place.
+fn aligned(bytes: &[u8]) -> Vec<u32> {
+    let mut aligned = vec![0u32; bytes.len() / 4];
+    for (word, chunk) in aligned.iter_mut().zip(bytes.chunks(4)) {
+        let mut b = [0; 4];
+        b.copy_from_slice(chunk);
+        *word = u32::from_ne_bytes(b);
+    }
+    aligned
+}
+
+#[test]
+fn trap_table_round_trip() {
+    let sites = vec![
+        TrapSite {
+            offset: 4,
+            code: TrapCode::HeapOutOfBounds,
+        },
+        TrapSite {
+            offset: 16,
+            code: TrapCode::Host(7),
+        },
+        TrapSite {
+            offset: 32,
+            code: TrapCode::Host(u16::MAX),
+        },
+        TrapSite {
+            offset: 48,
+            code: TrapCode::HeapMisaligned,
+        },
+    ];
+
+    let bytes = TrapSite::serialize(&sites);
+    let aligned = aligned(&bytes);
+    let aligned_bytes =
+        unsafe { std::slice::from_raw_parts(aligned.as_ptr() as *const u8, bytes.len()) };
+
+    let manifest = TrapManifest::from_bytes(aligned_bytes).expect("trap table is valid");
+    assert_eq!(manifest.traps.len(), sites.len());
+    for site in sites.iter() {
+        assert_eq!(manifest.lookup_addr(site.offset), Some(site.code));
+    }
+    assert_eq!(manifest.lookup_addr(8), None);
+}
+
+#[test]
+fn versioned_trap_table() {
+    let sites = vec![
+        TrapSite {
+            offset: 4,
+            code: TrapCode::StackOverflow,
+        },
+        TrapSite {
+            offset: 16,
+            code: TrapCode::Host(7),
+        },
+    ];
+
+    // Host codes can only be written in the current format, since older runtimes read codes in
+    // place without a subtype.
+    assert!(TrapSite::serialize_versioned(&sites, TRAP_TABLE_VERSION_UNVERSIONED).is_none());
+    assert!(TrapSite::serialize_versioned(&sites, TRAP_TABLE_VERSION_HEADER).is_none());
+    let unversioned = TrapSite::serialize_versioned(&sites[..1], TRAP_TABLE_VERSION_UNVERSIONED)
+        .expect("unversioned tables can be written");
+    assert_eq!(unversioned, TrapSite::serialize(&sites[..1]));
+    let header = TrapSite::serialize_versioned(&sites[..1], TRAP_TABLE_VERSION_HEADER)
+        .expect("tables with a header can be written");
+    assert_eq!(header.len(), unversioned.len() + 8);
+
+    let bytes = TrapSite::serialize_versioned(&sites, TRAP_TABLE_VERSION)
+        .expect("current tables can be written");
+    assert_eq!(bytes.len(), TrapSite::serialize(&sites).len() + 8);
+    let mut first = [0; 8];
+    first.copy_from_slice(&bytes[..8]);
+    assert_eq!(trap_table_version(first), TRAP_TABLE_VERSION);
+    first.copy_from_slice(&unversioned[..8]);
+    assert_eq!(trap_table_version(first), TRAP_TABLE_VERSION_UNVERSIONED);
+
+    let aligned = aligned(&bytes);
+    let aligned_bytes =
+        unsafe { std::slice::from_raw_parts(aligned.as_ptr() as *const u8, bytes.len()) };
+    let manifest = TrapManifest::from_bytes(aligned_bytes).expect("trap table is valid");
+    assert_eq!(manifest.traps.len(), sites.len());
+    for site in sites.iter() {
+        assert_eq!(manifest.lookup_addr(site.offset), Some(site.code));
+    }
+
+    assert!(TrapSite::serialize_versioned(&sites, TRAP_TABLE_VERSION + 1).is_none());
+}
+
+#[test]
+fn function_spec_traps() {
+    // `FunctionSpec::traps` reads the codes in place, without unpacking them.
+    let sites = vec![
+        TrapSite {
+            offset: 4,
+            code: TrapCode::HeapMisaligned,
+        },
+        TrapSite {
+            offset: 8,
+            code: TrapCode::Host(7),
+        },
+        TrapSite {
+            offset: 12,
+            code: TrapCode::MemoryLimitExceeded,
+        },
+        TrapSite {
+            offset: 16,
+            code: TrapCode::MissingImport,
+        },
+    ];
+    let bytes = TrapSite::serialize_versioned(&sites, TRAP_TABLE_VERSION)
+        .expect("current tables can be written");
+    let aligned = aligned(&bytes);
+    let spec = FunctionSpec::new(0, 32, aligned.as_ptr() as u64, sites.len() as u64);
+    let manifest = spec.traps().expect("trap table is readable");
+    assert_eq!(manifest.traps.len(), sites.len());
+    for site in sites.iter() {
+        assert_eq!(manifest.lookup_addr(site.offset), Some(site.code));
+    }
+}
+
+#[test]
+fn trap_code_packing() {
+    assert_eq!(TrapCode::StackOverflow.to_u32(), 0);
+    assert_eq!(TrapCode::Host(3).to_u32(), 3 << 16 | 11);
+    assert_eq!(TrapCode::from_u32(3 << 16 | 11), Some(TrapCode::Host(3)));
+    // Only host codes have a subtype.
+    assert_eq!(TrapCode::from_u32(1 << 16 | 1), None);
+    assert_eq!(TrapCode::from_u32(12), Some(TrapCode::MemoryLimitExceeded));
+    assert_eq!(TrapCode::from_u32(13), Some(TrapCode::MissingImport));
+    assert_eq!(TrapCode::from_u32(14), None);
+}
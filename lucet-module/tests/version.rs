@@ -1,4 +1,4 @@
-use lucet_module::VersionInfo;
+use lucet_module::{VersionInfo, ABI_REVISION};
 
 #[test]
 fn version_equality() {
@@ -16,3 +16,24 @@ fn version_equality() {
     // something running a version `major.minor.patch-commit` rejects a version less specific
     assert!(!precise.compatible_with(&imprecise));
 }
+
+#[test]
+fn abi_revision() {
+    let version = VersionInfo::new(0, 1, 2, [0, 0, 0, 0, 0, 0, 0, 0]);
+    assert!(version.valid());
+    assert_eq!(version.abi_revision(), ABI_REVISION);
+
+    // versions round-trip their ABI revision through serialization
+    let mut bytes = Vec::new();
+    version.write_to(&mut bytes).unwrap();
+    let read = VersionInfo::read_from(&mut bytes.as_slice()).unwrap();
+    assert_eq!(read.abi_revision(), ABI_REVISION);
+
+    // modules from before the ABI revision was recorded only set the valid bit, and report
+    // revision 0, which no runtime accepts
+    bytes[6..8].copy_from_slice(&0x8000u16.to_le_bytes());
+    let old = VersionInfo::read_from(&mut bytes.as_slice()).unwrap();
+    assert!(old.valid());
+    assert_eq!(old.abi_revision(), 0);
+    assert_ne!(old.abi_revision(), ABI_REVISION);
+}
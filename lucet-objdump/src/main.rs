@@ -1,8 +1,8 @@
 #![deny(bare_trait_objects)]
 
 use lucet_module::{
-    FunctionSpec, Module, ModuleData, SerializedModule, TableElement, TrapManifest, TrapSite,
-    VersionInfo,
+    trap_table_header_size, trap_table_version, FunctionSpec, Module, ModuleData, SerializedModule,
+    TableElement, TrapManifest, TrapSite, VersionInfo,
 };
 
 use byteorder::{LittleEndian, ReadBytesExt};
@@ -132,30 +132,48 @@ fn main() {
 /// access.
 ///
 /// In particular, trap pointers are correct with respect to 0 being the start of the file (or,
-/// buffer, after reading), which means we can (and must) rebuild a correct slice from the buffer.
+/// buffer, after reading), which means we can (and must) rebuild a correct slice from the buffer,
+/// skipping the table's header if it has one.
 fn parse_trap_manifest<'a>(
     summary: &'a ArtifactSummary<'a>,
     f: &FunctionSpec,
 ) -> Option<TrapManifest<'a>> {
-    if let Some(faulty_trap_manifest) = f.traps() {
-        let trap_ptr = faulty_trap_manifest.traps.as_ptr();
-        let traps_count = faulty_trap_manifest.traps.len();
-        let traps_byte_count = traps_count * std::mem::size_of::<TrapSite>();
-        if let Some(traps_byte_slice) =
-            summary.read_memory(trap_ptr as u64, traps_byte_count as u64)
-        {
-            let real_trap_ptr = traps_byte_slice.as_ptr() as *const TrapSite;
-            Some(TrapManifest {
-                traps: unsafe { std::slice::from_raw_parts(real_trap_ptr, traps_count) },
-            })
-        } else {
-            println!(
-                "Failed to read trap bytes for function {:?}, at {:p}",
-                f, trap_ptr
-            );
-            None
+    let trap_addr = f.traps_addr();
+    if trap_addr == 0 {
+        return None;
+    }
+    let traps_count = f.traps_len() as usize;
+    let header_size = if traps_count > 0 {
+        let first = summary.read_memory(trap_addr, 8)?;
+        let mut header = [0; 8];
+        header.copy_from_slice(first);
+        let version = trap_table_version(header);
+        match trap_table_header_size(version) {
+            Some(header_size) => header_size,
+            None => {
+                println!(
+                    "Unknown trap table version {} for function {:?}, at {:#x}",
+                    version, f, trap_addr
+                );
+                return None;
+            }
         }
     } else {
+        0
+    };
+    let traps_byte_count = traps_count * std::mem::size_of::<TrapSite>();
+    if let Some(traps_byte_slice) =
+        summary.read_memory(trap_addr + header_size as u64, traps_byte_count as u64)
+    {
+        let real_trap_ptr = traps_byte_slice.as_ptr() as *const TrapSite;
+        Some(TrapManifest {
+            traps: unsafe { std::slice::from_raw_parts(real_trap_ptr, traps_count) },
+        })
+    } else {
+        println!(
+            "Failed to read trap bytes for function {:?}, at {:#x}",
+            f, trap_addr
+        );
         None
     }
 }
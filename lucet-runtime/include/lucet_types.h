@@ -68,6 +68,9 @@ enum lucet_trapcode {
     lucet_trapcode_user,
     lucet_trapcode_heap_misaligned,
     lucet_trapcode_unknown,
+    lucet_trapcode_memory_limit_exceeded,
+    lucet_trapcode_host,
+    lucet_trapcode_missing_import,
 };
 
 enum lucet_val_type {
@@ -167,6 +170,8 @@ struct lucet_runtime_faulted {
     enum lucet_trapcode              trapcode;
     uintptr_t                        rip_addr;
     struct lucet_module_addr_details rip_addr_details;
+    // The host-defined code, when `trapcode` is `lucet_trapcode_host`.
+    uint16_t host_trapcode;
 };
 
 struct lucet_terminated {
@@ -28,6 +28,9 @@ void lucet_vmctx_terminate(struct lucet_vmctx const *, void *info);
 
 void *lucet_vmctx_yield(struct lucet_vmctx const *, void *val);
 
+// Ask the guest to trap when this hostcall returns, with the trap code of the import's binding.
+void lucet_vmctx_host_trap(struct lucet_vmctx const *);
+
 // returns the current number of wasm pages
 uint32_t lucet_vmctx_current_memory(struct lucet_vmctx const *);
 
@@ -283,6 +283,10 @@ pub mod lucet_result {
                             trapcode: details.trapcode.into(),
                             rip_addr: details.rip_addr,
                             rip_addr_details: details.rip_addr_details.into(),
+                            host_trapcode: match details.trapcode {
+                                Some(TrapCode::Host(code)) => code,
+                                _ => 0,
+                            },
                         },
                     },
                 },
@@ -404,6 +408,8 @@ pub mod lucet_result {
         pub trapcode: lucet_trapcode,
         pub rip_addr: libc::uintptr_t,
         pub rip_addr_details: lucet_module_addr_details,
+        /// The host-defined code, when `trapcode` is `Host`.
+        pub host_trapcode: u16,
     }
 
     #[repr(C)]
@@ -421,6 +427,9 @@ pub mod lucet_result {
         Unreachable,
         HeapMisaligned,
         Unknown,
+        MemoryLimitExceeded,
+        Host,
+        MissingImport,
     }
 
     impl From<Option<TrapCode>> for lucet_trapcode {
@@ -444,6 +453,10 @@ pub mod lucet_result {
                     TrapCode::TableOutOfBounds => lucet_trapcode::TableOutOfBounds,
                     TrapCode::Unreachable => lucet_trapcode::Unreachable,
                     TrapCode::HeapMisaligned => lucet_trapcode::HeapMisaligned,
+                    TrapCode::MemoryLimitExceeded => lucet_trapcode::MemoryLimitExceeded,
+                    TrapCode::MissingImport => lucet_trapcode::MissingImport,
+                    // The code itself is carried in `lucet_runtime_faulted::host_trapcode`.
+                    TrapCode::Host(_) => lucet_trapcode::Host,
                 }
             } else {
                 lucet_trapcode::Unknown
@@ -3,7 +3,9 @@ mod siginfo_ext;
 pub mod signals;
 pub mod state;
 
-pub use crate::instance::execution::{KillError, KillState, KillSuccess, KillSwitch};
+pub use crate::instance::execution::{
+    InterruptHandle, KillError, KillState, KillSuccess, KillSwitch,
+};
 pub use crate::instance::signals::{signal_handler_none, SignalBehavior, SignalHandler};
 pub use crate::instance::state::State;
 
@@ -28,6 +30,7 @@ use std::marker::PhantomData;
 use std::mem;
 use std::ops::{Deref, DerefMut};
 use std::ptr::{self, NonNull};
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 pub const LUCET_INSTANCE_MAGIC: u64 = 746_932_922;
@@ -228,6 +231,9 @@ pub struct Instance {
     /// Small mutexed state used for remote kill switch functionality
     pub(crate) kill_state: Arc<KillState>,
 
+    /// Shared by every `InterruptHandle` for this instance, and detached when it is dropped.
+    interrupt_handle: InterruptHandle,
+
     #[cfg(feature = "concurrent_testpoints")]
     /// Conditionally-present helpers to force permutations of possible races in testing.
     pub lock_testpoints: Arc<LockTestpoints>,
@@ -294,6 +300,8 @@ impl Drop for Instance {
         // Reset magic to indicate this instance
         // is no longer valid
         self.magic = 0;
+        // Interrupt handles must not touch the flag once the instance's memory may be released.
+        self.interrupt_handle.detach();
     }
 }
 
@@ -685,6 +693,9 @@ impl Instance {
             };
         }
 
+        self.set_interrupt_flag(false);
+        self.set_host_trap(false);
+
         if self.module.get_start_func()?.is_some() {
             self.state = State::NotStarted;
         } else {
@@ -962,6 +973,43 @@ impl Instance {
         implicits.instruction_count_adj = -delta;
     }
 
+    /// Ask the guest to trap with `TrapCode::Interrupt` at its next interrupt check.
+    ///
+    /// Only modules compiled with interrupt checks look at the flag, and only at loop back-edges.
+    /// The flag stays set until it is cleared here or the instance is reset. To interrupt a guest
+    /// running on another thread, use an [`InterruptHandle`](struct.InterruptHandle.html).
+    #[inline]
+    pub fn set_interrupt_flag(&self, interrupt: bool) {
+        self.get_instance_implicits()
+            .interrupt_flag
+            .store(interrupt as u64, Ordering::SeqCst);
+    }
+
+    #[inline]
+    pub fn interrupt_flag(&self) -> bool {
+        self.get_instance_implicits()
+            .interrupt_flag
+            .load(Ordering::SeqCst)
+            != 0
+    }
+
+    /// Set or clear the request for the guest to trap when the running hostcall returns. See
+    /// [`Vmctx::host_trap()`](vmctx/struct.Vmctx.html#method.host_trap).
+    #[inline]
+    pub(crate) fn set_host_trap(&mut self, trap: bool) {
+        self.get_instance_implicits_mut().host_trap = trap as u64;
+    }
+
+    /// A handle that sets this instance's interrupt flag from any thread. See
+    /// [`InterruptHandle`](struct.InterruptHandle.html).
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        // The instance doesn't move once it is placed in its region, so the flag's address is
+        // stable until the instance is dropped, which detaches the handle.
+        self.interrupt_handle
+            .attach(&self.get_instance_implicits().interrupt_flag);
+        self.interrupt_handle.clone()
+    }
+
     #[inline]
     pub fn set_hostcall_stack_reservation(&mut self) {
         let slot = self
@@ -1010,6 +1058,7 @@ impl Instance {
             ctx: Context::new(),
             state: State::Ready,
             kill_state,
+            interrupt_handle: InterruptHandle::detached(),
             #[cfg(feature = "concurrent_testpoints")]
             lock_testpoints,
             alloc,
@@ -1025,6 +1074,8 @@ impl Instance {
         };
         inst.set_globals_ptr(globals_ptr);
         inst.set_instruction_count_and_bound(0, 0);
+        inst.set_interrupt_flag(false);
+        inst.set_host_trap(false);
         // Ensure the hostcall limit tracked in this instance's guest-shared data is up-to-date.
         inst.set_hostcall_stack_reservation();
 
@@ -100,7 +100,8 @@
 //! For more information about signal-safe behavior, see `signal-safety(7)`.
 use libc::{pthread_kill, pthread_t, SIGALRM};
 use std::mem;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Condvar, Mutex, Weak};
 
 use crate::instance::{Instance, TerminationDetails};
@@ -697,3 +698,62 @@ impl KillSwitch {
         result
     }
 }
+
+/// An object that can be used to interrupt an instance's guest from a separate thread, for guests
+/// compiled with interrupt checks (see lucetc's `CompilerBuilder::interrupt_checks`).
+///
+/// Setting the interrupt flag makes the guest trap with `TrapCode::Interrupt` at its next
+/// interrupt check. Unlike a [`KillSwitch`](struct.KillSwitch.html), this never signals the
+/// instance's thread, and a guest that was not compiled with interrupt checks ignores the flag.
+/// The flag stays set until it is cleared or the instance is reset. A handle can outlive its
+/// instance, after which it has no effect.
+#[derive(Clone)]
+pub struct InterruptHandle {
+    flag: Arc<Mutex<Option<InterruptFlag>>>,
+}
+
+/// The address of an instance's interrupt flag.
+pub(crate) struct InterruptFlag(NonNull<AtomicU64>);
+
+// The flag is only ever accessed atomically, and the instance clears the pointer under the lock
+// before its memory is released.
+unsafe impl Send for InterruptFlag {}
+
+impl InterruptHandle {
+    /// A handle that is not yet attached to an instance's flag.
+    pub(crate) fn detached() -> Self {
+        InterruptHandle {
+            flag: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Point this handle, and every clone of it, at `flag`, which must stay valid until `detach`
+    /// is called.
+    pub(crate) fn attach(&self, flag: &AtomicU64) {
+        *self.flag.lock().unwrap() = Some(InterruptFlag(NonNull::from(flag)));
+    }
+
+    /// Make this handle, and every clone of it, have no effect from now on.
+    pub(crate) fn detach(&self) {
+        *self.flag.lock().unwrap() = None;
+    }
+
+    /// Set or clear the instance's interrupt flag, returning `false` if the instance no longer
+    /// exists.
+    pub fn set(&self, interrupt: bool) -> bool {
+        match self.flag.lock().unwrap().as_ref() {
+            Some(flag) => {
+                // Safety: the instance detaches the handle, under this lock, before it is dropped.
+                unsafe { flag.0.as_ref() }.store(interrupt as u64, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Ask the guest to trap with `TrapCode::Interrupt` at its next interrupt check, returning
+    /// `false` if the instance no longer exists.
+    pub fn interrupt(&self) -> bool {
+        self.set(true)
+    }
+}
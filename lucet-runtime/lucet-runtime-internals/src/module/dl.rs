@@ -4,7 +4,7 @@ use libc::c_void;
 use libloading::Library;
 use lucet_module::{
     FunctionHandle, FunctionIndex, FunctionSpec, ModuleData, ModuleFeatures, ModuleSignature,
-    PublicKey, SerializedModule, Signature, VersionInfo, LUCET_MODULE_SYM,
+    PublicKey, SerializedModule, Signature, VersionInfo, WasmProposals, LUCET_MODULE_SYM,
 };
 use std::ffi::CStr;
 use std::mem::MaybeUninit;
@@ -85,6 +85,30 @@ fn check_feature_support(module_features: &ModuleFeatures) -> Result<(), Error>
     Ok(())
 }
 
+fn check_proposal_support(proposals: &WasmProposals) -> Result<(), Error> {
+    fn unsupported_proposal(proposal: &str) -> Error {
+        Error::Unsupported(format!(
+            "Module requires WebAssembly proposal runtime does not support: {}",
+            proposal
+        ))
+    }
+
+    // Instances have no shared memories, and no way for guests to wait on or notify each other.
+    if proposals.threads {
+        return Err(unsupported_proposal("threads"));
+    }
+    // Instances only provide memory 0, at the vmctx. Code for any other memory would read its base
+    // from slots below the `InstanceRuntimeData` that the runtime never fills in.
+    if proposals.multi_memory {
+        return Err(unsupported_proposal("multi-memory"));
+    }
+    // Code for this runtime never reuses its caller's frame, as a tail call must.
+    if proposals.tail_call {
+        return Err(unsupported_proposal("tail-call"));
+    }
+    Ok(())
+}
+
 /// A Lucet module backed by a dynamically-loaded shared object.
 pub struct DlModule {
     /// A handle to the loaded object.
@@ -107,14 +131,27 @@ unsafe impl Sync for DlModule {}
 impl DlModule {
     /// Create a module, loading code from a shared object on the filesystem.
     pub fn load<P: AsRef<Path>>(so_path: P) -> Result<Arc<Self>, Error> {
-        Self::load_and_maybe_verify(so_path, None, true)
+        Self::load_and_maybe_verify(so_path, LUCET_MODULE_SYM, None, true)
     }
 
     pub fn load_with_version_match<P: AsRef<Path>>(
         so_path: P,
         enforce_version_match: bool,
     ) -> Result<Arc<Self>, Error> {
-        Self::load_and_maybe_verify(so_path, None, enforce_version_match)
+        Self::load_and_maybe_verify(so_path, LUCET_MODULE_SYM, None, enforce_version_match)
+    }
+
+    /// Create a module, loading code from a shared object on the filesystem, and finding the
+    /// module through the symbol `module_symbol` rather than `lucet_module`.
+    ///
+    /// This loads a module compiled with a symbol prefix, such as one of several guests linked
+    /// into the same shared object, where `module_symbol` is the prefix followed by
+    /// `lucet_module`.
+    pub fn load_with_symbol<P: AsRef<Path>>(
+        so_path: P,
+        module_symbol: &str,
+    ) -> Result<Arc<Self>, Error> {
+        Self::load_and_maybe_verify(so_path, module_symbol, None, true)
     }
 
     /// Create a module, loading code from a shared object on the filesystem
@@ -124,11 +161,23 @@ impl DlModule {
         pk: PublicKey,
         enforce_version_match: bool,
     ) -> Result<Arc<Self>, Error> {
-        Self::load_and_maybe_verify(so_path, Some(pk), enforce_version_match)
+        Self::load_and_maybe_verify(so_path, LUCET_MODULE_SYM, Some(pk), enforce_version_match)
+    }
+
+    /// Like `load_and_verify`, for a module found through the symbol `module_symbol` rather than
+    /// `lucet_module`, as with `load_with_symbol`.
+    pub fn load_and_verify_with_symbol<P: AsRef<Path>>(
+        so_path: P,
+        module_symbol: &str,
+        pk: PublicKey,
+        enforce_version_match: bool,
+    ) -> Result<Arc<Self>, Error> {
+        Self::load_and_maybe_verify(so_path, module_symbol, Some(pk), enforce_version_match)
     }
 
     fn load_and_maybe_verify<P: AsRef<Path>>(
         so_path: P,
+        module_symbol: &str,
         pk: Option<PublicKey>,
         enforce_version_match: bool,
     ) -> Result<Arc<Self>, Error> {
@@ -140,9 +189,13 @@ impl DlModule {
         let lib = Library::new(abs_so_path.as_os_str()).map_err(DlError::Loading)?;
 
         let serialized_module_ptr = unsafe {
-            lib.get::<*const SerializedModule>(LUCET_MODULE_SYM.as_bytes())
+            lib.get::<*const SerializedModule>(module_symbol.as_bytes())
                 .map_err(|e| {
-                    lucet_incorrect_module!("error loading required symbol `lucet_module`: {}", e)
+                    lucet_incorrect_module!(
+                        "error loading required symbol `{}`: {}",
+                        module_symbol,
+                        e
+                    )
                 })?
         };
 
@@ -161,6 +214,15 @@ impl DlModule {
             } else {
                 tracing::warn!("{}", msg);
             }
+        } else if module_version.abi_revision() != runtime_version.abi_revision() {
+            // Compiled code reaches into runtime structures directly, so a module built against a
+            // different layout can't run safely, whether or not versions are otherwise enforced.
+            return Err(lucet_incorrect_module!(
+                "ABI revision mismatch. module has ABI revision {}, while this runtime expects {}. \
+                 Recompile the module with a matching lucetc.",
+                module_version.abi_revision(),
+                runtime_version.abi_revision()
+            ));
         } else if !runtime_version.compatible_with(&module_version) {
             let msg = format!(
                 "version mismatch. module has version {}, while this runtime is version {}",
@@ -189,11 +251,12 @@ impl DlModule {
         let module_data = ModuleData::deserialize(module_data_slice)?;
 
         check_feature_support(module_data.features())?;
+        check_proposal_support(&module_data.required_features().wasm)?;
 
         // If a public key has been provided, verify the module signature
         // The TOCTOU issue is unavoidable without reimplenting `dlopen(3)`
         if let Some(pk) = pk {
-            ModuleSignature::verify(so_path, &pk, &module_data)?;
+            ModuleSignature::verify_with_symbol(so_path, &pk, &module_data, module_symbol)?;
         }
 
         let fbase = if let Some(dli) =
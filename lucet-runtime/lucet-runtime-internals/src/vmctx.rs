@@ -265,6 +265,16 @@ impl Vmctx {
         self.instance_mut().terminate(details)
     }
 
+    /// Ask the guest to trap when this hostcall returns.
+    ///
+    /// The guest traps with `TrapCode::Host`, carrying the code given to this import by the
+    /// `trap_code` of its binding, and the instance faults as it would for any other trap. Only
+    /// direct calls to an import bound with a trap code check for the request; otherwise it stays
+    /// pending until the instance is reset.
+    pub fn host_trap(&self) {
+        unsafe { self.instance_mut().set_host_trap(true) }
+    }
+
     /// Grow the guest memory by the given number of WebAssembly pages.
     ///
     /// On success, returns the number of pages that existed before the call.
@@ -412,6 +412,14 @@ pub fn ensure_linked() {
             lucet_vmctx_get_heap as _,
             lucet_vmctx_current_memory as _,
             lucet_vmctx_grow_memory as _,
+            crate::libcalls::lucet_libcall_ceilf as _,
+            crate::libcalls::lucet_libcall_ceil as _,
+            crate::libcalls::lucet_libcall_floorf as _,
+            crate::libcalls::lucet_libcall_floor as _,
+            crate::libcalls::lucet_libcall_truncf as _,
+            crate::libcalls::lucet_libcall_trunc as _,
+            crate::libcalls::lucet_libcall_nearbyintf as _,
+            crate::libcalls::lucet_libcall_nearbyint as _,
         ];
         for func in funcs {
             assert_ne!(*func, std::ptr::null(), "hostcall address is not null");
@@ -513,6 +521,12 @@ pub unsafe fn lucet_vmctx_yield_at_bound_expiration(vmctx: &Vmctx) {
     vmctx.yield_at_bound_expiration();
 }
 
+#[lucet_hostcall]
+#[no_mangle]
+pub unsafe fn lucet_vmctx_host_trap(vmctx: &Vmctx) {
+    vmctx.host_trap();
+}
+
 #[cfg(test)]
 mod tests {
     use super::lucet_dl_module;
@@ -402,6 +402,7 @@
 extern crate self as lucet_runtime;
 
 pub mod c_api;
+pub mod libcalls;
 
 pub use lucet_module::{PublicKey, TrapCode};
 pub use lucet_runtime_internals::alloc::{AllocStrategy, Limits, DEFAULT_SIGNAL_STACK_SIZE};
@@ -410,8 +411,8 @@ pub use lucet_runtime_internals::instance::signals::{
     install_lucet_signal_handler, remove_lucet_signal_handler,
 };
 pub use lucet_runtime_internals::instance::{
-    FaultDetails, Instance, InstanceHandle, KillError, KillSuccess, KillSwitch, RunResult,
-    SignalBehavior, TerminationDetails, YieldedVal,
+    FaultDetails, Instance, InstanceHandle, InterruptHandle, KillError, KillSuccess, KillSwitch,
+    RunResult, SignalBehavior, TerminationDetails, YieldedVal,
 };
 #[allow(deprecated)]
 pub use lucet_runtime_internals::lucet_hostcalls;
@@ -0,0 +1,111 @@
+//! Deterministic implementations of the float rounding libcalls.
+//!
+//! lucetc's x86-64 backend lowers `ceil`, `floor`, `trunc`, and `nearest` to libcalls when SSE4.1
+//! is not available. Modules compiled with lucetc's `CompilerBuilder::deterministic_libcalls`
+//! call these functions rather than the host's libm. They round using integer operations on
+//! the bits of the float, so the results do not depend on the libm the host links or on its
+//! floating-point environment. NaNs are returned quieted, with their payload otherwise intact.
+
+macro_rules! rounding_libcalls {
+    (
+        $float:ident,
+        $bits:ident,
+        $mantissa_bits:expr,
+        $trunc:ident,
+        $floor:ident,
+        $ceil:ident,
+        $nearest:ident
+    ) => {
+        /// Round toward zero.
+        #[no_mangle]
+        pub extern "C" fn $trunc(x: $float) -> $float {
+            const TOTAL_BITS: u32 = std::mem::size_of::<$bits>() as u32 * 8;
+            const EXPONENT_MASK: $bits = (1 << (TOTAL_BITS - 1 - $mantissa_bits)) - 1;
+            const EXPONENT_BIAS: i32 = (EXPONENT_MASK >> 1) as i32;
+            const SIGN: $bits = 1 << (TOTAL_BITS - 1);
+            const QUIET: $bits = 1 << ($mantissa_bits - 1);
+
+            let bits = x.to_bits();
+            let exponent = ((bits >> $mantissa_bits) & EXPONENT_MASK) as i32 - EXPONENT_BIAS;
+            if exponent >= $mantissa_bits as i32 {
+                // Infinite, NaN, or too large to have a fractional part.
+                return if x.is_nan() {
+                    $float::from_bits(bits | QUIET)
+                } else {
+                    x
+                };
+            }
+            if exponent < 0 {
+                // Less than one in magnitude.
+                return $float::from_bits(bits & SIGN);
+            }
+            let fraction: $bits = (1 << ($mantissa_bits - exponent as u32)) - 1;
+            $float::from_bits(bits & !fraction)
+        }
+
+        /// Round toward negative infinity.
+        #[no_mangle]
+        pub extern "C" fn $floor(x: $float) -> $float {
+            let t = $trunc(x);
+            // `t` is an integer small enough that subtracting one is exact.
+            if x < 0.0 && t != x {
+                t - 1.0
+            } else {
+                t
+            }
+        }
+
+        /// Round toward positive infinity.
+        #[no_mangle]
+        pub extern "C" fn $ceil(x: $float) -> $float {
+            let t = $trunc(x);
+            if x > 0.0 && t != x {
+                t + 1.0
+            } else {
+                t
+            }
+        }
+
+        /// Round to the nearest integer, with ties to even.
+        #[no_mangle]
+        pub extern "C" fn $nearest(x: $float) -> $float {
+            let t = $trunc(x);
+            if x.is_nan() {
+                return t;
+            }
+            // `x` and `t` are within a factor of two of each other, or `t` is zero, so the
+            // difference is exact, and has the sign of `x`.
+            let diff = x - t;
+            let odd = $trunc(t * 0.5) != t * 0.5;
+            if diff.abs() > 0.5 || (diff.abs() == 0.5 && odd) {
+                if diff < 0.0 {
+                    t - 1.0
+                } else {
+                    t + 1.0
+                }
+            } else {
+                t
+            }
+        }
+    };
+}
+
+rounding_libcalls!(
+    f32,
+    u32,
+    23,
+    lucet_libcall_truncf,
+    lucet_libcall_floorf,
+    lucet_libcall_ceilf,
+    lucet_libcall_nearbyintf
+);
+
+rounding_libcalls!(
+    f64,
+    u64,
+    52,
+    lucet_libcall_trunc,
+    lucet_libcall_floor,
+    lucet_libcall_ceil,
+    lucet_libcall_nearbyint
+);
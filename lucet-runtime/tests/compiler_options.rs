@@ -0,0 +1,320 @@
+use lucet_runtime::{DlModule, Error, Limits, MmapRegion, Region, TrapCode, UntypedRetVal, Val};
+use lucetc::{
+    read_module, Bindings, CompilerBuilder, CoverageMode, CpuFeatures, Lucetc, LucetcOpts,
+    ObjectFile,
+};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// Compile `input` with the options set in `builder`, and load it from `module_symbol`.
+fn load_with(
+    input: Lucetc,
+    builder: CompilerBuilder,
+    module_symbol: &str,
+) -> Result<Arc<DlModule>, Error> {
+    let workdir = TempDir::new().expect("create working directory");
+    let so_file = workdir.path().join("out.so");
+
+    input
+        .with_compiler_builder(builder.with_cpu_features(CpuFeatures::baseline()))
+        .with_bindings(Bindings::empty())
+        .shared_object_file(so_file.clone())
+        .expect("compile module");
+
+    DlModule::load_with_symbol(so_file, module_symbol)
+}
+
+fn load(input: Lucetc, builder: CompilerBuilder) -> Arc<DlModule> {
+    load_with(input, builder, "lucet_module").expect("load module")
+}
+
+fn load_wat(wat: &str, builder: CompilerBuilder) -> Arc<DlModule> {
+    load(Lucetc::try_from_bytes(wat).expect("read module"), builder)
+}
+
+/// Run `export` on a fresh instance of `module`.
+fn run(module: Arc<DlModule>, export: &str, args: &[Val]) -> Result<UntypedRetVal, Error> {
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+    inst.run(export, args).and_then(|res| res.returned())
+}
+
+fn assert_trap(res: Result<UntypedRetVal, Error>, trapcode: TrapCode) {
+    match res {
+        Err(Error::RuntimeFault(details)) => assert_eq!(details.trapcode, Some(trapcode)),
+        res => panic!("unexpected result: {:?}", res),
+    }
+}
+
+#[test]
+fn large_br_table() {
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    // Each arm of the `br_table` returns its own index times ten, and indices past the last arm
+    // take the default, which returns -1.
+    let arms = 64;
+    let mut wat = String::from("(module (func (export \"dispatch\") (param i32) (result i32)");
+    for _ in 0..=arms {
+        wat.push_str(" (block");
+    }
+    wat.push_str(" (br_table");
+    for arm in 0..arms {
+        wat.push_str(&format!(" {}", arm));
+    }
+    wat.push_str(&format!(" {} (local.get 0)))", arms));
+    for arm in 0..arms {
+        wat.push_str(&format!(" (return (i32.const {})))", arm * 10));
+    }
+    wat.push_str(" (i32.const -1)))");
+    let module = load_wat(&wat, CompilerBuilder::new());
+
+    for (arg, expected) in &[(0, 0), (1, 10), (37, 370), (63, 630), (64, -1), (1000, -1)] {
+        let retval = run(module.clone(), "dispatch", &[Val::I32(*arg)]).expect("run dispatch");
+        assert_eq!(retval.as_i32(), *expected, "dispatch({})", arg);
+    }
+}
+
+#[test]
+fn symbol_prefix() {
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    let builder = CompilerBuilder::new().with_symbol_prefix("guest_a_".to_owned());
+    let input = || Lucetc::new("./tests/compiler_options/locals.wat");
+
+    let module = load_with(input(), builder, "guest_a_lucet_module").expect("load prefixed module");
+    let retval = run(module, "main", &[]).expect("run main");
+    assert_eq!(retval.as_i32(), 74);
+
+    let builder = CompilerBuilder::new().with_symbol_prefix("guest_a_".to_owned());
+    assert!(load_with(input(), builder, "lucet_module").is_err());
+}
+
+#[test]
+fn hard_memory_limit() {
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    let wat = "(module (memory 1)
+        (func (export \"grow\") (param i32) (result i32) (memory.grow (local.get 0))))";
+    let module = load_wat(wat, CompilerBuilder::new().with_hard_memory_limit(Some(4)));
+
+    // Growing from 1 page to 4 stays within the limit.
+    let retval = run(module.clone(), "grow", &[Val::I32(3)]).expect("run grow");
+    assert_eq!(retval.as_i32(), 1);
+
+    assert_trap(
+        run(module, "grow", &[Val::I32(4)]),
+        TrapCode::MemoryLimitExceeded,
+    );
+}
+
+#[test]
+fn trap_uninit_table() {
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    for trap_uninit_table in &[false, true] {
+        let module = load(
+            Lucetc::new("./tests/compiler_options/icall_sparse.wat"),
+            CompilerBuilder::new().with_trap_uninit_table(*trap_uninit_table),
+        );
+
+        let retval = run(module.clone(), "foo", &[Val::I32(1)]).expect("run foo");
+        assert_eq!(retval.as_i32(), 1);
+
+        // Slot 0 precedes the element segment and slot 5 follows it; neither is initialized.
+        for index in &[0, 5] {
+            match run(module.clone(), "foo", &[Val::I32(*index)]) {
+                Err(Error::RuntimeFault(details)) => assert_eq!(
+                    details.trapcode,
+                    Some(TrapCode::BadSignature),
+                    "foo({}) with trap_uninit_table = {}",
+                    index,
+                    trap_uninit_table
+                ),
+                res => panic!("unexpected result: {:?}", res),
+            }
+        }
+    }
+}
+
+#[test]
+fn weak_import() {
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    // Nothing binds `inc`, so calls go to its default definition.
+    let wat = "(module
+        (func $inc (import \"env\" \"inc\") (result i32))
+        (func (export \"main\") (result i32) (call $inc)))";
+    let builder =
+        CompilerBuilder::new().with_weak_imports(["env::inc".to_owned()].iter().cloned().collect());
+    let module = load_wat(wat, builder);
+
+    assert_trap(run(module, "main", &[]), TrapCode::MissingImport);
+}
+
+#[test]
+fn multi_memory() {
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    // wabt can't assemble multi-memory modules, so this is written out by hand. It is equivalent
+    // to:
+    //
+    // (module
+    //   (memory $a 1)
+    //   (memory $b 2)
+    //   (func $main (export "main")
+    //     (i32.store $a (i32.const 0) (i32.const 1))
+    //     (i32.store $b (i32.const 0) (i32.load $a (i32.const 0)))))
+    #[rustfmt::skip]
+    let wasm = vec![
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+        // type section: () -> ()
+        0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+        // function section
+        0x03, 0x02, 0x01, 0x00,
+        // memory section: two memories, of one and two pages
+        0x05, 0x05, 0x02, 0x00, 0x01, 0x00, 0x02,
+        // export section: "main"
+        0x07, 0x08, 0x01, 0x04, b'm', b'a', b'i', b'n', 0x00, 0x00,
+        // code section
+        0x0a, 0x16, 0x01, 0x14, 0x00,
+        0x41, 0x00, 0x41, 0x01, 0x36, 0x02, 0x00,
+        0x41, 0x00, 0x41, 0x00, 0x28, 0x02, 0x00, 0x36, 0x42, 0x01, 0x00,
+        0x0b,
+    ];
+
+    // The runtime only provides memory 0, so it refuses to load a module that uses others.
+    let res = load_with(
+        Lucetc::try_from_bytes(wasm).expect("read module"),
+        CompilerBuilder::new().with_allow_multi_memory(true),
+        "lucet_module",
+    );
+    match res {
+        Err(Error::Unsupported(message)) => {
+            assert!(message.contains("multi-memory"), "{}", message)
+        }
+        res => panic!("unexpected result: {:?}", res.map(|_| ())),
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn merge() {
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    let bindings = Bindings::empty();
+    let objects = ["locals", "icall_sparse"]
+        .iter()
+        .map(|name| {
+            let path = format!("./tests/compiler_options/{}.wat", name);
+            let wasm = read_module(path, &None, false, true).expect("read module");
+            let obj = CompilerBuilder::new()
+                .with_cpu_features(CpuFeatures::baseline())
+                .create(&wasm, &bindings)
+                .expect("compile module")
+                .object_file()
+                .expect("codegen module");
+            (name.to_string(), obj)
+        })
+        .collect();
+    let merged = ObjectFile::merge(objects).expect("merge objects");
+
+    let workdir = TempDir::new().expect("create working directory");
+    let obj_file = workdir.path().join("guests.o");
+    let so_file = workdir.path().join("guests.so");
+    merged.write(&obj_file).expect("write merged object");
+    let status = std::process::Command::new("ld")
+        .arg("-shared")
+        .arg(&obj_file)
+        .arg("-o")
+        .arg(&so_file)
+        .status()
+        .expect("run ld");
+    assert!(status.success());
+
+    // Each guest is loaded through its own module symbol, and runs its own code.
+    let module = DlModule::load_with_symbol(&so_file, "locals_lucet_module").expect("load locals");
+    let retval = run(module, "main", &[]).expect("run main");
+    assert_eq!(retval.as_i32(), 74);
+
+    let module = DlModule::load_with_symbol(&so_file, "icall_sparse_lucet_module")
+        .expect("load icall_sparse");
+    let retval = run(module, "foo", &[Val::I32(1)]).expect("run foo");
+    assert_eq!(retval.as_i32(), 1);
+}
+
+#[test]
+fn flush_denormals() {
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    // The product of a normal and a small float is subnormal, and keeps the sign of its operands.
+    let wat = "(module (func (export \"mul\") (param f32 f32) (result f32)
+        (f32.mul (local.get 0) (local.get 1))))";
+    let args = [Val::F32(-2.0e-38), Val::F32(0.01)];
+
+    let module = load_wat(wat, CompilerBuilder::new());
+    let product = run(module, "mul", &args).expect("run mul").as_f32();
+    assert!(product.is_subnormal(), "{} is not subnormal", product);
+
+    let module = load_wat(wat, CompilerBuilder::new().with_flush_denormals(true));
+    let product = run(module, "mul", &args).expect("run mul").as_f32();
+    assert_eq!(product.to_bits(), (-0.0f32).to_bits());
+}
+
+#[test]
+fn split_debug_info() {
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    // `double` is local, so the production object doesn't keep its symbol, and is called both
+    // directly and through the table.
+    let wat = "(module
+        (type $t (func (param i32) (result i32)))
+        (table 1 anyfunc)
+        (elem (i32.const 0) $double)
+        (func $double (param i32) (result i32) (i32.add (local.get 0) (local.get 0)))
+        (func (export \"main\") (param i32) (result i32)
+            (call_indirect (type $t) (call $double (local.get 0)) (i32.const 0))))";
+    let workdir = TempDir::new().expect("create working directory");
+    let builder =
+        CompilerBuilder::new().with_split_debug_info(Some(workdir.path().join("debug.o")));
+    let module = load_wat(wat, builder);
+
+    let retval = run(module, "main", &[Val::I32(5)]).expect("run main");
+    assert_eq!(retval.as_i32(), 20);
+    assert!(workdir.path().join("debug.o").exists());
+}
+
+/// The coverage buffer of the module in `coverage`, which its instrumented code finds by symbol
+/// when it is loaded.
+#[export_name = "guest_cov_lucet_coverage_counters"]
+static mut COVERAGE_COUNTERS: [u64; 3] = [0; 3];
+
+#[test]
+fn coverage() {
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    let wat = "(module
+        (func $double (param i32) (result i32) (i32.add (local.get 0) (local.get 0)))
+        (func (export \"main\") (param i32) (result i32)
+            (call $double (call $double (local.get 0))))
+        (func (export \"unused\")))";
+    let builder = CompilerBuilder::new()
+        .with_coverage(CoverageMode::FunctionEntry)
+        .with_symbol_prefix("guest_cov_".to_owned());
+    let module = load_with(
+        Lucetc::try_from_bytes(wat).expect("read module"),
+        builder,
+        "guest_cov_lucet_module",
+    )
+    .expect("load module");
+
+    // There is one counter for each function, in function index order, and every instance
+    // increments the same ones.
+    for _ in 0..2 {
+        let retval = run(module.clone(), "main", &[Val::I32(3)]).expect("run main");
+        assert_eq!(retval.as_i32(), 12);
+    }
+    let counts = unsafe { std::ptr::read_volatile(&COVERAGE_COUNTERS) };
+    assert_eq!(counts, [4, 2, 0]);
+}
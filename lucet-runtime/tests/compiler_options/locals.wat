@@ -0,0 +1,140 @@
+(module
+  (func $main (export "main") (result i32) (local i32)
+    (set_local 0 (i32.const 10))
+    (i32.add (call $localpalooza) (get_local 0))
+  )
+  (func $localpalooza (export "localpalooza") (result i32) (local i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32)
+
+    (set_local 0 (i32.const 1))
+    (set_local 1 (i32.const 1))
+    (set_local 2 (i32.const 1))
+    (set_local 3 (i32.const 1))
+    (set_local 4 (i32.const 1))
+    (set_local 5 (i32.const 1))
+    (set_local 6 (i32.const 1))
+    (set_local 7 (i32.const 1))
+    (set_local 8 (i32.const 1))
+    (set_local 9 (i32.const 1))
+    (set_local 10 (i32.const 1))
+    (set_local 11 (i32.const 1))
+    (set_local 12 (i32.const 1))
+    (set_local 13 (i32.const 1))
+    (set_local 14 (i32.const 1))
+    (set_local 15 (i32.const 1))
+    (set_local 16 (i32.const 1))
+    (set_local 17 (i32.const 1))
+    (set_local 18 (i32.const 1))
+    (set_local 19 (i32.const 1))
+    (set_local 20 (i32.const 1))
+    (set_local 21 (i32.const 1))
+    (set_local 22 (i32.const 1))
+    (set_local 23 (i32.const 1))
+    (set_local 24 (i32.const 1))
+    (set_local 25 (i32.const 1))
+    (set_local 26 (i32.const 1))
+    (set_local 27 (i32.const 1))
+    (set_local 28 (i32.const 1))
+    (set_local 29 (i32.const 1))
+    (set_local 30 (i32.const 1))
+    (set_local 31 (i32.const 1))
+    (set_local 32 (i32.const 1))
+    (set_local 33 (i32.const 1))
+    (set_local 34 (i32.const 1))
+    (set_local 35 (i32.const 1))
+    (set_local 36 (i32.const 1))
+    (set_local 37 (i32.const 1))
+    (set_local 38 (i32.const 1))
+    (set_local 39 (i32.const 1))
+    (set_local 40 (i32.const 1))
+    (set_local 41 (i32.const 1))
+    (set_local 42 (i32.const 1))
+    (set_local 43 (i32.const 1))
+    (set_local 44 (i32.const 1))
+    (set_local 45 (i32.const 1))
+    (set_local 46 (i32.const 1))
+    (set_local 47 (i32.const 1))
+    (set_local 48 (i32.const 1))
+    (set_local 49 (i32.const 1))
+    (set_local 50 (i32.const 1))
+    (set_local 51 (i32.const 1))
+    (set_local 52 (i32.const 1))
+    (set_local 53 (i32.const 1))
+    (set_local 54 (i32.const 1))
+    (set_local 55 (i32.const 1))
+    (set_local 56 (i32.const 1))
+    (set_local 57 (i32.const 1))
+    (set_local 58 (i32.const 1))
+    (set_local 59 (i32.const 1))
+    (set_local 60 (i32.const 1))
+    (set_local 61 (i32.const 1))
+    (set_local 62 (i32.const 1))
+    (set_local 63 (i32.const 1))
+
+
+    (set_local 1 (i32.add (get_local 0) (get_local 1)))
+    (set_local 2 (i32.add (get_local 1) (get_local 2)))
+    (set_local 3 (i32.add (get_local 2) (get_local 3)))
+    (set_local 4 (i32.add (get_local 3) (get_local 4)))
+    (set_local 5 (i32.add (get_local 4) (get_local 5)))
+    (set_local 6 (i32.add (get_local 5) (get_local 6)))
+    (set_local 7 (i32.add (get_local 6) (get_local 7)))
+    (set_local 8 (i32.add (get_local 7) (get_local 8)))
+    (set_local 9 (i32.add (get_local 8) (get_local 9)))
+    (set_local 10 (i32.add (get_local 9) (get_local 10)))
+    (set_local 11 (i32.add (get_local 10) (get_local 11)))
+    (set_local 12 (i32.add (get_local 11) (get_local 12)))
+    (set_local 13 (i32.add (get_local 12) (get_local 13)))
+    (set_local 14 (i32.add (get_local 13) (get_local 14)))
+    (set_local 15 (i32.add (get_local 14) (get_local 15)))
+    (set_local 16 (i32.add (get_local 15) (get_local 16)))
+    (set_local 17 (i32.add (get_local 16) (get_local 17)))
+    (set_local 18 (i32.add (get_local 17) (get_local 18)))
+    (set_local 19 (i32.add (get_local 18) (get_local 19)))
+    (set_local 20 (i32.add (get_local 19) (get_local 20)))
+    (set_local 21 (i32.add (get_local 20) (get_local 21)))
+    (set_local 22 (i32.add (get_local 21) (get_local 22)))
+    (set_local 23 (i32.add (get_local 22) (get_local 23)))
+    (set_local 24 (i32.add (get_local 23) (get_local 24)))
+    (set_local 25 (i32.add (get_local 24) (get_local 25)))
+    (set_local 26 (i32.add (get_local 25) (get_local 26)))
+    (set_local 27 (i32.add (get_local 26) (get_local 27)))
+    (set_local 28 (i32.add (get_local 27) (get_local 28)))
+    (set_local 29 (i32.add (get_local 28) (get_local 29)))
+    (set_local 30 (i32.add (get_local 29) (get_local 30)))
+    (set_local 31 (i32.add (get_local 30) (get_local 31)))
+    (set_local 32 (i32.add (get_local 31) (get_local 32)))
+    (set_local 33 (i32.add (get_local 32) (get_local 33)))
+    (set_local 34 (i32.add (get_local 33) (get_local 34)))
+    (set_local 35 (i32.add (get_local 34) (get_local 35)))
+    (set_local 36 (i32.add (get_local 35) (get_local 36)))
+    (set_local 37 (i32.add (get_local 36) (get_local 37)))
+    (set_local 38 (i32.add (get_local 37) (get_local 38)))
+    (set_local 39 (i32.add (get_local 38) (get_local 39)))
+    (set_local 40 (i32.add (get_local 39) (get_local 40)))
+    (set_local 41 (i32.add (get_local 40) (get_local 41)))
+    (set_local 42 (i32.add (get_local 41) (get_local 42)))
+    (set_local 43 (i32.add (get_local 42) (get_local 43)))
+    (set_local 44 (i32.add (get_local 43) (get_local 44)))
+    (set_local 45 (i32.add (get_local 44) (get_local 45)))
+    (set_local 46 (i32.add (get_local 45) (get_local 46)))
+    (set_local 47 (i32.add (get_local 46) (get_local 47)))
+    (set_local 48 (i32.add (get_local 47) (get_local 48)))
+    (set_local 49 (i32.add (get_local 48) (get_local 49)))
+    (set_local 50 (i32.add (get_local 49) (get_local 50)))
+    (set_local 51 (i32.add (get_local 50) (get_local 51)))
+    (set_local 52 (i32.add (get_local 51) (get_local 52)))
+    (set_local 53 (i32.add (get_local 52) (get_local 53)))
+    (set_local 54 (i32.add (get_local 53) (get_local 54)))
+    (set_local 55 (i32.add (get_local 54) (get_local 55)))
+    (set_local 56 (i32.add (get_local 55) (get_local 56)))
+    (set_local 57 (i32.add (get_local 56) (get_local 57)))
+    (set_local 58 (i32.add (get_local 57) (get_local 58)))
+    (set_local 59 (i32.add (get_local 58) (get_local 59)))
+    (set_local 60 (i32.add (get_local 59) (get_local 60)))
+    (set_local 61 (i32.add (get_local 60) (get_local 61)))
+    (set_local 62 (i32.add (get_local 61) (get_local 62)))
+    (set_local 63 (i32.add (get_local 62) (get_local 63)))
+
+    (get_local 63)
+  )
+)
@@ -0,0 +1,70 @@
+use lucet_runtime::vmctx::Vmctx;
+use lucet_runtime::{lucet_hostcall, DlModule, Error, Limits, MmapRegion, Region, TrapCode};
+use lucetc::{Bindings, Lucetc, LucetcOpts};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn host_trap_test_fail(vmctx: &Vmctx, fail: u32) {
+    if fail != 0 {
+        vmctx.host_trap();
+    }
+}
+
+fn host_trap_test_module() -> Arc<DlModule> {
+    let workdir = TempDir::new().expect("create working directory");
+
+    let mut env = HashMap::new();
+    env.insert("fail".to_owned(), "host_trap_test_fail".to_owned());
+    let mut bindings = Bindings::env(env);
+    bindings.set_trap_code("env", "fail", 7);
+
+    let native_build = Lucetc::new("./tests/host_trap/host_trap.wat").with_bindings(bindings);
+
+    let so_file = workdir.path().join("out.so");
+
+    native_build
+        .shared_object_file(so_file.clone())
+        .expect("compile module");
+
+    DlModule::load(so_file).expect("load module")
+}
+
+#[test]
+fn host_trap_uses_binding_code() {
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    let module = host_trap_test_module();
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+
+    // The guest only traps when the host function asks it to.
+    inst.run("main", &[0u32.into()])
+        .expect("instance runs without trapping");
+
+    match inst.run("main", &[1u32.into()]) {
+        Err(Error::RuntimeFault(details)) => {
+            assert_eq!(details.trapcode, Some(TrapCode::Host(7)))
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+
+    // The request is cleared by the trap, so the instance can run again without a reset.
+    inst.run("main", &[0u32.into()])
+        .expect("instance runs without trapping");
+    match inst.run("main", &[1u32.into()]) {
+        Err(Error::RuntimeFault(details)) => {
+            assert_eq!(details.trapcode, Some(TrapCode::Host(7)))
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+
+    // Resetting the instance clears the request too.
+    inst.reset().expect("instance can be reset");
+    inst.run("main", &[0u32.into()])
+        .expect("instance runs without trapping");
+}
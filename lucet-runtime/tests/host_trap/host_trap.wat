@@ -0,0 +1,6 @@
+(module
+  (import "env" "fail" (func $fail (param i32)))
+  (func $main (export "main") (param i32)
+    (call $fail (local.get 0))
+  )
+)
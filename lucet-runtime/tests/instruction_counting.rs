@@ -12,10 +12,13 @@ use tempfile::TempDir;
 pub fn wasm_test<P: AsRef<Path>>(
     wasm_file: P,
     icount_option: bool,
+    canonicalize_nans: bool,
 ) -> Result<Arc<DlModule>, Error> {
     let workdir = TempDir::new().expect("create working directory");
 
-    let native_build = Lucetc::new(wasm_file).with_count_instructions(icount_option);
+    let native_build = Lucetc::new(wasm_file)
+        .with_count_instructions(icount_option)
+        .with_canonicalize_nans(canonicalize_nans);
 
     let so_file = workdir.path().join("out.so");
 
@@ -54,7 +57,7 @@ pub fn check_instruction_count_off() {
     files.par_iter().for_each(|ent| {
         let wasm_path = ent.path();
         let do_not_instrument = false;
-        let module = wasm_test(&wasm_path, do_not_instrument).expect("can load module");
+        let module = wasm_test(&wasm_path, do_not_instrument, false).expect("can load module");
 
         let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
 
@@ -73,6 +76,17 @@ pub fn check_instruction_count_off() {
 
 #[test]
 pub fn check_instruction_count() {
+    check_instruction_count_internal(/* canonicalize_nans = */ false);
+}
+
+/// NaN canonicalization inserts code after translation, which must not change the count of wasm
+/// instructions executed.
+#[test]
+pub fn check_instruction_count_canonicalize_nans() {
+    check_instruction_count_internal(/* canonicalize_nans = */ true);
+}
+
+fn check_instruction_count_internal(canonicalize_nans: bool) {
     let files: Vec<DirEntry> =
         get_instruction_count_test_files(/* want_start_function = */ false);
 
@@ -84,7 +98,8 @@ pub fn check_instruction_count() {
     files.par_iter().for_each(|ent| {
         let wasm_path = ent.path();
         let do_instrument = true;
-        let module = wasm_test(&wasm_path, do_instrument).expect("can load instrumented module");
+        let module = wasm_test(&wasm_path, do_instrument, canonicalize_nans)
+            .expect("can load instrumented module");
 
         let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
 
@@ -158,7 +173,8 @@ fn check_instruction_count_with_periodic_yields_internal(want_start_function: bo
     files.par_iter().for_each(|ent| {
         let wasm_path = ent.path();
         let do_instrument = true;
-        let module = wasm_test(&wasm_path, do_instrument).expect("can load instrumented module");
+        let module =
+            wasm_test(&wasm_path, do_instrument, false).expect("can load instrumented module");
 
         let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
 
@@ -0,0 +1,12 @@
+(module
+  (func $main (export "test_function")
+    f32.const 1.5
+    f32.const 2.5
+    f32.add
+    f32.sqrt
+    drop
+  )
+  (func $instruction_count (export "instruction_count") (result i64)
+    i64.const 4
+  )
+)
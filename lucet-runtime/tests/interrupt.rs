@@ -0,0 +1,61 @@
+use lucet_runtime::{DlModule, Error, Limits, MmapRegion, Region, TrapCode};
+use lucetc::{Lucetc, LucetcOpts};
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn interrupt_test_module() -> Arc<DlModule> {
+    let workdir = TempDir::new().expect("create working directory");
+
+    let native_build = Lucetc::new("./tests/interrupt/loop_forever.wat").with_interrupt_checks(1);
+
+    let so_file = workdir.path().join("out.so");
+
+    native_build
+        .shared_object_file(so_file.clone())
+        .expect("compile module");
+
+    DlModule::load(so_file).expect("load module")
+}
+
+#[test]
+fn interrupt_from_another_thread() {
+    let module = interrupt_test_module();
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+
+    let handle = inst.interrupt_handle();
+    let interrupter = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(handle.interrupt());
+    });
+
+    match inst.run("loop_forever", &[]) {
+        Err(Error::RuntimeFault(details)) => {
+            assert_eq!(details.trapcode, Some(TrapCode::Interrupt))
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+    interrupter.join().expect("interrupter thread");
+
+    // The flag stays set until the instance is reset.
+    assert!(inst.interrupt_flag());
+    inst.reset().expect("instance can be reset");
+    assert!(!inst.interrupt_flag());
+}
+
+#[test]
+fn interrupt_handle_outlives_instance() {
+    let module = interrupt_test_module();
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+
+    let handle = inst.interrupt_handle();
+    assert!(handle.set(false));
+    drop(inst);
+    assert!(!handle.interrupt());
+}
@@ -0,0 +1,7 @@
+(module
+  (func $main (export "loop_forever")
+    loop $inner
+      br $inner
+    end
+  )
+)
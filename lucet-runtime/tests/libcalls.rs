@@ -0,0 +1,134 @@
+use lucet_runtime::libcalls::*;
+
+/// Round to the nearest integer with ties to even, using std, which rounds ties away from zero.
+fn nearest_f64(x: f64) -> f64 {
+    let r = x.round();
+    if (r - x).abs() == 0.5 {
+        2.0 * (x / 2.0).round()
+    } else {
+        r
+    }
+}
+
+fn nearest_f32(x: f32) -> f32 {
+    let r = x.round();
+    if (r - x).abs() == 0.5 {
+        2.0 * (x / 2.0).round()
+    } else {
+        r
+    }
+}
+
+const F64_CASES: &[f64] = &[
+    0.0,
+    0.3,
+    0.5,
+    0.7,
+    1.0,
+    1.5,
+    2.5,
+    3.5,
+    -2.5,
+    123456.789,
+    4503599627370495.5,
+    4503599627370496.0,
+    9007199254740993.0,
+    f64::MIN_POSITIVE,
+    f64::MAX,
+    f64::INFINITY,
+];
+
+const F32_CASES: &[f32] = &[
+    0.0,
+    0.3,
+    0.5,
+    0.7,
+    1.0,
+    1.5,
+    2.5,
+    3.5,
+    -2.5,
+    12345.678,
+    8388607.5,
+    8388608.0,
+    16777217.0,
+    f32::MIN_POSITIVE,
+    f32::MAX,
+    f32::INFINITY,
+];
+
+#[test]
+fn rounding_matches_std_f64() {
+    for x in F64_CASES.iter().flat_map(|x| vec![*x, -*x]) {
+        assert_eq!(
+            lucet_libcall_trunc(x).to_bits(),
+            x.trunc().to_bits(),
+            "trunc {}",
+            x
+        );
+        assert_eq!(
+            lucet_libcall_floor(x).to_bits(),
+            x.floor().to_bits(),
+            "floor {}",
+            x
+        );
+        assert_eq!(
+            lucet_libcall_ceil(x).to_bits(),
+            x.ceil().to_bits(),
+            "ceil {}",
+            x
+        );
+        assert_eq!(
+            lucet_libcall_nearbyint(x).to_bits(),
+            nearest_f64(x).to_bits(),
+            "nearest {}",
+            x
+        );
+    }
+}
+
+#[test]
+fn rounding_matches_std_f32() {
+    for x in F32_CASES.iter().flat_map(|x| vec![*x, -*x]) {
+        assert_eq!(
+            lucet_libcall_truncf(x).to_bits(),
+            x.trunc().to_bits(),
+            "trunc {}",
+            x
+        );
+        assert_eq!(
+            lucet_libcall_floorf(x).to_bits(),
+            x.floor().to_bits(),
+            "floor {}",
+            x
+        );
+        assert_eq!(
+            lucet_libcall_ceilf(x).to_bits(),
+            x.ceil().to_bits(),
+            "ceil {}",
+            x
+        );
+        assert_eq!(
+            lucet_libcall_nearbyintf(x).to_bits(),
+            nearest_f32(x).to_bits(),
+            "nearest {}",
+            x
+        );
+    }
+}
+
+#[test]
+fn rounding_quiets_nans() {
+    let signaling = f64::from_bits(0x7ff0_0000_0000_0001);
+    assert_eq!(
+        lucet_libcall_floor(signaling).to_bits(),
+        0x7ff8_0000_0000_0001
+    );
+    assert_eq!(
+        lucet_libcall_nearbyint(signaling).to_bits(),
+        0x7ff8_0000_0000_0001
+    );
+    let signaling = f32::from_bits(0xff80_0001);
+    assert_eq!(lucet_libcall_ceilf(signaling).to_bits(), 0xffc0_0001);
+    assert_eq!(lucet_libcall_truncf(signaling).to_bits(), 0xffc0_0001);
+}
@@ -0,0 +1,49 @@
+use lucet_runtime::{DlModule, Limits, MmapRegion, Region};
+use lucetc::signature::{keygen, KeyPair};
+use lucetc::{Lucetc, LucetcOpts};
+use std::path::Path;
+use tempfile::TempDir;
+
+const MODULE_SYMBOL: &str = "guest_a_lucet_module";
+
+fn raw_keypair(workdir: &Path, name: &str) -> KeyPair {
+    let pk_path = workdir.join(format!("{}.pub", name));
+    let sk_path = format!("raw:{}", workdir.join(format!("{}.key", name)).display());
+    keygen(pk_path, sk_path).expect("generate key pair")
+}
+
+#[test]
+fn sign_and_verify_renamed_module() {
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    let workdir = TempDir::new().expect("create working directory");
+    let keypair = raw_keypair(workdir.path(), "guest");
+    let other_keypair = raw_keypair(workdir.path(), "other");
+
+    let so_file = workdir.path().join("out.so");
+    Lucetc::new("./tests/signature/answer.wat")
+        .with_module_symbol(MODULE_SYMBOL.to_owned())
+        .with_sk(keypair.sk)
+        .with_sign()
+        .shared_object_file(so_file.clone())
+        .expect("compile and sign module");
+
+    // The module is only found through its own symbol.
+    assert!(DlModule::load(&so_file).is_err());
+    assert!(
+        DlModule::load_and_verify_with_symbol(&so_file, MODULE_SYMBOL, other_keypair.pk, true)
+            .is_err()
+    );
+    let module = DlModule::load_and_verify_with_symbol(&so_file, MODULE_SYMBOL, keypair.pk, true)
+        .expect("load and verify module");
+
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+    let retval = inst
+        .run("main", &[])
+        .expect("instance runs")
+        .unwrap_returned();
+    assert_eq!(retval.as_i32(), 42);
+}
@@ -0,0 +1,5 @@
+(module
+  (func $main (export "main") (result i32)
+    (i32.const 42)
+  )
+)
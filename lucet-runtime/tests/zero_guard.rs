@@ -0,0 +1,45 @@
+use anyhow::Error;
+use lucet_runtime::{
+    DlModule, Error as RuntimeError, Limits, MmapRegion, Module, Region, TrapCode,
+};
+use lucetc::{Lucetc, LucetcOpts};
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+pub fn wasm_test<P: AsRef<Path>>(wasm_file: P) -> Result<Arc<DlModule>, Error> {
+    let workdir = TempDir::new().expect("create working directory");
+
+    let native_build = Lucetc::new(wasm_file).with_guard_size(0);
+
+    let so_file = workdir.path().join("out.so");
+
+    native_build.shared_object_file(so_file.clone())?;
+
+    let dlmodule = DlModule::load(so_file)?;
+
+    Ok(dlmodule)
+}
+
+#[test]
+pub fn zero_guard_heap_out_of_bounds() {
+    let module = wasm_test("./tests/zero_guard/oob.wat").expect("can load module");
+    assert_eq!(module.heap_spec().map(|spec| spec.guard_size), Some(0));
+
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+
+    inst.run("in_bounds", &[]).expect("in bounds access runs");
+
+    for func in &["oob_reserved", "oob_offset"] {
+        match inst.run(func, &[]) {
+            Err(RuntimeError::RuntimeFault(details)) => {
+                assert_eq!(details.trapcode, Some(TrapCode::HeapOutOfBounds));
+            }
+            res => panic!("unexpected result from {}: {:?}", func, res),
+        }
+        inst.reset().expect("instance resets");
+    }
+}
@@ -0,0 +1,13 @@
+(module
+  (memory 1)
+  ;; The default minimum reserved size is 4MiB, so this is just past the reserved heap.
+  (func (export "oob_reserved") (result i32)
+    (i32.load (i32.const 0x400000))
+  )
+  (func (export "oob_offset") (result i32)
+    (i32.load offset=0x400000 (i32.const 0))
+  )
+  (func (export "in_bounds") (result i32)
+    (i32.load (i32.const 0xfffc))
+  )
+)
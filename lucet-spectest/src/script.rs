@@ -36,7 +36,7 @@ impl ScriptError {
             ScriptError::ProgramError(ref lucetc_err)
             | ScriptError::ValidationError(ref lucetc_err)
             | ScriptError::CompileError(ref lucetc_err) => match lucetc_err {
-                &LucetcError::Unsupported(_) => true,
+                &LucetcError::Unsupported(_) | &LucetcError::UnsupportedFeature { .. } => true,
                 _ => false,
             },
             _ => false,
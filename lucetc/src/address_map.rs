@@ -0,0 +1,90 @@
+use byteorder::{ByteOrder, LittleEndian};
+use cranelift_codegen::isa::TargetIsa;
+use cranelift_codegen::Context as ClifContext;
+
+/// The prefix of the symbol for each function's address map.
+pub(crate) const ADDRESS_MAP_PREFIX: &str = "lucet_address_map_";
+
+/// The symbol for the address map of the function with symbol `sym`.
+pub(crate) fn address_map_sym(sym: &str) -> String {
+    format!("{}{}", ADDRESS_MAP_PREFIX, sym)
+}
+
+/// A range of a function's machine code, and the offset in the wasm module of the instruction
+/// it was generated from.
+///
+/// Code offsets are relative to the start of the function, and the range excludes `code_end`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AddressMapEntry {
+    pub code_start: u32,
+    pub code_end: u32,
+    pub wasm_offset: u32,
+}
+
+impl AddressMapEntry {
+    const SERIALIZED_SIZE: usize = 12;
+
+    /// Serialize entries as consecutive little-endian `(code_start, code_end, wasm_offset)`
+    /// triples, the layout of an address map in the object file.
+    pub(crate) fn serialize(entries: &[Self]) -> Box<[u8]> {
+        let mut bytes = vec![0; entries.len() * Self::SERIALIZED_SIZE];
+        for (entry, chunk) in entries.iter().zip(bytes.chunks_mut(Self::SERIALIZED_SIZE)) {
+            LittleEndian::write_u32(&mut chunk[0..4], entry.code_start);
+            LittleEndian::write_u32(&mut chunk[4..8], entry.code_end);
+            LittleEndian::write_u32(&mut chunk[8..12], entry.wasm_offset);
+        }
+        bytes.into_boxed_slice()
+    }
+
+    pub(crate) fn deserialize(bytes: &[u8]) -> Vec<Self> {
+        bytes
+            .chunks_exact(Self::SERIALIZED_SIZE)
+            .map(|chunk| AddressMapEntry {
+                code_start: LittleEndian::read_u32(&chunk[0..4]),
+                code_end: LittleEndian::read_u32(&chunk[4..8]),
+                wasm_offset: LittleEndian::read_u32(&chunk[8..12]),
+            })
+            .collect()
+    }
+}
+
+/// Collect the address map of a function that has been compiled in `clif`, in code order.
+///
+/// Cranelift's wasm translator tags each instruction with the offset of the wasm operator it
+/// came from. Code without such a tag, like the prologue and epilogue, is left out, as are empty
+/// ranges.
+pub(crate) fn function_address_map(
+    clif: &ClifContext,
+    isa: &dyn TargetIsa,
+) -> Vec<AddressMapEntry> {
+    if let Some(result) = clif.mach_compile_result.as_ref() {
+        return result
+            .buffer
+            .get_srclocs_sorted()
+            .iter()
+            .filter(|srcloc| !srcloc.loc.is_default() && srcloc.start < srcloc.end)
+            .map(|srcloc| AddressMapEntry {
+                code_start: srcloc.start,
+                code_end: srcloc.end,
+                wasm_offset: srcloc.loc.bits(),
+            })
+            .collect();
+    }
+
+    // The old backend records offsets in the function itself. Blocks are laid out in the order
+    // of their offsets, which isn't necessarily the layout order.
+    let func = &clif.func;
+    let encinfo = isa.encoding_info();
+    let mut blocks = func.layout.blocks().collect::<Vec<_>>();
+    blocks.sort_by_key(|block| func.offsets[*block]);
+    blocks
+        .into_iter()
+        .flat_map(|block| func.inst_offsets(block, &encinfo))
+        .filter(|(_offset, inst, size)| !func.srclocs[*inst].is_default() && *size > 0)
+        .map(|(offset, inst, size)| AddressMapEntry {
+            code_start: offset,
+            code_end: offset + size,
+            wasm_offset: func.srclocs[inst].bits(),
+        })
+        .collect()
+}
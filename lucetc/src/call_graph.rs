@@ -0,0 +1,175 @@
+use crate::decls::ModuleDecls;
+use crate::error::Error;
+use crate::module::UniqueFuncIndex;
+use cranelift_codegen::entity::EntityRef;
+use cranelift_wasm::wasmparser::{FunctionBody, Operator};
+use cranelift_wasm::{FuncIndex, TableIndex, TypeIndex};
+use std::collections::{BTreeSet, HashMap};
+
+/// The target of a call edge in a `CallGraph`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CallTarget {
+    Func(UniqueFuncIndex),
+    /// An indirect call through a table whose contents are not known at compile time, such as an
+    /// imported table, or one initialized at a global offset.
+    Unknown,
+}
+
+/// The calls made by each function defined in a module.
+///
+/// Direct calls are edges to their callee. Indirect calls are edges to every function in the
+/// table that has the signature of the call, or to `CallTarget::Unknown` if the table contents
+/// are not statically known.
+#[derive(Debug, Clone, Default)]
+pub struct CallGraph {
+    edges: HashMap<UniqueFuncIndex, Vec<CallTarget>>,
+}
+
+impl CallGraph {
+    pub(crate) fn new<'a>(
+        decls: &ModuleDecls<'_>,
+        function_bodies: impl Iterator<Item = (UniqueFuncIndex, &'a FunctionBody<'a>)>,
+    ) -> Result<Self, Error> {
+        let mut edges = HashMap::new();
+        for (func_index, body) in function_bodies {
+            let mut targets = BTreeSet::new();
+            for op in body.get_operators_reader()? {
+                match op? {
+                    Operator::Call { function_index } | Operator::ReturnCall { function_index } => {
+                        targets.insert(direct_target(decls, function_index)?);
+                    }
+                    Operator::CallIndirect { index, table_index }
+                    | Operator::ReturnCallIndirect { index, table_index } => {
+                        targets.extend(indirect_targets(decls, index, table_index)?);
+                    }
+                    _ => {}
+                }
+            }
+            edges.insert(func_index, targets.into_iter().collect());
+        }
+        Ok(Self { edges })
+    }
+
+    /// The calls made by `func`, sorted and without duplicates. Imported functions have no
+    /// entry, as their bodies are not part of the module.
+    pub fn callees(&self, func: UniqueFuncIndex) -> Option<&[CallTarget]> {
+        self.edges.get(&func).map(|targets| targets.as_slice())
+    }
+
+    /// The adjacency lists of the graph, keyed by caller.
+    pub fn edges(&self) -> &HashMap<UniqueFuncIndex, Vec<CallTarget>> {
+        &self.edges
+    }
+
+    /// The sets of defined functions that can recurse, directly or through each other: each
+    /// function that calls itself, and each larger strongly connected component of the graph.
+    ///
+    /// Each cycle is sorted, and the cycles are sorted by their first function. Calls to
+    /// `CallTarget::Unknown` are not followed, so recursion through such calls is not found.
+    pub fn recursion_cycles(&self) -> Vec<Vec<UniqueFuncIndex>> {
+        let mut callers = self.edges.keys().copied().collect::<Vec<_>>();
+        callers.sort();
+        let callees = |func: UniqueFuncIndex| {
+            self.edges
+                .get(&func)
+                .into_iter()
+                .flatten()
+                .filter_map(|target| match target {
+                    CallTarget::Func(callee) if self.edges.contains_key(callee) => Some(*callee),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Tarjan's strongly connected components algorithm, with an explicit stack of
+        // (function, index of the next callee to visit) so deep call chains can't overflow.
+        let mut index = HashMap::new();
+        let mut lowlink = HashMap::new();
+        let mut on_stack = BTreeSet::new();
+        let mut stack = Vec::new();
+        let mut cycles = Vec::new();
+        for root in callers {
+            if index.contains_key(&root) {
+                continue;
+            }
+            let mut work = vec![(root, callees(root), 0)];
+            index.insert(root, index.len());
+            lowlink.insert(root, index[&root]);
+            stack.push(root);
+            on_stack.insert(root);
+            while let Some((func, targets, next)) = work.last_mut() {
+                let func = *func;
+                if let Some(callee) = targets.get(*next).copied() {
+                    *next += 1;
+                    if !index.contains_key(&callee) {
+                        index.insert(callee, index.len());
+                        lowlink.insert(callee, index[&callee]);
+                        stack.push(callee);
+                        on_stack.insert(callee);
+                        work.push((callee, callees(callee), 0));
+                    } else if on_stack.contains(&callee) {
+                        let low = lowlink[&func].min(index[&callee]);
+                        lowlink.insert(func, low);
+                    }
+                    continue;
+                }
+                work.pop();
+                if let Some((caller, _, _)) = work.last() {
+                    let low = lowlink[caller].min(lowlink[&func]);
+                    lowlink.insert(*caller, low);
+                }
+                if lowlink[&func] == index[&func] {
+                    let mut component = Vec::new();
+                    loop {
+                        let member = stack.pop().expect("component members are on the stack");
+                        on_stack.remove(&member);
+                        component.push(member);
+                        if member == func {
+                            break;
+                        }
+                    }
+                    let recursive =
+                        component.len() > 1 || self.edges[&func].contains(&CallTarget::Func(func));
+                    if recursive {
+                        component.sort();
+                        cycles.push(component);
+                    }
+                }
+            }
+        }
+        cycles.sort();
+        cycles
+    }
+}
+
+fn direct_target(decls: &ModuleDecls<'_>, function_index: u32) -> Result<CallTarget, Error> {
+    decls
+        .info
+        .function_mapping
+        .get(FuncIndex::from_u32(function_index))
+        .map(|unique_index| CallTarget::Func(*unique_index))
+        .ok_or_else(|| Error::FunctionIndexError(format!("{}", function_index)))
+}
+
+fn indirect_targets(
+    decls: &ModuleDecls<'_>,
+    type_index: u32,
+    table_index: u32,
+) -> Result<Vec<CallTarget>, Error> {
+    let table = decls.get_table(TableIndex::new(table_index as usize))?;
+    if table.import_name.is_some() || table.elems.iter().any(|elems| elems.base.is_some()) {
+        return Ok(vec![CallTarget::Unknown]);
+    }
+    let signature_index = decls.get_signature_uid(TypeIndex::from_u32(type_index))?;
+
+    let mut targets = Vec::new();
+    for func_index in table.elems.iter().flat_map(|elems| elems.elements.iter()) {
+        let func = decls
+            .get_func(*func_index)
+            .ok_or_else(|| Error::FunctionIndexError(format!("{:?}", func_index)))?;
+        if func.signature_index == signature_index {
+            targets.push(CallTarget::Func(*func_index));
+        }
+    }
+    Ok(targets)
+}
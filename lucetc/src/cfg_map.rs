@@ -0,0 +1,79 @@
+use byteorder::{ByteOrder, LittleEndian};
+use cranelift_codegen::Context as ClifContext;
+
+/// The prefix of the symbol for each function's CFG map.
+pub(crate) const CFG_MAP_PREFIX: &str = "lucet_cfg_map_";
+
+/// The symbol for the CFG map of the function with symbol `sym`.
+pub(crate) fn cfg_map_sym(sym: &str) -> String {
+    format!("{}{}", CFG_MAP_PREFIX, sym)
+}
+
+/// The range of a function's machine code that makes up one of its basic blocks.
+///
+/// Offsets are relative to the start of the function, and the range excludes `code_end`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub code_start: u32,
+    pub code_end: u32,
+}
+
+impl BasicBlock {
+    const SERIALIZED_SIZE: usize = 8;
+
+    /// Serialize blocks as consecutive little-endian `(code_start, code_end)` pairs, the layout
+    /// of a CFG map in the object file.
+    pub(crate) fn serialize(blocks: &[Self]) -> Box<[u8]> {
+        let mut bytes = vec![0; blocks.len() * Self::SERIALIZED_SIZE];
+        for (block, chunk) in blocks.iter().zip(bytes.chunks_mut(Self::SERIALIZED_SIZE)) {
+            LittleEndian::write_u32(&mut chunk[0..4], block.code_start);
+            LittleEndian::write_u32(&mut chunk[4..8], block.code_end);
+        }
+        bytes.into_boxed_slice()
+    }
+
+    pub(crate) fn deserialize(bytes: &[u8]) -> Vec<Self> {
+        bytes
+            .chunks_exact(Self::SERIALIZED_SIZE)
+            .map(|chunk| BasicBlock {
+                code_start: LittleEndian::read_u32(&chunk[0..4]),
+                code_end: LittleEndian::read_u32(&chunk[4..8]),
+            })
+            .collect()
+    }
+}
+
+/// Collect the basic blocks of a function that has been compiled in `clif`, in code order.
+/// `code_size` is the size of the function's code, not including any read-only data after it.
+///
+/// The new backend only records where blocks start when the ISA was built with
+/// `machine_code_cfg_info` enabled. Blocks are those of the machine code, which can include
+/// blocks Cranelift adds to split critical edges, so they don't correspond one-to-one with
+/// wasm's blocks. Empty blocks are left out.
+pub(crate) fn function_cfg_map(clif: &ClifContext, code_size: u32) -> Vec<BasicBlock> {
+    let starts = match clif.mach_compile_result.as_ref() {
+        Some(result) => result.bb_starts.clone(),
+        None => {
+            // The old backend records offsets in the function itself. Blocks are laid out in the
+            // order of their offsets, which isn't necessarily the layout order.
+            let func = &clif.func;
+            let mut starts = func
+                .layout
+                .blocks()
+                .map(|block| func.offsets[block])
+                .collect::<Vec<_>>();
+            starts.sort_unstable();
+            starts
+        }
+    };
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, start)| BasicBlock {
+            code_start: *start,
+            code_end: starts.get(i + 1).copied().unwrap_or(code_size),
+        })
+        .filter(|block| block.code_start < block.code_end)
+        .collect()
+}
@@ -0,0 +1,56 @@
+//! Checks run over the Cranelift IR of every function before it is compiled.
+//!
+//! Add a check with `CompilerBuilder::add_codegen_check`. A check that returns `Err` fails
+//! compilation with `Error::CodegenCheckFailed`, naming the function and the reason given.
+
+use cranelift_codegen::dominator_tree::DominatorTree;
+use cranelift_codegen::flowgraph::ControlFlowGraph;
+use cranelift_codegen::ir;
+use cranelift_codegen::loop_analysis::LoopAnalysis;
+use std::sync::Arc;
+
+pub type CodegenCheck = dyn Fn(&ir::Function) -> Result<(), String> + Send + Sync;
+
+pub(crate) type CodegenChecks = Vec<Arc<CodegenCheck>>;
+
+/// Reject functions that make indirect calls, such as those produced by `call_indirect`.
+pub fn no_indirect_calls(func: &ir::Function) -> Result<(), String> {
+    for block in func.layout.blocks() {
+        for inst in func.layout.block_insts(block) {
+            if func.dfg[inst].opcode() == ir::Opcode::CallIndirect {
+                return Err(format!("indirect call at {} in {}", inst, block));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Count the loops in `func` that contain no call to `callee`. With instruction counting, every
+/// loop back-edge checks the instruction count bound, calling the yield hostcall if the bound is
+/// exceeded, so a loop without that call is one whose iterations are unbounded.
+pub(crate) fn loops_without_call(func: &ir::Function, callee: &ir::ExternalName) -> usize {
+    let cfg = ControlFlowGraph::with_function(func);
+    let domtree = DominatorTree::with_function(func, &cfg);
+    let mut loop_analysis = LoopAnalysis::new();
+    loop_analysis.compute(func, &cfg, &domtree);
+
+    let calls_callee = |block: ir::Block| {
+        func.layout
+            .block_insts(block)
+            .any(|inst| match func.dfg[inst] {
+                ir::InstructionData::Call { func_ref, .. } => {
+                    &func.dfg.ext_funcs[func_ref].name == callee
+                }
+                _ => false,
+            })
+    };
+    loop_analysis
+        .loops()
+        .filter(|lp| {
+            !func
+                .layout
+                .blocks()
+                .any(|block| loop_analysis.is_in_loop(block, *lp) && calls_callee(block))
+        })
+        .count()
+}
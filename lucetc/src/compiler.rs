@@ -1,22 +1,57 @@
+mod config_diff;
 mod cpu_features;
+mod incremental;
 
+pub use self::config_diff::ConfigDifference;
 pub use self::cpu_features::{CpuFeatures, SpecificFeature, TargetCpu};
-use crate::decls::ModuleDecls;
-use crate::error::Error;
+pub use self::incremental::PrevState;
+use self::incremental::RetainedFunction;
+use crate::address_map::{
+    address_map_sym, function_address_map, AddressMapEntry, ADDRESS_MAP_PREFIX,
+};
+use crate::call_graph::CallGraph;
+use crate::cfg_map::{cfg_map_sym, function_cfg_map, BasicBlock, CFG_MAP_PREFIX};
+use crate::codegen_check::{loops_without_call, CodegenCheck, CodegenChecks};
+use crate::coverage::{
+    coverage_layout, serialize_coverage_layout, CoverageCounters, CoverageMode, FunctionCoverage,
+    COVERAGE_COUNTERS_SYM, COVERAGE_METADATA_KEY,
+};
+use crate::custom_metadata::{
+    check_custom_metadata_key, write_custom_metadata, CUSTOM_METADATA_PREFIX,
+};
+use crate::data_segments::{
+    data_segments, memory_init_program, write_data_segments, write_heap_image, DataSegment,
+    MemoryInitOp, DATA_SEGMENTS_SYM, HEAP_IMAGE_SYM,
+};
+use crate::debug_object::{DebugFunction, DebugObject};
+use crate::decls::{GlobalInfo, ImportFilter, ImportInfo, ModuleDecls, SymbolScheme};
+use crate::denormals;
+use crate::error::{Error, WasmFeature};
 use crate::function::FuncInfo;
-use crate::heap::HeapSettings;
-use crate::module::{ModuleValidation, UniqueFuncIndex};
-use crate::output::{CraneliftFuncs, ObjectFile, FUNCTION_MANIFEST_SYM};
-use crate::runtime::Runtime;
+use crate::heap::{HeapSettings, ResolvedHeapLayout, WASM_PAGE_SIZE};
+use crate::module::{ModuleInfo, ModuleValidation, UniqueFuncIndex, ValidationProgress};
+use crate::name::Name;
+use crate::output::{CraneliftFuncs, ManifestRecord, ObjectFile, FUNCTION_MANIFEST_SYM};
+use crate::pointer::NATIVE_POINTER;
+use crate::runtime::{Runtime, RuntimeFunc};
+use crate::sig_table::{signature_table, SignatureTableEntry, SIGNATURE_TABLE_SYM};
 use crate::stack_probe;
-use crate::table::write_table_data;
-use crate::traps::{translate_trapcode, trap_sym_for_func};
+use crate::table::{
+    write_table_data, TableInfo, TableOverride, TABLE_DATA_PREFIX, TABLE_SYM,
+    UNINIT_TABLE_ENTRY_SYM,
+};
+use crate::traps::{
+    shared_trap_sym, translate_trapcode, trap_sym_for_func, MISSING_IMPORT_TRAP, TRAP_TABLE_PREFIX,
+};
 use crate::validate::Validator;
+use crate::vmctx_layout::VmContextLayout;
 use byteorder::{LittleEndian, WriteBytesExt};
 use cranelift_codegen::{
     binemit,
+    entity::EntityRef,
     ir::{self, InstBuilder},
     isa::BackendVariant,
+    isa::TargetFrontendConfig,
     isa::TargetIsa,
     settings::{self, Configurable},
     Context as ClifContext,
@@ -29,22 +64,29 @@ use cranelift_module::{
 use cranelift_object::{ObjectBuilder, ObjectModule, ObjectProduct};
 use cranelift_wasm::{
     translate_module,
-    wasmparser::{FuncValidator, FunctionBody, ValidatorResources},
-    FuncTranslator,
+    wasmparser::{
+        FuncValidator, FunctionBody, Operator, Parser, Payload, Type, TypeOrFuncType,
+        ValidatorResources,
+    },
+    FuncIndex, FuncTranslator, GlobalIndex, MemoryIndex, ModuleTranslationState, TypeIndex,
+    WasmFuncType, WasmType,
 };
 use lucet_module::bindings::Bindings;
 use lucet_module::{
-    InstanceRuntimeData, ModuleData, ModuleFeatures, SerializedModule, VersionInfo,
-    LUCET_MODULE_SYM, MODULE_DATA_SYM,
+    module_data_sym, GlobalDef, ModuleData, ModuleFeatures, RequiredFeatures, SerializedModule,
+    VersionInfo, WasmProposals, LUCET_MODULE_SYM,
 };
-use memoffset::offset_of;
 use rayon::prelude::*;
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{hash_map::Entry, BTreeMap, HashMap, HashSet};
 use std::io::Cursor;
-use std::sync::{Mutex, MutexGuard};
-use target_lexicon::Triple;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+use target_lexicon::{Architecture, BinaryFormat, Triple};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum OptLevel {
     None,
     Speed,
@@ -67,6 +109,28 @@ impl OptLevel {
     }
 }
 
+/// How the wasm `unreachable` instruction is lowered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnreachableStrategy {
+    /// Raise the trap with a hardware trap instruction, `ud2` on x86-64, which the host's signal
+    /// handler maps to `TrapCode::Unreachable` through the trap tables like any other trap.
+    Trap,
+    /// Call `lucet_vmctx_unreachable` before raising the trap, so a debugger or tracer can stop
+    /// with the guest's stack intact, before any signal is delivered.
+    ///
+    /// The handler has the signature of a lucet hostcall with no arguments or results, taking
+    /// only the vmctx, and the host must define it: lucet-runtime does not. It is expected not to
+    /// return, for example by terminating the instance. If it does return, the trap instruction
+    /// that follows the call raises `TrapCode::Unreachable` as with `Trap`.
+    CallHandler,
+}
+
+impl Default for UnreachableStrategy {
+    fn default() -> UnreachableStrategy {
+        UnreachableStrategy::Trap
+    }
+}
+
 #[cfg(not(target_os = "macos"))]
 #[derive(Default, Debug, Clone)]
 pub struct TargetVersion;
@@ -113,8 +177,62 @@ pub struct CompilerBuilder {
     heap_settings: HeapSettings,
     count_instructions: bool,
     canonicalize_nans: bool,
+    flush_denormals: bool,
+    deterministic_libcalls: bool,
     validator: Option<Validator>,
     target_version: TargetVersion,
+    trap_uninit_table: bool,
+    validation_progress: Option<ValidationProgress>,
+    separate_data_segments: bool,
+    codegen_checks: CodegenChecks,
+    bake_memory_image: bool,
+    custom_metadata: BTreeMap<String, Vec<u8>>,
+    record_compile_times: bool,
+    export_all_functions: bool,
+    allow_multi_memory: bool,
+    allow_simd: bool,
+    rejected_wasm_features: HashSet<WasmFeature>,
+    symbol_scheme: SymbolScheme,
+    module_symbol: String,
+    symbol_prefix: Option<String>,
+    report_unbounded_loops: bool,
+    elf_os_abi: Option<u8>,
+    elf_flags: Option<u32>,
+    forbid_stack_probe: bool,
+    max_stack_size: Option<usize>,
+    forbid_float: bool,
+    preserve_frame_pointer: bool,
+    spectre_mitigation: bool,
+    exact_div_traps: bool,
+    cancellation_token: Option<Arc<AtomicBool>>,
+    split_debug_info: Option<PathBuf>,
+    report_const_fold: bool,
+    report_recursion: bool,
+    table_overrides: Vec<TableOverride>,
+    global_overrides: Vec<(u32, GlobalDef)>,
+    unreachable_strategy: UnreachableStrategy,
+    max_module_bytes: Option<usize>,
+    max_total_code_size: Option<usize>,
+    pad_code_to: Option<usize>,
+    max_locals_per_function: Option<u32>,
+    hard_memory_limit: Option<u32>,
+    expected_exports: Vec<(String, WasmFuncType)>,
+    dedup_trap_tables: bool,
+    trap_table_version: u8,
+    emit_address_map: bool,
+    emit_cfg_map: bool,
+    emit_sig_table: bool,
+    function_sections: bool,
+    function_order: Vec<String>,
+    auto_heap_settings: bool,
+    on_heap_layout: Option<Box<dyn Fn(&ResolvedHeapLayout) + Send + Sync>>,
+    function_opt_level: Option<Box<dyn Fn(FuncIndex) -> OptLevel + Send + Sync>>,
+    external_stack_probe: bool,
+    weak_imports: HashSet<String>,
+    allowed_import_modules: Option<HashSet<String>>,
+    import_filter: Option<Box<ImportFilter>>,
+    interrupt_check_period: Option<u32>,
+    prev_state: Option<Arc<PrevState>>,
 }
 
 #[cfg(feature = "old-x64-backend")]
@@ -137,8 +255,63 @@ impl CompilerBuilder {
             heap_settings: HeapSettings::default(),
             count_instructions: false,
             canonicalize_nans: false,
+            flush_denormals: false,
+            deterministic_libcalls: false,
             validator: None,
             target_version: TargetVersion::default(),
+            trap_uninit_table: false,
+            validation_progress: None,
+            separate_data_segments: false,
+            codegen_checks: Vec::new(),
+            bake_memory_image: false,
+            custom_metadata: BTreeMap::new(),
+            record_compile_times: false,
+            export_all_functions: false,
+            allow_multi_memory: false,
+            allow_simd: false,
+            rejected_wasm_features: HashSet::new(),
+            symbol_scheme: SymbolScheme::default(),
+            module_symbol: LUCET_MODULE_SYM.to_owned(),
+            symbol_prefix: None,
+            report_unbounded_loops: false,
+            elf_os_abi: None,
+            elf_flags: None,
+            forbid_stack_probe: false,
+            max_stack_size: None,
+            forbid_float: false,
+            preserve_frame_pointer: true,
+            spectre_mitigation: true,
+            exact_div_traps: false,
+            cancellation_token: None,
+            split_debug_info: None,
+            report_const_fold: false,
+            report_recursion: false,
+            table_overrides: vec![],
+            global_overrides: vec![],
+            unreachable_strategy: UnreachableStrategy::default(),
+            max_module_bytes: None,
+            max_total_code_size: None,
+            pad_code_to: None,
+            max_locals_per_function: None,
+            hard_memory_limit: None,
+            expected_exports: Vec::new(),
+            dedup_trap_tables: false,
+            trap_table_version: lucet_module::TRAP_TABLE_VERSION,
+            emit_address_map: false,
+            emit_cfg_map: false,
+            emit_sig_table: false,
+            function_sections: false,
+            function_order: Vec::new(),
+            auto_heap_settings: false,
+            on_heap_layout: None,
+            function_opt_level: None,
+            external_stack_probe: false,
+            weak_imports: HashSet::new(),
+            allowed_import_modules: None,
+            import_filter: None,
+            interrupt_check_period: None,
+            prev_state: None,
+            coverage: None,
         }
     }
 
@@ -159,6 +332,24 @@ impl CompilerBuilder {
         &self.target_version
     }
 
+    /// The symbol the runtime finds the compiled module through.
+    pub(crate) fn prefixed_module_symbol(&self) -> String {
+        format!(
+            "{}{}",
+            self.symbol_prefix.as_deref().unwrap_or(""),
+            self.module_symbol
+        )
+    }
+
+    /// The symbol of the coverage buffer the module's instrumented code increments counters in.
+    pub(crate) fn coverage_counters_symbol(&self) -> String {
+        format!(
+            "{}{}",
+            self.symbol_prefix.as_deref().unwrap_or(""),
+            COVERAGE_COUNTERS_SYM
+        )
+    }
+
     pub fn target_version(&mut self, target_version: TargetVersion) {
         self.target_version = target_version;
     }
@@ -212,6 +403,61 @@ impl CompilerBuilder {
         &mut self.heap_settings
     }
 
+    /// Size the reserved heap from the memory limits the module declares, rather than from the
+    /// heap settings: exactly the largest memory's maximum size is reserved.
+    ///
+    /// The heap settings still apply where the module leaves them open: memories with no
+    /// maximum, or a maximum above `max_reserved_size`, reserve `max_reserved_size`, and the
+    /// guard size is always `guard_size`. Defaults to `false`.
+    pub fn auto_heap_settings(&mut self, auto_heap_settings: bool) {
+        self.auto_heap_settings = auto_heap_settings;
+    }
+
+    pub fn with_auto_heap_settings(mut self, auto_heap_settings: bool) -> Self {
+        self.auto_heap_settings(auto_heap_settings);
+        self
+    }
+
+    /// Call `callback` with the heap layout chosen for the module, once it is resolved while
+    /// creating the compiler, for example to log the reserved and guard sizes.
+    pub fn on_heap_layout(&mut self, callback: Box<dyn Fn(&ResolvedHeapLayout) + Send + Sync>) {
+        self.on_heap_layout = Some(callback);
+    }
+
+    pub fn with_on_heap_layout(
+        mut self,
+        callback: Box<dyn Fn(&ResolvedHeapLayout) + Send + Sync>,
+    ) -> Self {
+        self.on_heap_layout(callback);
+        self
+    }
+
+    /// Choose the optimization level of each function defined by the module, by its index in the
+    /// wasm function index space, overriding `opt_level` for that function.
+    ///
+    /// Cranelift's optimization level applies to a whole `TargetIsa`, so one is built for each
+    /// distinct level the callback returns, along with the ISA for `opt_level`. That costs some
+    /// time and memory when creating the compiler, where the callback is called once per
+    /// function. Functions compiled at different levels can call each other as usual.
+    pub fn function_opt_level(
+        &mut self,
+        callback: Box<dyn Fn(FuncIndex) -> OptLevel + Send + Sync>,
+    ) {
+        self.function_opt_level = Some(callback);
+    }
+
+    pub fn with_function_opt_level(
+        mut self,
+        callback: Box<dyn Fn(FuncIndex) -> OptLevel + Send + Sync>,
+    ) -> Self {
+        self.function_opt_level(callback);
+        self
+    }
+
+    /// Instrument the module to count the wasm instructions executed by each instance.
+    ///
+    /// Only wasm instructions are counted: the instrumentation itself, and any code inserted when
+    /// `canonicalize_nans` is enabled, do not contribute to the count.
     pub fn count_instructions(&mut self, count_instructions: bool) {
         self.count_instructions = count_instructions;
     }
@@ -221,6 +467,47 @@ impl CompilerBuilder {
         self
     }
 
+    /// Check the instance's interrupt flag on every `period`th loop back-edge a function takes,
+    /// and trap with `TrapCode::Interrupt` if the host has set it. A `br_table` is treated as a
+    /// back-edge, since any of its targets may be a loop. A period of 0 is the same as 1.
+    ///
+    /// This lets a host preempt long-running guests cooperatively, and is independent of
+    /// `count_instructions`. Checks are only emitted when a period is set.
+    pub fn interrupt_checks(&mut self, period: u32) {
+        self.interrupt_check_period = Some(period);
+    }
+
+    pub fn with_interrupt_checks(mut self, period: u32) -> Self {
+        self.interrupt_checks(period);
+        self
+    }
+
+    /// Instrument the module to count how often each function, or with `CoverageMode::BasicBlock`
+    /// each block, is entered, for measuring the coverage of guest code. By default, the module
+    /// is not instrumented.
+    ///
+    /// Instrumented code increments `u64` counters in a coverage buffer that the host provides
+    /// as the symbol `lucet_coverage_counters`, which must be visible when the module is loaded,
+    /// for example from the host executable. The buffer is shared by every instance of the
+    /// module. The layout of its counters, which the buffer must have room for, is recorded as
+    /// custom metadata and returned by `ObjectFile::coverage_layout`, so the host can map counts
+    /// back to functions and wasm offsets after execution.
+    ///
+    /// Each module needs a buffer of its own, since their layouts differ. The buffer's symbol is
+    /// an import, which `symbol_prefix` would otherwise leave alone, so it takes the prefix at
+    /// compile time instead: with a prefix of `guest_a_`, the host provides
+    /// `guest_a_lucet_coverage_counters`. Guests combined with `ObjectFile::merge` would share
+    /// the unprefixed buffer, so instrumented guests that are linked together should each be
+    /// compiled with their own `symbol_prefix`.
+    pub fn coverage(&mut self, mode: CoverageMode) {
+        self.coverage = Some(mode);
+    }
+
+    pub fn with_coverage(mut self, mode: CoverageMode) -> Self {
+        self.coverage(mode);
+        self
+    }
+
     pub fn canonicalize_nans(&mut self, canonicalize_nans: bool) {
         self.canonicalize_nans = canonicalize_nans;
     }
@@ -230,284 +517,2393 @@ impl CompilerBuilder {
         self
     }
 
-    pub fn validator(&mut self, validator: Option<Validator>) {
-        self.validator = validator;
+    /// Flush subnormal floats to zero, keeping their sign, in the operands and results of
+    /// scalar floating-point arithmetic.
+    ///
+    /// Without this, wasm's gradual underflow is only honored if the host's floating-point unit
+    /// isn't in a flush-to-zero or denormals-are-zero mode. With it, guests compute the same
+    /// results in any mode, at the cost of a few instructions around each operation, but those
+    /// results differ from wasm's semantics whenever a subnormal is involved. NaNs are left
+    /// as they are, so this composes with `canonicalize_nans`, which still applies to NaN
+    /// results. Defaults to `false`.
+    pub fn flush_denormals(&mut self, flush_denormals: bool) {
+        self.flush_denormals = flush_denormals;
     }
 
-    pub fn with_validator(mut self, validator: Option<Validator>) -> Self {
-        self.validator(validator);
+    pub fn with_flush_denormals(mut self, flush_denormals: bool) -> Self {
+        self.flush_denormals(flush_denormals);
         self
     }
 
-    pub fn create<'a>(
-        &'a self,
-        wasm_binary: &'a [u8],
-        bindings: &'a Bindings,
-    ) -> Result<Compiler<'a>, Error> {
-        Compiler::new(
-            wasm_binary,
-            self.target.clone(),
-            self.variant,
-            self.opt_level,
-            self.cpu_features.clone(),
-            bindings,
-            self.heap_settings.clone(),
-            self.count_instructions,
-            self.validator.clone(),
-            self.canonicalize_nans,
-        )
+    /// Call lucet-runtime's software implementations of float rounding, rather than the host's
+    /// libm, where Cranelift lowers rounding to a libcall.
+    ///
+    /// The x86-64 backend lowers `ceil`, `floor`, `trunc`, and `nearest` to libcalls when SSE4.1
+    /// is not enabled. With this set, they call `lucet_libcall_ceilf`, `lucet_libcall_ceil`,
+    /// `lucet_libcall_floorf`, `lucet_libcall_floor`, `lucet_libcall_truncf`,
+    /// `lucet_libcall_trunc`, `lucet_libcall_nearbyintf`, and `lucet_libcall_nearbyint`
+    /// instead of the libm functions of the same names without the prefix, and the runtime must
+    /// supply those symbols. lucet-runtime exports them. Any other libcall has no deterministic
+    /// alternative, so compiling a function that needs one fails with `Error::Unsupported`. The
+    /// stack probe is not a libcall in this sense, and is unaffected. Defaults to `false`.
+    pub fn deterministic_libcalls(&mut self, deterministic_libcalls: bool) {
+        self.deterministic_libcalls = deterministic_libcalls;
     }
-}
 
-pub struct Compiler<'a> {
-    decls: ModuleDecls<'a>,
-    codegen_context: CodegenContext,
-    target: Triple,
-    variant: BackendVariant,
-    opt_level: OptLevel,
-    cpu_features: CpuFeatures,
-    count_instructions: bool,
-    canonicalize_nans: bool,
-    function_bodies:
-        HashMap<UniqueFuncIndex, (FuncValidator<ValidatorResources>, FunctionBody<'a>)>,
-}
+    pub fn with_deterministic_libcalls(mut self, deterministic_libcalls: bool) -> Self {
+        self.deterministic_libcalls(deterministic_libcalls);
+        self
+    }
 
-impl<'a> Compiler<'a> {
-    pub fn new(
-        wasm_binary: &'a [u8],
-        target: Triple,
-        variant: BackendVariant,
-        opt_level: OptLevel,
-        cpu_features: CpuFeatures,
-        bindings: &'a Bindings,
-        heap_settings: HeapSettings,
-        count_instructions: bool,
-        validator: Option<Validator>,
-        canonicalize_nans: bool,
-    ) -> Result<Self, Error> {
-        let mk_isa = || {
-            Self::target_isa(
-                target.clone(),
-                variant,
-                opt_level,
-                &cpu_features,
-                canonicalize_nans,
-            )
-        };
+    /// Point uninitialized table slots at a shared function that traps with
+    /// `IndirectCallToNull`, rather than leaving a null function pointer.
+    ///
+    /// Uninitialized slots always have an out-of-bounds signature index, so a wasm `call_indirect`
+    /// to one traps with `BadSignature` before the function pointer is used, whether or not this is
+    /// enabled. This makes sure that any other use of the table entry traps cleanly too. Defaults
+    /// to `false`.
+    pub fn trap_uninit_table(&mut self, trap_uninit_table: bool) {
+        self.trap_uninit_table = trap_uninit_table;
+    }
 
-        let isa = mk_isa()?;
-        let frontend_config = isa.frontend_config();
-        let mut module_validation = ModuleValidation::new(frontend_config, validator);
+    pub fn with_trap_uninit_table(mut self, trap_uninit_table: bool) -> Self {
+        self.trap_uninit_table(trap_uninit_table);
+        self
+    }
 
-        let _module_translation_state = translate_module(wasm_binary, &mut module_validation)?;
+    /// Emit the module's data segments into their own section of the object file, under the
+    /// `lucet_data_segments` symbol, rather than as the initial contents of the heap in the module
+    /// data.
+    ///
+    /// With this enabled, every page of the heap starts zeroed, and the host is responsible for
+    /// initializing memory from the data segments. Defaults to `false`.
+    pub fn separate_data_segments(&mut self, separate_data_segments: bool) {
+        self.separate_data_segments = separate_data_segments;
+    }
 
-        module_validation.validation_errors()?;
+    pub fn with_separate_data_segments(mut self, separate_data_segments: bool) -> Self {
+        self.separate_data_segments(separate_data_segments);
+        self
+    }
 
-        let codegen_context = CodegenContext::new(isa, mk_isa()?)?;
+    /// Apply the module's data segments at compile time, and emit the initial contents of the heap
+    /// as a single block under the `lucet_heap_image` symbol, in its own section.
+    ///
+    /// Compilation fails if any data segments overlap, or extend past the initial size of the
+    /// heap. Defaults to `false`.
+    pub fn bake_memory_image(&mut self, bake_memory_image: bool) {
+        self.bake_memory_image = bake_memory_image;
+    }
 
-        let runtime = Runtime::lucet(frontend_config);
-        let decls = ModuleDecls::new(
-            module_validation.info,
-            &codegen_context,
-            bindings,
-            runtime,
-            heap_settings,
-        )?;
+    pub fn with_bake_memory_image(mut self, bake_memory_image: bool) -> Self {
+        self.bake_memory_image(bake_memory_image);
+        self
+    }
 
-        Ok(Self {
-            decls,
-            codegen_context,
-            opt_level,
-            cpu_features,
-            count_instructions,
-            target,
-            variant,
-            canonicalize_nans,
-            function_bodies: module_validation.function_bodies,
-        })
+    /// Store `value` in the object file under `key`, in its own section, with the exported
+    /// symbol `lucet_meta_<key>`. It can be read back with `ObjectFile::metadata`, or found with
+    /// `dlsym` once the module is loaded.
+    ///
+    /// The metadata is kept apart from the module data, so the runtime doesn't parse it. The
+    /// object file is only reproducible byte for byte if the metadata is too, so stamping it with
+    /// something like a build time gives different objects for the same module. Keys may only
+    /// contain ASCII letters, digits, and underscores; creating the compiler fails with
+    /// `Error::Input` otherwise. Storing a value under a key again replaces the earlier value.
+    pub fn custom_metadata(&mut self, key: String, value: Vec<u8>) {
+        self.custom_metadata.insert(key, value);
     }
 
-    pub fn builder() -> CompilerBuilder {
-        CompilerBuilder::new()
+    pub fn with_custom_metadata(mut self, key: String, value: Vec<u8>) -> Self {
+        self.custom_metadata(key, value);
+        self
     }
 
-    pub fn module_features(&self) -> ModuleFeatures {
-        let mut mf: ModuleFeatures = (&self.cpu_features).into();
-        mf.instruction_count = self.count_instructions;
-        mf
+    /// Measure how long each function takes to translate and compile, and record it in the
+    /// function's manifest entry, where `lucet_module::FunctionSpec::compile_nanos` reads it.
+    /// `ObjectFile::compile_times` reports the measurements.
+    ///
+    /// Functions that lucetc synthesizes rather than compiles from wasm have no compile time.
+    /// Timings vary from run to run, so with this enabled the object file is no longer
+    /// reproducible byte for byte. Defaults to `false`.
+    pub fn record_compile_times(&mut self, record_compile_times: bool) {
+        self.record_compile_times = record_compile_times;
     }
 
-    pub fn module_data(&self) -> Result<ModuleData<'_>, Error> {
-        self.decls.get_module_data(self.module_features())
+    pub fn with_record_compile_times(mut self, record_compile_times: bool) -> Self {
+        self.record_compile_times(record_compile_times);
+        self
     }
 
-    fn get_local_count(body: &FunctionBody, name: &str) -> Result<u32, Error> {
-        let error_mapper = |e| Error::FunctionTranslation {
-            symbol: name.to_string(),
-            source: Box::new(Error::from(e)),
-        };
-        Ok(body
-            .get_locals_reader()
-            .map_err(error_mapper)?
-            .into_iter()
-            .map(|result| result.map_err(error_mapper))
-            .collect::<Result<Vec<(u32, _)>, Error>>()?
-            .into_iter()
-            .map(|(count, _ty)| count)
-            .sum())
+    /// Give every function defined in the module an exported symbol in the object file, not just
+    /// the functions the module exports. This does not change the module's wasm exports. Defaults
+    /// to `false`, to keep the symbol table small.
+    ///
+    /// Creating the compiler fails with `Error::DuplicateFunctionSymbol` if two functions would
+    /// get the same symbol, such as an export named `1` and an unnamed function at index 1.
+    pub fn export_all_functions(&mut self, export_all_functions: bool) {
+        self.export_all_functions = export_all_functions;
     }
 
-    pub fn object_file(self) -> Result<ObjectFile, Error> {
-        let mut function_manifest_ctx = ClifDataContext::new();
-        let mut function_manifest_bytes = Cursor::new(Vec::new());
+    pub fn with_export_all_functions(mut self, export_all_functions: bool) -> Self {
+        self.export_all_functions(export_all_functions);
+        self
+    }
 
-        let module_data_bytes = self.module_data()?.serialize()?;
-        let module_data_len = module_data_bytes.len();
+    /// Accept modules that declare more than one memory, as in the multi-memory proposal. Each
+    /// memory gets its own reserved and guard regions, sized by the same `HeapSettings`.
+    ///
+    /// This only affects compilation: lucet-runtime can't run the result. Only memory 0 is
+    /// described by the module data, and it remains at the vmctx. The instance must also provide
+    /// a pointer to each additional memory `n` at
+    /// `vmctx - size_of::<InstanceRuntimeData>() - n * 8`, which lucet-runtime does not do. The
+    /// module data records that the module uses multi-memory, and lucet-runtime refuses to load
+    /// such modules, so they can only be run by embedders that set this up themselves.
+    /// `memory.size` and `memory.grow` are only supported on memory 0, and data segments for
+    /// other memories are only supported with `separate_data_segments`. Defaults to `false`.
+    pub fn allow_multi_memory(&mut self, allow_multi_memory: bool) {
+        self.allow_multi_memory = allow_multi_memory;
+    }
 
-        let mut decls = self.decls;
-        let codegen_context = self.codegen_context;
-        let count_instructions = self.count_instructions;
+    pub fn with_allow_multi_memory(mut self, allow_multi_memory: bool) -> Self {
+        self.allow_multi_memory(allow_multi_memory);
+        self
+    }
 
-        let mut function_map = self
-            .function_bodies
-            .into_par_iter()
-            .map(|(unique_func_ix, (mut validator, func_body))| {
-                let func = decls
-                    .get_func(unique_func_ix)
-                    .expect("decl exists for func body");
-                let arg_count = func.signature.params.len() as u32;
-                let local_count = Self::get_local_count(&func_body, func.name.symbol())?;
-                let mut func_info = FuncInfo::new(
-                    &decls,
-                    &codegen_context,
-                    count_instructions,
-                    arg_count,
-                    local_count,
-                );
-                let mut clif_context = ClifContext::new();
-                clif_context.func.name = func.name.as_externalname();
-                clif_context.func.signature = func.signature.clone();
+    /// Accept modules that use the SIMD proposal's 128-bit vector instructions.
+    ///
+    /// On x86-64, the backend lowers many of these instructions using SSSE3 and SSE4.1 without
+    /// a fallback, so both must be enabled in `cpu_features`, or creating the compiler fails with
+    /// `Error::SimdCpuFeatures`. Functions still can't take or return `v128` values, as module
+    /// data has no way to describe them in a signature. Defaults to `false`.
+    pub fn allow_simd(&mut self, allow_simd: bool) {
+        self.allow_simd = allow_simd;
+    }
 
-                FuncTranslator::new()
-                    .translate_body(
-                        &mut validator,
-                        func_body.clone(),
-                        &mut clif_context.func,
-                        &mut func_info,
-                    )
-                    .map_err(|source| Error::FunctionTranslation {
-                        symbol: func.name.symbol().to_string(),
-                        source: Box::new(Error::from(source)),
-                    })?;
-                let func_id = func.name.as_funcid().unwrap();
-                let trap_metadata =
-                    codegen_context.compile(&mut clif_context, func_id, func.name.symbol())?;
+    pub fn with_allow_simd(mut self, allow_simd: bool) -> Self {
+        self.allow_simd(allow_simd);
+        self
+    }
 
-                Ok((func_id, trap_metadata))
-            })
-            .collect::<Result<HashMap<FuncId, TrapMetadata>, Error>>()?;
+    /// Have the wasm validator reject modules that use `feature`, rather than accepting them and
+    /// failing with `Error::UnsupportedFeature` partway through translation.
+    ///
+    /// This is meant for retrying a compilation whose error's `Error::unsupported_feature` is
+    /// `Some`: the retry fails validation with the offset of the first use of the feature.
+    /// Rejecting `WasmFeature::MultiMemory` overrides `allow_multi_memory`.
+    pub fn reject_wasm_feature(&mut self, feature: WasmFeature) {
+        self.rejected_wasm_features.insert(feature);
+    }
 
-        // Now that we've defined all functions, we know what trampolines must also be created.
-        let trampoline_metas = codegen_context
-            .trampolines()
-            .par_iter()
-            .map(|(hostcall_name, (trampoline_id, hostcall_func_index))| {
-                let meta = synthesize_trampoline(
-                    &decls,
-                    &codegen_context,
-                    hostcall_name,
-                    *trampoline_id,
-                    *hostcall_func_index,
-                )?;
-                Ok((*trampoline_id, meta))
-            })
-            .collect::<Result<Vec<(FuncId, TrapMetadata)>, Error>>()?;
+    pub fn with_reject_wasm_feature(mut self, feature: WasmFeature) -> Self {
+        self.reject_wasm_feature(feature);
+        self
+    }
 
-        for (id, m) in trampoline_metas.into_iter() {
-            function_map.insert(id, m);
-        }
+    /// Choose how symbols are named for the functions defined in the module. The same symbols
+    /// are used by calls between functions, the function manifest, and the object's symbol table.
+    pub fn symbol_scheme(&mut self, symbol_scheme: SymbolScheme) {
+        self.symbol_scheme = symbol_scheme;
+    }
 
-        // Write out the stack probe and associated data.
-        let probe_id = stack_probe::declare(&mut decls, &codegen_context)?;
-        let probe_func = decls.get_func(probe_id).unwrap();
-        let probe_func_id = probe_func.name.as_funcid().unwrap();
-        let compiled = codegen_context.module().define_function_bytes(
-            probe_func_id,
-            stack_probe::STACK_PROBE_BINARY,
-            &[],
-        )?;
+    pub fn with_symbol_scheme(mut self, symbol_scheme: SymbolScheme) -> Self {
+        self.symbol_scheme(symbol_scheme);
+        self
+    }
 
-        let func_size = compiled.size;
-        let stack_probe_traps: TrapSites = stack_probe::trap_sites().into();
+    /// Name the global symbol the runtime finds the module through, so that several guests can be
+    /// linked into one shared object without their modules colliding. The module data is named
+    /// after it, as `lucet_module::module_data_sym` gives.
+    ///
+    /// The runtime loads a renamed module with `DlModule::load_with_symbol`, and signs and
+    /// verifies it with `ModuleSignature::sign_with_symbol` and
+    /// `DlModule::load_and_verify_with_symbol`. Defaults to `LUCET_MODULE_SYM`.
+    pub fn module_symbol(&mut self, module_symbol: String) {
+        self.module_symbol = module_symbol;
+    }
 
-        let trap_data_id = stack_probe_traps.write(&codegen_context, probe_func.name.symbol())?;
+    pub fn with_module_symbol(mut self, module_symbol: String) -> Self {
+        self.module_symbol(module_symbol);
+        self
+    }
 
-        function_map.insert(
-            probe_func_id,
-            TrapMetadata {
-                func_size,
-                trap_data_id,
-                trap_len: stack_probe_traps.len(),
-            },
-        );
+    /// Prefix every symbol the object defines with `symbol_prefix`, so that the objects of
+    /// several guests can be linked together without their symbols colliding.
+    ///
+    /// This covers the functions, the module symbol, the module data, the function manifest,
+    /// trap tables, and everything else lucetc emits, and relocations between them refer to the
+    /// prefixed symbols. Imports are left alone, so guests can share hostcalls. This is applied
+    /// to the finished object as `ObjectFile::merge` does, so it is only supported for ELF
+    /// objects. The runtime loads the module through the prefixed module symbol, with
+    /// `DlModule::load_with_symbol`, rather than `DlModule::load`. `ObjectFile` accessors that
+    /// take a function's symbol, such as `address_map_for`, take it without the prefix.
+    /// Defaults to no prefix.
+    pub fn symbol_prefix(&mut self, symbol_prefix: String) {
+        self.symbol_prefix = Some(symbol_prefix);
+    }
 
-        let module_data_id = write_module_data(&codegen_context, module_data_bytes)?;
-        let (table_id, table_len) = write_table_data(&codegen_context, &decls)?;
+    pub fn with_symbol_prefix(mut self, symbol_prefix: String) -> Self {
+        self.symbol_prefix(symbol_prefix);
+        self
+    }
 
-        // The function manifest must be written out in the order that
-        // cranelift-module is going to lay out the functions.  We also
-        // have to be careful to write function manifest entries for VM
-        // functions, which will not be represented in function_map.
+    /// Log a warning for each function containing loops that never check the instruction count
+    /// bound. Every loop back-edge is checked when instructions are counted, so this is most
+    /// useful for confirming where those checks land; without `count_instructions`, every loop
+    /// is reported.
+    pub fn report_unbounded_loops(&mut self, report_unbounded_loops: bool) {
+        self.report_unbounded_loops = report_unbounded_loops;
+    }
 
-        let ids: Vec<FuncId> = codegen_context
-            .module()
-            .declarations()
-            .get_functions()
-            .map(|(func_id, _f)| func_id)
-            .collect();
-        let function_manifest_len = ids.len();
+    pub fn with_report_unbounded_loops(mut self, report_unbounded_loops: bool) -> Self {
+        self.report_unbounded_loops(report_unbounded_loops);
+        self
+    }
 
-        for func_id in ids {
-            write_function_spec(
-                &codegen_context,
-                &mut function_manifest_ctx,
-                &mut function_manifest_bytes,
-                func_id,
-                function_map.get(&func_id),
-            )?;
-        }
+    /// Log, for each function whose instruction count Cranelift's constant folding reduces by at
+    /// least a tenth, the number of instructions before and after folding.
+    ///
+    /// The counts come from running the folding pass over a copy of each function's IR, so this
+    /// adds to compile time. Cranelift only folds constants when optimizing, so nothing is
+    /// reported with `OptLevel::None`. Defaults to `false`.
+    pub fn report_const_fold(&mut self, report_const_fold: bool) {
+        self.report_const_fold = report_const_fold;
+    }
 
-        function_manifest_ctx.define(function_manifest_bytes.into_inner().into());
-        let manifest_data_id = codegen_context.module().declare_data(
-            FUNCTION_MANIFEST_SYM,
-            ClifLinkage::Local,
-            false,
-            false,
-        )?;
-        codegen_context
-            .module()
-            .define_data(manifest_data_id, &function_manifest_ctx)?;
+    pub fn with_report_const_fold(mut self, report_const_fold: bool) -> Self {
+        self.report_const_fold(report_const_fold);
+        self
+    }
 
-        // Write out the structure tying everything together.
-        let mut native_data =
-            Cursor::new(Vec::with_capacity(std::mem::size_of::<SerializedModule>()));
-        let mut native_data_ctx = ClifDataContext::new();
-        let native_data_id = codegen_context.module().declare_data(
-            LUCET_MODULE_SYM,
-            ClifLinkage::Export,
-            false,
-            false,
-        )?;
+    /// Log a warning for each set of functions that can recurse, naming the functions involved.
+    ///
+    /// Cycles are found in the module's call graph, so recursion through indirect calls is only
+    /// reported when the table contents are known at compile time. Defaults to `false`.
+    pub fn report_recursion(&mut self, report_recursion: bool) {
+        self.report_recursion = report_recursion;
+    }
 
-        let version =
-            VersionInfo::current(include_str!(concat!(env!("OUT_DIR"), "/commit_hash")).as_bytes());
+    pub fn with_report_recursion(mut self, report_recursion: bool) -> Self {
+        self.report_recursion(report_recursion);
+        self
+    }
 
-        version.write_to(&mut native_data)?;
+    /// Replace the initial contents of a table slot with a reference to the function named by
+    /// `func_symbol`, which may be a defined or an imported function.
+    ///
+    /// Overrides are applied after the module's element segments, in the order they were added.
+    /// Writing the object file fails if the table or slot doesn't exist, or if no function has the
+    /// given symbol.
+    pub fn table_override(&mut self, table_index: u32, slot: u32, func_symbol: String) {
+        self.table_overrides.push(TableOverride {
+            table_index,
+            slot,
+            func_symbol,
+        });
+    }
 
-        fn write_slice(
-            codegen_context: &CodegenContext,
-            mut ctx: &mut ClifDataContext,
-            bytes: &mut Cursor<Vec<u8>>,
+    pub fn with_table_override(mut self, table_index: u32, slot: u32, func_symbol: String) -> Self {
+        self.table_override(table_index, slot, func_symbol);
+        self
+    }
+
+    /// Replace the initial value of the global at `index` with `value`, in place of the
+    /// initializer the module declares for it.
+    ///
+    /// This lets a host bake configuration, such as feature flags or build constants, into a
+    /// module without modifying the wasm. Overrides are applied in the order they were added, so
+    /// the last override of a global wins. Creating the compiler fails with
+    /// `Error::InvalidGlobalOverride` if the global is imported, if its initializer refers to
+    /// another global, or if `value` doesn't have the global's type, and with
+    /// `Error::GlobalIndexError` if there is no such global.
+    pub fn override_global(&mut self, index: u32, value: GlobalDef) {
+        self.global_overrides.push((index, value));
+    }
+
+    pub fn with_override_global(mut self, index: u32, value: GlobalDef) -> Self {
+        self.override_global(index, value);
+        self
+    }
+
+    /// Set the OS/ABI byte of the ELF header, `e_ident[EI_OSABI]`, in the object file. Creating
+    /// the compiler fails if the target's binary format is not ELF.
+    pub fn elf_os_abi(&mut self, elf_os_abi: u8) {
+        self.elf_os_abi = Some(elf_os_abi);
+    }
+
+    pub fn with_elf_os_abi(mut self, elf_os_abi: u8) -> Self {
+        self.elf_os_abi(elf_os_abi);
+        self
+    }
+
+    /// Set the machine flags of the ELF header, `e_flags`, in the object file. Creating the
+    /// compiler fails if the target's binary format is not ELF.
+    pub fn elf_flags(&mut self, elf_flags: u32) {
+        self.elf_flags = Some(elf_flags);
+    }
+
+    pub fn with_elf_flags(mut self, elf_flags: u32) -> Self {
+        self.elf_flags(elf_flags);
+        self
+    }
+
+    /// Fail compilation with `Error::StackProbeRequired` if any function's frame is large enough
+    /// that its prologue calls the stack probe.
+    pub fn forbid_stack_probe(&mut self, forbid_stack_probe: bool) {
+        self.forbid_stack_probe = forbid_stack_probe;
+    }
+
+    pub fn with_forbid_stack_probe(mut self, forbid_stack_probe: bool) -> Self {
+        self.forbid_stack_probe(forbid_stack_probe);
+        self
+    }
+
+    /// Compile for guests that run on a stack of `size` bytes, failing with
+    /// `Error::FrameExceedsStack` if any function's frame alone is larger than that.
+    ///
+    /// A function that fits can still overflow the stack at runtime once it is called with
+    /// other frames beneath it, so this bounds the stack any single call needs rather than a
+    /// whole call chain. Functions whose frames are larger than the greatest power of two no
+    /// larger than `size`, up to Cranelift's default of 4KiB, call the stack probe in their
+    /// prologue. By default, frames of any size are allowed.
+    pub fn max_stack_size(&mut self, size: usize) {
+        self.max_stack_size = Some(size);
+    }
+
+    pub fn with_max_stack_size(mut self, size: usize) -> Self {
+        self.max_stack_size(size);
+        self
+    }
+
+    /// Fail with `Error::FloatNotAllowed` if any function uses floating-point values, for targets
+    /// without an FPU.
+    ///
+    /// A function uses floats if its signature or locals have a float type, or if it has a float
+    /// instruction, including conversions, reinterpretations, float loads and stores, and access
+    /// to float globals. The check runs when the compiler is created, and reports the first use
+    /// in the first function found. Defaults to `false`.
+    pub fn forbid_float(&mut self, forbid_float: bool) {
+        self.forbid_float = forbid_float;
+    }
+
+    pub fn with_forbid_float(mut self, forbid_float: bool) -> Self {
+        self.forbid_float(forbid_float);
+        self
+    }
+
+    /// Declare the stack probe, `lucet_probestack`, as an undefined symbol rather than embedding
+    /// a copy of it in the object, so that many guests can share one copy provided by the host.
+    ///
+    /// The shared object must then be linked or loaded such that `lucet_probestack` resolves to
+    /// a probe with the same calling convention as the embedded one. The function manifest has
+    /// no trap table for an external probe, so the host is responsible for treating a fault in
+    /// the probe as a stack overflow in the guest. Defaults to `false`.
+    pub fn external_stack_probe(&mut self, external_stack_probe: bool) {
+        self.external_stack_probe = external_stack_probe;
+    }
+
+    pub fn with_external_stack_probe(mut self, external_stack_probe: bool) -> Self {
+        self.external_stack_probe(external_stack_probe);
+        self
+    }
+
+    /// Declare the imported functions named in `imports`, each written as `module::field`, as
+    /// weak symbols with a default definition that traps with `TrapCode::MissingImport`.
+    ///
+    /// If the host provides the import's symbol when the shared object is loaded, calls go to the
+    /// host function as usual; otherwise they trap, rather than the import failing to resolve.
+    /// Creating the compiler fails with `Error::Input` if the module has no such import.
+    pub fn weak_imports(&mut self, imports: HashSet<String>) {
+        self.weak_imports = imports;
+    }
+
+    pub fn with_weak_imports(mut self, imports: HashSet<String>) -> Self {
+        self.weak_imports(imports);
+        self
+    }
+
+    /// Only allow the module to import functions, globals, tables, and memories from the modules
+    /// named in `modules`.
+    ///
+    /// Creating the compiler fails with `Error::DisallowedImportModule` if the module imports
+    /// from any other module. If it imports from more than one, they are all reported together
+    /// in `Error::ImportModuleViolations`. By default, imports from any module are allowed.
+    pub fn allowed_import_modules(&mut self, modules: HashSet<String>) {
+        self.allowed_import_modules = Some(modules);
+    }
+
+    pub fn with_allowed_import_modules(mut self, modules: HashSet<String>) -> Self {
+        self.allowed_import_modules(modules);
+        self
+    }
+
+    /// Call `filter` with the module, field, and signature of each imported function as it is
+    /// declared, and reject the import if it returns `false`.
+    ///
+    /// The signature is the one the guest calls the import with, so it starts with the vmctx
+    /// parameter. Creating the compiler fails with `Error::ImportRejected` for the first import
+    /// that is rejected. Imported globals, tables, and memories are not passed to the filter.
+    pub fn import_filter(
+        &mut self,
+        filter: Box<dyn Fn(&str, &str, &ir::Signature) -> bool + Send + Sync>,
+    ) {
+        self.import_filter = Some(filter);
+    }
+
+    pub fn with_import_filter(
+        mut self,
+        filter: Box<dyn Fn(&str, &str, &ir::Signature) -> bool + Send + Sync>,
+    ) -> Self {
+        self.import_filter(filter);
+        self
+    }
+
+    /// Keep a frame pointer in every guest function, so the frames on a guest stack form a
+    /// linked list.
+    ///
+    /// Sampling profilers and debuggers can then walk guest stacks by following frame pointers,
+    /// without unwind tables for the guest code. The cost is one register, `rbp` on x86-64 and
+    /// `x29` on AArch64, that is not available for allocation, and a push and pop in each
+    /// function's prologue and epilogue. Cranelift always sets up a frame pointer and has no
+    /// setting to omit it, so creating the compiler fails with `Error::Unsupported` when this is
+    /// `false`. Defaults to `true`.
+    pub fn preserve_frame_pointer(&mut self, preserve_frame_pointer: bool) {
+        self.preserve_frame_pointer = preserve_frame_pointer;
+    }
+
+    pub fn with_preserve_frame_pointer(mut self, preserve_frame_pointer: bool) -> Self {
+        self.preserve_frame_pointer(preserve_frame_pointer);
+        self
+    }
+
+    /// Guard bounds-checked heap accesses against Spectre-style speculation past the bounds
+    /// check, with Cranelift's `enable_heap_access_spectre_mitigation`.
+    ///
+    /// The mitigation adds a compare and conditional move to each access that is bounds checked,
+    /// so that a mispredicted check can't speculatively read out of bounds; accesses the heap's
+    /// reservation and guard make safe without a check are unaffected. With the default heap
+    /// settings, which cover the whole 32-bit index space, no accesses are checked, so this only
+    /// costs anything with smaller reservations or guards. Defaults to `true`.
+    pub fn spectre_mitigation(&mut self, spectre_mitigation: bool) {
+        self.spectre_mitigation = spectre_mitigation;
+    }
+
+    pub fn with_spectre_mitigation(mut self, spectre_mitigation: bool) -> Self {
+        self.spectre_mitigation(spectre_mitigation);
+        self
+    }
+
+    /// Check the operands of integer division and remainder explicitly, so that each trap is
+    /// recorded with the code wasm specifies: `IntegerDivByZero` for a zero divisor, and
+    /// `IntegerOverflow` for a signed division of the minimum value by -1.
+    ///
+    /// Otherwise, x86-64 code relies on the division instruction faulting, and the hardware
+    /// raises the same fault for both cases, so an overflow is reported as `IntegerDivByZero`.
+    /// Either way, the same operations trap. Defaults to `false`.
+    pub fn exact_div_traps(&mut self, exact_div_traps: bool) {
+        self.exact_div_traps = exact_div_traps;
+    }
+
+    pub fn with_exact_div_traps(mut self, exact_div_traps: bool) -> Self {
+        self.exact_div_traps(exact_div_traps);
+        self
+    }
+
+    /// Abort compilation with `Error::Cancelled` once `token` is set.
+    ///
+    /// The token is checked before each function is compiled, so a module is abandoned within
+    /// one function's compile time of cancellation. Everything built so far is dropped along with
+    /// the error.
+    pub fn cancellation_token(&mut self, token: Arc<AtomicBool>) {
+        self.cancellation_token = Some(token);
+    }
+
+    pub fn with_cancellation_token(mut self, token: Arc<AtomicBool>) -> Self {
+        self.cancellation_token(token);
+        self
+    }
+
+    /// Reuse the IR translated in a previous compilation, retained in `prev`, for every function
+    /// whose wasm body is unchanged. The object file is the same as a full compilation would
+    /// produce. See `Compiler::object_file_incremental`.
+    pub fn prev_state(&mut self, prev: Arc<PrevState>) {
+        self.prev_state = Some(prev);
+    }
+
+    pub fn with_prev_state(mut self, prev: Arc<PrevState>) -> Self {
+        self.prev_state(prev);
+        self
+    }
+
+    /// Have `Compiler::object_file` write a debug object to `path`, and return the production
+    /// object it belongs to, as `Compiler::object_file_split` would. The production object is
+    /// linked to the debug object by the build ID they share. Only ELF targets are supported.
+    pub fn split_debug_info(&mut self, path: Option<PathBuf>) {
+        self.split_debug_info = path;
+    }
+
+    pub fn with_split_debug_info(mut self, path: Option<PathBuf>) -> Self {
+        self.split_debug_info(path);
+        self
+    }
+
+    /// Choose how `unreachable` is lowered. See `UnreachableStrategy` for the contract the
+    /// handler variant imposes on the host. Only `unreachable` instructions in the module are
+    /// affected: other traps, and the padding written by `pad_code_to`, which has no code to call
+    /// from, always use trap instructions. Defaults to `UnreachableStrategy::Trap`.
+    pub fn unreachable_strategy(&mut self, unreachable_strategy: UnreachableStrategy) {
+        self.unreachable_strategy = unreachable_strategy;
+    }
+
+    pub fn with_unreachable_strategy(mut self, unreachable_strategy: UnreachableStrategy) -> Self {
+        self.unreachable_strategy(unreachable_strategy);
+        self
+    }
+
+    /// Fail with `Error::ModuleTooLarge` if the wasm binary is longer than `limit` bytes.
+    ///
+    /// This is checked before the module is parsed at all, so it is the cheapest way to turn
+    /// away oversized untrusted input. There is no limit by default.
+    pub fn max_module_bytes(&mut self, limit: usize) {
+        self.max_module_bytes = Some(limit);
+    }
+
+    pub fn with_max_module_bytes(mut self, limit: usize) -> Self {
+        self.max_module_bytes(limit);
+        self
+    }
+
+    /// Fail with `Error::CodeSizeBudgetExceeded` if the machine code of all functions in the
+    /// object, as recorded in the function manifest, adds up to more than `budget` bytes.
+    ///
+    /// This can only be checked once every function has been compiled. To reject unlikely
+    /// candidates sooner, compare `Compiler::complexity_estimate` against a threshold first.
+    pub fn max_total_code_size(&mut self, budget: usize) {
+        self.max_total_code_size = Some(budget);
+    }
+
+    pub fn with_max_total_code_size(mut self, budget: usize) -> Self {
+        self.max_total_code_size(budget);
+        self
+    }
+
+    /// Pad the object's code section to `size` bytes, filling the bytes after the last function
+    /// with instructions that trap, so the code can be replaced in place by another module's
+    /// code of the same padded size.
+    ///
+    /// The code section includes hostcall trampolines and the stack probe as well as the guest's
+    /// functions. `object_file` fails with `Error::CodeSizeBudgetExceeded` if it is already larger
+    /// than `size`. Only x86_64 and aarch64 targets can be padded, and since the code must be in
+    /// a single section, creating the compiler fails with `Error::Input` if `function_sections`
+    /// is also set. On aarch64, the padding is whole 4-byte instructions, so `object_file` fails
+    /// with `Error::Input` if `size` is not a multiple of 4.
+    pub fn pad_code_to(&mut self, size: usize) {
+        self.pad_code_to = Some(size);
+    }
+
+    pub fn with_pad_code_to(mut self, size: usize) -> Self {
+        self.pad_code_to(size);
+        self
+    }
+
+    /// Fail with `Error::TooManyLocals` if a function declares more than `limit` locals, not
+    /// counting its parameters.
+    ///
+    /// Register allocation time grows with the number of locals, so this guards against
+    /// untrusted modules that declare huge numbers of them.
+    pub fn max_locals_per_function(&mut self, limit: u32) {
+        self.max_locals_per_function = Some(limit);
+    }
+
+    pub fn with_max_locals_per_function(mut self, limit: u32) -> Self {
+        self.max_locals_per_function(limit);
+        self
+    }
+
+    /// Trap with `TrapCode::MemoryLimitExceeded` when `memory.grow` would grow memory past
+    /// `limit` wasm pages, rather than have it fail and return -1.
+    ///
+    /// This departs from the semantics of `memory.grow` in the wasm specification, so that a
+    /// guest that exceeds a strict memory cap is stopped, rather than left to handle the failure.
+    /// Growth that stays within the limit can still fail, and return -1, if the runtime's own
+    /// limits don't allow it. Defaults to `None`, for no limit.
+    pub fn hard_memory_limit(&mut self, limit: Option<u32>) {
+        self.hard_memory_limit = limit;
+    }
+
+    pub fn with_hard_memory_limit(mut self, limit: Option<u32>) -> Self {
+        self.hard_memory_limit(limit);
+        self
+    }
+
+    /// Require the module to export a function named `name` with the given signature.
+    ///
+    /// Expectations are checked when the compiler is created. A missing export fails with
+    /// `Error::MissingExport`, and an export with another signature with
+    /// `Error::ExportSignatureMismatch`. If more than one expectation is unmet, they are all
+    /// reported together in `Error::ExportExpectations`.
+    pub fn expect_export(&mut self, name: String, params: Vec<WasmType>, results: Vec<WasmType>) {
+        self.expected_exports.push((
+            name,
+            WasmFuncType {
+                params: params.into_boxed_slice(),
+                returns: results.into_boxed_slice(),
+            },
+        ));
+    }
+
+    pub fn with_expect_export(
+        mut self,
+        name: String,
+        params: Vec<WasmType>,
+        results: Vec<WasmType>,
+    ) -> Self {
+        self.expect_export(name, params, results);
+        self
+    }
+
+    /// Share a single trap table between all functions whose trap tables are identical, rather
+    /// than emitting a table for each function. The manifest entries of those functions all
+    /// refer to the shared table. Defaults to `false`.
+    pub fn dedup_trap_tables(&mut self, dedup_trap_tables: bool) {
+        self.dedup_trap_tables = dedup_trap_tables;
+    }
+
+    pub fn with_dedup_trap_tables(mut self, dedup_trap_tables: bool) -> Self {
+        self.dedup_trap_tables(dedup_trap_tables);
+        self
+    }
+
+    /// Write trap tables in format `version`, so the object can be loaded by a runtime that
+    /// doesn't read the current format. `lucet_module::TRAP_TABLE_VERSION_UNVERSIONED` writes
+    /// tables without a header, which every runtime can read.
+    ///
+    /// Creating the compiler fails with `Error::Unsupported` if lucet-module can't write
+    /// `version`. Defaults to `lucet_module::TRAP_TABLE_VERSION`.
+    pub fn trap_table_version(&mut self, version: u8) {
+        self.trap_table_version = version;
+    }
+
+    pub fn with_trap_table_version(mut self, version: u8) -> Self {
+        self.trap_table_version(version);
+        self
+    }
+
+    /// Write a map from each function's machine code to the offsets of the wasm instructions it
+    /// was generated from, as a data symbol alongside the function. Read it back with
+    /// `ObjectFile::address_map_for`.
+    ///
+    /// The maps are not used by the runtime, and add to the size of the object, so they are only
+    /// worth emitting for profiling or debugging. Defaults to `false`.
+    pub fn emit_address_map(&mut self, emit_address_map: bool) {
+        self.emit_address_map = emit_address_map;
+    }
+
+    pub fn with_emit_address_map(mut self, emit_address_map: bool) -> Self {
+        self.emit_address_map(emit_address_map);
+        self
+    }
+
+    /// Write the basic blocks of each function's machine code, with their offset ranges, as a
+    /// data symbol alongside the function. Read them back with `ObjectFile::cfg_map_for`, for
+    /// example to map execution traces to blocks for coverage.
+    ///
+    /// Like address maps, CFG maps are not used by the runtime and add to the size of the
+    /// object. Defaults to `false`.
+    pub fn emit_cfg_map(&mut self, emit_cfg_map: bool) {
+        self.emit_cfg_map = emit_cfg_map;
+    }
+
+    pub fn with_emit_cfg_map(mut self, emit_cfg_map: bool) -> Self {
+        self.emit_cfg_map(emit_cfg_map);
+        self
+    }
+
+    /// Write a table of the signatures that the module's indirect calls check their callees
+    /// against, with the id of each, as a data symbol. Read it back with
+    /// `ObjectFile::signature_table`, for example to decode the ids involved in a `BadSignature`
+    /// trap. Defaults to `false`.
+    pub fn emit_sig_table(&mut self, emit_sig_table: bool) {
+        self.emit_sig_table = emit_sig_table;
+    }
+
+    pub fn with_emit_sig_table(mut self, emit_sig_table: bool) -> Self {
+        self.emit_sig_table(emit_sig_table);
+        self
+    }
+
+    /// Place each function in its own section, named after the function, like
+    /// `-ffunction-sections` does for C, so the linker can place each function on its own.
+    ///
+    /// The function manifest and trap tables refer to functions through relocations, so they
+    /// remain correct wherever the linker places each section. Those relocations also keep every
+    /// function alive, since the manifest lists all of them, so `--gc-sections` can't drop any.
+    /// Note that the linker is free to reorder the sections, which can undo `function_order`.
+    /// Defaults to `false`.
+    pub fn function_sections(&mut self, function_sections: bool) {
+        self.function_sections = function_sections;
+    }
+
+    pub fn with_function_sections(mut self, function_sections: bool) -> Self {
+        self.function_sections(function_sections);
+        self
+    }
+
+    /// Lay out the functions with the given symbols first in the code section, in the given
+    /// order, for example to place functions that a profile shows are hot next to each other.
+    ///
+    /// Functions that are not listed follow in function index order, as do all functions when
+    /// no order is given. Symbols that name no function defined in the module are ignored.
+    pub fn function_order(&mut self, function_order: Vec<String>) {
+        self.function_order = function_order;
+    }
+
+    pub fn with_function_order(mut self, function_order: Vec<String>) -> Self {
+        self.function_order(function_order);
+        self
+    }
+
+    /// Run `check` on the IR of each function after it is translated, failing compilation with
+    /// `Error::CodegenCheckFailed` if it returns an error. See `codegen_check` for built-in checks.
+    pub fn add_codegen_check(&mut self, check: Box<CodegenCheck>) {
+        self.codegen_checks.push(Arc::from(check));
+    }
+
+    pub fn with_codegen_check(mut self, check: Box<CodegenCheck>) -> Self {
+        self.add_codegen_check(check);
+        self
+    }
+
+    pub fn validator(&mut self, validator: Option<Validator>) {
+        self.validator = validator;
+    }
+
+    pub fn with_validator(mut self, validator: Option<Validator>) -> Self {
+        self.validator(validator);
+        self
+    }
+
+    /// Report progress while validating the module in `create`, as the number of bytes validated
+    /// so far and the total size of the module.
+    ///
+    /// Progress is reported from wasmparser's position as it validates the module. A custom
+    /// `Validator` is run alongside and does not report progress of its own.
+    pub fn validation_progress(&mut self, progress: Box<dyn FnMut(usize, usize) + Send>) {
+        self.validation_progress = Some(Arc::new(Mutex::new(progress)));
+    }
+
+    pub fn with_validation_progress(
+        mut self,
+        progress: Box<dyn FnMut(usize, usize) + Send>,
+    ) -> Self {
+        self.validation_progress(progress);
+        self
+    }
+
+    pub fn create<'a>(
+        &'a self,
+        wasm_binary: &'a [u8],
+        bindings: &'a Bindings,
+    ) -> Result<Compiler<'a>, Error> {
+        Compiler::from_builder(self, wasm_binary, bindings)
+    }
+
+    /// Parse and validate a module without creating a compiler for it.
+    ///
+    /// This is the first phase of `create`, and reports the same validation errors. Pass the
+    /// result to `create_with_state` to finish creating the compiler without validating the
+    /// module again.
+    pub fn validate_only<'a>(&self, wasm_binary: &'a [u8]) -> Result<ValidatedModule<'a>, Error> {
+        Compiler::validate(self, wasm_binary)
+    }
+
+    /// Create a compiler for a module already validated by `validate_only`.
+    ///
+    /// The module should have been validated by a builder with the same target and validation
+    /// settings; creating the compiler fails with `Error::Input` if it was validated for a
+    /// different target configuration.
+    pub fn create_with_state<'a>(
+        &'a self,
+        validated: ValidatedModule<'a>,
+        bindings: &'a Bindings,
+    ) -> Result<Compiler<'a>, Error> {
+        Compiler::from_validated(self, validated, bindings)
+    }
+}
+
+/// A module that has been parsed and validated by `CompilerBuilder::validate_only`, ready to be
+/// passed to `CompilerBuilder::create_with_state`.
+pub struct ValidatedModule<'a> {
+    wasm_binary: &'a [u8],
+    module_validation: ModuleValidation<'a>,
+    translation_state: ModuleTranslationState,
+    frontend_config: TargetFrontendConfig,
+}
+
+impl<'a> ValidatedModule<'a> {
+    /// The state Cranelift kept while translating the module, such as the signatures of its
+    /// block types.
+    pub fn translation_state(&self) -> &ModuleTranslationState {
+        &self.translation_state
+    }
+}
+
+/// The settings of a `CompilerBuilder` that configure Cranelift for the target, apart from the
+/// target itself and the optimization level.
+#[derive(Clone, Copy, Debug)]
+struct IsaFlags {
+    canonicalize_nans: bool,
+    preserve_frame_pointer: bool,
+    spectre_mitigation: bool,
+    exact_div_traps: bool,
+    machine_code_cfg_info: bool,
+    enable_simd: bool,
+    max_stack_size: Option<usize>,
+}
+
+impl IsaFlags {
+    fn new(builder: &CompilerBuilder) -> Self {
+        Self {
+            canonicalize_nans: builder.canonicalize_nans,
+            preserve_frame_pointer: builder.preserve_frame_pointer,
+            spectre_mitigation: builder.spectre_mitigation,
+            exact_div_traps: builder.exact_div_traps,
+            machine_code_cfg_info: builder.emit_cfg_map,
+            enable_simd: builder.allow_simd,
+            max_stack_size: builder.max_stack_size,
+        }
+    }
+}
+
+pub struct Compiler<'a> {
+    wasm_binary: &'a [u8],
+    decls: ModuleDecls<'a>,
+    codegen_context: CodegenContext,
+    target: Triple,
+    variant: BackendVariant,
+    opt_level: OptLevel,
+    cpu_features: CpuFeatures,
+    count_instructions: bool,
+    interrupt_check_period: Option<u32>,
+    prev_state: Option<Arc<PrevState>>,
+    split_debug_info: Option<PathBuf>,
+    coverage: Option<CoverageMode>,
+    canonicalize_nans: bool,
+    trap_uninit_table: bool,
+    separate_data_segments: bool,
+    codegen_checks: CodegenChecks,
+    bake_memory_image: bool,
+    custom_metadata: BTreeMap<String, Vec<u8>>,
+    record_compile_times: bool,
+    emit_sig_table: bool,
+    module_symbol: String,
+    symbol_prefix: Option<String>,
+    report_unbounded_loops: bool,
+    report_const_fold: bool,
+    report_recursion: bool,
+    table_overrides: Vec<TableOverride>,
+    elf_os_abi: Option<u8>,
+    elf_flags: Option<u32>,
+    forbid_stack_probe: bool,
+    max_stack_size: Option<usize>,
+    external_stack_probe: bool,
+    isa_flags: IsaFlags,
+    allow_simd: bool,
+    allow_multi_memory: bool,
+    cancellation_token: Option<Arc<AtomicBool>>,
+    max_total_code_size: Option<usize>,
+    pad_code_to: Option<usize>,
+    max_locals_per_function: Option<u32>,
+    function_order: Vec<String>,
+    function_opt_levels: HashMap<UniqueFuncIndex, OptLevel>,
+    weak_imports: HashSet<String>,
+    function_bodies:
+        HashMap<UniqueFuncIndex, (FuncValidator<ValidatorResources>, FunctionBody<'a>)>,
+}
+
+impl<'a> Compiler<'a> {
+    pub fn new(
+        wasm_binary: &'a [u8],
+        target: Triple,
+        variant: BackendVariant,
+        opt_level: OptLevel,
+        cpu_features: CpuFeatures,
+        bindings: &'a Bindings,
+        heap_settings: HeapSettings,
+        count_instructions: bool,
+        validator: Option<Validator>,
+        canonicalize_nans: bool,
+    ) -> Result<Self, Error> {
+        let builder = CompilerBuilder {
+            target,
+            variant,
+            opt_level,
+            cpu_features,
+            heap_settings,
+            count_instructions,
+            validator,
+            canonicalize_nans,
+            ..CompilerBuilder::new()
+        };
+        Self::from_builder(&builder, wasm_binary, bindings)
+    }
+
+    fn from_builder(
+        builder: &CompilerBuilder,
+        wasm_binary: &'a [u8],
+        bindings: &'a Bindings,
+    ) -> Result<Self, Error> {
+        let validated = Self::validate(builder, wasm_binary)?;
+        Self::from_validated(builder, validated, bindings)
+    }
+
+    fn builder_isa(builder: &CompilerBuilder) -> Result<Box<dyn TargetIsa>, Error> {
+        Self::builder_isa_at(builder, builder.opt_level)
+    }
+
+    fn builder_isa_at(
+        builder: &CompilerBuilder,
+        opt_level: OptLevel,
+    ) -> Result<Box<dyn TargetIsa>, Error> {
+        Self::target_isa(
+            builder.target.clone(),
+            builder.variant,
+            opt_level,
+            &builder.cpu_features,
+            &IsaFlags::new(builder),
+        )
+    }
+
+    fn validate(
+        builder: &CompilerBuilder,
+        wasm_binary: &'a [u8],
+    ) -> Result<ValidatedModule<'a>, Error> {
+        if let Some(limit) = builder.max_module_bytes {
+            if wasm_binary.len() > limit {
+                return Err(Error::ModuleTooLarge {
+                    size: wasm_binary.len(),
+                    limit,
+                });
+            }
+        }
+
+        let frontend_config = Self::builder_isa(builder)?.frontend_config();
+        let mut module_validation =
+            ModuleValidation::new(frontend_config, builder.validator.clone())
+                .with_progress(builder.validation_progress.clone(), wasm_binary.len())
+                .with_multi_memory(builder.allow_multi_memory)
+                .with_simd(builder.allow_simd)
+                .with_rejected_features(builder.rejected_wasm_features.clone());
+
+        check_unique_exports(wasm_binary)?;
+
+        let translation_state = translate_module(wasm_binary, &mut module_validation)?;
+        module_validation.report_progress(wasm_binary.len());
+
+        module_validation.validation_errors()?;
+
+        Ok(ValidatedModule {
+            wasm_binary,
+            module_validation,
+            translation_state,
+            frontend_config,
+        })
+    }
+
+    fn from_validated(
+        builder: &CompilerBuilder,
+        validated: ValidatedModule<'a>,
+        bindings: &'a Bindings,
+    ) -> Result<Self, Error> {
+        let target = builder.target.clone();
+        let variant = builder.variant;
+        let opt_level = builder.opt_level;
+        let cpu_features = builder.cpu_features.clone();
+        let canonicalize_nans = builder.canonicalize_nans;
+
+        let elf_header_set = builder.elf_os_abi.is_some() || builder.elf_flags.is_some();
+        if elf_header_set && target.binary_format != BinaryFormat::Elf {
+            return Err(Error::Unsupported(format!(
+                "ELF header fields cannot be set for {} binary format",
+                target.binary_format
+            )));
+        }
+
+        if builder.split_debug_info.is_some() && target.binary_format != BinaryFormat::Elf {
+            return Err(Error::Unsupported(format!(
+                "split debug objects for {} targets",
+                target
+            )));
+        }
+
+        let isa = Self::builder_isa(builder)?;
+        let frontend_config = isa.frontend_config();
+        if frontend_config.default_call_conv != validated.frontend_config.default_call_conv
+            || frontend_config.pointer_width != validated.frontend_config.pointer_width
+        {
+            return Err(Error::Input(
+                "module was validated for a different target configuration".to_owned(),
+            ));
+        }
+        let module_validation = validated.module_validation;
+
+        check_expected_exports(&module_validation.info, &builder.expected_exports)?;
+        if let Some(allowed) = builder.allowed_import_modules.as_ref() {
+            check_import_modules(&module_validation.info, allowed)?;
+        }
+
+        let imports = module_validation
+            .info
+            .imported_funcs
+            .values()
+            .map(|(module, field)| format!("{}::{}", module, field))
+            .collect::<HashSet<_>>();
+        if let Some(missing) = builder.weak_imports.difference(&imports).next() {
+            return Err(Error::Input(format!(
+                "weak import {} is not imported by the module",
+                missing
+            )));
+        }
+
+        if !builder.separate_data_segments {
+            let other_memory_data = module_validation
+                .info
+                .data_initializers
+                .iter()
+                .find(|(ix, inits)| **ix != MemoryIndex::new(0) && !inits.is_empty());
+            if let Some((ix, _)) = other_memory_data {
+                return Err(Error::Unsupported(format!(
+                    "data segments for memory {} require separate_data_segments",
+                    ix.as_u32()
+                )));
+            }
+        }
+
+        let mut codegen_context = CodegenContext::new(
+            isa,
+            Self::builder_isa(builder)?,
+            builder.function_sections,
+            builder.deterministic_libcalls,
+        )?;
+        if builder.dedup_trap_tables {
+            codegen_context.dedup_trap_tables();
+        }
+        if lucet_module::trap_table_header_size(builder.trap_table_version).is_none() {
+            return Err(Error::Unsupported(format!(
+                "trap table version {}",
+                builder.trap_table_version
+            )));
+        }
+        codegen_context.trap_table_version(builder.trap_table_version);
+        if builder.emit_address_map {
+            codegen_context.emit_address_maps();
+        }
+        if builder.emit_cfg_map {
+            codegen_context.emit_cfg_maps();
+        }
+        if builder.flush_denormals {
+            codegen_context.flush_denormals();
+        }
+        if let Some(limit) = builder.hard_memory_limit {
+            codegen_context.hard_memory_limit(limit);
+        }
+        if let Some(size) = builder.max_stack_size {
+            codegen_context.max_stack_size(size);
+        }
+
+        let mut function_opt_levels = HashMap::new();
+        if let Some(function_opt_level) = builder.function_opt_level.as_ref() {
+            let mut opt_level_isas = HashMap::new();
+            for (func_index, unique_func_ix) in module_validation.info.function_mapping.iter() {
+                if !module_validation
+                    .function_bodies
+                    .contains_key(unique_func_ix)
+                {
+                    continue;
+                }
+                let level = function_opt_level(func_index);
+                if level == opt_level {
+                    continue;
+                }
+                if let Entry::Vacant(entry) = opt_level_isas.entry(level) {
+                    entry.insert(Self::builder_isa_at(builder, level)?);
+                }
+                function_opt_levels.insert(*unique_func_ix, level);
+            }
+            codegen_context.opt_level_isas(opt_level_isas);
+        }
+
+        builder.heap_settings.check_page_size()?;
+        if builder.heap_settings.page_size != WASM_PAGE_SIZE {
+            check_fixed_memory_size(
+                &module_validation.function_bodies,
+                builder.heap_settings.page_size,
+            )?;
+        }
+        let heap_settings = if builder.auto_heap_settings {
+            HeapSettings {
+                guard_size: builder.heap_settings.guard_size,
+                ..HeapSettings::from_module_limits(
+                    &module_validation.info,
+                    builder.heap_settings.max_reserved_size,
+                    builder.heap_settings.page_size,
+                )
+            }
+        } else {
+            builder.heap_settings.clone()
+        };
+
+        let mut runtime = Runtime::lucet(frontend_config);
+        if builder.unreachable_strategy == UnreachableStrategy::CallHandler {
+            runtime = runtime.with_unreachable_handler(frontend_config);
+            codegen_context.call_unreachable_handler();
+        }
+        let mut decls = ModuleDecls::new(
+            module_validation.info,
+            &codegen_context,
+            bindings,
+            runtime,
+            heap_settings.clone(),
+            builder.export_all_functions,
+            builder.allow_multi_memory,
+            &builder.symbol_scheme,
+            builder.import_filter.as_deref(),
+        )?;
+        check_reserved_symbols(&decls, builder)?;
+        for key in builder.custom_metadata.keys() {
+            check_custom_metadata_key(key)?;
+        }
+        if builder.pad_code_to.is_some() && builder.function_sections {
+            return Err(Error::Input(
+                "code cannot be padded when each function has its own section".to_owned(),
+            ));
+        }
+        if builder.forbid_float {
+            check_no_floats(&decls, &module_validation.function_bodies)?;
+        }
+        if builder.separate_data_segments {
+            decls.clear_heap_initializer()?;
+        }
+        for (index, value) in builder.global_overrides.iter() {
+            decls.override_global(*index, *value)?;
+        }
+
+        let mut custom_metadata = builder.custom_metadata.clone();
+        if let Some(mode) = builder.coverage {
+            if custom_metadata.contains_key(COVERAGE_METADATA_KEY) {
+                return Err(Error::Input(format!(
+                    "custom metadata key {:?} is reserved for the coverage layout",
+                    COVERAGE_METADATA_KEY
+                )));
+            }
+            let layout = coverage_layout(
+                &decls,
+                mode,
+                module_validation
+                    .function_bodies
+                    .iter()
+                    .map(|(func_index, (_validator, body))| (*func_index, body)),
+            )?;
+            let counters_symbol = builder.coverage_counters_symbol();
+            let buffer = codegen_context.module().declare_data(
+                &counters_symbol,
+                ClifLinkage::Import,
+                true,
+                false,
+            )?;
+            codegen_context.instrument_coverage(CoverageCounters::new(
+                mode,
+                Name::new_data(counters_symbol, buffer),
+                &layout,
+            ));
+            custom_metadata.insert(
+                COVERAGE_METADATA_KEY.to_owned(),
+                serialize_coverage_layout(&layout),
+            );
+        }
+
+        if let Some(on_heap_layout) = builder.on_heap_layout.as_ref() {
+            let heaps = (0..decls.info.memories.len())
+                .filter_map(|ix| decls.get_heap_for(MemoryIndex::new(ix)).cloned())
+                .collect();
+            on_heap_layout(&ResolvedHeapLayout {
+                settings: heap_settings,
+                heaps,
+            });
+        }
+
+        Ok(Self {
+            wasm_binary: validated.wasm_binary,
+            decls,
+            codegen_context,
+            opt_level,
+            cpu_features,
+            count_instructions: builder.count_instructions,
+            interrupt_check_period: builder.interrupt_check_period,
+            prev_state: builder.prev_state.clone(),
+            split_debug_info: builder.split_debug_info.clone(),
+            target,
+            variant,
+            canonicalize_nans,
+            trap_uninit_table: builder.trap_uninit_table,
+            separate_data_segments: builder.separate_data_segments,
+            codegen_checks: builder.codegen_checks.clone(),
+            bake_memory_image: builder.bake_memory_image,
+            custom_metadata,
+            record_compile_times: builder.record_compile_times,
+            emit_sig_table: builder.emit_sig_table,
+            module_symbol: builder.module_symbol.clone(),
+            symbol_prefix: builder.symbol_prefix.clone(),
+            report_unbounded_loops: builder.report_unbounded_loops,
+            report_const_fold: builder.report_const_fold,
+            report_recursion: builder.report_recursion,
+            table_overrides: builder.table_overrides.clone(),
+            elf_os_abi: builder.elf_os_abi,
+            elf_flags: builder.elf_flags,
+            forbid_stack_probe: builder.forbid_stack_probe,
+            max_stack_size: builder.max_stack_size,
+            external_stack_probe: builder.external_stack_probe,
+            // Control flow graphs are only recorded for object files, which are built with the
+            // builder's own flags.
+            isa_flags: IsaFlags {
+                machine_code_cfg_info: false,
+                ..IsaFlags::new(builder)
+            },
+            allow_simd: builder.allow_simd,
+            allow_multi_memory: builder.allow_multi_memory,
+            cancellation_token: builder.cancellation_token.clone(),
+            max_total_code_size: builder.max_total_code_size,
+            pad_code_to: builder.pad_code_to,
+            max_locals_per_function: builder.max_locals_per_function,
+            function_order: builder.function_order.clone(),
+            function_opt_levels,
+            weak_imports: builder.weak_imports.clone(),
+            function_bodies: module_validation.function_bodies,
+        })
+    }
+
+    pub fn builder() -> CompilerBuilder {
+        CompilerBuilder::new()
+    }
+
+    pub fn module_features(&self) -> ModuleFeatures {
+        let mut mf: ModuleFeatures = (&self.cpu_features).into();
+        mf.instruction_count = self.count_instructions;
+        mf
+    }
+
+    pub fn module_data(&self) -> Result<ModuleData<'_>, Error> {
+        Ok(self
+            .decls
+            .get_module_data(self.module_features())?
+            .with_required_features(self.required_features()))
+    }
+
+    /// The target configuration the module is translated with, such as the target's pointer
+    /// width.
+    pub fn frontend_config(&self) -> TargetFrontendConfig {
+        self.decls.target_config()
+    }
+
+    /// Estimate the peak memory, in bytes, that `object_file` will use to compile this module.
+    ///
+    /// This is a deliberately generous heuristic rather than a measurement, meant for deciding
+    /// how many compilations can safely share a host. It adds up:
+    ///
+    /// * a fixed 32MiB for the compiler itself and the module's metadata,
+    /// * 256 bytes of IR and register allocation state per byte of function body, for as many
+    ///   functions as are compiled in parallel, each assumed to be as large as the largest,
+    /// * 16 bytes of machine code, relocations, and trap tables per byte of function body,
+    /// * 1KiB of declarations and manifest entries per function, and
+    /// * twice the size of the data segments, which are copied into the module data.
+    pub fn estimate_peak_memory(&self) -> usize {
+        const BASE: usize = 32 * 1024 * 1024;
+        const IR_PER_BODY_BYTE: usize = 256;
+        const OUTPUT_PER_BODY_BYTE: usize = 16;
+        const PER_FUNCTION: usize = 1024;
+
+        let body_sizes = self
+            .function_bodies
+            .values()
+            .map(|(_, body)| body.get_binary_reader().bytes_remaining());
+        let (total_body, max_body) = body_sizes.fold((0usize, 0usize), |(total, max), size| {
+            (total.saturating_add(size), max.max(size))
+        });
+        let parallelism = rayon::current_num_threads().min(self.function_bodies.len());
+        let function_count = self.decls.info.functions.len();
+        let data_size: usize = data_segments(&self.decls.info)
+            .iter()
+            .map(|segment| segment.data.len())
+            .sum();
+
+        BASE.saturating_add(
+            max_body
+                .saturating_mul(IR_PER_BODY_BYTE)
+                .saturating_mul(parallelism),
+        )
+        .saturating_add(total_body.saturating_mul(OUTPUT_PER_BODY_BYTE))
+        .saturating_add(function_count.saturating_mul(PER_FUNCTION))
+        .saturating_add(data_size.saturating_mul(2))
+    }
+
+    /// A cheap measure of how much code the module will compile to: the number of wasm
+    /// instructions across all function bodies.
+    ///
+    /// This does not translate or compile anything, so it is suited to rejecting modules before
+    /// paying for compilation. How instruction counts relate to machine code size varies between
+    /// modules, so thresholds should be chosen with some margin.
+    pub fn complexity_estimate(&self) -> usize {
+        self.function_bodies
+            .values()
+            .map(|(_validator, body)| {
+                body.get_operators_reader()
+                    .map(|ops| ops.into_iter().count())
+                    .unwrap_or(0)
+            })
+            .sum()
+    }
+
+    /// Describe every global in the module, imported globals first, in global index order.
+    pub fn globals(&self) -> Vec<GlobalInfo<'_>> {
+        self.decls.get_globals_info()
+    }
+
+    /// Describe every imported function, in function index order, with the symbol its binding
+    /// resolves to. The host must provide each of these symbols when the object is linked.
+    pub fn imports(&self) -> Vec<ImportInfo<'_>> {
+        self.decls.get_imports_info()
+    }
+
+    /// A SHA-256 digest of the module's functional interface: the module and field name of each
+    /// imported function, and the name of each exported function, along with their wasm
+    /// signatures.
+    ///
+    /// The entries are sorted before hashing, so the fingerprint does not depend on the order of
+    /// functions in the module, the bindings, or the symbols chosen for imports. Names are
+    /// length-prefixed and value types are encoded with their wasm binary type codes, so no two
+    /// interfaces hash the same bytes, whatever their names contain. A host can store the
+    /// fingerprint of a guest it was built against, and reject guests whose interface drifts.
+    pub fn abi_fingerprint(&self) -> [u8; 32] {
+        const IMPORT_TAG: u8 = 0;
+        const EXPORT_TAG: u8 = 1;
+        fn write_name(entry: &mut Vec<u8>, name: &str) {
+            entry.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            entry.extend_from_slice(name.as_bytes());
+        }
+        fn write_types(entry: &mut Vec<u8>, types: &[WasmType]) {
+            entry.extend_from_slice(&(types.len() as u32).to_le_bytes());
+            entry.extend(types.iter().map(|ty| match ty {
+                WasmType::I32 => 0x7f,
+                WasmType::I64 => 0x7e,
+                WasmType::F32 => 0x7d,
+                WasmType::F64 => 0x7c,
+                WasmType::V128 => 0x7b,
+                WasmType::FuncRef => 0x70,
+                WasmType::ExternRef => 0x6f,
+                WasmType::ExnRef => 0x68,
+            }));
+        }
+        fn write_signature(entry: &mut Vec<u8>, func_type: &WasmFuncType) {
+            write_types(entry, &func_type.params);
+            write_types(entry, &func_type.returns);
+        }
+
+        let imports = self.imports().into_iter().map(|import| {
+            let mut entry = vec![IMPORT_TAG];
+            write_name(&mut entry, import.module);
+            write_name(&mut entry, import.field);
+            write_signature(&mut entry, &import.signature);
+            entry
+        });
+        let exports = self
+            .decls
+            .info
+            .functions
+            .iter()
+            .flat_map(|(func_index, func)| {
+                let (_signature, func_type) = self.decls.info.signature_for_function(func_index);
+                func.export_names.iter().map(move |name| {
+                    let mut entry = vec![EXPORT_TAG];
+                    write_name(&mut entry, name);
+                    write_signature(&mut entry, func_type);
+                    entry
+                })
+            });
+        let mut entries = imports.chain(exports).collect::<Vec<_>>();
+        entries.sort();
+
+        // Each entry is self-delimiting, so they can be hashed back to back.
+        let mut hasher = Sha256::new();
+        for entry in entries {
+            hasher.update(&entry);
+        }
+        hasher.finalize().into()
+    }
+
+    /// The data segments of the module, active and passive.
+    pub fn data_segments(&self) -> Vec<DataSegment<'_>> {
+        data_segments(&self.decls.info)
+    }
+
+    /// The steps that initialize linear memory at instantiation, from the module's active data
+    /// segments. A host can replay these lazily, for example to initialize pages on demand,
+    /// rather than copying every segment in when an instance is created.
+    pub fn memory_init_program(&self) -> Vec<MemoryInitOp<'_>> {
+        memory_init_program(&self.decls.info)
+    }
+
+    /// The wasm code of each function defined in the module, in function index order, with the
+    /// offset of the code within the module binary.
+    ///
+    /// The code is borrowed from the module binary, and covers the body's local declarations as
+    /// well as its instructions.
+    pub fn function_bodies(&self) -> impl Iterator<Item = (UniqueFuncIndex, &'a [u8], usize)> {
+        let mut bodies = self
+            .function_bodies
+            .iter()
+            .map(|(func_index, (_validator, body))| {
+                let mut reader = body.get_binary_reader();
+                let offset = reader.original_position();
+                let code = reader
+                    .read_bytes(reader.bytes_remaining())
+                    .expect("remaining bytes of a function body can be read");
+                (*func_index, code, offset)
+            })
+            .collect::<Vec<_>>();
+        bodies.sort_by_key(|(func_index, _, _)| *func_index);
+        bodies.into_iter()
+    }
+
+    /// Build the graph of calls between functions in this module.
+    pub fn call_graph(&self) -> Result<CallGraph, Error> {
+        CallGraph::new(
+            &self.decls,
+            self.function_bodies
+                .iter()
+                .map(|(func_index, (_validator, body))| (*func_index, body)),
+        )
+    }
+
+    /// The function manifest entry of each function in the module, in wasm function index
+    /// order, followed by the entries of the functions the runtime provides.
+    ///
+    /// The manifest lists functions in the order they were declared to Cranelift, and imports
+    /// bound to the same symbol share an entry, so an entry's position in the manifest isn't its
+    /// wasm index in general. Each wasm function is listed, so an entry shared by several imports
+    /// appears once for each of them. Hostcall trampolines and the stack probe are added to the
+    /// end of the manifest while the object is built, and are not listed.
+    pub fn function_manifest_ordered(&self) -> Vec<FunctionManifestEntry> {
+        let entry_for = |wasm_index: Option<FuncIndex>, func_index: UniqueFuncIndex| {
+            let name = self
+                .decls
+                .get_func(func_index)
+                .expect("decl exists for function")
+                .name;
+            FunctionManifestEntry {
+                wasm_index,
+                manifest_index: name
+                    .as_funcid()
+                    .expect("function is named by a function id")
+                    .as_u32(),
+                symbol: name.symbol().to_owned(),
+            }
+        };
+
+        let function_mapping = &self.decls.info.function_mapping;
+        let wasm_funcs = function_mapping.values().copied().collect::<HashSet<_>>();
+        let mut entries = function_mapping
+            .iter()
+            .map(|(wasm_index, func_index)| entry_for(Some(wasm_index), *func_index))
+            .collect::<Vec<_>>();
+        let mut runtime_entries = self
+            .decls
+            .info
+            .functions
+            .keys()
+            .filter(|func_index| !wasm_funcs.contains(func_index))
+            .map(|func_index| entry_for(None, func_index))
+            .collect::<Vec<_>>();
+        runtime_entries.sort_by_key(|entry| entry.manifest_index);
+        entries.extend(runtime_entries);
+        entries
+    }
+
+    /// The offsets from the vmctx pointer at which generated code finds the instance's state.
+    ///
+    /// The offsets follow the `InstanceRuntimeData` of the lucet-module this compiler was built
+    /// with, so they can change between versions; a host that sets up instances itself should
+    /// take them from here rather than hardcode them.
+    pub fn vmcontext_layout(&self) -> VmContextLayout {
+        VmContextLayout::new(self.decls.info.memories.len())
+    }
+
+    /// The module's start function, if it has one.
+    ///
+    /// The runtime runs the start function when an instance is created, before any export can be
+    /// called.
+    pub fn start_function(&self) -> Option<StartFunctionInfo> {
+        let func_index = self.decls.info.start_func?;
+        let wasm_index = self
+            .decls
+            .info
+            .function_mapping
+            .iter()
+            .find(|(_, unique_index)| **unique_index == func_index)
+            .map(|(wasm_index, _)| wasm_index)
+            .expect("start function has a wasm index");
+        let func = self
+            .decls
+            .get_func(func_index)
+            .expect("decl exists for start function");
+        Some(StartFunctionInfo {
+            wasm_index,
+            symbol: func.name.symbol().to_owned(),
+            imported: func.imported(),
+        })
+    }
+
+    /// Whether `canonicalize_nans` has any effect on the code generated for this module.
+    ///
+    /// Canonicalization only rewrites the results of floating-point arithmetic, so this is false
+    /// if the option is disabled or if no function body contains a scalar or vector float
+    /// arithmetic instruction, and true otherwise.
+    pub fn canonicalization_applied(&self) -> bool {
+        self.canonicalize_nans
+            && self.function_bodies.values().any(|(_validator, body)| {
+                let ops = match body.get_operators_reader() {
+                    Ok(ops) => ops,
+                    // The body was validated, but err on the side of reporting the option as
+                    // meaningful.
+                    Err(_) => return true,
+                };
+                ops.into_iter()
+                    .any(|op| op.map_or(true, |op| is_canonicalized_float_op(&op)))
+            })
+    }
+
+    /// The CPU features each defined function's code can use, given the features enabled for
+    /// this compilation.
+    ///
+    /// This is derived from the wasm instructions in each body, following how the x86-64 backend
+    /// lowers them: `popcnt`, `clz` and `ctz` use POPCNT, LZCNT and BMI1 respectively, float
+    /// rounding uses SSE4.1, and SIMD instructions use SSSE3 and SSE4.1. Only features enabled
+    /// for this compilation are reported, since the backend falls back to other sequences for
+    /// features that are not. Functions that use no optional features map to
+    /// `CpuFeatures::baseline()`.
+    pub fn feature_usage(&self) -> HashMap<UniqueFuncIndex, CpuFeatures> {
+        let enabled = self.cpu_features.enabled_features();
+        self.function_bodies
+            .iter()
+            .map(|(func_index, (_validator, body))| {
+                let mut used = CpuFeatures::baseline();
+                let ops = match body.get_operators_reader() {
+                    Ok(ops) => ops.into_iter().collect::<Result<Vec<_>, _>>().ok(),
+                    Err(_) => None,
+                };
+                match ops {
+                    Some(ops) => {
+                        for op in ops.iter() {
+                            for feature in features_for_op(op) {
+                                if enabled.contains(feature) {
+                                    used.set(*feature, true);
+                                }
+                            }
+                        }
+                    }
+                    // The body was validated, but err on the side of reporting every enabled
+                    // feature as used.
+                    None => {
+                        for feature in enabled.iter() {
+                            used.set(*feature, true);
+                        }
+                    }
+                }
+                (*func_index, used)
+            })
+            .collect()
+    }
+
+    /// What the module needs to run: the WebAssembly proposals it uses, and the CPU features its
+    /// code uses, as reported for each function by `feature_usage`.
+    ///
+    /// This is also serialized in the module data, so the runtime can reject a module it cannot
+    /// run when loading it. A proposal is reported as used if any function uses one of its
+    /// instructions, or a block type with parameters or several results for multi-value, or if
+    /// any signature has several results, or the module declares several memories. The CPU
+    /// features also report whether the module counts instructions.
+    pub fn required_features(&self) -> RequiredFeatures {
+        let mut used = CpuFeatures::baseline();
+        for features in self.feature_usage().values() {
+            for feature in features.enabled_features() {
+                used.set(feature, true);
+            }
+        }
+        let mut cpu = ModuleFeatures::from(&used);
+        cpu.instruction_count = self.count_instructions;
+
+        let info = &self.decls.info;
+        let mut wasm = WasmProposals {
+            multi_value: info
+                .signatures
+                .values()
+                .any(|(_sig, wasm_func_type)| wasm_func_type.returns.len() > 1),
+            multi_memory: info.memories.len() > 1,
+            ..WasmProposals::default()
+        };
+        for (_validator, body) in self.function_bodies.values() {
+            let ops = match body.get_operators_reader() {
+                Ok(ops) => ops.into_iter().collect::<Result<Vec<_>, _>>().ok(),
+                Err(_) => None,
+            };
+            match ops {
+                Some(ops) => {
+                    for op in ops.iter() {
+                        add_proposals_for_op(&mut wasm, op);
+                    }
+                }
+                // The body was validated, but err on the side of reporting every proposal that
+                // validation accepts as used.
+                None => {
+                    wasm.simd |= self.allow_simd;
+                    wasm.bulk_memory = true;
+                    wasm.reference_types = true;
+                    wasm.multi_value = true;
+                }
+            }
+        }
+        RequiredFeatures { wasm, cpu }
+    }
+
+    /// The wasm instructions in each function that can trap, but whose compiled code has no
+    /// trap site, for checking that Cranelift removed those traps intentionally.
+    ///
+    /// Each function is translated and compiled again for this, as the module's own compilation
+    /// is only done by `object_file`, so this costs as much as compiling the module. A trap site
+    /// is matched to the instruction at the wasm offset Cranelift tags it with. Instructions
+    /// Cranelift never translates, like those after an `unreachable`, are reported too.
+    /// Cranelift doesn't record why it removed a trap, so no reason is given.
+    pub fn eliminated_traps(&self) -> Result<HashMap<UniqueFuncIndex, Vec<EliminatedTrap>>, Error> {
+        // The module's validators were consumed by this compiler's functions, so validate it
+        // again for a fresh set.
+        let mut module_validation = ModuleValidation::new(self.frontend_config(), None)
+            .with_multi_memory(self.allow_multi_memory)
+            .with_simd(self.allow_simd);
+        translate_module(self.wasm_binary, &mut module_validation)?;
+
+        let mut eliminated = HashMap::new();
+        for (unique_func_ix, (mut validator, func_body)) in module_validation.function_bodies {
+            let func = self
+                .decls
+                .get_func(unique_func_ix)
+                .expect("decl exists for func body");
+            let mut clif_context = ClifContext::new();
+            clif_context.func = Self::translate_function(
+                &self.decls,
+                &self.codegen_context,
+                self.count_instructions,
+                self.interrupt_check_period,
+                unique_func_ix,
+                &mut validator,
+                &func_body,
+            )?;
+            let compiled = self.codegen_context.compile(
+                &mut clif_context,
+                func.name.symbol(),
+                false,
+                self.function_opt_levels.get(&unique_func_ix).copied(),
+            )?;
+            let trap_offsets = compiled
+                .traps
+                .traps
+                .iter()
+                .filter(|site| !site.srcloc.is_default())
+                .map(|site| site.srcloc.bits() as usize)
+                .collect::<HashSet<_>>();
+
+            let mut func_eliminated = Vec::new();
+            for op in func_body.get_operators_reader()?.into_iter_with_offsets() {
+                let (op, offset) = op?;
+                match trapping_operator_name(&op) {
+                    Some(operator) if !trap_offsets.contains(&offset) => {
+                        func_eliminated.push(EliminatedTrap {
+                            wasm_offset: offset,
+                            operator: operator.to_owned(),
+                        })
+                    }
+                    _ => {}
+                }
+            }
+            eliminated.insert(unique_func_ix, func_eliminated);
+        }
+        Ok(eliminated)
+    }
+
+    /// The symbols that code generated for this module references and that the runtime must
+    /// supply at link time, in addition to the module's imports.
+    ///
+    /// Like `feature_usage`, this is derived from the wasm instructions in each body rather than
+    /// from compiled code: `memory.size` and `memory.grow` call into lucet-runtime, counting
+    /// instructions calls it to yield once the bound expires, and the x86-64 backend lowers float
+    /// rounding to libcalls when SSE4.1 is not enabled. The stack probe is listed if
+    /// `external_stack_probe` is set and it isn't forbidden, as whether any function needs it
+    /// depends on frame sizes that are only known after compilation. The symbols are sorted.
+    pub fn required_runtime_symbols(&self) -> Vec<String> {
+        let rounding_libcalls = self.target.architecture == Architecture::X86_64
+            && !self
+                .cpu_features
+                .enabled_features()
+                .contains(&SpecificFeature::SSE41);
+        let mut runtime_funcs = HashSet::new();
+        let mut libcalls = HashSet::new();
+        for (_validator, body) in self.function_bodies.values() {
+            if self.count_instructions {
+                runtime_funcs.insert(RuntimeFunc::YieldAtBoundExpiration);
+            }
+            let ops = match body.get_operators_reader() {
+                Ok(ops) => ops.into_iter().collect::<Result<Vec<_>, _>>().ok(),
+                Err(_) => None,
+            };
+            match ops {
+                Some(ops) => {
+                    for op in ops.iter() {
+                        match op {
+                            Operator::MemorySize { .. } => {
+                                runtime_funcs.insert(RuntimeFunc::MemSize);
+                            }
+                            Operator::MemoryGrow { .. } => {
+                                runtime_funcs.insert(RuntimeFunc::MemGrow);
+                                // The memory limit is checked against the current size.
+                                if self.codegen_context.memory_limit().is_some() {
+                                    runtime_funcs.insert(RuntimeFunc::MemSize);
+                                }
+                            }
+                            Operator::Unreachable if self.codegen_context.unreachable_handler() => {
+                                runtime_funcs.insert(RuntimeFunc::UnreachableHandler);
+                            }
+                            op if rounding_libcalls => libcalls.extend(rounding_libcall(op)),
+                            _ => {}
+                        }
+                    }
+                }
+                // The body was validated, but err on the side of reporting every symbol it could
+                // reference.
+                None => {
+                    runtime_funcs.insert(RuntimeFunc::MemSize);
+                    runtime_funcs.insert(RuntimeFunc::MemGrow);
+                    if self.codegen_context.unreachable_handler() {
+                        runtime_funcs.insert(RuntimeFunc::UnreachableHandler);
+                    }
+                    if rounding_libcalls {
+                        libcalls.extend(ROUNDING_LIBCALLS.iter().copied());
+                    }
+                }
+            }
+        }
+
+        let deterministic_libcalls = self.codegen_context.deterministic_libcalls;
+        let mut symbols = runtime_funcs
+            .into_iter()
+            .map(|runtime_func| {
+                self.decls
+                    .get_runtime(runtime_func)
+                    .expect("runtime functions are declared")
+                    .name
+                    .symbol()
+                    .to_owned()
+            })
+            .chain(
+                libcalls
+                    .into_iter()
+                    .map(|libcall| libcall_name(libcall, deterministic_libcalls)),
+            )
+            .collect::<Vec<_>>();
+        if self.external_stack_probe && !self.forbid_stack_probe {
+            symbols.push(stack_probe::STACK_PROBE_SYM.to_owned());
+        }
+        symbols.sort();
+        symbols
+    }
+
+    /// Describe every table in the module, imported tables first, in table index order.
+    pub fn tables(&self) -> Vec<TableInfo<'_>> {
+        self.decls
+            .info
+            .tables
+            .keys()
+            .map(|table_index| {
+                let decl = self
+                    .decls
+                    .get_table(table_index)
+                    .expect("decl exists for table");
+                TableInfo::new(table_index, decl)
+            })
+            .collect()
+    }
+
+    fn get_local_count(body: &FunctionBody, name: &str) -> Result<u32, Error> {
+        let error_mapper = |e| Error::FunctionTranslation {
+            symbol: name.to_string(),
+            source: Box::new(Error::from(e)),
+        };
+        Ok(body
+            .get_locals_reader()
+            .map_err(error_mapper)?
+            .into_iter()
+            .map(|result| result.map_err(error_mapper))
+            .collect::<Result<Vec<(u32, _)>, Error>>()?
+            .into_iter()
+            .map(|(count, _ty)| count)
+            .sum())
+    }
+
+    fn check_local_count(body: &FunctionBody, name: &str, limit: Option<u32>) -> Result<(), Error> {
+        if let Some(limit) = limit {
+            let count = Self::get_local_count(body, name)?;
+            if count > limit {
+                return Err(Error::TooManyLocals {
+                    symbol: name.to_string(),
+                    count,
+                    limit,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn object_file(mut self) -> Result<ObjectFile, Error> {
+        if let Some(path) = self.split_debug_info.take() {
+            let (obj, debug_obj) = self.object_file_split()?;
+            debug_obj.write(path)?;
+            return Ok(obj);
+        }
+        let prev = self.prev_state.clone();
+        let (obj, _state) = self.build_object_file(prev.as_deref())?;
+        Ok(obj)
+    }
+
+    /// Compile to an object file for production, and a separate debug object holding the
+    /// debugging information for its code. See `DebugObject` for what that includes.
+    ///
+    /// The production object is stripped of the symbols that carry names from the module's name
+    /// section: the local symbols of functions and of their trap tables, which move to the debug
+    /// object. Relocations that referred to them refer to their sections instead, so the code is
+    /// the same. The production object also has no address maps, even with
+    /// `CompilerBuilder::emit_address_map`, and is otherwise the same as `object_file` would
+    /// produce, plus a GNU build ID note. `ObjectFile::self_check` still checks the stripped
+    /// functions against the function manifest. The build ID is computed from the rest of the
+    /// production object's contents, and is recorded in the debug object as well, so a debug
+    /// object can be matched to the production object it belongs to. Only ELF targets are
+    /// supported.
+    pub fn object_file_split(mut self) -> Result<(ObjectFile, DebugObject), Error> {
+        use object::read::{Object as _, ObjectSymbol as _};
+
+        if self.target.binary_format != BinaryFormat::Elf {
+            return Err(Error::Unsupported(format!(
+                "split debug objects for {} targets",
+                self.target
+            )));
+        }
+
+        let prefix = self.symbol_prefix.clone().unwrap_or_default();
+        let mut func_indices = self.function_bodies.keys().copied().collect::<Vec<_>>();
+        func_indices.sort();
+        let functions = func_indices
+            .into_iter()
+            .map(|func_index| {
+                let func = self
+                    .decls
+                    .get_func(func_index)
+                    .expect("defined function is declared");
+                let name = self
+                    .decls
+                    .info
+                    .function_names
+                    .get(func_index)
+                    .filter(|name| !name.is_empty())
+                    .map(|name| (*name).to_owned());
+                (func.name.symbol().to_owned(), name)
+            })
+            .collect::<Vec<_>>();
+
+        let address_maps = Arc::new(Mutex::new(Vec::new()));
+        self.codegen_context
+            .collect_address_maps(address_maps.clone());
+        self.split_debug_info = None;
+        let obj = self.object_file()?;
+
+        // Where each function's code is, read before its symbol is stripped.
+        let bytes = obj.bytes()?;
+        let file = object::File::parse(&bytes).map_err(|e| Error::Input(e.to_string()))?;
+        let code = file
+            .symbols()
+            .filter(|symbol| symbol.kind() == object::SymbolKind::Text && !symbol.is_undefined())
+            .filter_map(|symbol| {
+                let name = symbol.name().ok()?;
+                Some((name.to_owned(), (symbol.address(), symbol.size())))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut obj = obj.strip_local_functions()?;
+        let build_id = obj.add_build_id()?;
+
+        let mut address_maps = address_maps
+            .lock()
+            .unwrap()
+            .drain(..)
+            .collect::<HashMap<_, _>>();
+        let functions = functions
+            .into_iter()
+            .map(|(symbol, name)| {
+                let address_map = address_maps.remove(&symbol).unwrap_or_default();
+                let symbol = format!("{}{}", prefix, symbol);
+                let (code_offset, code_len) = code.get(&symbol).copied().unwrap_or_default();
+                DebugFunction {
+                    symbol,
+                    code_offset,
+                    code_len,
+                    name,
+                    address_map,
+                }
+            })
+            .collect();
+
+        let debug_obj =
+            DebugObject::new(build_id, file.architecture(), file.endianness(), functions);
+        Ok((obj, debug_obj))
+    }
+
+    /// Compile to an object file, reusing IR translated in the compilation whose state was given
+    /// to `CompilerBuilder::prev_state` for any function whose wasm body is unchanged.
+    ///
+    /// The returned `PrevState` retains the IR for every function in this module, and should be
+    /// passed to the next incremental compilation. The object file is the same as `object_file`
+    /// would produce.
+    ///
+    /// Like `object_file`, this consumes the compiler, since the Cranelift module that the object
+    /// is emitted from can only be finished once; a dev server creates a new `Compiler` for each
+    /// recompile. The previous state is taken by the builder rather than by this method, so that
+    /// `object_file` and `object_file_split` reuse it too.
+    pub fn object_file_incremental(self) -> Result<(ObjectFile, PrevState), Error> {
+        let prev = self.prev_state.clone().unwrap_or_default();
+        let (obj, state) = self.build_object_file(Some(&prev))?;
+        Ok((obj, state.expect("incremental compilation produces state")))
+    }
+
+    fn translate_function(
+        decls: &ModuleDecls<'_>,
+        codegen_context: &CodegenContext,
+        count_instructions: bool,
+        interrupt_check_period: Option<u32>,
+        unique_func_ix: UniqueFuncIndex,
+        validator: &mut FuncValidator<ValidatorResources>,
+        func_body: &FunctionBody<'_>,
+    ) -> Result<ir::Function, Error> {
+        let func = decls
+            .get_func(unique_func_ix)
+            .expect("decl exists for func body");
+        let arg_count = func.signature.params.len() as u32;
+        let local_count = Self::get_local_count(func_body, func.name.symbol())?;
+        let mut func_info = FuncInfo::new(
+            decls,
+            codegen_context,
+            count_instructions,
+            interrupt_check_period,
+            codegen_context.coverage_for(unique_func_ix),
+            arg_count,
+            local_count,
+        );
+        let mut clif_func = ir::Function::new();
+        clif_func.name = func.name.as_externalname();
+        clif_func.signature = func.signature.clone();
+
+        FuncTranslator::new()
+            .translate_body(validator, func_body.clone(), &mut clif_func, &mut func_info)
+            .map_err(|source| Error::FunctionTranslation {
+                symbol: func.name.symbol().to_string(),
+                source: Box::new(func_info.translation_error(source)),
+            })?;
+
+        Ok(clif_func)
+    }
+
+    fn build_object_file(
+        self,
+        prev: Option<&PrevState>,
+    ) -> Result<(ObjectFile, Option<PrevState>), Error> {
+        let mut function_manifest_ctx = ClifDataContext::new();
+        let mut function_manifest_bytes = Cursor::new(Vec::new());
+
+        let module_data_bytes = self.module_data()?.serialize()?;
+        let module_data_len = module_data_bytes.len();
+
+        if self.report_recursion {
+            for cycle in self.call_graph()?.recursion_cycles() {
+                let symbols = cycle
+                    .into_iter()
+                    .map(|func_index| {
+                        let func = self
+                            .decls
+                            .get_func(func_index)
+                            .expect("decl exists for func body");
+                        func.name.symbol().to_owned()
+                    })
+                    .collect::<Vec<_>>();
+                log::warn!("recursion among functions: {}", symbols.join(", "));
+            }
+        }
+
+        let mut decls = self.decls;
+        let codegen_context = self.codegen_context;
+        let count_instructions = self.count_instructions;
+        let interrupt_check_period = self.interrupt_check_period;
+        let trap_uninit_table = self.trap_uninit_table;
+        let codegen_checks = self.codegen_checks;
+        let function_opt_levels = self.function_opt_levels;
+        let module_symbol = self.module_symbol;
+        let module_data_symbol = module_data_sym(&module_symbol);
+        let symbol_prefix = self.symbol_prefix;
+        let (elf_os_abi, elf_flags) = (self.elf_os_abi, self.elf_flags);
+        let forbid_stack_probe = self.forbid_stack_probe;
+        let external_stack_probe = self.external_stack_probe;
+        let cancellation_token = self.cancellation_token;
+        let max_total_code_size = self.max_total_code_size;
+        let code_padding = match self.pad_code_to {
+            Some(size) => Some((size, trap_fill(&self.target)?)),
+            None => None,
+        };
+        let max_locals_per_function = self.max_locals_per_function;
+        let table_overrides = self.table_overrides;
+        let weak_imports = self.weak_imports;
+        let function_order = self
+            .function_order
+            .iter()
+            .enumerate()
+            .map(|(position, symbol)| (symbol.as_str(), position))
+            .collect::<HashMap<_, _>>();
+        let report_const_fold = self.report_const_fold && !matches!(self.opt_level, OptLevel::None);
+        let record_compile_times = self.record_compile_times;
+        let yield_name = if self.report_unbounded_loops {
+            let yield_decl = decls.get_runtime(RuntimeFunc::YieldAtBoundExpiration)?;
+            Some(yield_decl.name.as_externalname())
+        } else {
+            None
+        };
+        if self.separate_data_segments {
+            write_data_segments(&codegen_context, &data_segments(&decls.info))?;
+        }
+        if self.bake_memory_image {
+            if let Some(heap) = decls.get_heap() {
+                write_heap_image(&codegen_context, &decls.info, heap)?;
+            }
+        }
+        for (key, value) in self.custom_metadata.iter() {
+            write_custom_metadata(&codegen_context, key, value)?;
+        }
+        if self.emit_sig_table {
+            let sig_table = signature_table(
+                &decls.info,
+                self.function_bodies.values().map(|(_validator, body)| body),
+            )?;
+            codegen_context.define_local_data(
+                SIGNATURE_TABLE_SYM,
+                SignatureTableEntry::serialize(&sig_table)?,
+            )?;
+        }
+
+        let environment = incremental::environment_hash(
+            &decls,
+            &codegen_context,
+            &module_data_bytes,
+            count_instructions,
+            interrupt_check_period,
+            self.custom_metadata
+                .get(COVERAGE_METADATA_KEY)
+                .map(|layout| layout.as_slice()),
+        );
+
+        let mut compiled_funcs = self
+            .function_bodies
+            .into_par_iter()
+            .map(|(unique_func_ix, (mut validator, func_body))| {
+                if let Some(token) = cancellation_token.as_ref() {
+                    if token.load(Ordering::Relaxed) {
+                        return Err(Error::Cancelled);
+                    }
+                }
+                let started = Instant::now();
+
+                let func = decls
+                    .get_func(unique_func_ix)
+                    .expect("decl exists for func body");
+                let symbol = func.name.symbol();
+                Self::check_local_count(&func_body, symbol, max_locals_per_function)?;
+
+                let mut clif_context = ClifContext::new();
+                let mut retained = None;
+                if let Some(prev) = prev {
+                    let body_hash = incremental::body_hash(&func_body)?;
+                    let reused =
+                        prev.reuse(environment, symbol, body_hash, &decls, &codegen_context)?;
+                    let was_reused = reused.is_some();
+                    clif_context.func = match reused {
+                        Some(reused) => reused,
+                        None => Self::translate_function(
+                            &decls,
+                            &codegen_context,
+                            count_instructions,
+                            interrupt_check_period,
+                            unique_func_ix,
+                            &mut validator,
+                            &func_body,
+                        )?,
+                    };
+                    retained = Some((
+                        RetainedFunction::new(&codegen_context, body_hash, &clif_context.func),
+                        was_reused,
+                    ));
+                } else {
+                    clif_context.func = Self::translate_function(
+                        &decls,
+                        &codegen_context,
+                        count_instructions,
+                        interrupt_check_period,
+                        unique_func_ix,
+                        &mut validator,
+                        &func_body,
+                    )?;
+                }
+
+                if let Some(yield_name) = yield_name.as_ref() {
+                    let unbounded = loops_without_call(&clif_context.func, yield_name);
+                    if unbounded > 0 {
+                        log::warn!(
+                            "{} contains {} loop(s) that never check the instruction count bound",
+                            symbol,
+                            unbounded
+                        );
+                    }
+                }
+
+                if report_const_fold {
+                    let (before, after) =
+                        codegen_context.const_fold_counts(&clif_context.func, symbol)?;
+                    if before > after && (before - after) * 10 >= before {
+                        log::info!(
+                            "{}: constant folding reduced {} instructions to {}",
+                            symbol,
+                            before,
+                            after
+                        );
+                    }
+                }
+
+                for check in codegen_checks.iter() {
+                    check(&clif_context.func).map_err(|reason| Error::CodegenCheckFailed {
+                        symbol: symbol.to_string(),
+                        reason,
+                    })?;
+                }
+
+                let func_id = func.name.as_funcid().unwrap();
+                let compiled = codegen_context.compile(
+                    &mut clif_context,
+                    symbol,
+                    forbid_stack_probe,
+                    function_opt_levels.get(&unique_func_ix).copied(),
+                )?;
+
+                // 0 in the manifest means no time was recorded, so a recorded time is never 0.
+                let compile_nanos = if record_compile_times {
+                    Some((started.elapsed().as_nanos() as u64).max(1))
+                } else {
+                    None
+                };
+
+                Ok((
+                    unique_func_ix,
+                    func_id,
+                    compiled,
+                    (symbol, retained, compile_nanos),
+                ))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        // Functions are compiled in parallel, but laid out in the object in the order they are
+        // defined, so define them one at a time in the requested order.
+        compiled_funcs.sort_by_key(|(unique_func_ix, _, _, (symbol, _, _))| {
+            (
+                function_order.get(symbol).copied().unwrap_or(usize::MAX),
+                *unique_func_ix,
+            )
+        });
+
+        let mut state = prev.map(|_| PrevState::new(environment));
+        let mut function_map = HashMap::new();
+        let mut compile_times = HashMap::new();
+        for (_, func_id, compiled, (symbol, retained, compile_nanos)) in compiled_funcs.into_iter()
+        {
+            let trap_metadata = codegen_context.define(func_id, symbol, compiled)?;
+            function_map.insert(func_id, trap_metadata);
+            if let Some(compile_nanos) = compile_nanos {
+                compile_times.insert(func_id, compile_nanos);
+            }
+            if let (Some(state), Some((retained, reused))) = (state.as_mut(), retained) {
+                state.insert(symbol.to_string(), retained, reused);
+            }
+        }
+
+        // Now that we've defined all functions, we know what trampolines must also be created.
+        let trampoline_metas = codegen_context
+            .trampolines()
+            .par_iter()
+            .map(|(hostcall_name, (trampoline_id, hostcall_func_index))| {
+                let meta = synthesize_trampoline(
+                    &decls,
+                    &codegen_context,
+                    hostcall_name,
+                    *trampoline_id,
+                    *hostcall_func_index,
+                )?;
+                Ok((*trampoline_id, meta))
+            })
+            .collect::<Result<Vec<(FuncId, TrapMetadata)>, Error>>()?;
+
+        for (id, m) in trampoline_metas.into_iter() {
+            function_map.insert(id, m);
+        }
+
+        // Write out the stack probe and associated data, unless the host provides it.
+        if external_stack_probe {
+            stack_probe::declare(&mut decls, &codegen_context, ClifLinkage::Import)?;
+        } else {
+            let probe_id = stack_probe::declare(&mut decls, &codegen_context, ClifLinkage::Local)?;
+            let probe_func = decls.get_func(probe_id).unwrap();
+            let probe_func_id = probe_func.name.as_funcid().unwrap();
+            let compiled = codegen_context.module().define_function_bytes(
+                probe_func_id,
+                stack_probe::STACK_PROBE_BINARY,
+                &[],
+            )?;
+
+            let func_size = compiled.size;
+            let stack_probe_traps: TrapSites = stack_probe::trap_sites().into();
+
+            let trap_data_id =
+                stack_probe_traps.write(&codegen_context, probe_func.name.symbol())?;
+
+            function_map.insert(
+                probe_func_id,
+                TrapMetadata {
+                    func_size,
+                    trap_data_id,
+                    trap_len: stack_probe_traps.len(),
+                },
+            );
+        }
+
+        for (func_index, (module, field)) in decls.info.imported_funcs.iter() {
+            if weak_imports.contains(&format!("{}::{}", module, field)) {
+                let (id, meta) = synthesize_weak_import(&decls, &codegen_context, func_index)?;
+                function_map.insert(id, meta);
+            }
+        }
+
+        let uninit_table_entry = if trap_uninit_table {
+            let (id, meta) = synthesize_uninit_table_entry(&codegen_context)?;
+            function_map.insert(id, meta);
+            Some(id)
+        } else {
+            None
+        };
+
+        let module_data_id = write_module_data(
+            &codegen_context,
+            &module_data_symbol,
+            module_data_bytes.clone(),
+        )?;
+        let (table_id, table_len) = write_table_data(
+            &codegen_context,
+            &decls,
+            uninit_table_entry,
+            &table_overrides,
+        )?;
+
+        // The function manifest must be written out in the order that
+        // cranelift-module is going to lay out the functions.  We also
+        // have to be careful to write function manifest entries for VM
+        // functions, which will not be represented in function_map.
+
+        let ids: Vec<FuncId> = codegen_context
+            .module()
+            .declarations()
+            .get_functions()
+            .map(|(func_id, _f)| func_id)
+            .collect();
+        let function_manifest_len = ids.len();
+        // The runtime refers to functions in the manifest by a `u32` index. Validation limits a
+        // module to far fewer functions than that, and lucetc only adds a handful of its own.
+        debug_assert!(function_manifest_len <= u32::MAX as usize);
+
+        let mut manifest_records = Vec::with_capacity(function_manifest_len);
+        for func_id in ids {
+            let metadata = function_map.get(&func_id);
+            let compile_nanos = compile_times.get(&func_id).copied();
+            write_function_spec(
+                &codegen_context,
+                &mut function_manifest_ctx,
+                &mut function_manifest_bytes,
+                func_id,
+                metadata,
+                compile_nanos,
+            )?;
+            manifest_records.push(manifest_record(
+                &codegen_context,
+                func_id,
+                metadata,
+                compile_nanos,
+            ));
+        }
+
+        if let Some(budget) = max_total_code_size {
+            let actual = manifest_records
+                .iter()
+                .map(|record| record.code_len as usize)
+                .sum();
+            if actual > budget {
+                return Err(Error::CodeSizeBudgetExceeded { actual, budget });
+            }
+        }
+
+        function_manifest_ctx.define(function_manifest_bytes.into_inner().into());
+        let manifest_data_id = codegen_context.module().declare_data(
+            FUNCTION_MANIFEST_SYM,
+            ClifLinkage::Local,
+            false,
+            false,
+        )?;
+        codegen_context
+            .module()
+            .define_data(manifest_data_id, &function_manifest_ctx)?;
+
+        // Write out the structure tying everything together.
+        let mut native_data =
+            Cursor::new(Vec::with_capacity(std::mem::size_of::<SerializedModule>()));
+        let mut native_data_ctx = ClifDataContext::new();
+        let native_data_id = codegen_context.module().declare_data(
+            &module_symbol,
+            ClifLinkage::Export,
+            false,
+            false,
+        )?;
+
+        let version =
+            VersionInfo::current(include_str!(concat!(env!("OUT_DIR"), "/commit_hash")).as_bytes());
+
+        version.write_to(&mut native_data)?;
+
+        fn write_slice(
+            codegen_context: &CodegenContext,
+            mut ctx: &mut ClifDataContext,
+            bytes: &mut Cursor<Vec<u8>>,
             id: DataId,
             len: usize,
         ) -> Result<(), Error> {
@@ -546,9 +2942,75 @@ impl<'a> Compiler<'a> {
             .module()
             .define_data(native_data_id, &native_data_ctx)?;
 
-        let obj = ObjectFile::new(codegen_context.finish())?;
+        let mut obj = ObjectFile::new(codegen_context.finish())?
+            .with_contents(
+                manifest_records,
+                module_symbol,
+                module_data_symbol,
+                module_data_bytes,
+            )
+            .with_elf_header(elf_os_abi, elf_flags);
+        if let Some((size, fill)) = code_padding {
+            obj.pad_code_to(size, fill)?;
+        }
+        if let Some(prefix) = symbol_prefix {
+            obj = obj.with_symbol_prefix(&prefix)?;
+        }
+
+        Ok((obj, state))
+    }
+
+    /// Compile each function defined by the module to machine code, without assembling them
+    /// into an object file. Functions are returned in the order they are defined in the module.
+    ///
+    /// This is a lower-level alternative to `object_file` for embedders that load code
+    /// themselves. Relocations refer to symbols by the names they have in the object, and the
+    /// loader must provide any that aren't among the returned functions: imports, the stack
+    /// probe, and hostcall trampolines, which are only synthesized by `object_file`.
+    pub fn compiled_functions(self) -> Result<Vec<CompiledFunction>, Error> {
+        let decls = &self.decls;
+        let codegen_context = &self.codegen_context;
+        let count_instructions = self.count_instructions;
+        let interrupt_check_period = self.interrupt_check_period;
+        let forbid_stack_probe = self.forbid_stack_probe;
+        let max_locals_per_function = self.max_locals_per_function;
+        let function_opt_levels = &self.function_opt_levels;
+
+        let mut compiled_funcs = self
+            .function_bodies
+            .into_par_iter()
+            .map(|(unique_func_ix, (mut validator, func_body))| {
+                let func = decls
+                    .get_func(unique_func_ix)
+                    .expect("decl exists for func body");
+                let symbol = func.name.symbol();
+                Self::check_local_count(&func_body, symbol, max_locals_per_function)?;
+
+                let mut clif_context = ClifContext::new();
+                clif_context.func = Self::translate_function(
+                    decls,
+                    codegen_context,
+                    count_instructions,
+                    interrupt_check_period,
+                    unique_func_ix,
+                    &mut validator,
+                    &func_body,
+                )?;
+                let compiled = codegen_context.compile(
+                    &mut clif_context,
+                    symbol,
+                    forbid_stack_probe,
+                    function_opt_levels.get(&unique_func_ix).copied(),
+                )?;
+                Ok((unique_func_ix, codegen_context.export(symbol, compiled)))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
 
-        Ok(obj)
+        compiled_funcs.sort_by_key(|(unique_func_ix, _)| *unique_func_ix);
+        Ok(compiled_funcs
+            .into_iter()
+            .map(|(_, compiled)| compiled)
+            .collect())
     }
 
     pub fn cranelift_funcs(self) -> Result<CraneliftFuncs, Error> {
@@ -560,12 +3022,15 @@ impl<'a> Compiler<'a> {
                 .decls
                 .get_func(unique_func_ix)
                 .expect("decl exists for func body");
+            Self::check_local_count(&body, func.name.symbol(), self.max_locals_per_function)?;
             let arg_count = func.signature.params.len() as u32;
             let local_count = Self::get_local_count(&body, func.name.symbol())?;
             let mut func_info = FuncInfo::new(
                 &self.decls,
                 &self.codegen_context,
                 self.count_instructions,
+                self.interrupt_check_period,
+                self.codegen_context.coverage_for(unique_func_ix),
                 arg_count,
                 local_count,
             );
@@ -582,7 +3047,7 @@ impl<'a> Compiler<'a> {
                 )
                 .map_err(|source| Error::FunctionTranslation {
                     symbol: func.name.symbol().to_string(),
-                    source: Box::new(Error::from(source)),
+                    source: Box::new(func_info.translation_error(source)),
                 })?;
 
             funcs.insert(func.name.clone(), clif_context.func);
@@ -594,7 +3059,7 @@ impl<'a> Compiler<'a> {
                 self.variant,
                 self.opt_level,
                 &self.cpu_features,
-                self.canonicalize_nans,
+                &self.isa_flags,
             )?,
         ))
     }
@@ -604,10 +3069,19 @@ impl<'a> Compiler<'a> {
         variant: BackendVariant,
         opt_level: OptLevel,
         cpu_features: &CpuFeatures,
-        canonicalize_nans: bool,
+        flags: &IsaFlags,
     ) -> Result<Box<dyn TargetIsa>, Error> {
+        let IsaFlags {
+            canonicalize_nans,
+            preserve_frame_pointer,
+            spectre_mitigation,
+            exact_div_traps,
+            machine_code_cfg_info,
+            enable_simd,
+            max_stack_size,
+        } = *flags;
         let mut flags_builder = settings::builder();
-        let isa_builder = cpu_features.isa_builder(target, variant)?;
+        let isa_builder = cpu_features.isa_builder(target.clone(), variant)?;
         let enable_verifier = if cfg!(debug_assertions) {
             "true"
         } else {
@@ -616,15 +3090,1102 @@ impl<'a> Compiler<'a> {
         flags_builder
             .set("enable_verifier", enable_verifier)
             .unwrap();
+        // Objects are always position-independent. Jump tables for `br_table` are emitted inline
+        // as offsets relative to the table itself, so they need no relocations either way.
         flags_builder.enable("is_pic").unwrap();
         flags_builder.set("opt_level", opt_level.to_flag()).unwrap();
         if canonicalize_nans {
             flags_builder.enable("enable_nan_canonicalization").unwrap();
         }
+        if exact_div_traps {
+            flags_builder.enable("avoid_div_traps").unwrap();
+        }
+        let spectre_mitigation = if spectre_mitigation { "true" } else { "false" };
+        flags_builder
+            .set("enable_heap_access_spectre_mitigation", spectre_mitigation)
+            .unwrap();
+        if machine_code_cfg_info {
+            flags_builder.enable("machine_code_cfg_info").unwrap();
+        }
+        if let Some(size) = max_stack_size {
+            if size == 0 {
+                return Err(Error::Input(
+                    "maximum stack size must be nonzero".to_owned(),
+                ));
+            }
+            let size_log2 = (std::mem::size_of::<usize>() * 8) as u32 - 1 - size.leading_zeros();
+            flags_builder
+                .set("probestack_size_log2", &size_log2.min(12).to_string())
+                .unwrap();
+        }
+        if enable_simd {
+            if target.architecture == Architecture::X86_64 {
+                cpu_features.check_simd()?;
+            }
+            flags_builder.enable("enable_simd").unwrap();
+        }
+        if !preserve_frame_pointer {
+            return Err(Error::Unsupported(
+                "frame pointer omission: Cranelift always keeps a frame pointer".to_owned(),
+            ));
+        }
         Ok(isa_builder.finish(settings::Flags::new(flags_builder)))
     }
 }
 
+/// The size in bytes of the frame of a function that has been compiled in `clif`: its stack
+/// slots and spill slots, not including saved registers or the return address.
+fn frame_size(clif: &ClifContext) -> u32 {
+    match clif.mach_compile_result.as_ref() {
+        Some(result) => result.frame_size,
+        // The old backend records the frame layout with the stack slots.
+        None => clif
+            .func
+            .stack_slots
+            .layout_info
+            .map_or(0, |layout| layout.frame_size),
+    }
+}
+
+/// An instruction that traps on `target`, to fill the padding added by `pad_code_to`.
+fn trap_fill(target: &Triple) -> Result<&'static [u8], Error> {
+    match target.architecture {
+        // int3
+        Architecture::X86_64 => Ok(&[0xcc]),
+        // udf #0
+        Architecture::Aarch64(_) => Ok(&[0x00, 0x00, 0x00, 0x00]),
+        _ => Err(Error::Unsupported(format!(
+            "padding code for target {}",
+            target
+        ))),
+    }
+}
+
+/// Check that each expected export is a function with the expected signature, reporting every
+/// unmet expectation.
+fn check_expected_exports(
+    info: &ModuleInfo<'_>,
+    expected_exports: &[(String, WasmFuncType)],
+) -> Result<(), Error> {
+    let mut errors = expected_exports
+        .iter()
+        .filter_map(|(name, expected)| {
+            let func_index = info
+                .functions
+                .iter()
+                .find(|(_, func)| func.export_names.contains(&name.as_str()))
+                .map(|(func_index, _)| func_index);
+            let func_index = match func_index {
+                Some(func_index) => func_index,
+                None => return Some(Error::MissingExport { name: name.clone() }),
+            };
+            let (_signature, actual) = info.signature_for_function(func_index);
+            if actual.params == expected.params && actual.returns == expected.returns {
+                None
+            } else {
+                Some(Error::ExportSignatureMismatch {
+                    name: name.clone(),
+                    expected: expected.clone(),
+                    actual: actual.clone(),
+                })
+            }
+        })
+        .collect::<Vec<_>>();
+    match errors.len() {
+        0 => Ok(()),
+        1 => Err(errors.remove(0)),
+        _ => Err(Error::ExportExpectations(errors)),
+    }
+}
+
+/// Check that the module only imports from the allowed modules, reporting each other module it
+/// imports from once.
+fn check_import_modules(info: &ModuleInfo<'_>, allowed: &HashSet<String>) -> Result<(), Error> {
+    let import_modules = info
+        .imported_funcs
+        .values()
+        .chain(info.imported_globals.values())
+        .chain(info.imported_tables.values())
+        .chain(info.imported_memories.values())
+        .map(|(module, _field)| *module);
+    let mut disallowed: Vec<&str> = Vec::new();
+    for module in import_modules {
+        if !allowed.contains(module) && !disallowed.contains(&module) {
+            disallowed.push(module);
+        }
+    }
+    let mut errors = disallowed
+        .into_iter()
+        .map(|module| Error::DisallowedImportModule {
+            module: module.to_owned(),
+        })
+        .collect::<Vec<_>>();
+    match errors.len() {
+        0 => Ok(()),
+        1 => Err(errors.remove(0)),
+        _ => Err(Error::ImportModuleViolations(errors)),
+    }
+}
+
+/// Reject a module that resizes its memories or queries their size, which the runtime does in
+/// 64KiB pages, when it is compiled with another page size.
+fn check_fixed_memory_size(
+    function_bodies: &HashMap<
+        UniqueFuncIndex,
+        (FuncValidator<ValidatorResources>, FunctionBody<'_>),
+    >,
+    page_size: u32,
+) -> Result<(), Error> {
+    for (_validator, body) in function_bodies.values() {
+        for op in body.get_operators_reader()?.into_iter() {
+            match op? {
+                Operator::MemorySize { .. } | Operator::MemoryGrow { .. } => {
+                    return Err(Error::Unsupported(format!(
+                        "memory.size and memory.grow with a page size of {}: the runtime only \
+                         supports {}",
+                        page_size, WASM_PAGE_SIZE
+                    )));
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reject a module with a function whose symbol is one lucetc reserves for the runtime, such as
+/// an import bound to the stack probe's symbol, or starts with one of the prefixes of the
+/// symbols lucetc emits for each function, table, or metadata key. Otherwise the guest's
+/// function and lucetc's definition would be merged into one symbol, or fail to be declared with
+/// a confusing error.
+fn check_reserved_symbols(decls: &ModuleDecls<'_>, builder: &CompilerBuilder) -> Result<(), Error> {
+    let module_data_symbol = module_data_sym(&builder.module_symbol);
+    let coverage_counters_symbol = builder.coverage_counters_symbol();
+    let reserved = [
+        stack_probe::STACK_PROBE_SYM,
+        builder.module_symbol.as_str(),
+        module_data_symbol.as_str(),
+        FUNCTION_MANIFEST_SYM,
+        TABLE_SYM,
+        UNINIT_TABLE_ENTRY_SYM,
+        DATA_SEGMENTS_SYM,
+        HEAP_IMAGE_SYM,
+        SIGNATURE_TABLE_SYM,
+        COVERAGE_COUNTERS_SYM,
+        coverage_counters_symbol.as_str(),
+    ];
+    let reserved_prefixes = [
+        TABLE_DATA_PREFIX,
+        TRAP_TABLE_PREFIX,
+        ADDRESS_MAP_PREFIX,
+        CFG_MAP_PREFIX,
+        CUSTOM_METADATA_PREFIX,
+    ];
+    for func_index in decls.info.functions.keys() {
+        let func = decls.get_func(func_index).expect("decl exists for func");
+        let symbol = func.name.symbol();
+        if reserved.contains(&symbol)
+            || reserved_prefixes
+                .iter()
+                .any(|prefix| symbol.starts_with(prefix))
+        {
+            return Err(Error::ReservedSymbolConflict {
+                symbol: symbol.to_owned(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Reject a module whose functions use floating-point values. See
+/// `CompilerBuilder::forbid_float`.
+///
+/// A float parameter or result is reported at the start of the function's body, or at offset 0
+/// for an imported function.
+fn check_no_floats(
+    decls: &ModuleDecls<'_>,
+    function_bodies: &HashMap<
+        UniqueFuncIndex,
+        (FuncValidator<ValidatorResources>, FunctionBody<'_>),
+    >,
+) -> Result<(), Error> {
+    for func_index in decls.info.functions.keys() {
+        let func = decls.get_func(func_index).expect("decl exists for func");
+        let float_not_allowed = |offset| Error::FloatNotAllowed {
+            symbol: func.name.symbol().to_owned(),
+            offset,
+        };
+        let body = function_bodies
+            .get(&func_index)
+            .map(|(_validator, body)| body);
+
+        if func
+            .signature
+            .params
+            .iter()
+            .chain(func.signature.returns.iter())
+            .any(|param| param.value_type.is_float())
+        {
+            let offset = body.map_or(0, |body| body.get_binary_reader().original_position());
+            return Err(float_not_allowed(offset));
+        }
+
+        let body = match body {
+            Some(body) => body,
+            None => continue,
+        };
+        let mut locals = body.get_locals_reader()?;
+        for _ in 0..locals.get_count() {
+            let offset = locals.original_position();
+            let (_count, ty) = locals.read()?;
+            if ty == Type::F32 || ty == Type::F64 {
+                return Err(float_not_allowed(offset));
+            }
+        }
+        for op in body.get_operators_reader()?.into_iter_with_offsets() {
+            let (op, offset) = op?;
+            if uses_floats(decls, &op) {
+                return Err(float_not_allowed(offset));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether `op` operates on floating-point values, including calls and blocks whose signatures
+/// have them.
+fn uses_floats(decls: &ModuleDecls<'_>, op: &Operator<'_>) -> bool {
+    let is_float = |ty: &Type| *ty == Type::F32 || *ty == Type::F64;
+    let signature_uses_floats = |signature: &ir::Signature| {
+        signature
+            .params
+            .iter()
+            .chain(signature.returns.iter())
+            .any(|param| param.value_type.is_float())
+    };
+    let type_uses_floats = |index: u32| {
+        decls
+            .get_signature(TypeIndex::from_u32(index))
+            .map_or(false, signature_uses_floats)
+    };
+    match op {
+        Operator::GlobalGet { global_index } | Operator::GlobalSet { global_index } => decls
+            .get_global(GlobalIndex::from_u32(*global_index))
+            .map_or(false, |global| global.entity.ty.is_float()),
+        Operator::Call { function_index } | Operator::ReturnCall { function_index } => decls
+            .info
+            .function_mapping
+            .get(FuncIndex::from_u32(*function_index))
+            .and_then(|func_index| decls.get_func(*func_index))
+            .map_or(false, |func| signature_uses_floats(func.signature)),
+        Operator::CallIndirect { index, .. } | Operator::ReturnCallIndirect { index, .. } => {
+            type_uses_floats(*index)
+        }
+        Operator::Block { ty } | Operator::Loop { ty } | Operator::If { ty } => match ty {
+            TypeOrFuncType::Type(ty) => is_float(ty),
+            TypeOrFuncType::FuncType(index) => type_uses_floats(*index),
+        },
+        Operator::TypedSelect { ty } => is_float(ty),
+        Operator::F32Load { .. }
+        | Operator::F64Load { .. }
+        | Operator::F32Store { .. }
+        | Operator::F64Store { .. }
+        | Operator::F32Const { .. }
+        | Operator::F64Const { .. }
+        | Operator::F32Eq { .. }
+        | Operator::F32Ne { .. }
+        | Operator::F32Lt { .. }
+        | Operator::F32Gt { .. }
+        | Operator::F32Le { .. }
+        | Operator::F32Ge { .. }
+        | Operator::F64Eq { .. }
+        | Operator::F64Ne { .. }
+        | Operator::F64Lt { .. }
+        | Operator::F64Gt { .. }
+        | Operator::F64Le { .. }
+        | Operator::F64Ge { .. }
+        | Operator::F32Abs { .. }
+        | Operator::F32Neg { .. }
+        | Operator::F32Ceil { .. }
+        | Operator::F32Floor { .. }
+        | Operator::F32Trunc { .. }
+        | Operator::F32Nearest { .. }
+        | Operator::F32Sqrt { .. }
+        | Operator::F32Add { .. }
+        | Operator::F32Sub { .. }
+        | Operator::F32Mul { .. }
+        | Operator::F32Div { .. }
+        | Operator::F32Min { .. }
+        | Operator::F32Max { .. }
+        | Operator::F32Copysign { .. }
+        | Operator::F64Abs { .. }
+        | Operator::F64Neg { .. }
+        | Operator::F64Ceil { .. }
+        | Operator::F64Floor { .. }
+        | Operator::F64Trunc { .. }
+        | Operator::F64Nearest { .. }
+        | Operator::F64Sqrt { .. }
+        | Operator::F64Add { .. }
+        | Operator::F64Sub { .. }
+        | Operator::F64Mul { .. }
+        | Operator::F64Div { .. }
+        | Operator::F64Min { .. }
+        | Operator::F64Max { .. }
+        | Operator::F64Copysign { .. }
+        | Operator::I32TruncF32S { .. }
+        | Operator::I32TruncF32U { .. }
+        | Operator::I32TruncF64S { .. }
+        | Operator::I32TruncF64U { .. }
+        | Operator::I64TruncF32S { .. }
+        | Operator::I64TruncF32U { .. }
+        | Operator::I64TruncF64S { .. }
+        | Operator::I64TruncF64U { .. }
+        | Operator::I32TruncSatF32S { .. }
+        | Operator::I32TruncSatF32U { .. }
+        | Operator::I32TruncSatF64S { .. }
+        | Operator::I32TruncSatF64U { .. }
+        | Operator::I64TruncSatF32S { .. }
+        | Operator::I64TruncSatF32U { .. }
+        | Operator::I64TruncSatF64S { .. }
+        | Operator::I64TruncSatF64U { .. }
+        | Operator::F32ConvertI32S { .. }
+        | Operator::F32ConvertI32U { .. }
+        | Operator::F32ConvertI64S { .. }
+        | Operator::F32ConvertI64U { .. }
+        | Operator::F32DemoteF64 { .. }
+        | Operator::F64ConvertI32S { .. }
+        | Operator::F64ConvertI32U { .. }
+        | Operator::F64ConvertI64S { .. }
+        | Operator::F64ConvertI64U { .. }
+        | Operator::F64PromoteF32 { .. }
+        | Operator::I32ReinterpretF32 { .. }
+        | Operator::I64ReinterpretF64 { .. }
+        | Operator::F32ReinterpretI32 { .. }
+        | Operator::F64ReinterpretI64 { .. }
+        | Operator::F32x4Splat { .. }
+        | Operator::F64x2Splat { .. }
+        | Operator::F32x4ExtractLane { .. }
+        | Operator::F32x4ReplaceLane { .. }
+        | Operator::F64x2ExtractLane { .. }
+        | Operator::F64x2ReplaceLane { .. }
+        | Operator::F32x4Eq { .. }
+        | Operator::F32x4Ne { .. }
+        | Operator::F32x4Lt { .. }
+        | Operator::F32x4Gt { .. }
+        | Operator::F32x4Le { .. }
+        | Operator::F32x4Ge { .. }
+        | Operator::F64x2Eq { .. }
+        | Operator::F64x2Ne { .. }
+        | Operator::F64x2Lt { .. }
+        | Operator::F64x2Gt { .. }
+        | Operator::F64x2Le { .. }
+        | Operator::F64x2Ge { .. }
+        | Operator::F32x4Ceil { .. }
+        | Operator::F32x4Floor { .. }
+        | Operator::F32x4Trunc { .. }
+        | Operator::F32x4Nearest { .. }
+        | Operator::F32x4Abs { .. }
+        | Operator::F32x4Neg { .. }
+        | Operator::F32x4Sqrt { .. }
+        | Operator::F32x4Add { .. }
+        | Operator::F32x4Sub { .. }
+        | Operator::F32x4Mul { .. }
+        | Operator::F32x4Div { .. }
+        | Operator::F32x4Min { .. }
+        | Operator::F32x4Max { .. }
+        | Operator::F32x4PMin { .. }
+        | Operator::F32x4PMax { .. }
+        | Operator::F64x2Ceil { .. }
+        | Operator::F64x2Floor { .. }
+        | Operator::F64x2Trunc { .. }
+        | Operator::F64x2Nearest { .. }
+        | Operator::F64x2Abs { .. }
+        | Operator::F64x2Neg { .. }
+        | Operator::F64x2Sqrt { .. }
+        | Operator::F64x2Add { .. }
+        | Operator::F64x2Sub { .. }
+        | Operator::F64x2Mul { .. }
+        | Operator::F64x2Div { .. }
+        | Operator::F64x2Min { .. }
+        | Operator::F64x2Max { .. }
+        | Operator::F64x2PMin { .. }
+        | Operator::F64x2PMax { .. }
+        | Operator::I32x4TruncSatF32x4S { .. }
+        | Operator::I32x4TruncSatF32x4U { .. }
+        | Operator::F32x4ConvertI32x4S { .. }
+        | Operator::F32x4ConvertI32x4U { .. } => true,
+        _ => false,
+    }
+}
+
+/// Reject a module that exports two items under the same name.
+///
+/// This runs ahead of translation so the module gets a clear error rather than a generic
+/// validation failure, or conflicting symbols later on. A malformed export section is left for
+/// translation to report.
+fn check_unique_exports(wasm_binary: &[u8]) -> Result<(), Error> {
+    for payload in Parser::new(0).parse_all(wasm_binary) {
+        let exports = match payload {
+            Ok(Payload::ExportSection(exports)) => exports,
+            Ok(_) => continue,
+            Err(_) => return Ok(()),
+        };
+        let mut names = HashSet::new();
+        for export in exports {
+            let export = match export {
+                Ok(export) => export,
+                Err(_) => return Ok(()),
+            };
+            if !names.insert(export.field) {
+                return Err(Error::DuplicateExport {
+                    name: export.field.to_owned(),
+                });
+            }
+        }
+        break;
+    }
+    Ok(())
+}
+
+/// The optional CPU features the x86-64 backend can use to lower `op`.
+fn features_for_op(op: &Operator<'_>) -> &'static [SpecificFeature] {
+    use SpecificFeature::*;
+    match op {
+        Operator::I32Popcnt | Operator::I64Popcnt => &[Popcnt],
+        Operator::I32Clz | Operator::I64Clz => &[Lzcnt],
+        Operator::I32Ctz | Operator::I64Ctz => &[BMI1],
+        Operator::F32Ceil
+        | Operator::F32Floor
+        | Operator::F32Trunc
+        | Operator::F32Nearest
+        | Operator::F64Ceil
+        | Operator::F64Floor
+        | Operator::F64Trunc
+        | Operator::F64Nearest => &[SSE41],
+        op if is_simd_op(op) => &[SSSE3, SSE41],
+        _ => &[],
+    }
+}
+
+/// Record the WebAssembly proposal that introduced `op`, if any, in `proposals`.
+fn add_proposals_for_op(proposals: &mut WasmProposals, op: &Operator<'_>) {
+    match op {
+        Operator::MemoryInit { .. }
+        | Operator::DataDrop { .. }
+        | Operator::MemoryCopy { .. }
+        | Operator::MemoryFill { .. }
+        | Operator::TableInit { .. }
+        | Operator::ElemDrop { .. }
+        | Operator::TableCopy { .. } => proposals.bulk_memory = true,
+        Operator::RefNull { .. }
+        | Operator::RefIsNull
+        | Operator::RefFunc { .. }
+        | Operator::TypedSelect { .. }
+        | Operator::TableGet { .. }
+        | Operator::TableSet { .. }
+        | Operator::TableGrow { .. }
+        | Operator::TableSize { .. }
+        | Operator::TableFill { .. } => proposals.reference_types = true,
+        Operator::ReturnCall { .. } | Operator::ReturnCallIndirect { .. } => {
+            proposals.tail_call = true
+        }
+        Operator::Block {
+            ty: TypeOrFuncType::FuncType(_),
+        }
+        | Operator::Loop {
+            ty: TypeOrFuncType::FuncType(_),
+        }
+        | Operator::If {
+            ty: TypeOrFuncType::FuncType(_),
+        } => proposals.multi_value = true,
+        Operator::MemoryAtomicNotify { .. }
+        | Operator::MemoryAtomicWait32 { .. }
+        | Operator::MemoryAtomicWait64 { .. }
+        | Operator::AtomicFence { .. }
+        | Operator::I32AtomicLoad { .. }
+        | Operator::I64AtomicLoad { .. }
+        | Operator::I32AtomicLoad8U { .. }
+        | Operator::I32AtomicLoad16U { .. }
+        | Operator::I64AtomicLoad8U { .. }
+        | Operator::I64AtomicLoad16U { .. }
+        | Operator::I64AtomicLoad32U { .. }
+        | Operator::I32AtomicStore { .. }
+        | Operator::I64AtomicStore { .. }
+        | Operator::I32AtomicStore8 { .. }
+        | Operator::I32AtomicStore16 { .. }
+        | Operator::I64AtomicStore8 { .. }
+        | Operator::I64AtomicStore16 { .. }
+        | Operator::I64AtomicStore32 { .. }
+        | Operator::I32AtomicRmwAdd { .. }
+        | Operator::I64AtomicRmwAdd { .. }
+        | Operator::I32AtomicRmw8AddU { .. }
+        | Operator::I32AtomicRmw16AddU { .. }
+        | Operator::I64AtomicRmw8AddU { .. }
+        | Operator::I64AtomicRmw16AddU { .. }
+        | Operator::I64AtomicRmw32AddU { .. }
+        | Operator::I32AtomicRmwSub { .. }
+        | Operator::I64AtomicRmwSub { .. }
+        | Operator::I32AtomicRmw8SubU { .. }
+        | Operator::I32AtomicRmw16SubU { .. }
+        | Operator::I64AtomicRmw8SubU { .. }
+        | Operator::I64AtomicRmw16SubU { .. }
+        | Operator::I64AtomicRmw32SubU { .. }
+        | Operator::I32AtomicRmwAnd { .. }
+        | Operator::I64AtomicRmwAnd { .. }
+        | Operator::I32AtomicRmw8AndU { .. }
+        | Operator::I32AtomicRmw16AndU { .. }
+        | Operator::I64AtomicRmw8AndU { .. }
+        | Operator::I64AtomicRmw16AndU { .. }
+        | Operator::I64AtomicRmw32AndU { .. }
+        | Operator::I32AtomicRmwOr { .. }
+        | Operator::I64AtomicRmwOr { .. }
+        | Operator::I32AtomicRmw8OrU { .. }
+        | Operator::I32AtomicRmw16OrU { .. }
+        | Operator::I64AtomicRmw8OrU { .. }
+        | Operator::I64AtomicRmw16OrU { .. }
+        | Operator::I64AtomicRmw32OrU { .. }
+        | Operator::I32AtomicRmwXor { .. }
+        | Operator::I64AtomicRmwXor { .. }
+        | Operator::I32AtomicRmw8XorU { .. }
+        | Operator::I32AtomicRmw16XorU { .. }
+        | Operator::I64AtomicRmw8XorU { .. }
+        | Operator::I64AtomicRmw16XorU { .. }
+        | Operator::I64AtomicRmw32XorU { .. }
+        | Operator::I32AtomicRmwXchg { .. }
+        | Operator::I64AtomicRmwXchg { .. }
+        | Operator::I32AtomicRmw8XchgU { .. }
+        | Operator::I32AtomicRmw16XchgU { .. }
+        | Operator::I64AtomicRmw8XchgU { .. }
+        | Operator::I64AtomicRmw16XchgU { .. }
+        | Operator::I64AtomicRmw32XchgU { .. }
+        | Operator::I32AtomicRmwCmpxchg { .. }
+        | Operator::I64AtomicRmwCmpxchg { .. }
+        | Operator::I32AtomicRmw8CmpxchgU { .. }
+        | Operator::I32AtomicRmw16CmpxchgU { .. }
+        | Operator::I64AtomicRmw8CmpxchgU { .. }
+        | Operator::I64AtomicRmw16CmpxchgU { .. }
+        | Operator::I64AtomicRmw32CmpxchgU { .. } => proposals.threads = true,
+        op if is_simd_op(op) => proposals.simd = true,
+        _ => {}
+    }
+}
+
+const ROUNDING_LIBCALLS: [ir::LibCall; 8] = [
+    ir::LibCall::CeilF32,
+    ir::LibCall::CeilF64,
+    ir::LibCall::FloorF32,
+    ir::LibCall::FloorF64,
+    ir::LibCall::TruncF32,
+    ir::LibCall::TruncF64,
+    ir::LibCall::NearestF32,
+    ir::LibCall::NearestF64,
+];
+
+/// The symbol that code calls for `libcall`: lucet's own stack probe, lucet-runtime's
+/// implementation if `deterministic` is set and it has one, or else the symbol Cranelift uses by
+/// default.
+fn libcall_name(libcall: ir::LibCall, deterministic: bool) -> String {
+    match libcall {
+        ir::LibCall::Probestack => stack_probe::STACK_PROBE_SYM.to_owned(),
+        _ => match deterministic_libcall_name(libcall) {
+            Some(name) if deterministic => name.to_owned(),
+            _ => (cranelift_module::default_libcall_names())(libcall),
+        },
+    }
+}
+
+/// The symbol of lucet-runtime's deterministic implementation of `libcall`, if it has one.
+fn deterministic_libcall_name(libcall: ir::LibCall) -> Option<&'static str> {
+    match libcall {
+        ir::LibCall::CeilF32 => Some("lucet_libcall_ceilf"),
+        ir::LibCall::CeilF64 => Some("lucet_libcall_ceil"),
+        ir::LibCall::FloorF32 => Some("lucet_libcall_floorf"),
+        ir::LibCall::FloorF64 => Some("lucet_libcall_floor"),
+        ir::LibCall::TruncF32 => Some("lucet_libcall_truncf"),
+        ir::LibCall::TruncF64 => Some("lucet_libcall_trunc"),
+        ir::LibCall::NearestF32 => Some("lucet_libcall_nearbyintf"),
+        ir::LibCall::NearestF64 => Some("lucet_libcall_nearbyint"),
+        _ => None,
+    }
+}
+
+/// The libcall that float rounding `op` is lowered to when SSE4.1 is not available, if `op` is
+/// a float rounding instruction.
+fn rounding_libcall(op: &Operator<'_>) -> Option<ir::LibCall> {
+    match op {
+        Operator::F32Ceil => Some(ir::LibCall::CeilF32),
+        Operator::F64Ceil => Some(ir::LibCall::CeilF64),
+        Operator::F32Floor => Some(ir::LibCall::FloorF32),
+        Operator::F64Floor => Some(ir::LibCall::FloorF64),
+        Operator::F32Trunc => Some(ir::LibCall::TruncF32),
+        Operator::F64Trunc => Some(ir::LibCall::TruncF64),
+        Operator::F32Nearest => Some(ir::LibCall::NearestF32),
+        Operator::F64Nearest => Some(ir::LibCall::NearestF64),
+        _ => None,
+    }
+}
+
+/// The name of `op`'s instruction, as wasmparser names its operators, if it can trap when
+/// executed, so its compiled code should have a trap site.
+fn trapping_operator_name(op: &Operator<'_>) -> Option<&'static str> {
+    macro_rules! trapping {
+        ($($name:ident,)*) => {
+            match op {
+                $(Operator::$name { .. } => Some(stringify!($name)),)*
+                _ => None,
+            }
+        };
+    }
+    trapping!(
+        // Traps raised by the instruction itself.
+        Unreachable,
+        CallIndirect,
+        ReturnCallIndirect,
+        I32DivS,
+        I32DivU,
+        I32RemS,
+        I32RemU,
+        I64DivS,
+        I64DivU,
+        I64RemS,
+        I64RemU,
+        I32TruncF32S,
+        I32TruncF32U,
+        I32TruncF64S,
+        I32TruncF64U,
+        I64TruncF32S,
+        I64TruncF32U,
+        I64TruncF64S,
+        I64TruncF64U,
+        // Linear memory accesses, which can be out of bounds.
+        I32Load,
+        I64Load,
+        F32Load,
+        F64Load,
+        I32Load8S,
+        I32Load8U,
+        I32Load16S,
+        I32Load16U,
+        I64Load8S,
+        I64Load8U,
+        I64Load16S,
+        I64Load16U,
+        I64Load32S,
+        I64Load32U,
+        I32Store,
+        I64Store,
+        F32Store,
+        F64Store,
+        I32Store8,
+        I32Store16,
+        I64Store8,
+        I64Store16,
+        I64Store32,
+        // Bulk memory and table instructions, which can be out of bounds.
+        MemoryInit,
+        MemoryCopy,
+        MemoryFill,
+        TableGet,
+        TableSet,
+        TableInit,
+        TableCopy,
+        TableFill,
+        // Atomic accesses, which can also be misaligned.
+        MemoryAtomicNotify,
+        MemoryAtomicWait32,
+        MemoryAtomicWait64,
+        I32AtomicLoad,
+        I64AtomicLoad,
+        I32AtomicLoad8U,
+        I32AtomicLoad16U,
+        I64AtomicLoad8U,
+        I64AtomicLoad16U,
+        I64AtomicLoad32U,
+        I32AtomicStore,
+        I64AtomicStore,
+        I32AtomicStore8,
+        I32AtomicStore16,
+        I64AtomicStore8,
+        I64AtomicStore16,
+        I64AtomicStore32,
+        I32AtomicRmwAdd,
+        I64AtomicRmwAdd,
+        I32AtomicRmw8AddU,
+        I32AtomicRmw16AddU,
+        I64AtomicRmw8AddU,
+        I64AtomicRmw16AddU,
+        I64AtomicRmw32AddU,
+        I32AtomicRmwSub,
+        I64AtomicRmwSub,
+        I32AtomicRmw8SubU,
+        I32AtomicRmw16SubU,
+        I64AtomicRmw8SubU,
+        I64AtomicRmw16SubU,
+        I64AtomicRmw32SubU,
+        I32AtomicRmwAnd,
+        I64AtomicRmwAnd,
+        I32AtomicRmw8AndU,
+        I32AtomicRmw16AndU,
+        I64AtomicRmw8AndU,
+        I64AtomicRmw16AndU,
+        I64AtomicRmw32AndU,
+        I32AtomicRmwOr,
+        I64AtomicRmwOr,
+        I32AtomicRmw8OrU,
+        I32AtomicRmw16OrU,
+        I64AtomicRmw8OrU,
+        I64AtomicRmw16OrU,
+        I64AtomicRmw32OrU,
+        I32AtomicRmwXor,
+        I64AtomicRmwXor,
+        I32AtomicRmw8XorU,
+        I32AtomicRmw16XorU,
+        I64AtomicRmw8XorU,
+        I64AtomicRmw16XorU,
+        I64AtomicRmw32XorU,
+        I32AtomicRmwXchg,
+        I64AtomicRmwXchg,
+        I32AtomicRmw8XchgU,
+        I32AtomicRmw16XchgU,
+        I64AtomicRmw8XchgU,
+        I64AtomicRmw16XchgU,
+        I64AtomicRmw32XchgU,
+        I32AtomicRmwCmpxchg,
+        I64AtomicRmwCmpxchg,
+        I32AtomicRmw8CmpxchgU,
+        I32AtomicRmw16CmpxchgU,
+        I64AtomicRmw8CmpxchgU,
+        I64AtomicRmw16CmpxchgU,
+        I64AtomicRmw32CmpxchgU,
+        // SIMD memory accesses.
+        V128Load,
+        V128Load8x8S,
+        V128Load8x8U,
+        V128Load16x4S,
+        V128Load16x4U,
+        V128Load32x2S,
+        V128Load32x2U,
+        V128Load8Splat,
+        V128Load16Splat,
+        V128Load32Splat,
+        V128Load64Splat,
+        V128Load32Zero,
+        V128Load64Zero,
+        V128Store,
+    )
+}
+
+/// Whether `op` is part of the SIMD proposal.
+fn is_simd_op(op: &Operator<'_>) -> bool {
+    match op {
+        Operator::V128Load { .. }
+        | Operator::V128Load8x8S { .. }
+        | Operator::V128Load8x8U { .. }
+        | Operator::V128Load16x4S { .. }
+        | Operator::V128Load16x4U { .. }
+        | Operator::V128Load32x2S { .. }
+        | Operator::V128Load32x2U { .. }
+        | Operator::V128Load8Splat { .. }
+        | Operator::V128Load16Splat { .. }
+        | Operator::V128Load32Splat { .. }
+        | Operator::V128Load64Splat { .. }
+        | Operator::V128Load32Zero { .. }
+        | Operator::V128Load64Zero { .. }
+        | Operator::V128Store { .. }
+        | Operator::V128Const { .. }
+        | Operator::I8x16Shuffle { .. }
+        | Operator::I8x16Swizzle
+        | Operator::I8x16Splat
+        | Operator::I16x8Splat
+        | Operator::I32x4Splat
+        | Operator::I64x2Splat
+        | Operator::F32x4Splat
+        | Operator::F64x2Splat
+        | Operator::I8x16ExtractLaneS { .. }
+        | Operator::I8x16ExtractLaneU { .. }
+        | Operator::I8x16ReplaceLane { .. }
+        | Operator::I16x8ExtractLaneS { .. }
+        | Operator::I16x8ExtractLaneU { .. }
+        | Operator::I16x8ReplaceLane { .. }
+        | Operator::I32x4ExtractLane { .. }
+        | Operator::I32x4ReplaceLane { .. }
+        | Operator::I64x2ExtractLane { .. }
+        | Operator::I64x2ReplaceLane { .. }
+        | Operator::F32x4ExtractLane { .. }
+        | Operator::F32x4ReplaceLane { .. }
+        | Operator::F64x2ExtractLane { .. }
+        | Operator::F64x2ReplaceLane { .. }
+        | Operator::I8x16Eq
+        | Operator::I8x16Ne
+        | Operator::I8x16LtS
+        | Operator::I8x16LtU
+        | Operator::I8x16GtS
+        | Operator::I8x16GtU
+        | Operator::I8x16LeS
+        | Operator::I8x16LeU
+        | Operator::I8x16GeS
+        | Operator::I8x16GeU
+        | Operator::I16x8Eq
+        | Operator::I16x8Ne
+        | Operator::I16x8LtS
+        | Operator::I16x8LtU
+        | Operator::I16x8GtS
+        | Operator::I16x8GtU
+        | Operator::I16x8LeS
+        | Operator::I16x8LeU
+        | Operator::I16x8GeS
+        | Operator::I16x8GeU
+        | Operator::I32x4Eq
+        | Operator::I32x4Ne
+        | Operator::I32x4LtS
+        | Operator::I32x4LtU
+        | Operator::I32x4GtS
+        | Operator::I32x4GtU
+        | Operator::I32x4LeS
+        | Operator::I32x4LeU
+        | Operator::I32x4GeS
+        | Operator::I32x4GeU
+        | Operator::F32x4Eq
+        | Operator::F32x4Ne
+        | Operator::F32x4Lt
+        | Operator::F32x4Gt
+        | Operator::F32x4Le
+        | Operator::F32x4Ge
+        | Operator::F64x2Eq
+        | Operator::F64x2Ne
+        | Operator::F64x2Lt
+        | Operator::F64x2Gt
+        | Operator::F64x2Le
+        | Operator::F64x2Ge
+        | Operator::V128Not
+        | Operator::V128And
+        | Operator::V128AndNot
+        | Operator::V128Or
+        | Operator::V128Xor
+        | Operator::V128Bitselect
+        | Operator::I8x16Abs
+        | Operator::I8x16Neg
+        | Operator::I8x16AnyTrue
+        | Operator::I8x16AllTrue
+        | Operator::I8x16Bitmask
+        | Operator::I8x16Shl
+        | Operator::I8x16ShrS
+        | Operator::I8x16ShrU
+        | Operator::I8x16Add
+        | Operator::I8x16AddSatS
+        | Operator::I8x16AddSatU
+        | Operator::I8x16Sub
+        | Operator::I8x16SubSatS
+        | Operator::I8x16SubSatU
+        | Operator::I8x16MinS
+        | Operator::I8x16MinU
+        | Operator::I8x16MaxS
+        | Operator::I8x16MaxU
+        | Operator::I8x16RoundingAverageU
+        | Operator::I16x8Abs
+        | Operator::I16x8Neg
+        | Operator::I16x8AnyTrue
+        | Operator::I16x8AllTrue
+        | Operator::I16x8Bitmask
+        | Operator::I16x8Shl
+        | Operator::I16x8ShrS
+        | Operator::I16x8ShrU
+        | Operator::I16x8Add
+        | Operator::I16x8AddSatS
+        | Operator::I16x8AddSatU
+        | Operator::I16x8Sub
+        | Operator::I16x8SubSatS
+        | Operator::I16x8SubSatU
+        | Operator::I16x8MinS
+        | Operator::I16x8MinU
+        | Operator::I16x8MaxS
+        | Operator::I16x8MaxU
+        | Operator::I16x8RoundingAverageU
+        | Operator::I16x8Mul
+        | Operator::I32x4Abs
+        | Operator::I32x4Neg
+        | Operator::I32x4AnyTrue
+        | Operator::I32x4AllTrue
+        | Operator::I32x4Bitmask
+        | Operator::I32x4Shl
+        | Operator::I32x4ShrS
+        | Operator::I32x4ShrU
+        | Operator::I32x4Add
+        | Operator::I32x4Sub
+        | Operator::I32x4Mul
+        | Operator::I32x4MinS
+        | Operator::I32x4MinU
+        | Operator::I32x4MaxS
+        | Operator::I32x4MaxU
+        | Operator::I32x4DotI16x8S
+        | Operator::I64x2Neg
+        | Operator::I64x2Shl
+        | Operator::I64x2ShrS
+        | Operator::I64x2ShrU
+        | Operator::I64x2Add
+        | Operator::I64x2Sub
+        | Operator::I64x2Mul
+        | Operator::F32x4Ceil
+        | Operator::F32x4Floor
+        | Operator::F32x4Trunc
+        | Operator::F32x4Nearest
+        | Operator::F32x4Abs
+        | Operator::F32x4Neg
+        | Operator::F32x4Sqrt
+        | Operator::F32x4Add
+        | Operator::F32x4Sub
+        | Operator::F32x4Mul
+        | Operator::F32x4Div
+        | Operator::F32x4Min
+        | Operator::F32x4Max
+        | Operator::F32x4PMin
+        | Operator::F32x4PMax
+        | Operator::F64x2Ceil
+        | Operator::F64x2Floor
+        | Operator::F64x2Trunc
+        | Operator::F64x2Nearest
+        | Operator::F64x2Abs
+        | Operator::F64x2Neg
+        | Operator::F64x2Sqrt
+        | Operator::F64x2Add
+        | Operator::F64x2Sub
+        | Operator::F64x2Mul
+        | Operator::F64x2Div
+        | Operator::F64x2Min
+        | Operator::F64x2Max
+        | Operator::F64x2PMin
+        | Operator::F64x2PMax
+        | Operator::I32x4TruncSatF32x4S
+        | Operator::I32x4TruncSatF32x4U
+        | Operator::F32x4ConvertI32x4S
+        | Operator::F32x4ConvertI32x4U
+        | Operator::I8x16NarrowI16x8S
+        | Operator::I8x16NarrowI16x8U
+        | Operator::I16x8NarrowI32x4S
+        | Operator::I16x8NarrowI32x4U
+        | Operator::I16x8WidenLowI8x16S
+        | Operator::I16x8WidenHighI8x16S
+        | Operator::I16x8WidenLowI8x16U
+        | Operator::I16x8WidenHighI8x16U
+        | Operator::I32x4WidenLowI16x8S
+        | Operator::I32x4WidenHighI16x8S
+        | Operator::I32x4WidenLowI16x8U
+        | Operator::I32x4WidenHighI16x8U => true,
+        _ => false,
+    }
+}
+
+/// Whether `op` translates to a Cranelift instruction that NaN canonicalization rewrites.
+fn is_canonicalized_float_op(op: &Operator<'_>) -> bool {
+    match op {
+        Operator::F32Add
+        | Operator::F32Sub
+        | Operator::F32Mul
+        | Operator::F32Div
+        | Operator::F32Min
+        | Operator::F32Max
+        | Operator::F32Sqrt
+        | Operator::F32Ceil
+        | Operator::F32Floor
+        | Operator::F32Trunc
+        | Operator::F32Nearest
+        | Operator::F64Add
+        | Operator::F64Sub
+        | Operator::F64Mul
+        | Operator::F64Div
+        | Operator::F64Min
+        | Operator::F64Max
+        | Operator::F64Sqrt
+        | Operator::F64Ceil
+        | Operator::F64Floor
+        | Operator::F64Trunc
+        | Operator::F64Nearest
+        | Operator::F32x4Add
+        | Operator::F32x4Sub
+        | Operator::F32x4Mul
+        | Operator::F32x4Div
+        | Operator::F32x4Min
+        | Operator::F32x4Max
+        | Operator::F32x4Sqrt
+        | Operator::F32x4Ceil
+        | Operator::F32x4Floor
+        | Operator::F32x4Trunc
+        | Operator::F32x4Nearest
+        | Operator::F64x2Add
+        | Operator::F64x2Sub
+        | Operator::F64x2Mul
+        | Operator::F64x2Div
+        | Operator::F64x2Min
+        | Operator::F64x2Max
+        | Operator::F64x2Sqrt
+        | Operator::F64x2Ceil
+        | Operator::F64x2Floor
+        | Operator::F64x2Trunc
+        | Operator::F64x2Nearest => true,
+        _ => false,
+    }
+}
+
+/// The machine code of a guest function, with the relocations and trap sites needed to load it.
+/// See `Compiler::compiled_functions`.
+#[derive(Clone, Debug)]
+pub struct CompiledFunction {
+    pub symbol: String,
+    pub code: Vec<u8>,
+    pub relocs: Vec<Relocation>,
+    pub traps: Vec<lucet_module::TrapSite>,
+}
+
+/// A reference from a function's code to a symbol, to be patched in when the code is loaded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Relocation {
+    /// The offset of the reference in the function's code.
+    pub offset: u32,
+    pub kind: binemit::Reloc,
+    pub symbol: String,
+    pub addend: i64,
+}
+
+/// A function's entry in the function manifest. See `Compiler::function_manifest_ordered`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionManifestEntry {
+    /// The function's index in the wasm module, or `None` for functions the runtime provides.
+    pub wasm_index: Option<FuncIndex>,
+    /// The position of the function's entry in the manifest.
+    pub manifest_index: u32,
+    pub symbol: String,
+}
+
+/// A wasm instruction that can trap, but whose compiled code has no trap site. See
+/// `Compiler::eliminated_traps`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EliminatedTrap {
+    /// The offset of the instruction in the wasm module.
+    pub wasm_offset: usize,
+    /// The instruction's name, as wasmparser names its operators, such as `I32DivU`.
+    pub operator: String,
+}
+
+/// The start function of a module. See `Compiler::start_function`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StartFunctionInfo {
+    /// The function's index in the wasm module. If several imports of the same symbol share a
+    /// function, this is the index of the first.
+    pub wasm_index: FuncIndex,
+    pub symbol: String,
+    /// Whether the start function is an import, rather than defined in the module.
+    pub imported: bool,
+}
+
+/// The machine code of a function that has been compiled, but not yet defined in the object.
+struct CompiledCode {
+    code: Vec<u8>,
+    relocs: Vec<RelocRecord>,
+    traps: TrapSites,
+    address_map: Option<Vec<AddressMapEntry>>,
+    cfg_map: Option<Vec<BasicBlock>>,
+}
+
 struct TrapMetadata {
     func_size: u32,
     trap_data_id: DataId,
@@ -636,6 +4197,30 @@ pub struct CodegenContext {
     // the `FuncId` references the declared trampoline function Cranelift knows, but the
     // `UniqueFuncIndex` references the hostcall being trampoline'd to.
     trampolines: Mutex<HashMap<String, (FuncId, UniqueFuncIndex)>>,
+    // serialized trap tables already written, when identical tables are shared between functions
+    trap_tables: Option<Mutex<HashMap<Box<[u8]>, DataId>>>,
+    // the format version to write trap tables in
+    trap_table_version: u8,
+    // whether to write a map from machine code to wasm offsets for each function
+    address_maps: bool,
+    // where address maps are collected instead of being written, when they go in a debug object
+    address_map_sink: Option<Arc<Mutex<Vec<(String, Vec<AddressMapEntry>)>>>>,
+    // whether to write the basic blocks of each function's machine code
+    cfg_maps: bool,
+    // whether to flush subnormal floats to zero around arithmetic
+    flush_denormals: bool,
+    // whether libcalls go to lucet-runtime's deterministic implementations rather than libm
+    deterministic_libcalls: bool,
+    // the number of wasm pages past which `memory.grow` traps
+    memory_limit: Option<u32>,
+    // whether `unreachable` calls the runtime's handler before trapping
+    unreachable_handler: bool,
+    // the largest frame a function may have, in bytes
+    max_stack_size: Option<usize>,
+    // the coverage counters to increment, in a coverage-instrumented build
+    coverage: Option<CoverageCounters>,
+    // ISAs for functions compiled at an optimization level other than `isa`'s
+    opt_level_isas: HashMap<OptLevel, Box<dyn TargetIsa>>,
     clif_module: Mutex<ObjectModule>,
 }
 
@@ -647,21 +4232,106 @@ impl CodegenContext {
     pub fn new(
         isa: Box<dyn TargetIsa>,
         isa_copy: Box<dyn TargetIsa>,
+        function_sections: bool,
+        deterministic_libcalls: bool,
     ) -> Result<CodegenContext, Error> {
-        let libcalls = Box::new(move |libcall| match libcall {
-            ir::LibCall::Probestack => stack_probe::STACK_PROBE_SYM.to_owned(),
-            _ => (cranelift_module::default_libcall_names())(libcall),
-        });
+        let libcalls = Box::new(move |libcall| libcall_name(libcall, deterministic_libcalls));
         let mut builder = ObjectBuilder::new(isa_copy, "lucet_guest".to_owned(), libcalls)?;
         builder.function_alignment(16);
+        builder.per_function_section(function_sections);
         let clif_module = ObjectModule::new(builder);
         Ok(CodegenContext {
             isa,
             trampolines: Mutex::new(HashMap::new()),
+            trap_tables: None,
+            trap_table_version: lucet_module::TRAP_TABLE_VERSION,
+            address_maps: false,
+            address_map_sink: None,
+            cfg_maps: false,
+            flush_denormals: false,
+            deterministic_libcalls,
+            memory_limit: None,
+            unreachable_handler: false,
+            max_stack_size: None,
+            coverage: None,
+            opt_level_isas: HashMap::new(),
             clif_module: Mutex::new(clif_module),
         })
     }
 
+    /// Share trap tables between functions with identical trap sites.
+    pub fn dedup_trap_tables(&mut self) {
+        self.trap_tables = Some(Mutex::new(HashMap::new()));
+    }
+
+    /// Write trap tables in format `version`, which lucet-module must be able to write.
+    pub fn trap_table_version(&mut self, version: u8) {
+        self.trap_table_version = version;
+    }
+
+    /// Write an address map alongside each function with code translated from wasm.
+    pub fn emit_address_maps(&mut self) {
+        self.address_maps = true;
+    }
+
+    /// Collect an address map for each function with code translated from wasm into `sink`,
+    /// rather than writing it into the object.
+    pub fn collect_address_maps(&mut self, sink: Arc<Mutex<Vec<(String, Vec<AddressMapEntry>)>>>) {
+        self.address_maps = true;
+        self.address_map_sink = Some(sink);
+    }
+
+    /// Write a CFG map alongside each function with code translated from wasm.
+    pub fn emit_cfg_maps(&mut self) {
+        self.cfg_maps = true;
+    }
+
+    /// Flush subnormal floats to zero in every function before it is compiled.
+    pub fn flush_denormals(&mut self) {
+        self.flush_denormals = true;
+    }
+
+    /// Trap in `memory.grow` rather than grow memory past `limit` wasm pages.
+    pub fn hard_memory_limit(&mut self, limit: u32) {
+        self.memory_limit = Some(limit);
+    }
+
+    pub fn memory_limit(&self) -> Option<u32> {
+        self.memory_limit
+    }
+
+    /// Fail to compile any function whose frame is larger than `size` bytes.
+    pub fn max_stack_size(&mut self, size: usize) {
+        self.max_stack_size = Some(size);
+    }
+
+    /// Call the runtime's unreachable handler before trapping in `unreachable`.
+    pub fn call_unreachable_handler(&mut self) {
+        self.unreachable_handler = true;
+    }
+
+    pub fn unreachable_handler(&self) -> bool {
+        self.unreachable_handler
+    }
+
+    /// Instrument each function to increment its counters in `counters`.
+    pub(crate) fn instrument_coverage(&mut self, counters: CoverageCounters) {
+        self.coverage = Some(counters);
+    }
+
+    /// The coverage instrumentation for the function `func_index`, if any.
+    pub(crate) fn coverage_for(&self, func_index: UniqueFuncIndex) -> Option<FunctionCoverage> {
+        self.coverage
+            .as_ref()
+            .and_then(|coverage| coverage.for_function(func_index))
+    }
+
+    /// Use these ISAs, rather than the default one, for functions compiled at their optimization
+    /// levels.
+    pub fn opt_level_isas(&mut self, opt_level_isas: HashMap<OptLevel, Box<dyn TargetIsa>>) {
+        self.opt_level_isas = opt_level_isas;
+    }
+
     pub fn module(&self) -> MutexGuard<'_, ObjectModule> {
         self.clif_module
             .lock()
@@ -684,14 +4354,22 @@ impl CodegenContext {
     fn compile(
         &self,
         clif: &mut ClifContext,
-        func_id: FuncId,
         symbol: &str,
-    ) -> Result<TrapMetadata, Error> {
+        forbid_stack_probe: bool,
+        opt_level: Option<OptLevel>,
+    ) -> Result<CompiledCode, Error> {
+        let isa = opt_level
+            .and_then(|opt_level| self.opt_level_isas.get(&opt_level))
+            .unwrap_or(&self.isa);
+        if self.flush_denormals {
+            denormals::flush_denormals(&mut clif.func);
+        }
         let binemit::CodeInfo {
+            code_size: instructions_size,
             total_size: code_size,
             ..
         } = clif
-            .compile(&*self.isa)
+            .compile(&**isa)
             .map_err(|source| Error::FunctionDefinition {
                 symbol: symbol.to_string(),
                 source: source.into(),
@@ -703,7 +4381,7 @@ impl CodegenContext {
         let mut traps = TrapSites::new();
         unsafe {
             clif.emit_to_memory(
-                &*self.isa,
+                &**isa,
                 code.as_mut_ptr(),
                 &mut reloc_sink,
                 &mut traps,
@@ -711,24 +4389,199 @@ impl CodegenContext {
             )
         };
 
-        let compiled = self
+        if let Some(max_stack_size) = self.max_stack_size {
+            let frame_size = frame_size(clif);
+            if frame_size as usize > max_stack_size {
+                return Err(Error::FrameExceedsStack {
+                    symbol: symbol.to_string(),
+                    frame_size,
+                });
+            }
+        }
+
+        // The stack probe is only ever called from a function's prologue.
+        let probestack = ir::ExternalName::LibCall(ir::LibCall::Probestack);
+        if forbid_stack_probe && reloc_sink.relocs.iter().any(|r| r.name == probestack) {
+            return Err(Error::StackProbeRequired {
+                symbol: symbol.to_string(),
+            });
+        }
+
+        if self.deterministic_libcalls {
+            for reloc in reloc_sink.relocs.iter() {
+                if let ir::ExternalName::LibCall(libcall) = reloc.name {
+                    if libcall != ir::LibCall::Probestack
+                        && deterministic_libcall_name(libcall).is_none()
+                    {
+                        return Err(Error::Unsupported(format!(
+                            "{} calls libcall {:?}, which has no deterministic implementation",
+                            symbol, libcall
+                        )));
+                    }
+                }
+            }
+        }
+
+        let address_map = if self.address_maps {
+            Some(function_address_map(clif, &**isa))
+        } else {
+            None
+        };
+        let cfg_map = if self.cfg_maps {
+            Some(function_cfg_map(clif, instructions_size))
+        } else {
+            None
+        };
+
+        Ok(CompiledCode {
+            code,
+            relocs: reloc_sink.relocs,
+            traps,
+            address_map,
+            cfg_map,
+        })
+    }
+
+    /// Define a compiled function in the object. Functions are laid out in the order they are
+    /// defined.
+    fn define(
+        &self,
+        func_id: FuncId,
+        symbol: &str,
+        compiled: CompiledCode,
+    ) -> Result<TrapMetadata, Error> {
+        let defined = self
             .module()
-            .define_function_bytes(func_id, &code, &reloc_sink.relocs)
+            .define_function_bytes(func_id, &compiled.code, &compiled.relocs)
             .map_err(|source| Error::FunctionDefinition {
                 symbol: symbol.to_string(),
                 source,
             })?;
 
-        let func_size = compiled.size;
+        let func_size = defined.size;
+
+        let trap_data_id = compiled.traps.write(self, symbol)?;
 
-        let trap_data_id = traps.write(self, symbol)?;
+        match compiled.address_map {
+            Some(address_map) if !address_map.is_empty() => match &self.address_map_sink {
+                Some(sink) => sink.lock().unwrap().push((symbol.to_owned(), address_map)),
+                None => {
+                    self.define_local_data(
+                        &address_map_sym(symbol),
+                        AddressMapEntry::serialize(&address_map),
+                    )?;
+                }
+            },
+            _ => {}
+        }
+        match compiled.cfg_map {
+            Some(cfg_map) if !cfg_map.is_empty() => {
+                self.define_local_data(&cfg_map_sym(symbol), BasicBlock::serialize(&cfg_map))?;
+            }
+            _ => {}
+        }
 
         Ok(TrapMetadata {
             func_size,
             trap_data_id,
-            trap_len: traps.len(),
+            trap_len: compiled.traps.len(),
         })
     }
+
+    /// Resolve the symbols `compiled` refers to, for loaders that don't go through the object.
+    fn export(&self, symbol: &str, compiled: CompiledCode) -> CompiledFunction {
+        let relocs = compiled
+            .relocs
+            .iter()
+            .map(|reloc| Relocation {
+                offset: reloc.offset,
+                kind: reloc.reloc,
+                symbol: self.symbol_for(&reloc.name),
+                addend: reloc.addend,
+            })
+            .collect();
+        let traps = compiled
+            .traps
+            .traps
+            .iter()
+            .map(|site| lucet_module::TrapSite {
+                offset: site.offset,
+                code: translate_trapcode(site.code),
+            })
+            .collect();
+        CompiledFunction {
+            symbol: symbol.to_owned(),
+            code: compiled.code,
+            relocs,
+            traps,
+        }
+    }
+
+    /// The symbol a relocation's target is known by in the object.
+    fn symbol_for(&self, name: &ir::ExternalName) -> String {
+        let module = self.module();
+        let declarations = module.declarations();
+        // cranelift-module names functions in namespace 0 and data in namespace 1.
+        match name {
+            ir::ExternalName::User { namespace: 0, .. } => declarations
+                .get_function_decl(FuncId::from_name(name))
+                .name
+                .clone(),
+            ir::ExternalName::User { namespace: 1, .. } => declarations
+                .get_data_decl(DataId::from_name(name))
+                .name
+                .clone(),
+            ir::ExternalName::LibCall(libcall) => {
+                libcall_name(*libcall, self.deterministic_libcalls)
+            }
+            _ => name.to_string(),
+        }
+    }
+
+    /// Define a data symbol, local to the object, that holds `contents`.
+    fn define_local_data(&self, symbol: &str, contents: Box<[u8]>) -> Result<DataId, Error> {
+        let mut data_ctx = ClifDataContext::new();
+        data_ctx.define(contents);
+        let data_id = self
+            .module()
+            .declare_data(symbol, ClifLinkage::Local, false, false)?;
+        self.module().define_data(data_id, &data_ctx)?;
+        Ok(data_id)
+    }
+
+    fn compile_and_define(
+        &self,
+        clif: &mut ClifContext,
+        func_id: FuncId,
+        symbol: &str,
+    ) -> Result<TrapMetadata, Error> {
+        let compiled = self.compile(clif, symbol, false, None)?;
+        self.define(func_id, symbol, compiled)
+    }
+
+    /// Count the instructions in `func` before and after Cranelift's constant folding, which
+    /// is run over a copy of the function.
+    fn const_fold_counts(
+        &self,
+        func: &ir::Function,
+        symbol: &str,
+    ) -> Result<(usize, usize), Error> {
+        let count = |func: &ir::Function| {
+            func.layout
+                .blocks()
+                .map(|block| func.layout.block_insts(block).count())
+                .sum::<usize>()
+        };
+        let mut folded = ClifContext::for_function(func.clone());
+        folded.compute_cfg();
+        folded
+            .preopt(&*self.isa)
+            .map_err(|source| Error::FunctionDefinition {
+                symbol: symbol.to_string(),
+                source: source.into(),
+            })?;
+        Ok((count(func), count(&folded.func)))
+    }
 }
 
 // Hostcall trampolines have the general shape of:
@@ -764,6 +4617,7 @@ fn synthesize_trampoline(
 
     // We're going to load the stack limit later, create the global value to load while we
     // can.
+    let layout = VmContextLayout::new(decls.info.memories.len());
     let vmctx = trampoline_context
         .func
         .create_global_value(ir::GlobalValueData::VMContext);
@@ -771,12 +4625,12 @@ fn synthesize_trampoline(
         .func
         .create_global_value(ir::GlobalValueData::Load {
             base: vmctx,
-            offset: (-(std::mem::size_of::<InstanceRuntimeData>() as i32)
-                + (offset_of!(InstanceRuntimeData, stack_limit) as i32))
-                .into(),
+            offset: layout.stack_limit.into(),
             global_type: ir::types::I64,
             readonly: true,
         });
+    let host_trap_code = decls.get_host_trap_code(hostcall_func_index);
+    let host_trap_offset = layout.host_trap;
 
     let mut builder_ctx = FunctionBuilderContext::new();
     let mut builder = FunctionBuilder::new(&mut trampoline_context.func, &mut builder_ctx);
@@ -820,17 +4674,121 @@ fn synthesize_trampoline(
     let hostcall_args = builder.block_params(hostcall_block).to_vec();
     let call_inst = builder.ins().call(hostcall_ref, &hostcall_args);
     let results = builder.inst_results(call_inst).to_vec();
+    if let Some(code) = host_trap_code {
+        // The host function sets the flag while it runs, so it is loaded after the call. It is
+        // cleared before trapping, as an instance that faulted can run again without a reset.
+        let host_trap_block = builder.create_block();
+        let return_block = builder.create_block();
+        let flags = ir::MemFlags::trusted();
+        let vmctx_ptr = builder.ins().global_value(NATIVE_POINTER, vmctx);
+        let host_trap = builder
+            .ins()
+            .load(ir::types::I64, flags, vmctx_ptr, host_trap_offset);
+        builder.ins().brnz(host_trap, host_trap_block, &[]);
+        builder.ins().jump(return_block, &[]);
+
+        builder.switch_to_block(host_trap_block);
+        let cleared = builder.ins().iconst(ir::types::I64, 0);
+        builder
+            .ins()
+            .store(flags, cleared, vmctx_ptr, host_trap_offset);
+        builder.ins().trap(ir::TrapCode::User(code));
+
+        builder.switch_to_block(return_block);
+    }
     builder.ins().return_(&results);
 
     builder.switch_to_block(stack_check_fail);
     builder.ins().trap(ir::TrapCode::StackOverflow);
 
     let trampoline_name = format!("trampoline_{}", hostcall_name);
-    codegen_context.compile(&mut trampoline_context, trampoline_id, &trampoline_name)
+    codegen_context.compile_and_define(&mut trampoline_context, trampoline_id, &trampoline_name)
+}
+
+// The function uninitialized table slots point to, when `trap_uninit_table` is enabled. It is
+// never called through `call_indirect`, since the signature check for an uninitialized slot always
+// fails, so it takes only the vmctx and traps immediately:
+//
+// ```
+// fn lucet_uninit_table_entry(&vmctx) {
+//     trap(IndirectCallToNull);
+// }
+// ```
+fn synthesize_uninit_table_entry(
+    codegen_context: &CodegenContext,
+) -> Result<(FuncId, TrapMetadata), Error> {
+    let mut signature = ir::Signature::new(codegen_context.isa.default_call_conv());
+    signature.params.push(ir::AbiParam::special(
+        NATIVE_POINTER,
+        ir::ArgumentPurpose::VMContext,
+    ));
+    let func_id = codegen_context.module().declare_function(
+        UNINIT_TABLE_ENTRY_SYM,
+        ClifLinkage::Local,
+        &signature,
+    )?;
+
+    let mut context = ClifContext::new();
+    context.func.name = ir::ExternalName::from(func_id);
+    context.func.signature = signature;
+
+    let mut builder_ctx = FunctionBuilderContext::new();
+    let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_ctx);
+    let entry = builder.create_block();
+    builder.append_block_params_for_function_params(entry);
+    builder.switch_to_block(entry);
+    builder.seal_block(entry);
+    builder.ins().trap(ir::TrapCode::IndirectCallToNull);
+    builder.finalize();
+
+    let meta = codegen_context.compile_and_define(&mut context, func_id, UNINIT_TABLE_ENTRY_SYM)?;
+    Ok((func_id, meta))
+}
+
+// The default definition of a weak import, used when the host does not provide the import. It
+// has the import's signature, and traps as soon as it is called:
+//
+// ```
+// fn <import symbol>(&vmctx, ...) {
+//     trap(MissingImport);
+// }
+// ```
+fn synthesize_weak_import(
+    decls: &ModuleDecls<'_>,
+    codegen_context: &CodegenContext,
+    func_index: UniqueFuncIndex,
+) -> Result<(FuncId, TrapMetadata), Error> {
+    let func = decls
+        .get_func(func_index)
+        .expect("decl exists for imported func");
+    let symbol = func.name.symbol();
+    // Redeclaring the import as preemptible turns it into a weak definition.
+    let func_id = codegen_context.module().declare_function(
+        symbol,
+        ClifLinkage::Preemptible,
+        func.signature,
+    )?;
+
+    let mut context = ClifContext::new();
+    context.func.name = ir::ExternalName::from(func_id);
+    context.func.signature = func.signature.clone();
+
+    let mut builder_ctx = FunctionBuilderContext::new();
+    let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_ctx);
+    let entry = builder.create_block();
+    builder.append_block_params_for_function_params(entry);
+    builder.switch_to_block(entry);
+    builder.seal_block(entry);
+    builder.ins().trap(MISSING_IMPORT_TRAP);
+    builder.finalize();
+
+    let meta = codegen_context.compile_and_define(&mut context, func_id, symbol)?;
+    Ok((func_id, meta))
 }
 
 fn write_module_data(
     codegen_context: &CodegenContext,
+    module_data_symbol: &str,
     module_data_bytes: Vec<u8>,
 ) -> Result<DataId, Error> {
     use cranelift_module::{DataContext, Linkage};
@@ -840,7 +4798,7 @@ fn write_module_data(
 
     let module_data_decl = codegen_context
         .module()
-        .declare_data(MODULE_DATA_SYM, Linkage::Local, true, false)
+        .declare_data(module_data_symbol, Linkage::Local, true, false)
         .map_err(Error::ClifModuleError)?;
     codegen_context
         .module()
@@ -867,8 +4825,8 @@ impl TrapSites {
     fn new() -> Self {
         Self { traps: Vec::new() }
     }
-    /// Serialize for lucet_module:
-    fn serialize(&self) -> Box<[u8]> {
+    /// Serialize for lucet_module, in trap table format `version`:
+    fn serialize(&self, version: u8, func_name: &str) -> Result<Box<[u8]>, Error> {
         let traps: Vec<lucet_module::TrapSite> = self
             .traps
             .iter()
@@ -878,14 +4836,16 @@ impl TrapSites {
             })
             .collect();
 
-        let trap_site_bytes = unsafe {
-            std::slice::from_raw_parts(
-                traps.as_ptr() as *const u8,
-                traps.len() * std::mem::size_of::<lucet_module::TrapSite>(),
-            )
-        };
-
-        trap_site_bytes.to_vec().into()
+        // The version itself is checked when the compiler is created, so this only fails for a
+        // code the format can't hold.
+        lucet_module::TrapSite::serialize_versioned(&traps, version)
+            .map(Vec::into_boxed_slice)
+            .ok_or_else(|| {
+                Error::Unsupported(format!(
+                    "trap table version {} can't hold the trap codes of {}",
+                    version, func_name
+                ))
+            })
     }
     /// Write traps for a given function into the cranelift module:
     pub fn write(
@@ -893,20 +4853,38 @@ impl TrapSites {
         codegen_context: &CodegenContext,
         func_name: &str,
     ) -> Result<DataId, Error> {
-        let trap_sym = trap_sym_for_func(func_name);
-        let mut trap_sym_ctx = ClifDataContext::new();
-        trap_sym_ctx.define(self.serialize());
+        let serialized = self.serialize(codegen_context.trap_table_version, func_name)?;
+        let define = |trap_sym: &str, serialized: Box<[u8]>| -> Result<DataId, Error> {
+            let mut trap_sym_ctx = ClifDataContext::new();
+            trap_sym_ctx.define(serialized);
+
+            let trap_data_id = codegen_context.module().declare_data(
+                trap_sym,
+                ClifLinkage::Local,
+                false,
+                false,
+            )?;
 
-        let trap_data_id =
             codegen_context
                 .module()
-                .declare_data(&trap_sym, ClifLinkage::Local, false, false)?;
+                .define_data(trap_data_id, &trap_sym_ctx)?;
 
-        codegen_context
-            .module()
-            .define_data(trap_data_id, &trap_sym_ctx)?;
+            Ok(trap_data_id)
+        };
 
-        Ok(trap_data_id)
+        match codegen_context.trap_tables.as_ref() {
+            Some(trap_tables) => {
+                // Hold the lock while defining a new table, so that it is only defined once.
+                let mut trap_tables = trap_tables.lock().expect("possible to lock trap tables");
+                if let Some(trap_data_id) = trap_tables.get(&serialized) {
+                    return Ok(*trap_data_id);
+                }
+                let trap_data_id = define(&shared_trap_sym(&serialized), serialized.clone())?;
+                trap_tables.insert(serialized, trap_data_id);
+                Ok(trap_data_id)
+            }
+            None => define(&trap_sym_for_func(func_name), serialized),
+        }
     }
     pub fn len(&self) -> usize {
         self.traps.len()
@@ -934,6 +4912,7 @@ fn write_function_spec(
     manifest_bytes: &mut Cursor<Vec<u8>>,
     func_id: FuncId,
     metadata: Option<&TrapMetadata>,
+    compile_nanos: Option<u64>,
 ) -> Result<(), Error> {
     let size = metadata.as_ref().map(|m| m.func_size).unwrap_or(0);
     // This code has implicit knowledge of the layout of `FunctionSpec`!
@@ -961,10 +4940,32 @@ fn write_function_spec(
     // len data
     let trap_len = metadata.as_ref().map(|m| m.trap_len).unwrap_or(0);
     manifest_bytes.write_u64::<LittleEndian>(trap_len as u64)?;
+    // The compile time, with 0 for none recorded.
+    manifest_bytes.write_u64::<LittleEndian>(compile_nanos.unwrap_or(0))?;
 
     Ok(())
 }
 
+fn manifest_record(
+    codegen_context: &CodegenContext,
+    func_id: FuncId,
+    metadata: Option<&TrapMetadata>,
+    compile_nanos: Option<u64>,
+) -> ManifestRecord {
+    let module = codegen_context.module();
+    let declarations = module.declarations();
+    ManifestRecord {
+        symbol: declarations.get_function_decl(func_id).name.clone(),
+        code_len: metadata.map(|m| m.func_size).unwrap_or(0),
+        traps: metadata.filter(|m| m.trap_len > 0).map(|m| {
+            let trap_symbol = declarations.get_data_decl(m.trap_data_id).name.clone();
+            (trap_symbol, m.trap_len)
+        }),
+        trap_table_version: codegen_context.trap_table_version,
+        compile_nanos,
+    }
+}
+
 #[derive(Default)]
 struct ObjectRelocSink {
     relocs: Vec<RelocRecord>,
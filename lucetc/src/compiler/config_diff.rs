@@ -0,0 +1,144 @@
+//! Comparison of two compiler configurations, for diagnosing why a module behaves differently
+//! when built two ways.
+//!
+//! Settings are compared by their `Debug` representation, which also serves as the
+//! human-readable value in each difference. Settings that are callbacks, such as
+//! `on_heap_layout`, `function_opt_level`, and codegen checks, can't be compared and are left
+//! out, as are the validator, cancellation token, and previous incremental state.
+
+use crate::compiler::CompilerBuilder;
+use std::fmt;
+
+/// A setting that differs between two `CompilerBuilder`s, as reported by `CompilerBuilder::diff`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigDifference {
+    /// The name of the setting, matching the builder method that sets it. Heap settings are
+    /// named after their field, as in `heap_settings.guard_size`.
+    pub setting: &'static str,
+    /// The value in the builder `diff` was called on.
+    pub this: String,
+    /// The value in the builder it was compared against.
+    pub other: String,
+}
+
+impl fmt::Display for ConfigDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} vs. {}", self.setting, self.this, self.other)
+    }
+}
+
+/// The `Debug` representations of `items`, sorted, so that sets compare equal regardless of
+/// their iteration order.
+fn sorted<T: fmt::Debug>(items: impl IntoIterator<Item = T>) -> Vec<String> {
+    let mut items = items
+        .into_iter()
+        .map(|item| format!("{:?}", item))
+        .collect::<Vec<_>>();
+    items.sort();
+    items
+}
+
+impl CompilerBuilder {
+    /// The settings that differ between this builder and `other`, in a fixed order: the target,
+    /// optimization level, CPU features, and heap settings first, then the remaining options.
+    ///
+    /// CPU features are compared as the set of features each configuration enables, so a CPU
+    /// profile and the same features set individually compare equal. With `detect_cpuid`, that
+    /// is the set of features detected on this host.
+    pub fn diff(&self, other: &CompilerBuilder) -> Vec<ConfigDifference> {
+        let mut differences = Vec::new();
+        let mut check = |setting: &'static str, this: String, other: String| {
+            if this != other {
+                differences.push(ConfigDifference {
+                    setting,
+                    this,
+                    other,
+                });
+            }
+        };
+        macro_rules! compare {
+            ($setting:expr, |$b:ident| $value:expr) => {{
+                let value = |$b: &CompilerBuilder| format!("{:?}", $value);
+                check($setting, value(self), value(other))
+            }};
+            ($field:ident) => {
+                compare!(stringify!($field), |b| b.$field)
+            };
+        }
+
+        check("target", self.target.to_string(), other.target.to_string());
+        compare!("backend_variant", |b| b.variant);
+        compare!(opt_level);
+        compare!("cpu_features", |b| sorted(
+            b.cpu_features.enabled_features()
+        ));
+        compare!("heap_settings.min_reserved_size", |b| b
+            .heap_settings
+            .min_reserved_size);
+        compare!("heap_settings.max_reserved_size", |b| b
+            .heap_settings
+            .max_reserved_size);
+        compare!("heap_settings.guard_size", |b| b.heap_settings.guard_size);
+        compare!("heap_settings.page_size", |b| b.heap_settings.page_size);
+        compare!(auto_heap_settings);
+        compare!(hard_memory_limit);
+        compare!(target_version);
+
+        compare!(count_instructions);
+        compare!("interrupt_checks", |b| b.interrupt_check_period);
+        compare!(coverage);
+        compare!(canonicalize_nans);
+        compare!(flush_denormals);
+        compare!(deterministic_libcalls);
+        compare!(exact_div_traps);
+        compare!(allow_simd);
+        compare!(allow_multi_memory);
+        compare!("reject_wasm_feature", |b| sorted(
+            b.rejected_wasm_features.iter()
+        ));
+        compare!(unreachable_strategy);
+        compare!(trap_uninit_table);
+        compare!(preserve_frame_pointer);
+        compare!(spectre_mitigation);
+        compare!(forbid_stack_probe);
+        compare!(max_stack_size);
+        compare!(external_stack_probe);
+        compare!(forbid_float);
+        compare!(max_module_bytes);
+        compare!(max_total_code_size);
+        compare!(max_locals_per_function);
+        compare!(pad_code_to);
+        compare!("expect_export", |b| b.expected_exports);
+        compare!("allowed_import_modules", |b| b
+            .allowed_import_modules
+            .as_ref()
+            .map(sorted));
+        compare!("weak_imports", |b| sorted(b.weak_imports.iter()));
+        compare!("table_override", |b| b.table_overrides);
+        compare!("override_global", |b| b.global_overrides);
+
+        compare!(symbol_scheme);
+        compare!(symbol_prefix);
+        compare!(module_symbol);
+        compare!(export_all_functions);
+        compare!(function_sections);
+        compare!(function_order);
+        compare!(separate_data_segments);
+        compare!(bake_memory_image);
+        compare!(dedup_trap_tables);
+        compare!(trap_table_version);
+        compare!(emit_address_map);
+        compare!(emit_cfg_map);
+        compare!(emit_sig_table);
+        compare!(record_compile_times);
+        compare!(custom_metadata);
+        compare!(elf_os_abi);
+        compare!(elf_flags);
+        compare!(split_debug_info);
+        compare!(report_unbounded_loops);
+        compare!(report_const_fold);
+        compare!(report_recursion);
+
+        differences
+    }
+}
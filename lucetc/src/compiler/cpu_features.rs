@@ -182,6 +182,44 @@ impl CpuFeatures {
         self.specific_features.insert(sf, enabled);
     }
 
+    /// The specific features enabled by this configuration, after resolving the CPU profile and
+    /// any CPUID detection.
+    pub(crate) fn enabled_features(&self) -> HashSet<SpecificFeature> {
+        use SpecificFeature::*;
+
+        let mf = ModuleFeatures::from(self);
+        [
+            (SSE3, mf.sse3),
+            (SSSE3, mf.ssse3),
+            (SSE41, mf.sse41),
+            (SSE42, mf.sse42),
+            (Popcnt, mf.popcnt),
+            (AVX, mf.avx),
+            (BMI1, mf.bmi1),
+            (BMI2, mf.bmi2),
+            (Lzcnt, mf.lzcnt),
+        ]
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(feature, _)| *feature)
+        .collect()
+    }
+
+    /// Check that the features the x86-64 backend needs to lower SIMD instructions are enabled.
+    pub(crate) fn check_simd(&self) -> Result<(), Error> {
+        let enabled = self.enabled_features();
+        let missing = [SpecificFeature::SSSE3, SpecificFeature::SSE41]
+            .iter()
+            .filter(|feature| !enabled.contains(feature))
+            .copied()
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::SimdCpuFeatures { missing })
+        }
+    }
+
     /// Return a `cranelift_codegen::isa::Builder` configured with these CPU features.
 
     pub fn isa_builder(
@@ -0,0 +1,264 @@
+//! Reuse of translated Cranelift IR across compilations of a changing module.
+//!
+//! Translating wasm to Cranelift IR is a significant portion of compile time, and when a module is
+//! recompiled after a small edit most function bodies are byte-for-byte identical to the previous
+//! build. `PrevState` retains the translated `ir::Function` for each defined function along with a
+//! hash of its wasm body, so that `Compiler::object_file_incremental` can skip translation for
+//! functions that did not change.
+//!
+//! Retained IR refers to other functions and data by `ExternalName`, which are indices assigned by
+//! `cranelift-module` in declaration order and are not stable from one compilation to the next. We
+//! record the symbol behind each name when retaining a function, and rewrite the names against the
+//! new module's declarations when reusing it.
+
+use crate::compiler::CodegenContext;
+use crate::decls::ModuleDecls;
+use crate::error::Error;
+use crate::function::get_trampoline_func;
+use cranelift_codegen::ir;
+use cranelift_module::{DataId, FuncId, Module as ClifModule};
+use cranelift_wasm::wasmparser::FunctionBody;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// Translation results from a previous compilation, keyed by function symbol.
+///
+/// Pass the state returned by each `Compiler::object_file_incremental` call to
+/// `CompilerBuilder::prev_state` for the next compilation.
+#[derive(Default)]
+pub struct PrevState {
+    /// Hash of everything outside of function bodies that influences translation. If this
+    /// differs, none of the retained functions can be reused.
+    environment: u64,
+    funcs: HashMap<String, RetainedFunction>,
+    /// The number of retained functions whose IR was itself reused from the previous state.
+    reused: usize,
+}
+
+impl PrevState {
+    pub(crate) fn new(environment: u64) -> Self {
+        Self {
+            environment,
+            funcs: HashMap::new(),
+            reused: 0,
+        }
+    }
+
+    /// The number of functions with retained IR.
+    pub fn len(&self) -> usize {
+        self.funcs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.funcs.is_empty()
+    }
+
+    /// The number of functions the compilation producing this state did not have to translate,
+    /// because their IR was reused from the state before it.
+    pub fn reused(&self) -> usize {
+        self.reused
+    }
+
+    pub(crate) fn insert(&mut self, symbol: String, func: RetainedFunction, reused: bool) {
+        self.funcs.insert(symbol, func);
+        if reused {
+            self.reused += 1;
+        }
+    }
+
+    /// Get the IR for `symbol`, if it was retained from a compilation in the same environment and
+    /// the function body has not changed since.
+    pub(crate) fn reuse(
+        &self,
+        environment: u64,
+        symbol: &str,
+        body_hash: u64,
+        decls: &ModuleDecls<'_>,
+        codegen_context: &CodegenContext,
+    ) -> Result<Option<ir::Function>, Error> {
+        if environment != self.environment {
+            return Ok(None);
+        }
+        match self.funcs.get(symbol) {
+            Some(retained) if retained.body_hash == body_hash => {
+                retained.restore(decls, codegen_context)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+pub(crate) struct RetainedFunction {
+    body_hash: u64,
+    func: ir::Function,
+    /// The symbol referred to by each `ExternalName` in `func`, at the time it was retained.
+    symbols: HashMap<ir::ExternalName, String>,
+}
+
+impl RetainedFunction {
+    /// Retain a freshly translated function. This must be called before the function is compiled,
+    /// since compilation rewrites the IR in place.
+    pub(crate) fn new(
+        codegen_context: &CodegenContext,
+        body_hash: u64,
+        func: &ir::Function,
+    ) -> Self {
+        let module = codegen_context.module();
+        let declarations = module.declarations();
+        let symbols = external_names(func)
+            .filter_map(|name| {
+                let symbol = match name {
+                    ir::ExternalName::User { namespace: 0, .. } => declarations
+                        .get_function_decl(FuncId::from_name(name))
+                        .name
+                        .clone(),
+                    ir::ExternalName::User { namespace: 1, .. } => declarations
+                        .get_data_decl(DataId::from_name(name))
+                        .name
+                        .clone(),
+                    // Libcalls and test names are not indices into the module's declarations.
+                    _ => return None,
+                };
+                Some((name.clone(), symbol))
+            })
+            .collect();
+        Self {
+            body_hash,
+            func: func.clone(),
+            symbols,
+        }
+    }
+
+    /// Rewrite the retained IR to refer to this compilation's declarations.
+    ///
+    /// Every symbol the function refers to must already be declared, with the exception of
+    /// hostcall trampolines: those are declared on demand during translation, so we declare them
+    /// here just as translation would have. If any other symbol has gone away, the function must
+    /// be translated again.
+    fn restore(
+        &self,
+        decls: &ModuleDecls<'_>,
+        codegen_context: &CodegenContext,
+    ) -> Result<Option<ir::Function>, Error> {
+        let mut renames = HashMap::new();
+        for (old_name, symbol) in self.symbols.iter() {
+            let id = codegen_context.module().get_name(symbol);
+            let new_name = match id {
+                Some(id) => ir::ExternalName::from(id),
+                None => match restore_trampoline(decls, codegen_context, symbol)? {
+                    Some(name) => name,
+                    None => return Ok(None),
+                },
+            };
+            renames.insert(old_name.clone(), new_name);
+        }
+
+        let mut func = self.func.clone();
+        let rename = |name: &mut ir::ExternalName| {
+            if let Some(new_name) = renames.get(name) {
+                *name = new_name.clone();
+            }
+        };
+        rename(&mut func.name);
+        for ext_func in func.dfg.ext_funcs.values_mut() {
+            rename(&mut ext_func.name);
+        }
+        for global_value in func.global_values.values_mut() {
+            if let ir::GlobalValueData::Symbol { name, .. } = global_value {
+                rename(name);
+            }
+        }
+        Ok(Some(func))
+    }
+}
+
+fn restore_trampoline(
+    decls: &ModuleDecls<'_>,
+    codegen_context: &CodegenContext,
+    symbol: &str,
+) -> Result<Option<ir::ExternalName>, Error> {
+    let hostcall_symbol = match symbol.strip_prefix("trampoline_") {
+        Some(hostcall_symbol) => hostcall_symbol,
+        None => return Ok(None),
+    };
+    let hostcall_index = match decls.get_func_index(hostcall_symbol) {
+        Some(hostcall_index) => hostcall_index,
+        None => return Ok(None),
+    };
+    let hostcall_decl = decls
+        .get_func(hostcall_index)
+        .expect("decl exists for func index");
+    let name = get_trampoline_func(
+        codegen_context,
+        hostcall_index,
+        &hostcall_decl,
+        hostcall_decl.signature,
+    )?;
+    Ok(Some(name))
+}
+
+fn external_names(func: &ir::Function) -> impl Iterator<Item = &ir::ExternalName> {
+    let ext_funcs = func.dfg.ext_funcs.values().map(|ext_func| &ext_func.name);
+    let global_values = func
+        .global_values
+        .values()
+        .filter_map(|global_value| match global_value {
+            ir::GlobalValueData::Symbol { name, .. } => Some(name),
+            _ => None,
+        });
+    std::iter::once(&func.name)
+        .chain(ext_funcs)
+        .chain(global_values)
+}
+
+/// Hash the environment a module's functions are translated in: the module data (signatures,
+/// heap, globals, imports and exports, function names), the layout of every memory, the types of
+/// globals, the sizes of tables, and the `CompilerBuilder` options baked into translated IR.
+///
+/// Options that only take effect when the IR is compiled, such as the optimization level and
+/// the ISA flags, are left out: reused IR is compiled with the current options just as freshly
+/// translated IR is.
+pub(crate) fn environment_hash(
+    decls: &ModuleDecls<'_>,
+    codegen_context: &CodegenContext,
+    module_data_bytes: &[u8],
+    count_instructions: bool,
+    interrupt_check_period: Option<u32>,
+    coverage_layout: Option<&[u8]>,
+) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    module_data_bytes.hash(&mut hasher);
+    count_instructions.hash(&mut hasher);
+    interrupt_check_period.hash(&mut hasher);
+    codegen_context.memory_limit().hash(&mut hasher);
+    codegen_context.unreachable_handler().hash(&mut hasher);
+    // The layout fixes which counters each function increments, so any change to it
+    // invalidates every function in a coverage build.
+    coverage_layout.hash(&mut hasher);
+    decls.target_config().pointer_bits().hash(&mut hasher);
+    for memory_index in decls.info.memories.keys() {
+        if let Some(heap) = decls.get_heap_for(memory_index) {
+            heap.reserved_size.hash(&mut hasher);
+            heap.guard_size.hash(&mut hasher);
+            heap.initial_size.hash(&mut hasher);
+            heap.max_size.hash(&mut hasher);
+        }
+    }
+    for global in decls.info.globals.values() {
+        global.entity.ty.hash(&mut hasher);
+    }
+    for table in decls.info.tables.values() {
+        table.entity.minimum.hash(&mut hasher);
+        table.entity.maximum.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+pub(crate) fn body_hash(body: &FunctionBody<'_>) -> Result<u64, Error> {
+    let mut reader = body.get_binary_reader();
+    let bytes = reader.read_bytes(reader.bytes_remaining())?;
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    Ok(hasher.finish())
+}
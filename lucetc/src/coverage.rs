@@ -0,0 +1,219 @@
+use crate::decls::ModuleDecls;
+use crate::error::Error;
+use crate::module::UniqueFuncIndex;
+use crate::name::Name;
+use byteorder::{ByteOrder, LittleEndian};
+use cranelift_wasm::wasmparser::{FunctionBody, Operator};
+use std::collections::HashMap;
+
+/// The symbol of the coverage buffer that instrumented code increments counters in. The host
+/// provides it; see `CompilerBuilder::coverage`.
+pub(crate) const COVERAGE_COUNTERS_SYM: &str = "lucet_coverage_counters";
+
+/// The custom metadata key the coverage counter layout is stored under.
+pub(crate) const COVERAGE_METADATA_KEY: &str = "coverage";
+
+/// The size of each counter in the coverage buffer: a native-endian `u64`.
+pub const COVERAGE_COUNTER_SIZE: usize = 8;
+
+/// What a coverage-instrumented build counts. See `CompilerBuilder::coverage`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CoverageMode {
+    /// Count each entry to a function.
+    FunctionEntry,
+    /// Count each entry to a function, and each time control reaches the start of a block
+    /// within it.
+    BasicBlock,
+}
+
+/// A counter in the coverage buffer. Counter `i` in the layout of an object is the `i`th
+/// `u64` of the buffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CoverageCounter {
+    /// The symbol of the function the counter is in.
+    pub symbol: String,
+    /// For a block counter, the offset in the module of the wasm instruction that starts the
+    /// block; `None` for the counter of entries to the function.
+    pub wasm_offset: Option<u32>,
+}
+
+impl CoverageCounter {
+    /// Deserialize the counter layout stored in the coverage metadata, in counter order.
+    pub(crate) fn deserialize(bytes: &[u8]) -> Result<Vec<Self>, Error> {
+        let mut counters = Vec::new();
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let len = LittleEndian::read_u32(take(&mut rest, 4)?) as usize;
+            let symbol = String::from_utf8(take(&mut rest, len)?.to_vec())
+                .map_err(|_| Error::Input("coverage layout has a non-UTF-8 symbol".to_owned()))?;
+            counters.push(CoverageCounter {
+                symbol: symbol.clone(),
+                wasm_offset: None,
+            });
+            let blocks = LittleEndian::read_u32(take(&mut rest, 4)?);
+            for _ in 0..blocks {
+                counters.push(CoverageCounter {
+                    symbol: symbol.clone(),
+                    wasm_offset: Some(LittleEndian::read_u32(take(&mut rest, 4)?)),
+                });
+            }
+        }
+        Ok(counters)
+    }
+}
+
+/// Serialize the counter layout as it is stored in the coverage metadata: for each function, its
+/// symbol as a little-endian `u32` length followed by the bytes, then its block counters as a
+/// little-endian `u32` count followed by each block's wasm offset as a little-endian `u32`. A
+/// function's entry counter comes before its block counters, and is implied.
+pub(crate) fn serialize_coverage_layout(layout: &[(UniqueFuncIndex, FunctionCounters)]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for (_func_index, func) in layout {
+        bytes.extend_from_slice(&(func.symbol.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(func.symbol.as_bytes());
+        bytes.extend_from_slice(&(func.block_offsets.len() as u32).to_le_bytes());
+        for offset in func.block_offsets.iter() {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+/// Split the first `len` bytes off of `rest`.
+fn take<'a>(rest: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if rest.len() < len {
+        return Err(Error::Input("coverage layout is truncated".to_owned()));
+    }
+    let (taken, tail) = (*rest).split_at(len);
+    *rest = tail;
+    Ok(taken)
+}
+
+/// The counters of one function: its entry counter, then one for each block start.
+pub(crate) struct FunctionCounters {
+    symbol: String,
+    block_offsets: Vec<u32>,
+}
+
+/// Finds the operators after which a new block starts, for per-block coverage.
+///
+/// A block starts at the top of a loop, at the start of each arm of an `if`, after the `end` of
+/// any construct other than the function body, and after a `br_if`, where control continues when
+/// the branch is not taken. The same operators must be found when laying out the counters and
+/// when instrumenting, so both go through this.
+#[derive(Default)]
+pub(crate) struct BlockStarts {
+    depth: u32,
+}
+
+impl BlockStarts {
+    /// Whether a block starts after `op`, which must be the next operator in the function.
+    pub(crate) fn after(&mut self, op: &Operator<'_>) -> bool {
+        match op {
+            Operator::Block { .. } => {
+                self.depth += 1;
+                false
+            }
+            Operator::Loop { .. } | Operator::If { .. } => {
+                self.depth += 1;
+                true
+            }
+            Operator::End if self.depth == 0 => false,
+            Operator::End => {
+                self.depth -= 1;
+                true
+            }
+            Operator::Else | Operator::BrIf { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// Lay out the coverage counters for the functions with `function_bodies`, in function index
+/// order.
+pub(crate) fn coverage_layout<'a>(
+    decls: &ModuleDecls<'_>,
+    mode: CoverageMode,
+    function_bodies: impl Iterator<Item = (UniqueFuncIndex, &'a FunctionBody<'a>)>,
+) -> Result<Vec<(UniqueFuncIndex, FunctionCounters)>, Error> {
+    let mut function_bodies = function_bodies.collect::<Vec<_>>();
+    function_bodies.sort_by_key(|(func_index, _body)| *func_index);
+    let mut functions = Vec::with_capacity(function_bodies.len());
+    for (func_index, body) in function_bodies {
+        let func = decls
+            .get_func(func_index)
+            .expect("decl exists for func body");
+        let mut block_offsets = Vec::new();
+        if mode == CoverageMode::BasicBlock {
+            let mut block_starts = BlockStarts::default();
+            let mut reader = body.get_operators_reader()?;
+            while !reader.eof() {
+                let (op, offset) = reader.read_with_offset()?;
+                if block_starts.after(&op) {
+                    block_offsets.push(offset as u32);
+                }
+            }
+        }
+        functions.push((
+            func_index,
+            FunctionCounters {
+                symbol: func.name.symbol().to_owned(),
+                block_offsets,
+            },
+        ));
+    }
+    Ok(functions)
+}
+
+/// The coverage instrumentation for a module: the mode, the coverage buffer, and where each
+/// function's counters start in it.
+pub(crate) struct CoverageCounters {
+    mode: CoverageMode,
+    buffer: Name,
+    first_counters: HashMap<UniqueFuncIndex, u32>,
+}
+
+impl CoverageCounters {
+    pub(crate) fn new(
+        mode: CoverageMode,
+        buffer: Name,
+        layout: &[(UniqueFuncIndex, FunctionCounters)],
+    ) -> Self {
+        let mut next = 0;
+        let first_counters = layout
+            .iter()
+            .map(|(func_index, func)| {
+                let first = next;
+                next += 1 + func.block_offsets.len() as u32;
+                (*func_index, first)
+            })
+            .collect();
+        Self {
+            mode,
+            buffer,
+            first_counters,
+        }
+    }
+
+    /// The instrumentation for the function `func_index`.
+    pub(crate) fn for_function(&self, func_index: UniqueFuncIndex) -> Option<FunctionCoverage> {
+        self.first_counters
+            .get(&func_index)
+            .map(|first_counter| FunctionCoverage {
+                mode: self.mode,
+                buffer: self.buffer.clone(),
+                next_counter: *first_counter,
+                block_starts: BlockStarts::default(),
+            })
+    }
+}
+
+/// The coverage instrumentation for one function, as it is translated.
+pub(crate) struct FunctionCoverage {
+    pub(crate) mode: CoverageMode,
+    pub(crate) buffer: Name,
+    /// The index of the counter for the next block to start; the entry counter, before
+    /// translation begins.
+    pub(crate) next_counter: u32,
+    pub(crate) block_starts: BlockStarts,
+}
@@ -0,0 +1,42 @@
+use crate::compiler::CodegenContext;
+use crate::error::Error;
+use cranelift_module::{DataContext, Linkage, Module as ClifModule};
+
+/// The prefix of the symbol for each piece of custom metadata.
+pub(crate) const CUSTOM_METADATA_PREFIX: &str = "lucet_meta_";
+
+/// The symbol for the custom metadata stored under `key`. The metadata is in its own section of
+/// the object file, named after the symbol with a leading `.`.
+pub(crate) fn custom_metadata_sym(key: &str) -> String {
+    format!("{}{}", CUSTOM_METADATA_PREFIX, key)
+}
+
+/// Check that `key` can be used in a symbol and section name.
+pub(crate) fn check_custom_metadata_key(key: &str) -> Result<(), Error> {
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(Error::Input(format!(
+            "custom metadata key {:?} must be non-empty and only contain ASCII letters, digits, \
+             and underscores",
+            key
+        )));
+    }
+    Ok(())
+}
+
+/// Write the custom metadata stored under `key` into its own section of the object file.
+pub(crate) fn write_custom_metadata(
+    codegen_context: &CodegenContext,
+    key: &str,
+    value: &[u8],
+) -> Result<(), Error> {
+    let symbol = custom_metadata_sym(key);
+    let mut data_ctx = DataContext::new();
+    data_ctx.set_segment_section("", &format!(".{}", symbol));
+    data_ctx.define(value.into());
+
+    let data_id = codegen_context
+        .module()
+        .declare_data(&symbol, Linkage::Export, false, false)?;
+    codegen_context.module().define_data(data_id, &data_ctx)?;
+    Ok(())
+}
@@ -0,0 +1,236 @@
+use crate::compiler::CodegenContext;
+use crate::error::Error;
+use crate::module::ModuleInfo;
+use byteorder::{LittleEndian, WriteBytesExt};
+use cranelift_codegen::entity::EntityRef;
+use cranelift_module::{DataContext, DataId, Linkage, Module as ClifModule};
+use cranelift_wasm::{DataIndex, GlobalIndex, MemoryIndex};
+use lucet_module::HeapSpec;
+use std::io::{Cursor, Write};
+
+/// This symbol refers to the serialized data segments, when they are emitted separately from the
+/// module data.
+pub const DATA_SEGMENTS_SYM: &str = "lucet_data_segments";
+/// The object file section holding `DATA_SEGMENTS_SYM`.
+pub const DATA_SEGMENTS_SECTION: &str = ".lucet_data_segments";
+/// This symbol refers to the initial contents of the heap, when the memory image is baked in.
+pub const HEAP_IMAGE_SYM: &str = "lucet_heap_image";
+/// The object file section holding `HEAP_IMAGE_SYM`.
+pub const HEAP_IMAGE_SECTION: &str = ".lucet_heap_image";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataSegmentKind {
+    /// Copied into `memory_index` at instantiation. The offset in linear memory is `offset`, plus
+    /// the value of the global `base` if there is one.
+    Active {
+        memory_index: MemoryIndex,
+        base: Option<GlobalIndex>,
+        offset: usize,
+    },
+    /// Only copied into memory by a `memory.init` instruction referring to `data_index`.
+    Passive { data_index: DataIndex },
+}
+
+/// A data segment, as declared in the wasm binary.
+#[derive(Debug, Clone)]
+pub struct DataSegment<'a> {
+    pub kind: DataSegmentKind,
+    pub data: &'a [u8],
+}
+
+impl<'a> DataSegment<'a> {
+    pub fn is_active(&self) -> bool {
+        match self.kind {
+            DataSegmentKind::Active { .. } => true,
+            DataSegmentKind::Passive { .. } => false,
+        }
+    }
+}
+
+/// Collect the data segments of a module: active segments in memory index order, followed by
+/// passive segments in data index order. Active segments for the same memory are in the order
+/// they are declared, which is the order they must be applied in.
+pub fn data_segments<'a>(info: &ModuleInfo<'a>) -> Vec<DataSegment<'a>> {
+    let mut memories: Vec<_> = info.data_initializers.iter().collect();
+    memories.sort_by_key(|(memory_index, _)| **memory_index);
+    let active = memories
+        .into_iter()
+        .flat_map(|(memory_index, initializers)| {
+            initializers.iter().map(move |initializer| DataSegment {
+                kind: DataSegmentKind::Active {
+                    memory_index: *memory_index,
+                    base: initializer.base,
+                    offset: initializer.offset,
+                },
+                data: initializer.data,
+            })
+        });
+
+    let mut passive: Vec<_> = info
+        .passive_data
+        .iter()
+        .map(|(data_index, data)| DataSegment {
+            kind: DataSegmentKind::Passive {
+                data_index: *data_index,
+            },
+            data: *data,
+        })
+        .collect();
+    passive.sort_by_key(|segment| match segment.kind {
+        DataSegmentKind::Passive { data_index } => data_index,
+        DataSegmentKind::Active { .. } => unreachable!(),
+    });
+
+    active.chain(passive).collect()
+}
+
+/// Where a `MemoryInitOp` copies its data to in linear memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitOffset {
+    /// A constant offset, known at compile time.
+    Constant(usize),
+    /// The value of the global `global_index`, which is always imported, plus `offset`. This is
+    /// only known at instantiation.
+    Global {
+        global_index: GlobalIndex,
+        offset: usize,
+    },
+}
+
+/// One step of initializing linear memory at instantiation: copying `data` into memory
+/// `memory_index` at `offset`.
+#[derive(Debug, Clone)]
+pub struct MemoryInitOp<'a> {
+    pub memory_index: MemoryIndex,
+    pub offset: InitOffset,
+    pub data: &'a [u8],
+}
+
+/// The steps that initialize linear memory from the module's active data segments, in the order
+/// they must be applied. Segments for different memories are independent, so they are grouped by
+/// memory, in memory index order.
+pub fn memory_init_program<'a>(info: &ModuleInfo<'a>) -> Vec<MemoryInitOp<'a>> {
+    data_segments(info)
+        .into_iter()
+        .filter_map(|segment| match segment.kind {
+            DataSegmentKind::Active {
+                memory_index,
+                base,
+                offset,
+            } => Some(MemoryInitOp {
+                memory_index,
+                offset: match base {
+                    Some(global_index) => InitOffset::Global {
+                        global_index,
+                        offset,
+                    },
+                    None => InitOffset::Constant(offset),
+                },
+                data: segment.data,
+            }),
+            DataSegmentKind::Passive { .. } => None,
+        })
+        .collect()
+}
+
+/// Write the data segments into their own section of the object file.
+///
+/// The contents are a little-endian `u64` count of segments, followed by each segment as:
+///
+/// * `u32` kind: 0 for active, 1 for passive
+/// * `u32` index: the memory index of an active segment, or the data index of a passive one
+/// * `u32` base: the global index of an active segment's base, or `u32::MAX` if it has none
+/// * `u32` reserved, always 0
+/// * `u64` offset: the offset of an active segment, or 0
+/// * `u64` length of the data, followed by the data itself, padded with zeros to 8 bytes
+pub fn write_data_segments(
+    codegen_context: &CodegenContext,
+    segments: &[DataSegment<'_>],
+) -> Result<DataId, Error> {
+    let mut bytes = Cursor::new(Vec::new());
+    bytes.write_u64::<LittleEndian>(segments.len() as u64)?;
+    for segment in segments {
+        let (kind, index, base, offset) = match segment.kind {
+            DataSegmentKind::Active {
+                memory_index,
+                base,
+                offset,
+            } => (
+                0,
+                memory_index.index() as u32,
+                base.map(|base| base.index() as u32).unwrap_or(u32::MAX),
+                offset as u64,
+            ),
+            DataSegmentKind::Passive { data_index } => (1, data_index.index() as u32, u32::MAX, 0),
+        };
+        bytes.write_u32::<LittleEndian>(kind)?;
+        bytes.write_u32::<LittleEndian>(index)?;
+        bytes.write_u32::<LittleEndian>(base)?;
+        bytes.write_u32::<LittleEndian>(0)?;
+        bytes.write_u64::<LittleEndian>(offset)?;
+        bytes.write_u64::<LittleEndian>(segment.data.len() as u64)?;
+        bytes.write_all(segment.data)?;
+        let padding = (8 - segment.data.len() % 8) % 8;
+        bytes.write_all(&[0; 8][..padding])?;
+    }
+
+    let mut data_ctx = DataContext::new();
+    data_ctx.set_segment_section("", DATA_SEGMENTS_SECTION);
+    data_ctx.define(bytes.into_inner().into_boxed_slice());
+
+    let data_id =
+        codegen_context
+            .module()
+            .declare_data(DATA_SEGMENTS_SYM, Linkage::Export, false, false)?;
+    codegen_context.module().define_data(data_id, &data_ctx)?;
+    Ok(data_id)
+}
+
+/// Apply the active data segments for the heap to produce its initial contents, and write them
+/// into their own section of the object file. The image covers the heap's initial size.
+///
+/// Unlike instantiation, which applies segments in order so that later segments overwrite
+/// earlier ones, segments that overlap are an error here: a baked image must not depend on the
+/// order segments were declared in.
+pub fn write_heap_image(
+    codegen_context: &CodegenContext,
+    info: &ModuleInfo<'_>,
+    heap: &HeapSpec,
+) -> Result<DataId, Error> {
+    let mut image = vec![0u8; heap.initial_size as usize];
+    let mut written: Vec<(usize, usize)> = Vec::new();
+    let initializers = info
+        .data_initializers
+        .get(&MemoryIndex::new(0))
+        .map(|initializers| initializers.as_slice())
+        .unwrap_or(&[]);
+    for initializer in initializers {
+        if initializer.base.is_some() {
+            let message = "cannot bake heap image: data initializer uses global as base".to_owned();
+            return Err(Error::Unsupported(message));
+        }
+        let start = initializer.offset;
+        let end = start
+            .checked_add(initializer.data.len())
+            .ok_or(Error::InitData)?;
+        if end > image.len() {
+            return Err(Error::InitData);
+        }
+        if written.iter().any(|(s, e)| start < *e && *s < end) {
+            return Err(Error::OverlappingDataSegments { offset: start });
+        }
+        written.push((start, end));
+        image[start..end].copy_from_slice(initializer.data);
+    }
+
+    let mut data_ctx = DataContext::new();
+    data_ctx.set_segment_section("", HEAP_IMAGE_SECTION);
+    data_ctx.define(image.into_boxed_slice());
+
+    let data_id =
+        codegen_context
+            .module()
+            .declare_data(HEAP_IMAGE_SYM, Linkage::Export, false, false)?;
+    codegen_context.module().define_data(data_id, &data_ctx)?;
+    Ok(data_id)
+}
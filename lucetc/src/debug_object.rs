@@ -0,0 +1,210 @@
+//! Debugging information split out of an object file by `Compiler::object_file_split`.
+//!
+//! The production object and its debug object are tied together by a build ID, stored in both as
+//! a GNU build ID note, so the debug object for a deployed module can be found by its build ID.
+//! The debug object is not DWARF, though, so native debuggers and symbolizers don't understand
+//! it: tools have to read its symbols, as described on `DebugObject`, themselves.
+
+use crate::address_map::{address_map_sym, AddressMapEntry};
+use crate::error::Error;
+use object::write::{self, Object, SymbolSection};
+use object::{
+    Architecture, BinaryFormat, Endianness, SectionKind, SymbolFlags, SymbolKind, SymbolScope,
+};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// The section holding an object's build ID note.
+pub(crate) const BUILD_ID_SECTION: &str = ".note.gnu.build-id";
+
+/// The size of a build ID in bytes: SHA-256 truncated to the length of the SHA-1 build IDs that
+/// GNU ld generates, which is what most tools expect.
+pub(crate) const BUILD_ID_SIZE: usize = 20;
+
+/// The section of a debug object holding its address maps and function names.
+const DEBUG_SECTION: &str = ".lucet_debug";
+
+const NT_GNU_BUILD_ID: u32 = 3;
+const GNU_NOTE_NAME: &[u8; 4] = b"GNU\0";
+
+/// The symbol for the name-section name of the function with symbol `sym`.
+fn function_name_sym(sym: &str) -> String {
+    format!("lucet_function_name_{}", sym)
+}
+
+/// The contents of a GNU build ID note for `build_id`: the name size, descriptor size, and type
+/// as 32-bit words in the object's byte order, then the name and the build ID itself.
+pub(crate) fn build_id_note(build_id: &[u8], endianness: Endianness) -> Vec<u8> {
+    let word = |value: u32| match endianness {
+        Endianness::Little => value.to_le_bytes(),
+        Endianness::Big => value.to_be_bytes(),
+    };
+    let mut note = Vec::with_capacity(12 + GNU_NOTE_NAME.len() + build_id.len());
+    note.extend_from_slice(&word(GNU_NOTE_NAME.len() as u32));
+    note.extend_from_slice(&word(build_id.len() as u32));
+    note.extend_from_slice(&word(NT_GNU_BUILD_ID));
+    note.extend_from_slice(GNU_NOTE_NAME);
+    note.extend_from_slice(build_id);
+    note
+}
+
+/// The build ID in the contents of a GNU build ID note, or `None` if `note` is not one.
+pub(crate) fn parse_build_id_note(note: &[u8], endianness: Endianness) -> Option<Vec<u8>> {
+    let word = |at: usize| {
+        let bytes = [note[at], note[at + 1], note[at + 2], note[at + 3]];
+        match endianness {
+            Endianness::Little => u32::from_le_bytes(bytes),
+            Endianness::Big => u32::from_be_bytes(bytes),
+        }
+    };
+    let header_len = 12 + GNU_NOTE_NAME.len();
+    if note.len() < header_len
+        || word(0) as usize != GNU_NOTE_NAME.len()
+        || word(8) != NT_GNU_BUILD_ID
+        || &note[12..header_len] != GNU_NOTE_NAME
+    {
+        return None;
+    }
+    note.get(header_len..header_len + word(4) as usize)
+        .map(|build_id| build_id.to_vec())
+}
+
+/// A function's debugging information in a `DebugObject`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DebugFunction {
+    /// The function's symbol. The production object only keeps it if the function is exported.
+    pub symbol: String,
+    /// The offset of the function's code in the production object's code section.
+    pub code_offset: u64,
+    /// The length of the function's code in bytes.
+    pub code_len: u64,
+    /// The function's name from the module's name section, if it has one.
+    pub name: Option<String>,
+    /// The function's address map, as `ObjectFile::address_map_for` would return it. Empty for
+    /// functions with no code translated from wasm.
+    pub address_map: Vec<AddressMapEntry>,
+}
+
+/// The debugging information for a production object, as returned by
+/// `Compiler::object_file_split`.
+///
+/// lucetc does not emit DWARF, so the debug object holds what lucetc does know about the code:
+/// each function's symbol and where its code is, its name from the module's name section, and its
+/// address map. When written, it is an ELF object for the same target as the production object,
+/// with the same build ID note. Each function's symbol is an absolute local symbol whose value is
+/// the offset of its code in the production object's code section, and its address map and name
+/// are in local data symbols named `lucet_address_map_<symbol>` and
+/// `lucet_function_name_<symbol>`.
+#[derive(Clone, Debug)]
+pub struct DebugObject {
+    build_id: Vec<u8>,
+    architecture: Architecture,
+    endianness: Endianness,
+    functions: Vec<DebugFunction>,
+}
+
+impl DebugObject {
+    pub(crate) fn new(
+        build_id: Vec<u8>,
+        architecture: Architecture,
+        endianness: Endianness,
+        functions: Vec<DebugFunction>,
+    ) -> Self {
+        Self {
+            build_id,
+            architecture,
+            endianness,
+            functions,
+        }
+    }
+
+    /// The build ID of the production object this debug object belongs to. See
+    /// `ObjectFile::build_id`.
+    pub fn build_id(&self) -> &[u8] {
+        &self.build_id
+    }
+
+    /// Every function defined from wasm in the production object, in function index order.
+    pub fn functions(&self) -> &[DebugFunction] {
+        &self.functions
+    }
+
+    /// The address map of the function with the given symbol, or `None` if the function has no
+    /// code translated from wasm.
+    pub fn address_map_for(&self, symbol: &str) -> Option<&[AddressMapEntry]> {
+        self.functions
+            .iter()
+            .find(|func| func.symbol == symbol && !func.address_map.is_empty())
+            .map(|func| func.address_map.as_slice())
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut file = File::create(path)?;
+        let bytes = self.bytes()?;
+        file.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// The contents of the debug object, as `write` would write them.
+    pub fn bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut obj = Object::new(BinaryFormat::Elf, self.architecture, self.endianness);
+
+        let note_section = obj.add_section(
+            vec![],
+            BUILD_ID_SECTION.as_bytes().to_vec(),
+            SectionKind::Note,
+        );
+        obj.append_section_data(
+            note_section,
+            &build_id_note(&self.build_id, self.endianness),
+            4,
+        );
+
+        for func in self.functions.iter() {
+            obj.add_symbol(write::Symbol {
+                name: func.symbol.as_bytes().to_vec(),
+                value: func.code_offset,
+                size: func.code_len,
+                kind: SymbolKind::Text,
+                scope: SymbolScope::Compilation,
+                weak: false,
+                section: SymbolSection::Absolute,
+                flags: SymbolFlags::None,
+            });
+        }
+
+        let debug_section = obj.add_section(
+            vec![],
+            DEBUG_SECTION.as_bytes().to_vec(),
+            SectionKind::Debug,
+        );
+        let mut add_data = |name: String, data: &[u8]| {
+            let offset = obj.append_section_data(debug_section, data, 4);
+            obj.add_symbol(write::Symbol {
+                name: name.into_bytes(),
+                value: offset,
+                size: data.len() as u64,
+                kind: SymbolKind::Data,
+                scope: SymbolScope::Compilation,
+                weak: false,
+                section: SymbolSection::Section(debug_section),
+                flags: SymbolFlags::None,
+            });
+        };
+        for func in self.functions.iter() {
+            if !func.address_map.is_empty() {
+                add_data(
+                    address_map_sym(&func.symbol),
+                    &AddressMapEntry::serialize(&func.address_map),
+                );
+            }
+            if let Some(name) = &func.name {
+                add_data(function_name_sym(&func.symbol), name.as_bytes());
+            }
+        }
+
+        obj.write()
+            .map_err(|source| Error::ObjectArtifact(source, "Write error".to_owned()))
+    }
+}
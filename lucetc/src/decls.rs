@@ -5,7 +5,7 @@ pub use crate::module::{Exportable, TableElems};
 use crate::module::{ModuleInfo, UniqueFuncIndex};
 use crate::name::Name;
 use crate::runtime::{Runtime, RuntimeFunc};
-use crate::table::TABLE_SYM;
+use crate::table::{TABLE_DATA_PREFIX, TABLE_SYM};
 use crate::types::to_lucet_signature;
 use cranelift_codegen::entity::{EntityRef, PrimaryMap};
 use cranelift_codegen::ir;
@@ -13,7 +13,7 @@ use cranelift_codegen::isa::TargetFrontendConfig;
 use cranelift_module::{Linkage, Module as ClifModule};
 use cranelift_wasm::{
     Global, GlobalIndex, GlobalInit, MemoryIndex, Table, TableIndex, TargetEnvironment, TypeIndex,
-    WasmFuncType,
+    WasmFuncType, WasmType,
 };
 use lucet_module::bindings::Bindings;
 use lucet_module::ModuleFeatures;
@@ -22,7 +22,7 @@ use lucet_module::{
     FunctionMetadata, Global as GlobalVariant, GlobalDef, GlobalSpec, HeapSpec, ImportFunction,
     ModuleData, Signature as LucetSignature, UniqueSignatureIndex,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug)]
 pub struct FunctionDecl<'a> {
@@ -65,6 +65,88 @@ pub struct TableDecl<'a> {
     pub contents_name: Name,
 }
 
+/// A callback consulted for each imported function as it is declared, with its module, field,
+/// and signature, which returns `false` to reject the import. See `CompilerBuilder::import_filter`.
+pub(crate) type ImportFilter = dyn Fn(&str, &str, &ir::Signature) -> bool + Send + Sync;
+
+/// How symbols are chosen for the functions defined in a module. Imported functions always use
+/// the symbol given by the bindings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SymbolScheme {
+    /// `guest_func_<export>` for exported functions, `<name>_<index>` for functions named in the
+    /// names section, and `guest_func_<index>` otherwise.
+    Default,
+    /// `guest_func_<export>` for exported functions, and `guest_func_<index>` for all others,
+    /// regardless of the names section.
+    Index,
+    /// `<module>__<export>` for exported functions, `<module>__<name>_<index>` for functions named
+    /// in the names section, and `<module>__<index>` otherwise.
+    Qualified { module: String },
+}
+
+impl Default for SymbolScheme {
+    fn default() -> Self {
+        SymbolScheme::Default
+    }
+}
+
+impl SymbolScheme {
+    fn exported_symbol(&self, export_name: &str) -> String {
+        match self {
+            SymbolScheme::Default | SymbolScheme::Index => format!("guest_func_{}", export_name),
+            SymbolScheme::Qualified { module } => format!("{}__{}", module, export_name),
+        }
+    }
+
+    // Names in the names section don't have to be unique, so we append the index to them.
+    fn local_symbol(&self, index: usize, custom_name: Option<&str>) -> String {
+        match (self, custom_name) {
+            (SymbolScheme::Default, Some(name)) => format!("{}_{}", name, index),
+            (SymbolScheme::Default, None) | (SymbolScheme::Index, _) => {
+                format!("guest_func_{}", index)
+            }
+            (SymbolScheme::Qualified { module }, Some(name)) => {
+                format!("{}__{}_{}", module, name, index)
+            }
+            (SymbolScheme::Qualified { module }, None) => format!("{}__{}", module, index),
+        }
+    }
+}
+
+/// A global variable in a module, as declared in the wasm binary.
+#[derive(Debug, Clone)]
+pub struct GlobalInfo<'a> {
+    pub index: GlobalIndex,
+    pub ty: WasmType,
+    pub mutable: bool,
+    pub initial_value: GlobalInitialValue<'a>,
+    pub export_names: Vec<&'a str>,
+}
+
+/// A function imported by a module, with the symbol the object file expects the host to provide
+/// for it.
+#[derive(Debug, Clone)]
+pub struct ImportInfo<'a> {
+    pub index: UniqueFuncIndex,
+    pub module: &'a str,
+    pub field: &'a str,
+    /// The symbol the import's binding resolves to.
+    pub symbol: &'a str,
+    pub signature: WasmFuncType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GlobalInitialValue<'a> {
+    /// Initialized to a constant.
+    Const(GlobalDef),
+    /// Imported from another module; the value is provided at instantiation.
+    Import { module: &'a str, field: &'a str },
+    /// Initialized to the value of another global, which is only known at instantiation.
+    Computed { global: GlobalIndex },
+    /// Initialized with a kind of value Lucet does not support.
+    Unsupported,
+}
+
 pub struct ModuleDecls<'a> {
     pub info: ModuleInfo<'a>,
     function_names: PrimaryMap<UniqueFuncIndex, Name>,
@@ -75,20 +157,31 @@ pub struct ModuleDecls<'a> {
     runtime_names: HashMap<RuntimeFunc, UniqueFuncIndex>,
     globals_spec: Vec<GlobalSpec<'a>>,
     linear_memory_spec: Option<OwnedLinearMemorySpec>,
+    /// Heaps for memories other than memory 0, which are not described by the module data.
+    additional_heaps: Vec<HeapSpec>,
+    /// The host-defined trap code of each import bound with one.
+    host_trap_codes: HashMap<UniqueFuncIndex, u16>,
 }
 
 impl<'a> ModuleDecls<'a> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         info: ModuleInfo<'a>,
         codegen_context: &CodegenContext,
         bindings: &'a Bindings,
         runtime: Runtime,
         heap_settings: HeapSettings,
+        export_all_functions: bool,
+        allow_multi_memory: bool,
+        symbol_scheme: &SymbolScheme,
+        import_filter: Option<&ImportFilter>,
     ) -> Result<Self, Error> {
         let imports: Vec<ImportFunction<'a>> = Vec::with_capacity(info.imported_funcs.len());
         let (tables_list_name, table_names) = Self::declare_tables(&info, codegen_context)?;
         let globals_spec = Self::build_globals_spec(&info)?;
-        let linear_memory_spec = Self::build_linear_memory_spec(&info, heap_settings)?;
+        let linear_memory_spec =
+            Self::build_linear_memory_spec(&info, heap_settings.clone(), allow_multi_memory)?;
+        let additional_heaps = Self::build_additional_heaps(&info, heap_settings)?;
         let mut decls = Self {
             info,
             function_names: PrimaryMap::new(),
@@ -99,9 +192,18 @@ impl<'a> ModuleDecls<'a> {
             runtime_names: HashMap::new(),
             globals_spec,
             linear_memory_spec,
+            additional_heaps,
+            host_trap_codes: HashMap::new(),
         };
 
-        Self::declare_funcs(&mut decls, codegen_context, bindings)?;
+        Self::declare_funcs(
+            &mut decls,
+            codegen_context,
+            bindings,
+            export_all_functions,
+            symbol_scheme,
+            import_filter,
+        )?;
         Self::declare_runtime(&mut decls, codegen_context, runtime)?;
 
         Ok(decls)
@@ -113,32 +215,30 @@ impl<'a> ModuleDecls<'a> {
         decls: &mut ModuleDecls<'a>,
         codegen_context: &CodegenContext,
         bindings: &'a Bindings,
+        export_all_functions: bool,
+        symbol_scheme: &SymbolScheme,
+        import_filter: Option<&ImportFilter>,
     ) -> Result<(), Error> {
         // Get the name for this function from the module names section, if it exists.
-        // Because names have to be unique, we append the index value (ix) to the name.
         fn custom_name_for<'a>(
-            ix: usize,
             func_index: UniqueFuncIndex,
-            decls: &mut ModuleDecls<'a>,
-        ) -> Option<String> {
-            decls
-                .info
-                .function_names
-                .get(func_index)
-                .map(|s| format!("{}_{}", s, ix))
+            decls: &ModuleDecls<'a>,
+        ) -> Option<&'a str> {
+            decls.info.function_names.get(func_index).copied()
         }
 
         fn export_name_for<'a>(
             func_ix: UniqueFuncIndex,
             decls: &mut ModuleDecls<'a>,
-        ) -> Option<String> {
+        ) -> Option<&'a str> {
             let export = decls.info.functions.get(func_ix).unwrap();
             if !export.export_names.is_empty() {
+                let export_name = export.export_names[0];
                 decls.exports.push(ExportFunction {
                     fn_idx: LucetFunctionIndex::from_u32(decls.function_names.len() as u32),
                     names: export.export_names.clone(),
                 });
-                Some(format!("guest_func_{}", export.export_names[0]))
+                Some(export_name)
             } else {
                 None
             }
@@ -148,9 +248,28 @@ impl<'a> ModuleDecls<'a> {
             func_ix: UniqueFuncIndex,
             decls: &mut ModuleDecls<'a>,
             bindings: &'a Bindings,
+            import_filter: Option<&ImportFilter>,
         ) -> Result<Option<String>, Error> {
             if let Some((import_mod, import_field)) = decls.info.imported_funcs.get(func_ix) {
+                if let Some(import_filter) = import_filter {
+                    let (signature, _wasm_func_type) = decls.info.signature_for_function(func_ix);
+                    if !import_filter(import_mod, import_field, signature) {
+                        return Err(Error::ImportRejected {
+                            module: import_mod.to_string(),
+                            field: import_field.to_string(),
+                        });
+                    }
+                }
                 let import_symbol = bindings.translate(import_mod, import_field)?;
+                if let Some(code) = bindings.trap_code(import_mod, import_field) {
+                    if code > lucet_module::MAX_HOST_TRAP_CODE {
+                        return Err(Error::Unsupported(format!(
+                            "trap code {} of {}::{} is reserved for lucetc",
+                            code, import_mod, import_field
+                        )));
+                    }
+                    decls.host_trap_codes.insert(func_ix, code);
+                }
                 decls.imports.push(ImportFunction {
                     fn_idx: LucetFunctionIndex::from_u32(decls.function_names.len() as u32),
                     module: import_mod,
@@ -162,9 +281,28 @@ impl<'a> ModuleDecls<'a> {
             }
         }
 
+        // `clif_module` merges every declaration of a symbol into one function, so a function
+        // defined here must not share its symbol with any other function, or it would be defined
+        // twice, or stand in for an import. This is only checked with `export_all_functions`,
+        // which makes the symbols of local functions visible outside the object.
+        fn define_symbol(
+            symbol: &str,
+            defined: &mut HashSet<String>,
+            imported: &HashSet<String>,
+        ) -> Result<(), Error> {
+            if imported.contains(symbol) || !defined.insert(symbol.to_owned()) {
+                return Err(Error::DuplicateFunctionSymbol {
+                    symbol: symbol.to_owned(),
+                });
+            }
+            Ok(())
+        }
+
+        let mut defined_symbols = HashSet::new();
+        let mut imported_symbols = HashSet::new();
         for ix in 0..decls.info.functions.len() {
             let func_index = UniqueFuncIndex::new(ix);
-            let import_info = import_name_for(func_index, decls, bindings)?;
+            let import_info = import_name_for(func_index, decls, bindings, import_filter)?;
             let export_info = export_name_for(func_index, decls);
 
             match (import_info, export_info) {
@@ -173,6 +311,10 @@ impl<'a> ModuleDecls<'a> {
                     // if a function is an export and import, it will not have a real function body
                     // in this program, and we must not declare it with Linkage::Export (there will
                     // never be a define to satisfy the symbol!)
+                    if export_all_functions && defined_symbols.contains(&import_sym) {
+                        return Err(Error::DuplicateFunctionSymbol { symbol: import_sym });
+                    }
+                    imported_symbols.insert(import_sym.clone());
                     decls.declare_function(
                         codegen_context,
                         import_sym,
@@ -180,9 +322,13 @@ impl<'a> ModuleDecls<'a> {
                         func_index,
                     )?;
                 }
-                (None, Some(export_sym)) => {
+                (None, Some(export_name)) => {
                     // This is a function that is only exported, so there will be a body in this
                     // artifact. We can declare the export.
+                    let export_sym = symbol_scheme.exported_symbol(export_name);
+                    if export_all_functions {
+                        define_symbol(&export_sym, &mut defined_symbols, &imported_symbols)?;
+                    }
                     decls.declare_function(
                         codegen_context,
                         export_sym,
@@ -194,14 +340,19 @@ impl<'a> ModuleDecls<'a> {
                     // No import or export for this function, which means that it is local. We can
                     // look for a name provided in the custom names section, otherwise we have to
                     // make up a placeholder name for it using its index.
-                    let local_sym = custom_name_for(ix, func_index, decls)
-                        .unwrap_or_else(|| format!("guest_func_{}", ix));
-                    decls.declare_function(
-                        codegen_context,
-                        local_sym,
-                        Linkage::Local,
-                        func_index,
-                    )?;
+                    let local_sym =
+                        symbol_scheme.local_symbol(ix, custom_name_for(func_index, decls));
+                    if export_all_functions {
+                        define_symbol(&local_sym, &mut defined_symbols, &imported_symbols)?;
+                    }
+                    // The function is still not a wasm export, but its symbol can be made visible
+                    // for tools to resolve it by name.
+                    let linkage = if export_all_functions {
+                        Linkage::Export
+                    } else {
+                        Linkage::Local
+                    };
+                    decls.declare_function(codegen_context, local_sym, linkage, func_index)?;
                 }
             }
         }
@@ -269,7 +420,7 @@ impl<'a> ModuleDecls<'a> {
     ) -> Result<(Name, PrimaryMap<TableIndex, Name>), Error> {
         let mut table_names = PrimaryMap::new();
         for ix in 0..info.tables.len() {
-            let def_symbol = format!("guest_table_{}", ix);
+            let def_symbol = format!("{}{}", TABLE_DATA_PREFIX, ix);
             let def_data_id =
                 codegen_context
                     .module()
@@ -310,9 +461,15 @@ impl<'a> ModuleDecls<'a> {
     fn build_linear_memory_spec(
         info: &ModuleInfo<'a>,
         heap_settings: HeapSettings,
+        allow_multi_memory: bool,
     ) -> Result<Option<OwnedLinearMemorySpec>, Error> {
         use crate::sparsedata::owned_sparse_data_from_initializers;
-        if let Some(heap_spec) = Self::build_heap_spec(info, heap_settings)? {
+        if info.memories.len() > 1 && !allow_multi_memory {
+            return Err(Error::Unsupported(
+                "lucetc only supports memory 0".to_string(),
+            ));
+        }
+        if let Some(heap_spec) = Self::build_heap_spec(info, MemoryIndex::new(0), &heap_settings)? {
             let data_initializers = info
                 .data_initializers
                 .get(&MemoryIndex::new(0))
@@ -328,6 +485,58 @@ impl<'a> ModuleDecls<'a> {
         }
     }
 
+    /// Leave the initial contents of linear memory out of the module data, so that every page in
+    /// the heap starts zeroed. Data initializers are still validated against the heap.
+    pub fn clear_heap_initializer(&mut self) -> Result<(), Error> {
+        use crate::sparsedata::owned_sparse_data_empty;
+        if let Some(linear_memory_spec) = self.linear_memory_spec.as_mut() {
+            linear_memory_spec.initializer = owned_sparse_data_empty(&linear_memory_spec.heap)?;
+        }
+        Ok(())
+    }
+
+    /// Replace the initial value of the global at `index` with `value`. See
+    /// `CompilerBuilder::override_global`.
+    pub fn override_global(&mut self, index: u32, value: GlobalDef) -> Result<(), Error> {
+        let global_index = GlobalIndex::from_u32(index);
+        let g_decl = self.get_global(global_index)?;
+        let invalid = |reason: &str| Error::InvalidGlobalOverride {
+            index,
+            reason: reason.to_owned(),
+        };
+        match g_decl.entity.initializer {
+            GlobalInit::Import => return Err(invalid("the global is imported")),
+            GlobalInit::GetGlobal(_) => {
+                return Err(invalid("the global is initialized from another global"))
+            }
+            _ => {}
+        }
+        let ty = match value {
+            GlobalDef::I32(_) => WasmType::I32,
+            GlobalDef::I64(_) => WasmType::I64,
+            GlobalDef::F32(_) => WasmType::F32,
+            GlobalDef::F64(_) => WasmType::F64,
+        };
+        if ty != g_decl.entity.wasm_ty {
+            return Err(invalid(&format!(
+                "the global has type {:?}, but the value has type {:?}",
+                g_decl.entity.wasm_ty, ty
+            )));
+        }
+        let export_names = g_decl.export_names.clone();
+        // Both the module data and `get_globals_info` describe the global, so update the
+        // declaration along with its spec.
+        self.info.globals[global_index].entity.initializer = match value {
+            GlobalDef::I32(i) => GlobalInit::I32Const(i),
+            GlobalDef::I64(i) => GlobalInit::I64Const(i),
+            GlobalDef::F32(f) => GlobalInit::F32Const(f.to_bits()),
+            GlobalDef::F64(f) => GlobalInit::F64Const(f.to_bits()),
+        };
+        self.globals_spec[index as usize] =
+            GlobalSpec::new(GlobalVariant::Def(value), export_names);
+        Ok(())
+    }
+
     fn build_globals_spec(info: &ModuleInfo<'a>) -> Result<Vec<GlobalSpec<'a>>, Error> {
         let mut globals = Vec::new();
         for ix in 0..info.globals.len() {
@@ -373,20 +582,30 @@ impl<'a> ModuleDecls<'a> {
         Ok(globals)
     }
 
-    fn build_heap_spec(
+    fn build_additional_heaps(
         info: &ModuleInfo<'a>,
         heap_settings: HeapSettings,
+    ) -> Result<Vec<HeapSpec>, Error> {
+        let mut heaps = Vec::new();
+        for ix in 1..info.memories.len() {
+            let heap = Self::build_heap_spec(info, MemoryIndex::new(ix), &heap_settings)?
+                .expect("memory in range");
+            heaps.push(heap);
+        }
+        Ok(heaps)
+    }
+
+    fn build_heap_spec(
+        info: &ModuleInfo<'a>,
+        memory_index: MemoryIndex,
+        heap_settings: &HeapSettings,
     ) -> Result<Option<HeapSpec>, Error> {
-        match info.memories.len() {
-            0 => Ok(None),
-            1 => {
-                let memory = info
-                    .memories
-                    .get(MemoryIndex::new(0))
-                    .expect("memory in range")
-                    .entity;
-
-                let wasm_page: u64 = 64 * 1024;
+        match info.memories.get(memory_index) {
+            None => Ok(None),
+            Some(memory) => {
+                let memory = memory.entity;
+
+                let wasm_page = heap_settings.page_size as u64;
                 let initial_size = memory.minimum as u64 * wasm_page;
 
                 let reserved_size = std::cmp::max(initial_size, heap_settings.min_reserved_size);
@@ -406,9 +625,6 @@ impl<'a> ModuleDecls<'a> {
                     max_size,
                 }))
             }
-            _ => Err(Error::Unsupported(
-                "lucetc only supports memory 0".to_string(),
-            )),
         }
     }
     // ********************* Public Interface **************************
@@ -432,6 +648,20 @@ impl<'a> ModuleDecls<'a> {
         })
     }
 
+    /// The host-defined code the import `func_index` traps with when its host function asks the
+    /// guest to trap, if its binding gives one.
+    pub fn get_host_trap_code(&self, func_index: UniqueFuncIndex) -> Option<u16> {
+        self.host_trap_codes.get(&func_index).copied()
+    }
+
+    /// Find the function declared with the given symbol.
+    pub fn get_func_index(&self, symbol: &str) -> Option<UniqueFuncIndex> {
+        self.function_names
+            .iter()
+            .find(|(_, name)| name.symbol() == symbol)
+            .map(|(func_index, _)| func_index)
+    }
+
     pub fn get_runtime(&self, runtime_func: RuntimeFunc) -> Result<RuntimeDecl<'_>, Error> {
         let func_id = *self.runtime_names.get(&runtime_func).unwrap();
         let name = self.function_names.get(func_id).unwrap();
@@ -504,6 +734,67 @@ impl<'a> ModuleDecls<'a> {
         })
     }
 
+    pub fn get_globals_info(&self) -> Vec<GlobalInfo<'_>> {
+        self.info
+            .globals
+            .iter()
+            .map(|(index, g_decl)| {
+                let initial_value = match g_decl.entity.initializer {
+                    GlobalInit::I32Const(i) => GlobalInitialValue::Const(GlobalDef::I32(i)),
+                    GlobalInit::I64Const(i) => GlobalInitialValue::Const(GlobalDef::I64(i)),
+                    GlobalInit::F32Const(f) => {
+                        GlobalInitialValue::Const(GlobalDef::F32(f32::from_bits(f)))
+                    }
+                    GlobalInit::F64Const(f) => {
+                        GlobalInitialValue::Const(GlobalDef::F64(f64::from_bits(f)))
+                    }
+                    GlobalInit::GetGlobal(global) => GlobalInitialValue::Computed { global },
+                    GlobalInit::Import => match self.info.imported_globals.get(index) {
+                        Some((module, field)) => GlobalInitialValue::Import { module, field },
+                        None => GlobalInitialValue::Unsupported,
+                    },
+                    GlobalInit::V128Const(_)
+                    | GlobalInit::RefNullConst
+                    | GlobalInit::RefFunc(_) => GlobalInitialValue::Unsupported,
+                };
+                GlobalInfo {
+                    index,
+                    ty: g_decl.entity.wasm_ty,
+                    mutable: g_decl.entity.mutability,
+                    initial_value,
+                    export_names: g_decl.export_names.clone(),
+                }
+            })
+            .collect()
+    }
+
+    pub fn get_imports_info(&self) -> Vec<ImportInfo<'_>> {
+        self.info
+            .imported_funcs
+            .iter()
+            .map(|(index, (module, field))| {
+                let (_signature, wasm_func_type) = self.info.signature_for_function(index);
+                ImportInfo {
+                    index,
+                    module,
+                    field,
+                    symbol: self.function_names[index].symbol(),
+                    signature: wasm_func_type.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// Get the heap for any memory in the module. Memory 0 is the heap described by the module
+    /// data, as returned by `get_heap`.
+    pub fn get_heap_for(&self, memory_index: MemoryIndex) -> Option<&HeapSpec> {
+        if memory_index == MemoryIndex::new(0) {
+            self.get_heap()
+        } else {
+            self.additional_heaps.get(memory_index.index() - 1)
+        }
+    }
+
     pub fn get_heap(&self) -> Option<&HeapSpec> {
         if let Some(ref spec) = self.linear_memory_spec {
             Some(&spec.heap)
@@ -559,6 +850,7 @@ impl<'a> ModuleDecls<'a> {
             signatures,
             features,
             start_func,
-        ))
+        )
+        .with_pointer_width(self.target_config().pointer_width.bits()))
     }
 }
@@ -0,0 +1,120 @@
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
+use cranelift_codegen::ir::{
+    self,
+    condcodes::FloatCC,
+    immediates::{Ieee32, Ieee64},
+    types, InstBuilder, Opcode,
+};
+
+/// Flush subnormal floats to zero, of the same sign, wherever floating-point arithmetic could
+/// observe or produce them.
+///
+/// Whether subnormals are preserved otherwise depends on the flush-to-zero and
+/// denormals-are-zero modes of the host's floating-point unit, which the guest doesn't control.
+/// Flushing the operands and results of arithmetic explicitly gives the same results in any mode.
+/// Only scalar operations are rewritten. Values that are merely moved, stored, or reinterpreted
+/// are left alone, as the floating-point mode doesn't affect them either.
+pub(crate) fn flush_denormals(func: &mut ir::Function) {
+    let mut pos = FuncCursor::new(func);
+    while let Some(_block) = pos.next_block() {
+        while let Some(inst) = pos.next_inst() {
+            let opcode = pos.func.dfg[inst].opcode();
+            if reads_floats(opcode) {
+                let args = pos.func.dfg.inst_args(inst).to_vec();
+                for (i, arg) in args.into_iter().enumerate() {
+                    if is_scalar_float(pos.func.dfg.value_type(arg)) {
+                        let flushed = flush(&mut pos, arg);
+                        pos.func.dfg.inst_args_mut(inst)[i] = flushed;
+                    }
+                }
+            }
+            if writes_floats(opcode) {
+                let result = pos.func.dfg.first_result(inst);
+                let ty = pos.func.dfg.value_type(result);
+                if is_scalar_float(ty) {
+                    // Define the original result with the flushed value, so its uses are
+                    // unchanged, and step back so the next iteration resumes after it.
+                    let unflushed = pos.func.dfg.replace_result(result, ty);
+                    pos.next_inst().expect("block missing terminator!");
+                    flush_into(&mut pos, unflushed, Some(result));
+                    pos.prev_inst();
+                }
+            }
+        }
+    }
+}
+
+fn reads_floats(opcode: Opcode) -> bool {
+    match opcode {
+        Opcode::Fadd
+        | Opcode::Fsub
+        | Opcode::Fmul
+        | Opcode::Fdiv
+        | Opcode::Sqrt
+        | Opcode::Fma
+        | Opcode::Fmin
+        | Opcode::Fmax
+        | Opcode::Fcmp
+        | Opcode::Fpromote
+        | Opcode::Fdemote
+        | Opcode::Ceil
+        | Opcode::Floor
+        | Opcode::Trunc
+        | Opcode::Nearest
+        | Opcode::FcvtToSint
+        | Opcode::FcvtToUint
+        | Opcode::FcvtToSintSat
+        | Opcode::FcvtToUintSat => true,
+        _ => false,
+    }
+}
+
+// Every other operation that reads floats returns zero, one of its operands, or a value too
+// large in magnitude to be subnormal when its operands are not subnormal.
+fn writes_floats(opcode: Opcode) -> bool {
+    match opcode {
+        Opcode::Fadd
+        | Opcode::Fsub
+        | Opcode::Fmul
+        | Opcode::Fdiv
+        | Opcode::Sqrt
+        | Opcode::Fma
+        | Opcode::Fdemote => true,
+        _ => false,
+    }
+}
+
+fn is_scalar_float(ty: ir::Type) -> bool {
+    ty == types::F32 || ty == types::F64
+}
+
+fn flush(pos: &mut FuncCursor<'_>, val: ir::Value) -> ir::Value {
+    flush_into(pos, val, None)
+}
+
+/// Insert `|val| < MIN_POSITIVE ? copysign(0, val) : val` before the cursor, defining `result`
+/// with it if given. NaNs compare as not less than anything, so they pass through unchanged.
+fn flush_into(pos: &mut FuncCursor<'_>, val: ir::Value, result: Option<ir::Value>) -> ir::Value {
+    let ty = pos.func.dfg.value_type(val);
+    let (min_positive, zero) = if ty == types::F32 {
+        (
+            pos.ins().f32const(Ieee32::with_float(f32::MIN_POSITIVE)),
+            pos.ins().f32const(Ieee32::with_float(0.0)),
+        )
+    } else {
+        (
+            pos.ins().f64const(Ieee64::with_float(f64::MIN_POSITIVE)),
+            pos.ins().f64const(Ieee64::with_float(0.0)),
+        )
+    };
+    let magnitude = pos.ins().fabs(val);
+    let is_subnormal = pos.ins().fcmp(FloatCC::LessThan, magnitude, min_positive);
+    let signed_zero = pos.ins().fcopysign(zero, val);
+    match result {
+        Some(result) => pos
+            .ins()
+            .with_result(result)
+            .select(is_subnormal, signed_zero, val),
+        None => pos.ins().select(is_subnormal, signed_zero, val),
+    }
+}
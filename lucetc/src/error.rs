@@ -1,7 +1,8 @@
+use crate::compiler::SpecificFeature;
 use crate::types::SignatureError;
 use crate::validate::Error as ValidationError;
 use cranelift_module::ModuleError as ClifModuleError;
-use cranelift_wasm::wasmparser::BinaryReaderError as ClifWasmReaderError;
+use cranelift_wasm::wasmparser::{BinaryReaderError as ClifWasmReaderError, WasmFeatures};
 use cranelift_wasm::WasmError as ClifWasmError;
 use lucet_module::error::Error as LucetModuleError;
 
@@ -32,9 +33,37 @@ pub enum Error {
     ClifWasmValidation(#[from] ClifWasmReaderError),
     #[error("Wat input: {0}")]
     WatInput(#[from] wabt::Error),
+    #[error("Merging objects: {0}")]
+    ObjectMerge(String),
     #[error("Object artifact: {1}. {0:?}")]
     ObjectArtifact(#[source] object::write::Error, String),
     // And all the rest
+    #[error("Compilation cancelled")]
+    Cancelled,
+    #[error("Code size budget exceeded: {actual} bytes of code exceeds the budget of {budget}")]
+    CodeSizeBudgetExceeded { actual: usize, budget: usize },
+    #[error("Codegen check failed for {symbol}: {reason}")]
+    CodegenCheckFailed { symbol: String, reason: String },
+    #[error("Module imports from {module}, which is not an allowed import module")]
+    DisallowedImportModule { module: String },
+    #[error("Export name {name} is used more than once")]
+    DuplicateExport { name: String },
+    #[error("Symbol {symbol} is declared for more than one function")]
+    DuplicateFunctionSymbol { symbol: String },
+    #[error("Expected exports are missing or mismatched: {0:?}")]
+    ExportExpectations(Vec<Error>),
+    #[error("Export {name} has signature {actual:?}, expected {expected:?}")]
+    ExportSignatureMismatch {
+        name: String,
+        expected: cranelift_wasm::WasmFuncType,
+        actual: cranelift_wasm::WasmFuncType,
+    },
+    #[error("Function {symbol} uses floating point at offset {offset}, which is not allowed")]
+    FloatNotAllowed { symbol: String, offset: usize },
+    #[error(
+        "Function {symbol} has a {frame_size}-byte frame, which exceeds the maximum stack size"
+    )]
+    FrameExceedsStack { symbol: String, frame_size: u32 },
     #[error("Function definition error in {symbol}")]
     FunctionDefinition {
         symbol: String,
@@ -49,6 +78,10 @@ pub enum Error {
         #[source]
         source: Box<Error>,
     },
+    #[error("Module imports from disallowed modules: {0:?}")]
+    ImportModuleViolations(Vec<Error>),
+    #[error("Import {module}::{field} was rejected by the import filter")]
+    ImportRejected { module: String, field: String },
     #[error("Inconsistent state when translating module: global {0} is declared as an import but has no entry in imported_globals")]
     GlobalDeclarationError(u32),
     #[error("global out of bounds: {0}")]
@@ -57,32 +90,114 @@ pub enum Error {
     GlobalInitError(u32),
     #[error("v128const type is not supported: {0}")]
     GlobalUnsupported(u32),
+    #[error("Object file is inconsistent: {0:?}")]
+    InconsistentObject(Vec<String>),
     #[error("Cannot initialize data beyond linear memory's initial size")]
     InitData,
     #[error("Input error: {0}")]
     Input(String),
+    #[error("Global {index} cannot be overridden: {reason}")]
+    InvalidGlobalOverride { index: u32, reason: String },
     #[error("Ld error: {0}")]
     LdError(String),
     #[error("Memory specs: {0}")]
     MemorySpecs(String),
     #[error("Metadata serializer; start index points to a non-function: {0}")]
     MetadataSerializer(#[source] ClifModuleError),
+    #[error("Expected export {name} is not exported by the module")]
+    MissingExport { name: String },
+    #[error("Module is {size} bytes, exceeding the limit of {limit}")]
+    ModuleTooLarge { size: usize, limit: usize },
+    #[error("Data segment at offset {offset} overlaps an earlier data segment")]
+    OverlappingDataSegments { offset: usize },
     #[error("Output function: error writing function {1}")]
     OutputFunction(#[source] std::fmt::Error, String),
+    #[error("Symbol {symbol} is reserved by lucetc, but the module declares a function with it")]
+    ReservedSymbolConflict { symbol: String },
     #[error("Signature error: {0}")]
     Signature(String),
+    #[error("SIMD requires CPU features that are not enabled: {missing:?}")]
+    SimdCpuFeatures { missing: Vec<SpecificFeature> },
+    #[error("Function {symbol} requires a stack probe")]
+    StackProbeRequired { symbol: String },
     #[error("Table index is out of bounds: {0}")]
     TableIndexError(String),
     #[error("Initializer {0:?} out of range for {1:?}")]
     ElementInitializerOutOfRange(crate::module::TableElems, cranelift_wasm::Table),
+    #[error("Function {symbol} declares {count} locals, exceeding the limit of {limit}")]
+    TooManyLocals {
+        symbol: String,
+        count: u32,
+        limit: u32,
+    },
+    #[cfg(feature = "testing")]
+    #[error("Test run: {0}")]
+    TestRun(#[source] lucet_runtime::Error),
     #[error("Trap records are present for function {0} but the function does not exist.")]
     TrapRecord(String),
     #[error("Unsupported: {0}")]
     Unsupported(String),
+    #[error("Unsupported: {message}")]
+    UnsupportedFeature {
+        feature: WasmFeature,
+        message: String,
+    },
     #[error("host machine is not a supported target: {0}")]
     UnsupportedIsa(#[from] cranelift_codegen::isa::LookupError),
 }
 
+impl Error {
+    /// The WebAssembly feature this error reports as unsupported, if any, looking through
+    /// `FunctionTranslation` to the error that caused it.
+    ///
+    /// A caller can retry the compilation with `CompilerBuilder::reject_wasm_feature` to have
+    /// the module fail validation instead, with the offset of the offending instruction.
+    pub fn unsupported_feature(&self) -> Option<WasmFeature> {
+        match self {
+            Error::UnsupportedFeature { feature, .. } => Some(*feature),
+            Error::FunctionTranslation { source, .. } => source.unsupported_feature(),
+            _ => None,
+        }
+    }
+}
+
+/// A WebAssembly proposal that lucetc accepts in validation but cannot compile in full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WasmFeature {
+    BulkMemory,
+    ReferenceTypes,
+    MultiMemory,
+}
+
+impl WasmFeature {
+    fn description(self) -> &'static str {
+        match self {
+            WasmFeature::BulkMemory => "bulk memory operations",
+            WasmFeature::ReferenceTypes => "reference type operations",
+            WasmFeature::MultiMemory => "multi-memory operations",
+        }
+    }
+
+    /// The error to report from translation when the module uses this feature, optionally
+    /// naming the particular operation. See `FuncInfo::translation_error`.
+    pub(crate) fn unsupported(self, operation: Option<String>) -> ClifWasmError {
+        let message = match operation {
+            Some(operation) => format!("{} not supported yet: {}", self.description(), operation),
+            None => format!("{} not supported yet", self.description()),
+        };
+        ClifWasmError::Unsupported(message)
+    }
+
+    /// Turn this feature off in `features`, so the validator rejects modules that use it.
+    pub(crate) fn disable(self, features: &mut WasmFeatures) {
+        match self {
+            WasmFeature::BulkMemory => features.bulk_memory = false,
+            WasmFeature::ReferenceTypes => features.reference_types = false,
+            WasmFeature::MultiMemory => features.multi_memory = false,
+        }
+    }
+}
+
 impl From<ClifWasmError> for Error {
     fn from(e: ClifWasmError) -> Error {
         match e {
@@ -1,9 +1,13 @@
 use super::runtime::RuntimeFunc;
 use crate::compiler::CodegenContext;
+use crate::coverage::{CoverageMode, FunctionCoverage, COVERAGE_COUNTER_SIZE};
 use crate::decls::{FunctionDecl, ModuleDecls};
+use crate::error::{Error, WasmFeature};
 use crate::module::UniqueFuncIndex;
 use crate::pointer::{NATIVE_POINTER, NATIVE_POINTER_SIZE};
 use crate::table::TABLE_REF_SIZE;
+use crate::traps::MEMORY_LIMIT_TRAP;
+use crate::vmctx_layout::VmContextLayout;
 use cranelift_codegen::cursor::FuncCursor;
 use cranelift_codegen::entity::EntityRef;
 use cranelift_codegen::ir::{self, condcodes::IntCC, InstBuilder};
@@ -14,19 +18,41 @@ use cranelift_wasm::{
     wasmparser::Operator, FuncEnvironment, FuncIndex, FuncTranslationState, GlobalIndex,
     GlobalVariable, MemoryIndex, TableIndex, TargetEnvironment, TypeIndex, WasmError, WasmResult,
 };
-use lucet_module::InstanceRuntimeData;
-use memoffset::offset_of;
 use std::collections::HashMap;
 
 pub struct FuncInfo<'a> {
     module_decls: &'a ModuleDecls<'a>,
     codegen_context: &'a CodegenContext,
+    /// Instrument the function to count the wasm instructions it executes.
+    ///
+    /// Costs are assigned per wasm `Operator` in `before_translate_operator`, so the count only
+    /// ever reflects guest-visible wasm instructions. Neither the counter updates and bound checks
+    /// emitted here nor the code inserted by NaN canonicalization, which runs as a Cranelift pass
+    /// after translation, are counted, and the count is the same whether or not canonicalization
+    /// is enabled.
     count_instructions: bool,
     scope_costs: Vec<ScopeInfo>,
+    /// Check the interrupt flag in the instance data on every `n`th loop back-edge, trapping with
+    /// `Interrupt` if it is set.
+    ///
+    /// Back-edges are found the same way as for instruction counting, but the two are
+    /// independent: `loop_scopes` tracks which open scopes are loops even when instructions are
+    /// not counted.
+    interrupt_check_period: Option<u32>,
+    loop_scopes: Vec<bool>,
+    /// Increment this function's counters in the coverage buffer as control reaches the
+    /// function, and with `CoverageMode::BasicBlock`, each block in it.
+    coverage: Option<FunctionCoverage>,
+    coverage_buffer_value: Option<ir::GlobalValue>,
     vmctx_value: Option<ir::GlobalValue>,
+    /// Where the instance's state is relative to the vmctx.
+    vmctx_layout: VmContextLayout,
     global_base_value: Option<ir::GlobalValue>,
     runtime_funcs: HashMap<RuntimeFunc, ir::FuncRef>,
     instr_count_var: Variable,
+    backedge_count_var: Variable,
+    /// The feature that stopped translation, if it stopped on one lucetc can't compile.
+    unsupported_feature: Option<WasmFeature>,
 }
 
 struct ScopeInfo {
@@ -39,6 +65,8 @@ impl<'a> FuncInfo<'a> {
         module_decls: &'a ModuleDecls<'a>,
         codegen_context: &'a CodegenContext,
         count_instructions: bool,
+        interrupt_check_period: Option<u32>,
+        coverage: Option<FunctionCoverage>,
         arg_count: u32,
         local_count: u32,
     ) -> Self {
@@ -50,16 +78,43 @@ impl<'a> FuncInfo<'a> {
                 cost: 0,
                 is_loop: false,
             }],
+            interrupt_check_period,
+            loop_scopes: vec![false],
+            coverage,
+            coverage_buffer_value: None,
             vmctx_value: None,
+            vmctx_layout: VmContextLayout::new(module_decls.info.memories.len()),
             global_base_value: None,
             runtime_funcs: HashMap::new(),
             // variable indices correspond to Wasm bytecode's index space,
-            // so we designate a new one after all the Wasm locals to hold
-            // the instruction count.
+            // so we designate new ones after all the Wasm locals to hold
+            // the instruction count and the back-edges taken since the
+            // interrupt flag was last checked.
             instr_count_var: Variable::with_u32(arg_count + local_count),
+            backedge_count_var: Variable::with_u32(arg_count + local_count + 1),
+            unsupported_feature: None,
         }
     }
 
+    /// The error to report for `source`, which stopped the function's translation.
+    ///
+    /// Translation can only report unsupported features as strings, so the feature is recorded
+    /// here when it is rejected, and attached to the error afterwards.
+    pub fn translation_error(&self, source: WasmError) -> Error {
+        match (self.unsupported_feature, source) {
+            (Some(feature), WasmError::Unsupported(message)) => {
+                Error::UnsupportedFeature { feature, message }
+            }
+            (_, source) => Error::from(source),
+        }
+    }
+
+    /// Reject the function for using `feature`, optionally naming the particular operation.
+    fn unsupported(&mut self, feature: WasmFeature, operation: Option<String>) -> WasmError {
+        self.unsupported_feature = Some(feature);
+        feature.unsupported(operation)
+    }
+
     pub fn get_vmctx(&mut self, func: &mut ir::Function) -> ir::GlobalValue {
         self.vmctx_value.unwrap_or_else(|| {
             let vmctx_value = func.create_global_value(ir::GlobalValueData::VMContext);
@@ -73,9 +128,7 @@ impl<'a> FuncInfo<'a> {
             let vmctx = self.get_vmctx(func);
             let global_base_value = func.create_global_value(ir::GlobalValueData::Load {
                 base: vmctx,
-                offset: (-(std::mem::size_of::<InstanceRuntimeData>() as i32)
-                    + (offset_of!(InstanceRuntimeData, globals_ptr) as i32))
-                    .into(),
+                offset: self.vmctx_layout.globals_ptr.into(),
                 global_type: ir::types::I64,
                 readonly: false,
             });
@@ -104,14 +157,46 @@ impl<'a> FuncInfo<'a> {
         })
     }
 
+    /// Increment the next coverage counter, if control reaches this point.
+    fn increment_coverage_counter(&mut self, builder: &mut FunctionBuilder<'_>, reachable: bool) {
+        let coverage = self.coverage.as_mut().expect("coverage is instrumented");
+        let counter = coverage.next_counter;
+        coverage.next_counter += 1;
+        if !reachable {
+            return;
+        }
+
+        let buffer_gv = match self.coverage_buffer_value {
+            Some(buffer_gv) => buffer_gv,
+            None => {
+                let buffer_gv = builder
+                    .func
+                    .create_global_value(ir::GlobalValueData::Symbol {
+                        name: coverage.buffer.clone().into(),
+                        offset: 0.into(),
+                        colocated: false,
+                        tls: false,
+                    });
+                self.coverage_buffer_value = Some(buffer_gv);
+                buffer_gv
+            }
+        };
+        let offset = (counter as usize * COVERAGE_COUNTER_SIZE) as i32;
+        let trusted_mem = ir::MemFlags::trusted();
+        let addr = builder.ins().global_value(self.pointer_type(), buffer_gv);
+        let count = builder
+            .ins()
+            .load(ir::types::I64, trusted_mem, addr, offset);
+        let count = builder.ins().iadd_imm(count, 1);
+        builder.ins().store(trusted_mem, count, addr, offset);
+    }
+
     fn get_instr_count_addr_offset(
         &mut self,
         builder: &mut FunctionBuilder<'_>,
     ) -> (ir::Value, ir::immediates::Offset32) {
         let instr_count_offset: ir::immediates::Offset32 =
-            (-(std::mem::size_of::<InstanceRuntimeData>() as i32)
-                + offset_of!(InstanceRuntimeData, instruction_count_adj) as i32)
-                .into();
+            self.vmctx_layout.instruction_count_adj.into();
         let vmctx_gv = self.get_vmctx(builder.func);
         let addr = builder.ins().global_value(self.pointer_type(), vmctx_gv);
         (addr, instr_count_offset)
@@ -148,6 +233,84 @@ impl<'a> FuncInfo<'a> {
             .store(trusted_mem, new_instr_count, addr, instr_count_offset);
     }
 
+    fn update_interrupt_check_instrumentation_pre(
+        &mut self,
+        op: &Operator<'_>,
+        builder: &mut FunctionBuilder<'_>,
+        reachable: bool,
+    ) -> WasmResult<()> {
+        let is_backedge = match op {
+            Operator::Br { relative_depth } | Operator::BrIf { relative_depth } => {
+                self.loop_scopes[self.loop_scopes.len() - 1 - *relative_depth as usize]
+            }
+            // As with instruction counting, treat every `br_table` as if it may branch to a loop.
+            Operator::BrTable { .. } => true,
+            _ => false,
+        };
+        // Like the instruction counter, only instrument reachable code, since the current block
+        // may already be sealed otherwise.
+        if reachable && is_backedge {
+            self.check_interrupt_flag(builder);
+        }
+
+        match op {
+            Operator::Block { .. } | Operator::If { .. } => self.loop_scopes.push(false),
+            Operator::Loop { .. } => self.loop_scopes.push(true),
+            Operator::End => {
+                self.loop_scopes.pop();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn check_interrupt_flag(&mut self, builder: &mut FunctionBuilder<'_>) {
+        let period = self.interrupt_check_period.unwrap_or(1).max(1);
+        let check_block = builder.create_block();
+        let continuation_block = builder.create_block();
+
+        // Do the equivalent of:
+        //
+        //    vars[backedge_count] += 1;
+        //    if vars[backedge_count] >= period {
+        //        vars[backedge_count] = 0;
+        //        if vmctx.interrupt_flag != 0 {
+        //            trap(Interrupt);
+        //        }
+        //    }
+        let count = builder.use_var(self.backedge_count_var);
+        let count = builder.ins().iadd_imm(count, 1);
+        builder.def_var(self.backedge_count_var, count);
+        let due =
+            builder
+                .ins()
+                .icmp_imm(IntCC::UnsignedGreaterThanOrEqual, count, i64::from(period));
+        builder.ins().brnz(due, check_block, &[]);
+        builder.ins().jump(continuation_block, &[]);
+        builder.seal_block(check_block);
+
+        builder.switch_to_block(check_block);
+        let zero = builder.ins().iconst(ir::types::I32, 0);
+        builder.def_var(self.backedge_count_var, zero);
+        let interrupt_flag_offset: ir::immediates::Offset32 =
+            self.vmctx_layout.interrupt_flag.into();
+        // The host may set the flag from another thread. The load is aligned, so it is atomic, and
+        // it isn't `readonly`, so it can't be hoisted out of the loop.
+        let vmctx_gv = self.get_vmctx(builder.func);
+        let addr = builder.ins().global_value(self.pointer_type(), vmctx_gv);
+        let flag = builder.ins().load(
+            ir::types::I64,
+            ir::MemFlags::trusted(),
+            addr,
+            interrupt_flag_offset,
+        );
+        builder.ins().trapnz(flag, ir::TrapCode::Interrupt);
+        builder.ins().jump(continuation_block, &[]);
+        builder.seal_block(continuation_block);
+
+        builder.switch_to_block(continuation_block);
+    }
+
     fn update_instruction_count_instrumentation_pre(
         &mut self,
         op: &Operator<'_>,
@@ -426,7 +589,7 @@ impl<'a> FuncInfo<'a> {
 }
 
 /// Get the local trampoline function to do safety checks before calling an imported hostcall.
-fn get_trampoline_func(
+pub(crate) fn get_trampoline_func(
     codegen_context: &CodegenContext,
     hostcall_index: UniqueFuncIndex,
     func_decl: &FunctionDecl,
@@ -481,11 +644,26 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
         func: &mut ir::Function,
         index: MemoryIndex,
     ) -> Result<ir::Heap, WasmError> {
-        assert_eq!(index, MemoryIndex::new(0), "only memory 0 is supported");
-        let heap_spec = self.module_decls.get_heap().expect("valid heap");
+        let heap_spec = self.module_decls.get_heap_for(index).expect("valid heap");
         let vmctx = self.get_vmctx(func);
+        // Memory 0 starts at the vmctx. The runtime places a pointer to each additional memory
+        // just below the `InstanceRuntimeData`; see `VmContextLayout::memory_base_ptrs`.
+        let base = if index == MemoryIndex::new(0) {
+            vmctx
+        } else {
+            let offset = self.vmctx_layout.memory_base_ptrs[index.index() - 1];
+            func.create_global_value(ir::GlobalValueData::Load {
+                base: vmctx,
+                offset: offset.into(),
+                global_type: NATIVE_POINTER,
+                readonly: true,
+            })
+        };
+        // Cranelift bounds checks any access that might extend past `bound` plus the guard
+        // region, so with a zero-sized guard every access that could leave the reserved heap is
+        // checked explicitly.
         Ok(func.create_heap(ir::HeapData {
-            base: vmctx,
+            base,
             min_size: heap_spec.initial_size.into(),
             offset_guard_size: heap_spec.guard_size.into(),
             style: ir::HeapStyle::Static {
@@ -667,13 +845,30 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
         _heap: ir::Heap,
         val: ir::Value,
     ) -> WasmResult<ir::Value> {
-        assert!(index == MemoryIndex::new(0));
+        if index != MemoryIndex::new(0) {
+            let operation = format!("memory.grow on memory {}", index.as_u32());
+            return Err(self.unsupported(WasmFeature::MultiMemory, Some(operation)));
+        }
         // TODO memory grow function doesnt take heap index as argument
         let mem_grow_func = self.get_runtime_func(RuntimeFunc::MemGrow, &mut pos.func);
         let vmctx = pos
             .func
             .special_param(ir::ArgumentPurpose::VMContext)
             .unwrap();
+        if let Some(limit) = self.codegen_context.memory_limit() {
+            // Trap if the size after growing would exceed the limit. The sum is computed in 64
+            // bits, so it can't overflow.
+            let mem_size_func = self.get_runtime_func(RuntimeFunc::MemSize, &mut pos.func);
+            let size_inst = pos.ins().call(mem_size_func, &[vmctx]);
+            let size = pos.func.dfg.first_result(size_inst);
+            let size = pos.ins().uextend(ir::types::I64, size);
+            let delta = pos.ins().uextend(ir::types::I64, val);
+            let grown_size = pos.ins().iadd(size, delta);
+            let exceeds_limit =
+                pos.ins()
+                    .icmp_imm(IntCC::UnsignedGreaterThan, grown_size, i64::from(limit));
+            pos.ins().trapnz(exceeds_limit, MEMORY_LIMIT_TRAP);
+        }
         let inst = pos.ins().call(mem_grow_func, &[vmctx, val]);
         Ok(*pos.func.dfg.inst_results(inst).first().unwrap())
     }
@@ -684,7 +879,10 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
         index: MemoryIndex,
         _heap: ir::Heap,
     ) -> WasmResult<ir::Value> {
-        assert!(index == MemoryIndex::new(0));
+        if index != MemoryIndex::new(0) {
+            let operation = format!("memory.size on memory {}", index.as_u32());
+            return Err(self.unsupported(WasmFeature::MultiMemory, Some(operation)));
+        }
         // TODO memory size function doesnt take heap index as argument
         let mem_size_func = self.get_runtime_func(RuntimeFunc::MemSize, &mut pos.func);
         let vmctx = pos
@@ -706,9 +904,7 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
         _src: ir::Value,
         _len: ir::Value,
     ) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "bulk memory operations not supported yet".into(),
-        ))
+        Err(self.unsupported(WasmFeature::BulkMemory, None))
     }
 
     fn translate_memory_fill(
@@ -720,9 +916,7 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
         _val: ir::Value,
         _len: ir::Value,
     ) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "bulk memory operations not supported yet".into(),
-        ))
+        Err(self.unsupported(WasmFeature::BulkMemory, None))
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -736,15 +930,11 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
         _src: ir::Value,
         _len: ir::Value,
     ) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "bulk memory operations not supported yet".into(),
-        ))
+        Err(self.unsupported(WasmFeature::BulkMemory, None))
     }
 
     fn translate_data_drop(&mut self, _pos: FuncCursor, _seg_index: u32) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "bulk memory operations not supported yet".into(),
-        ))
+        Err(self.unsupported(WasmFeature::BulkMemory, None))
     }
 
     fn translate_table_size(
@@ -753,9 +943,7 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
         _index: TableIndex,
         _table: ir::Table,
     ) -> WasmResult<ir::Value> {
-        Err(WasmError::Unsupported(
-            "reference type operations not supported yet".into(),
-        ))
+        Err(self.unsupported(WasmFeature::ReferenceTypes, None))
     }
 
     fn translate_table_grow(
@@ -766,9 +954,7 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
         _delta: ir::Value,
         _init_value: ir::Value,
     ) -> WasmResult<ir::Value> {
-        Err(WasmError::Unsupported(
-            "reference type operations not supported yet".into(),
-        ))
+        Err(self.unsupported(WasmFeature::ReferenceTypes, None))
     }
 
     fn translate_table_get(
@@ -778,9 +964,7 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
         _table: ir::Table,
         _index: ir::Value,
     ) -> WasmResult<ir::Value> {
-        Err(WasmError::Unsupported(
-            "reference type operations not supported yet".into(),
-        ))
+        Err(self.unsupported(WasmFeature::ReferenceTypes, None))
     }
 
     fn translate_table_set(
@@ -791,9 +975,7 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
         _value: ir::Value,
         _index: ir::Value,
     ) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "reference type operations not supported yet".into(),
-        ))
+        Err(self.unsupported(WasmFeature::ReferenceTypes, None))
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -808,9 +990,7 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
         _src: ir::Value,
         _len: ir::Value,
     ) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "reference type operations not supported yet".into(),
-        ))
+        Err(self.unsupported(WasmFeature::ReferenceTypes, None))
     }
 
     fn translate_table_fill(
@@ -821,9 +1001,7 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
         _val: ir::Value,
         _len: ir::Value,
     ) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "reference type operations not supported yet".into(),
-        ))
+        Err(self.unsupported(WasmFeature::ReferenceTypes, None))
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -837,15 +1015,11 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
         _src: ir::Value,
         _len: ir::Value,
     ) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "reference type operations not supported yet".into(),
-        ))
+        Err(self.unsupported(WasmFeature::ReferenceTypes, None))
     }
 
     fn translate_elem_drop(&mut self, _pos: FuncCursor, _seg_index: u32) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "bulk memory operations not supported yet".into(),
-        ))
+        Err(self.unsupported(WasmFeature::BulkMemory, None))
     }
 
     fn translate_ref_func(
@@ -853,9 +1027,7 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
         _pos: FuncCursor,
         _func_index: FuncIndex,
     ) -> WasmResult<ir::Value> {
-        Err(WasmError::Unsupported(
-            "reference type operations not supported yet".into(),
-        ))
+        Err(self.unsupported(WasmFeature::ReferenceTypes, None))
     }
 
     fn translate_custom_global_get(
@@ -888,6 +1060,19 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
         if self.count_instructions {
             self.update_instruction_count_instrumentation_pre(op, builder, state.reachable())?;
         }
+        if self.interrupt_check_period.is_some() {
+            self.update_interrupt_check_instrumentation_pre(op, builder, state.reachable())?;
+        }
+        if let Operator::Unreachable = op {
+            if state.reachable() && self.codegen_context.unreachable_handler() {
+                // Cranelift emits the trap itself after this, in case the handler returns.
+                let handler =
+                    self.get_runtime_func(RuntimeFunc::UnreachableHandler, &mut builder.func);
+                let vmctx_gv = self.get_vmctx(builder.func);
+                let addr = builder.ins().global_value(self.pointer_type(), vmctx_gv);
+                builder.ins().call(handler, &[addr]);
+            }
+        }
         Ok(())
     }
 
@@ -900,6 +1085,11 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
         if self.count_instructions {
             self.update_instruction_count_instrumentation_post(op, builder, state.reachable())?;
         }
+        if let Some(coverage) = self.coverage.as_mut() {
+            if coverage.mode == CoverageMode::BasicBlock && coverage.block_starts.after(op) {
+                self.increment_coverage_counter(builder, state.reachable());
+            }
+        }
         Ok(())
     }
 
@@ -911,6 +1101,14 @@ impl<'a> FuncEnvironment for FuncInfo<'a> {
         if self.count_instructions {
             self.update_instruction_count_instrumentation_before_func(builder)?;
         }
+        if self.coverage.is_some() {
+            self.increment_coverage_counter(builder, true);
+        }
+        if self.interrupt_check_period.is_some() {
+            builder.declare_var(self.backedge_count_var, ir::types::I32);
+            let zero = builder.ins().iconst(ir::types::I32, 0);
+            builder.def_var(self.backedge_count_var, zero);
+        }
         Ok(())
     }
 
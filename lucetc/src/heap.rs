@@ -1,8 +1,51 @@
+use crate::error::Error;
+use crate::module::ModuleInfo;
+use lucet_module::HeapSpec;
+
+/// The sizes lucetc reserves for each linear memory.
+///
+/// Generated code doesn't read the heap's base or bound from globals, so they can't be moved to
+/// other vmctx slots. It relies on this contract with the runtime instead:
+///
+/// * The base of memory 0 is the vmctx pointer passed to every guest function.
+/// * The base of each additional memory `n` is a pointer stored at
+///   `vmctx - size_of::<InstanceRuntimeData>() - n * size_of::<usize>()`.
+/// * Each memory has `reserved_size` bytes of address space followed by `guard_size` bytes that
+///   fault on access. Bounds are static: accesses the guard region can catch are not checked,
+///   and the rest are checked against `reserved_size` rather than the memory's current size.
+///
+/// A runtime with a different vmctx layout must still place memories this way.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct HeapSettings {
     pub min_reserved_size: u64,
     pub max_reserved_size: u64,
+    /// The size of the guard region following the reserved heap. Accesses that the guard region
+    /// can't be relied on to catch are bounds checked explicitly, so a `guard_size` of 0 is
+    /// permitted: every access whose end may lie beyond the reserved size is then checked, and
+    /// traps with `HeapOutOfBounds`. This suits hosts that can't reserve a large guard region.
     pub guard_size: u64,
+    /// The size of a wasm page, in which the module's memory limits are given. This must be a
+    /// power of two from 4KiB, the smallest host page, up to the standard 64KiB.
+    ///
+    /// The runtime always grows memories in 64KiB pages, so a module compiled with a smaller page
+    /// size can't use `memory.size` or `memory.grow`; its memories keep their initial size.
+    pub page_size: u32,
+}
+
+/// The page size wasm specifies, and the one the runtime grows memories by.
+pub(crate) const WASM_PAGE_SIZE: u32 = 64 * 1024;
+const MIN_PAGE_SIZE: u32 = 4 * 1024;
+
+/// The heap layout chosen for a module, once the memories it declares are combined with the
+/// heap settings.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolvedHeapLayout {
+    /// The heap settings the layout was resolved with. With `auto_heap_settings`, these are the
+    /// settings derived from the module's memory limits.
+    pub settings: HeapSettings,
+    /// The heap for each memory the module declares, in memory index order. Empty if the module
+    /// has no memory.
+    pub heaps: Vec<HeapSpec>,
 }
 
 impl Default for HeapSettings {
@@ -11,6 +54,57 @@ impl Default for HeapSettings {
             min_reserved_size: 4 * 1024 * 1024,
             max_reserved_size: 6 * 1024 * 1024 * 1024,
             guard_size: 4 * 1024 * 1024,
+            page_size: WASM_PAGE_SIZE,
+        }
+    }
+}
+
+impl HeapSettings {
+    /// Derive heap settings from the memories the module declares, reserving exactly as much as
+    /// the largest memory's maximum size. Memories with no maximum reserve `unbounded_cap` bytes,
+    /// as do memories whose maximum exceeds it. Limits are in pages of `page_size` bytes. The guard
+    /// size is the default.
+    pub(crate) fn from_module_limits(
+        module_info: &ModuleInfo<'_>,
+        unbounded_cap: u64,
+        page_size: u32,
+    ) -> Self {
+        let wasm_page = page_size as u64;
+        let reserved_size = module_info
+            .memories
+            .values()
+            .map(|memory| {
+                let memory = &memory.entity;
+                let initial_size = memory.minimum as u64 * wasm_page;
+                let max_size = memory
+                    .maximum
+                    .map(|pages| pages as u64 * wasm_page)
+                    .unwrap_or(unbounded_cap);
+                std::cmp::max(initial_size, std::cmp::min(max_size, unbounded_cap))
+            })
+            .max();
+        match reserved_size {
+            Some(reserved_size) => Self {
+                min_reserved_size: reserved_size,
+                max_reserved_size: reserved_size,
+                page_size,
+                ..Self::default()
+            },
+            None => Self {
+                page_size,
+                ..Self::default()
+            },
+        }
+    }
+
+    pub(crate) fn check_page_size(&self) -> Result<(), Error> {
+        let page_size = self.page_size;
+        if !page_size.is_power_of_two() || page_size < MIN_PAGE_SIZE || page_size > WASM_PAGE_SIZE {
+            return Err(Error::MemorySpecs(format!(
+                "page size {} is not a power of two from {} to {}",
+                page_size, MIN_PAGE_SIZE, WASM_PAGE_SIZE
+            )));
         }
+        Ok(())
     }
 }
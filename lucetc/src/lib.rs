@@ -1,43 +1,83 @@
 #![deny(bare_trait_objects)]
 
+mod address_map;
+mod call_graph;
+mod cfg_map;
+pub mod codegen_check;
 mod compiler;
+mod coverage;
+mod custom_metadata;
+mod data_segments;
+mod debug_object;
 mod decls;
+mod denormals;
 mod error;
 mod function;
 mod heap;
 mod load;
+mod manifest_diff;
 mod module;
 mod name;
 mod output;
 mod pointer;
 mod runtime;
+mod sig_table;
 pub mod signature;
 mod sparsedata;
 mod stack_probe;
 mod table;
+#[cfg(feature = "testing")]
+mod test_run;
 mod traps;
 mod types;
 mod validate;
+mod vmctx_layout;
 
 use crate::load::read_bytes;
+#[cfg(feature = "testing")]
+pub use crate::test_run::{test_run, test_run_with};
 pub use crate::{
+    address_map::AddressMapEntry,
+    call_graph::{CallGraph, CallTarget},
+    cfg_map::BasicBlock,
     compiler::{
-        Compiler, CompilerBuilder, CpuFeatures, OptLevel, SpecificFeature, TargetCpu, TargetVersion,
+        CompiledFunction, Compiler, CompilerBuilder, ConfigDifference, CpuFeatures, EliminatedTrap,
+        FunctionManifestEntry, OptLevel, PrevState, Relocation, SpecificFeature, StartFunctionInfo,
+        TargetCpu, TargetVersion, UnreachableStrategy, ValidatedModule,
     },
-    error::Error,
-    heap::HeapSettings,
+    coverage::{CoverageCounter, CoverageMode, COVERAGE_COUNTER_SIZE},
+    data_segments::{DataSegment, DataSegmentKind, InitOffset, MemoryInitOp},
+    debug_object::{DebugFunction, DebugObject},
+    decls::{GlobalInfo, GlobalInitialValue, ImportInfo, SymbolScheme},
+    error::{Error, WasmFeature},
+    heap::{HeapSettings, ResolvedHeapLayout},
     load::read_module,
+    manifest_diff::{diff_manifests, ManifestDiff, SizeDelta},
+    module::UniqueFuncIndex,
+    output::{DataSymbolInfo, DataSymbolKind, ObjectFile, RelocationEntry},
+    sig_table::SignatureTableEntry,
+    table::TableInfo,
+    vmctx_layout::VmContextLayout,
 };
 pub use lucet_module::bindings::Bindings;
+pub use lucet_module::GlobalDef;
+#[cfg(feature = "testing")]
+pub use lucet_runtime::Val;
 use signature::{PublicKey, SecretKey};
 use std::env;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 pub use validate::{Error as ValidationError, Validator, ValidatorBuilder, WasiMode};
 
-// Re-export `Triple` and `BackendVariant` so that clients can use these types
-// without needing a direct dependency on the respective source crates.
+// Re-export `Triple`, `BackendVariant`, `Reloc`, `RelocationKind`, and wasm entity types so that
+// clients can use these types without needing a direct dependency on the respective source crates.
+pub use cranelift_codegen::binemit::Reloc;
 pub use cranelift_codegen::isa::BackendVariant;
+pub use cranelift_wasm::{
+    DataIndex, FuncIndex, GlobalIndex, MemoryIndex, ModuleTranslationState, TableElementType,
+    TableIndex, WasmType,
+};
+pub use object::{RelocationKind, SymbolScope};
 pub use target_lexicon::Triple;
 
 enum LucetcInput {
@@ -116,6 +156,10 @@ pub trait LucetcOpts {
     fn with_sign(self) -> Self;
     fn count_instructions(&mut self, enable_count: bool);
     fn with_count_instructions(self, enable_count: bool) -> Self;
+    fn interrupt_checks(&mut self, period: u32);
+    fn with_interrupt_checks(self, period: u32) -> Self;
+    fn module_symbol(&mut self, module_symbol: String);
+    fn with_module_symbol(self, module_symbol: String) -> Self;
     fn canonicalize_nans(&mut self, enable_canonicalize_nans: bool);
     fn with_canonicalize_nans(self, enable_canonicalize_nans: bool) -> Self;
     fn translate_wat(&mut self, enable_translate_wat: bool);
@@ -282,6 +326,24 @@ impl<T: AsLucetc> LucetcOpts for T {
         self
     }
 
+    fn interrupt_checks(&mut self, period: u32) {
+        self.as_lucetc().builder.interrupt_checks(period);
+    }
+
+    fn with_interrupt_checks(mut self, period: u32) -> Self {
+        self.interrupt_checks(period);
+        self
+    }
+
+    fn module_symbol(&mut self, module_symbol: String) {
+        self.as_lucetc().builder.module_symbol(module_symbol);
+    }
+
+    fn with_module_symbol(mut self, module_symbol: String) -> Self {
+        self.module_symbol(module_symbol);
+        self
+    }
+
     fn canonicalize_nans(&mut self, enable_nans_canonicalization: bool) {
         self.as_lucetc()
             .builder
@@ -331,6 +393,21 @@ impl Lucetc {
         })
     }
 
+    /// Compile with the options set in `builder`, for options `LucetcOpts` does not cover.
+    ///
+    /// This replaces the compiler options set through `LucetcOpts` beforehand.
+    pub fn compiler_builder(&mut self, builder: CompilerBuilder) {
+        self.builder = builder;
+    }
+
+    /// Compile with the options set in `builder`, for options `LucetcOpts` does not cover.
+    ///
+    /// This replaces the compiler options set through `LucetcOpts` beforehand.
+    pub fn with_compiler_builder(mut self, builder: CompilerBuilder) -> Self {
+        self.compiler_builder(builder);
+        self
+    }
+
     fn build(&self) -> Result<(Vec<u8>, Bindings), Error> {
         let module_binary = match &self.input {
             LucetcInput::Bytes(bytes) => bytes.clone(),
@@ -381,7 +458,11 @@ impl Lucetc {
             let sk = self.sk.as_ref().ok_or(Error::Signature(
                 "signing requires a secret key".to_string(),
             ))?;
-            signature::sign_module(&output, sk)?;
+            signature::sign_module_with_symbol(
+                &output,
+                sk,
+                &self.builder.prefixed_module_symbol(),
+            )?;
         }
         Ok(())
     }
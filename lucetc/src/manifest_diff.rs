@@ -0,0 +1,69 @@
+use lucet_module::FunctionSpec;
+use std::collections::HashMap;
+
+/// The code size of a function present in both manifests compared by `diff_manifests`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeDelta {
+    pub symbol: String,
+    pub old_len: u32,
+    pub new_len: u32,
+}
+
+impl SizeDelta {
+    /// The change in code size, in bytes. Negative if the function shrank.
+    pub fn delta(&self) -> i64 {
+        self.new_len as i64 - self.old_len as i64
+    }
+}
+
+/// The differences between two function manifests, as computed by `diff_manifests`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestDiff {
+    /// Functions only in the new manifest, with their code size, in manifest order.
+    pub added: Vec<(String, u32)>,
+    /// Functions only in the old manifest, with their code size, in manifest order.
+    pub removed: Vec<(String, u32)>,
+    /// Functions in both manifests, in the order of the new manifest, including those whose size
+    /// did not change.
+    pub sizes: Vec<SizeDelta>,
+}
+
+impl ManifestDiff {
+    /// The functions in both manifests whose code size changed.
+    pub fn changed(&self) -> impl Iterator<Item = &SizeDelta> {
+        self.sizes.iter().filter(|size| size.delta() != 0)
+    }
+}
+
+/// Compare the function manifests of two builds, matching functions by symbol.
+pub fn diff_manifests(
+    old: &[(String, FunctionSpec)],
+    new: &[(String, FunctionSpec)],
+) -> ManifestDiff {
+    let old_lens: HashMap<&str, u32> = old
+        .iter()
+        .map(|(symbol, spec)| (symbol.as_str(), spec.code_len()))
+        .collect();
+    let new_lens: HashMap<&str, u32> = new
+        .iter()
+        .map(|(symbol, spec)| (symbol.as_str(), spec.code_len()))
+        .collect();
+
+    let mut diff = ManifestDiff::default();
+    for (symbol, spec) in new.iter() {
+        match old_lens.get(symbol.as_str()) {
+            Some(old_len) => diff.sizes.push(SizeDelta {
+                symbol: symbol.clone(),
+                old_len: *old_len,
+                new_len: spec.code_len(),
+            }),
+            None => diff.added.push((symbol.clone(), spec.code_len())),
+        }
+    }
+    diff.removed = old
+        .iter()
+        .filter(|(symbol, _)| !new_lens.contains_key(symbol.as_str()))
+        .map(|(symbol, spec)| (symbol.clone(), spec.code_len()))
+        .collect();
+    diff
+}
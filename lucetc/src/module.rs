@@ -1,18 +1,23 @@
 //! Implements ModuleEnvironment for cranelift-wasm. Code derived from cranelift-wasm/environ/dummy.rs
-use crate::error::Error;
+use crate::error::{Error, WasmFeature};
 use crate::pointer::NATIVE_POINTER;
 use crate::validate::Validator;
 use cranelift_codegen::entity::{entity_impl, EntityRef, PrimaryMap, SecondaryMap};
 use cranelift_codegen::ir;
 use cranelift_codegen::isa::TargetFrontendConfig;
 use cranelift_wasm::{
-    wasmparser::{FuncValidator, FunctionBody, ValidatorResources},
+    wasmparser::{FuncValidator, FunctionBody, ValidatorResources, WasmFeatures},
     DataIndex, ElemIndex, FuncIndex, Global, GlobalIndex, Memory, MemoryIndex, ModuleEnvironment,
     Table, TableElementType, TableIndex, TargetEnvironment, TypeIndex, WasmError, WasmFuncType,
     WasmResult, WasmType,
 };
 use lucet_module::UniqueSignatureIndex;
-use std::collections::{hash_map::Entry, HashMap};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+/// A callback reporting the number of bytes of the module validated so far, and the total size of
+/// the module.
+pub type ValidationProgress = Arc<Mutex<Box<dyn FnMut(usize, usize) + Send>>>;
 
 /// UniqueFuncIndex names a function after merging duplicate function declarations to a single
 /// identifier, whereas FuncIndex is maintained by Cranelift and may have multiple indices referring
@@ -90,6 +95,9 @@ pub struct ModuleInfo<'a> {
 
     /// Data initializers: local only
     pub data_initializers: HashMap<MemoryIndex, Vec<DataInitializer<'a>>>,
+
+    /// Provided by `declare_passive_data`
+    pub passive_data: HashMap<DataIndex, &'a [u8]>,
 }
 
 pub struct ModuleValidation<'a> {
@@ -100,6 +108,14 @@ pub struct ModuleValidation<'a> {
     /// Function bodies: local only
     pub function_bodies:
         HashMap<UniqueFuncIndex, (FuncValidator<ValidatorResources>, FunctionBody<'a>)>,
+    /// Progress reporting, along with the size of the module being validated
+    progress: Option<(ValidationProgress, usize)>,
+    /// Accept modules with more than one memory
+    allow_multi_memory: bool,
+    /// Accept modules that use SIMD instructions
+    allow_simd: bool,
+    /// Features the wasm validator should reject
+    rejected_features: HashSet<WasmFeature>,
 }
 
 impl<'a> ModuleValidation<'a> {
@@ -108,6 +124,38 @@ impl<'a> ModuleValidation<'a> {
             validator,
             info: ModuleInfo::new(target_config),
             function_bodies: HashMap::new(),
+            progress: None,
+            allow_multi_memory: false,
+            allow_simd: false,
+            rejected_features: HashSet::new(),
+        }
+    }
+
+    pub fn with_multi_memory(mut self, allow_multi_memory: bool) -> Self {
+        self.allow_multi_memory = allow_multi_memory;
+        self
+    }
+
+    pub fn with_simd(mut self, allow_simd: bool) -> Self {
+        self.allow_simd = allow_simd;
+        self
+    }
+
+    pub fn with_rejected_features(mut self, rejected_features: HashSet<WasmFeature>) -> Self {
+        self.rejected_features = rejected_features;
+        self
+    }
+
+    pub fn with_progress(mut self, progress: Option<ValidationProgress>, total_len: usize) -> Self {
+        self.progress = progress.map(|progress| (progress, total_len));
+        self
+    }
+
+    /// Report that the first `position` bytes of the module have been validated.
+    pub fn report_progress(&self, position: usize) {
+        if let Some((progress, total_len)) = &self.progress {
+            let mut progress = progress.lock().expect("possible to lock progress callback");
+            (*progress)(position.min(*total_len), *total_len);
         }
     }
 
@@ -139,6 +187,7 @@ impl<'a> ModuleInfo<'a> {
             start_func: None,
             table_elems: HashMap::new(),
             data_initializers: HashMap::new(),
+            passive_data: HashMap::new(),
         }
     }
 
@@ -216,6 +265,18 @@ impl<'a> TargetEnvironment for ModuleValidation<'a> {
 }
 
 impl<'a> ModuleEnvironment<'a> for ModuleValidation<'a> {
+    fn wasm_features(&self) -> WasmFeatures {
+        let mut features = WasmFeatures {
+            multi_memory: self.allow_multi_memory,
+            simd: self.allow_simd,
+            ..WasmFeatures::default()
+        };
+        for feature in self.rejected_features.iter() {
+            feature.disable(&mut features);
+        }
+        features
+    }
+
     fn declare_type_func(
         &mut self,
         wasm_func_type: WasmFuncType,
@@ -414,6 +475,8 @@ impl<'a> ModuleEnvironment<'a> for ModuleValidation<'a> {
     ) -> WasmResult<()> {
         let func_index =
             UniqueFuncIndex::new(self.info.imported_funcs.len() + self.function_bodies.len());
+        let reader = body.get_binary_reader();
+        self.report_progress(reader.original_position() + reader.bytes_remaining());
         self.function_bodies
             .insert(func_index, (func_validator, body));
         Ok(())
@@ -499,7 +562,8 @@ impl<'a> ModuleEnvironment<'a> for ModuleValidation<'a> {
         unimplemented!();
     }
 
-    fn declare_passive_data(&mut self, _data_index: DataIndex, _data: &'a [u8]) -> WasmResult<()> {
-        unimplemented!();
+    fn declare_passive_data(&mut self, data_index: DataIndex, data: &'a [u8]) -> WasmResult<()> {
+        self.info.passive_data.insert(data_index, data);
+        Ok(())
     }
 }
@@ -1,14 +1,29 @@
+use crate::address_map::{address_map_sym, AddressMapEntry, ADDRESS_MAP_PREFIX};
+use crate::cfg_map::{cfg_map_sym, BasicBlock, CFG_MAP_PREFIX};
+use crate::coverage::{CoverageCounter, COVERAGE_METADATA_KEY};
+use crate::custom_metadata::{custom_metadata_sym, CUSTOM_METADATA_PREFIX};
+use crate::data_segments::{DATA_SEGMENTS_SYM, HEAP_IMAGE_SYM};
+use crate::debug_object::{build_id_note, parse_build_id_note, BUILD_ID_SECTION, BUILD_ID_SIZE};
 use crate::error::Error;
 use crate::name::Name;
+use crate::sig_table::{SignatureTableEntry, SIGNATURE_TABLE_SYM};
+use crate::stack_probe;
+use crate::table::{TABLE_DATA_PREFIX, TABLE_SYM};
+use crate::traps::{SHARED_TRAP_TABLE_PREFIX, TRAP_TABLE_PREFIX};
 use cranelift_codegen::{ir, isa};
 use cranelift_object::ObjectProduct;
-use object::write::Object;
-use std::collections::HashMap;
+use lucet_module::{trap_table_header_size, FunctionSpec, ModuleData, SerializedModule, TrapSite};
+use memoffset::offset_of;
+use object::write::{self, Object, SectionId, StandardSection, SymbolId, SymbolSection};
+use object::{BinaryFormat, RelocationKind, SectionKind, SymbolFlags, SymbolKind, SymbolScope};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
 
 pub(crate) const FUNCTION_MANIFEST_SYM: &str = "lucet_function_manifest";
+/// The symbol for the compile time of each function, when they are recorded.
 
 pub struct CraneliftFuncs {
     funcs: HashMap<Name, ir::Function>,
@@ -34,31 +49,1249 @@ impl CraneliftFuncs {
         file.write_all(buffer.as_bytes())?;
         Ok(())
     }
+
+    /// Write each function to its own .clif file in `dir`, named after the function's symbol,
+    /// creating `dir` if it doesn't exist.
+    ///
+    /// Characters other than ASCII letters, digits, `_`, `-`, and `.` are replaced with `_` in
+    /// file names. If that makes two functions' file names collide, this fails with
+    /// `Error::Input` rather than overwrite one function with another.
+    pub fn write_per_function<P: AsRef<Path>>(&self, dir: P) -> Result<(), Error> {
+        use cranelift_codegen::write_function;
+        std::fs::create_dir_all(dir.as_ref())?;
+        let mut file_names = HashSet::new();
+        for (n, func) in self.funcs.iter() {
+            let file_name = format!("{}.clif", sanitize_file_name(n.symbol()));
+            if !file_names.insert(file_name.clone()) {
+                return Err(Error::Input(format!(
+                    "more than one function would be written to {}",
+                    file_name
+                )));
+            }
+            let mut buffer = String::new();
+            write_function(&mut buffer, func, &Some(self.isa.as_ref()).into()).map_err(|e| {
+                let message = format!("{:?}", n);
+                Error::OutputFunction(e, message)
+            })?;
+            let mut file = File::create(dir.as_ref().join(file_name))?;
+            file.write_all(buffer.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+fn sanitize_file_name(symbol: &str) -> String {
+    symbol
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' | '.' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// A symbol defined in a serialized object, as `ObjectFile::self_check` reads it.
+struct ObjectSymbolInfo<'a> {
+    kind: SymbolKind,
+    size: u64,
+    /// The symbol's contents, if it is in a section with data.
+    contents: Option<&'a [u8]>,
+}
+
+fn check_symbol(
+    defined: &HashMap<String, ObjectSymbolInfo<'_>>,
+    name: &str,
+    kind: SymbolKind,
+    size: u64,
+    problems: &mut Vec<String>,
+) {
+    let symbol = match defined.get(name) {
+        Some(symbol) => symbol,
+        None => {
+            problems.push(format!("symbol {} is missing or undefined", name));
+            return;
+        }
+    };
+    if symbol.kind != kind {
+        problems.push(format!(
+            "symbol {} has kind {:?}, expected {:?}",
+            name, symbol.kind, kind
+        ));
+    }
+    if symbol.size != size {
+        problems.push(format!(
+            "symbol {} has size {}, but the manifest expects {}",
+            name, symbol.size, size
+        ));
+    }
+}
+
+/// A module as it was written by `Compiler::object_file`, kept so that `ObjectFile::self_check`
+/// can compare it against the object.
+struct ModuleRecord {
+    /// The global symbol the runtime finds the module through.
+    symbol: String,
+    data_symbol: String,
+    /// The serialized module data.
+    data: Vec<u8>,
+}
+
+/// A local symbol removed by `ObjectFile::strip_local_functions`, kept so that
+/// `ObjectFile::self_check` can still find what it defined.
+struct StrippedSymbol {
+    /// The name of the section the symbol was defined in.
+    section: String,
+    /// The symbol's offset in its section, which relocations to it now use as their addend.
+    offset: u64,
+    size: u64,
+    kind: SymbolKind,
+}
+
+/// A function manifest entry as it was written by `Compiler::object_file`, kept so that
+/// `ObjectFile::self_check` can compare it against the object.
+pub(crate) struct ManifestRecord {
+    pub symbol: String,
+    pub code_len: u32,
+    /// The trap table symbol and number of trap sites, for functions that have traps.
+    pub traps: Option<(String, usize)>,
+    /// The format version of the trap table, which determines the size of its header.
+    pub trap_table_version: u8,
+    /// How long the function took to translate and compile, if compile times were recorded and
+    /// the function was compiled from wasm.
+    pub compile_nanos: Option<u64>,
+}
+
+/// A relocation in an object file, as reported by `ObjectFile::relocations`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RelocationEntry {
+    /// The symbol whose contents are patched by the relocation, or `None` if the relocation isn't
+    /// within a symbol.
+    pub symbol: Option<String>,
+    /// The offset of the relocation from the start of `symbol`, or from the start of its section
+    /// if there is no symbol.
+    pub offset: u64,
+    /// The name of the symbol or section the relocation refers to.
+    pub target: String,
+    /// Whether the target is undefined in this object, so it must be provided at link time.
+    pub target_undefined: bool,
+    pub kind: RelocationKind,
+    pub addend: i64,
+}
+
+/// The role of a data symbol in an object file, as reported by `ObjectFile::data_symbols`.
+///
+/// Roles that belong to a function name it by its symbol without the object's symbol prefix, as
+/// `ObjectFile::address_map_for` takes it. The start function has no data symbol of its own; it
+/// is recorded in `Module`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DataSymbolKind {
+    /// The `Module` structure the runtime loads the object through.
+    Module,
+    /// The serialized `ModuleData`: heap specification, globals, signatures, and exports.
+    ModuleData,
+    /// The function manifest that `Module` points to.
+    FunctionManifest,
+    /// The trap table of the function `func`.
+    TrapTable { func: String },
+    /// A trap table shared by every function with the same trap sites. See
+    /// `CompilerBuilder::dedup_trap_tables`.
+    SharedTrapTable,
+    /// The sequence of tables that `Module` points to.
+    Tables,
+    /// The entries of the table with index `index`.
+    TableData { index: u32 },
+    /// The data segments, when they are emitted apart from the module data. See
+    /// `CompilerBuilder::separate_data_segments`.
+    DataSegments,
+    /// The initial contents of the heap. See `CompilerBuilder::bake_memory_image`.
+    HeapImage,
+    /// The address map of the function `func`. See `CompilerBuilder::emit_address_map`.
+    AddressMap { func: String },
+    /// The CFG map of the function `func`. See `CompilerBuilder::emit_cfg_map`.
+    CfgMap { func: String },
+    /// See `CompilerBuilder::emit_sig_table`.
+    SignatureTable,
+    /// The custom metadata stored under `key`. See `CompilerBuilder::custom_metadata`.
+    Custom { key: String },
+    /// A data symbol lucetc does not recognize, such as one in an object produced by
+    /// `ObjectFile::merge`, whose symbols carry each guest's namespace.
+    Other,
+}
+
+impl DataSymbolKind {
+    /// The role of the data symbol `name`, with the object's symbol prefix removed.
+    fn of(name: &str) -> Self {
+        match name {
+            FUNCTION_MANIFEST_SYM => DataSymbolKind::FunctionManifest,
+            TABLE_SYM => DataSymbolKind::Tables,
+            DATA_SEGMENTS_SYM => DataSymbolKind::DataSegments,
+            HEAP_IMAGE_SYM => DataSymbolKind::HeapImage,
+            SIGNATURE_TABLE_SYM => DataSymbolKind::SignatureTable,
+            // Shared trap tables come first, as their names also start like a function's.
+            _ if name.starts_with(SHARED_TRAP_TABLE_PREFIX) => DataSymbolKind::SharedTrapTable,
+            _ => Self::of_prefixed(name),
+        }
+    }
+
+    /// The role of a data symbol named with one of the per-function or per-key prefixes.
+    fn of_prefixed(name: &str) -> Self {
+        let suffix = |prefix: &str| name.strip_prefix(prefix).map(str::to_owned);
+        if let Some(func) = suffix(TRAP_TABLE_PREFIX) {
+            DataSymbolKind::TrapTable { func }
+        } else if let Some(func) = suffix(ADDRESS_MAP_PREFIX) {
+            DataSymbolKind::AddressMap { func }
+        } else if let Some(func) = suffix(CFG_MAP_PREFIX) {
+            DataSymbolKind::CfgMap { func }
+        } else if let Some(key) = suffix(CUSTOM_METADATA_PREFIX) {
+            DataSymbolKind::Custom { key }
+        } else if let Some(index) = suffix(TABLE_DATA_PREFIX).and_then(|ix| ix.parse().ok()) {
+            DataSymbolKind::TableData { index }
+        } else {
+            DataSymbolKind::Other
+        }
+    }
+}
+
+/// A data symbol defined in an object file, as reported by `ObjectFile::data_symbols`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DataSymbolInfo {
+    /// The symbol's name in the object, including the object's symbol prefix.
+    pub name: String,
+    pub kind: DataSymbolKind,
+    /// The size of the symbol's contents in bytes.
+    pub size: u64,
+    /// The symbol's linkage: `Compilation` for symbols local to the object, `Linkage` for
+    /// hidden globals, and `Dynamic` for exported symbols.
+    pub scope: SymbolScope,
+    pub weak: bool,
 }
 
 pub struct ObjectFile {
     object: Object,
+    manifest: Vec<ManifestRecord>,
+    /// Each module in the object. There is one module unless the object was produced by
+    /// `ObjectFile::merge`.
+    modules: Vec<ModuleRecord>,
+    /// The prefix given to every symbol the object defines, from
+    /// `CompilerBuilder::symbol_prefix`. Empty for merged objects, whose guests each have their
+    /// own prefix.
+    symbol_prefix: String,
+    /// The symbols removed by `strip_local_functions`, by name.
+    stripped: HashMap<String, StrippedSymbol>,
+    elf_os_abi: Option<u8>,
+    elf_flags: Option<u32>,
 }
 impl ObjectFile {
     pub fn new(product: ObjectProduct) -> Result<Self, Error> {
         let obj = Self {
             object: product.object,
+            manifest: Vec::new(),
+            modules: Vec::new(),
+            symbol_prefix: String::new(),
+            stripped: HashMap::new(),
+            elf_os_abi: None,
+            elf_flags: None,
         };
 
         Ok(obj)
     }
 
+    /// Combine the objects of several guests into one relocatable object, with each guest's
+    /// defined symbols prefixed by `<namespace>_`. That includes each guest's module symbol,
+    /// so each guest's module can still be found and loaded on its own, with
+    /// `DlModule::load_with_symbol`. Undefined symbols, such as hostcalls, are shared by all the
+    /// guests. Sections with the same name are concatenated.
+    ///
+    /// Only ELF objects can be merged.
+    pub fn merge(objects: Vec<(String, ObjectFile)>) -> Result<ObjectFile, Error> {
+        Self::merge_prefixed(
+            objects
+                .into_iter()
+                .map(|(namespace, obj)| (format!("{}_", namespace), obj))
+                .collect(),
+            &HashSet::new(),
+        )
+    }
+
+    /// Prefix every symbol defined in the object with `prefix`. See
+    /// `CompilerBuilder::symbol_prefix`.
+    pub(crate) fn with_symbol_prefix(self, prefix: &str) -> Result<ObjectFile, Error> {
+        let (elf_os_abi, elf_flags) = (self.elf_os_abi, self.elf_flags);
+        let mut obj = Self::merge_prefixed(vec![(prefix.to_owned(), self)], &HashSet::new())?;
+        obj.symbol_prefix = prefix.to_owned();
+        Ok(obj.with_elf_header(elf_os_abi, elf_flags))
+    }
+
+    /// Remove the local symbols of the object's functions and of their trap tables, whose names
+    /// come from the module's name section. See `Compiler::object_file_split`.
+    ///
+    /// Relocations to a removed symbol refer to its section instead, with the symbol's offset
+    /// added to their addend, so the code and data are unchanged. Symbols that are the target of
+    /// a GOT or other relocation that can't be rewritten that way are kept.
+    pub(crate) fn strip_local_functions(self) -> Result<ObjectFile, Error> {
+        let mut strip = HashSet::new();
+        for record in self.manifest.iter() {
+            strip.insert(record.symbol.clone());
+            if let Some((trap_symbol, _trap_len)) = record.traps.as_ref() {
+                let unprefixed = trap_symbol
+                    .strip_prefix(self.symbol_prefix.as_str())
+                    .unwrap_or(trap_symbol);
+                if unprefixed.starts_with(TRAP_TABLE_PREFIX) {
+                    strip.insert(trap_symbol.clone());
+                }
+            }
+        }
+        let (symbol_prefix, elf_os_abi, elf_flags) =
+            (self.symbol_prefix.clone(), self.elf_os_abi, self.elf_flags);
+        let mut obj = Self::merge_prefixed(vec![(String::new(), self)], &strip)?;
+        obj.symbol_prefix = symbol_prefix;
+        Ok(obj.with_elf_header(elf_os_abi, elf_flags))
+    }
+
+    /// Like `merge`, but with each object's defined symbols prefixed by exactly the given string.
+    ///
+    /// Local symbols named in `strip`, before prefixing, are removed as `strip_local_functions`
+    /// describes.
+    fn merge_prefixed(
+        objects: Vec<(String, ObjectFile)>,
+        strip: &HashSet<String>,
+    ) -> Result<ObjectFile, Error> {
+        use object::read::{Object as _, ObjectSection as _, ObjectSymbol as _};
+        use object::{RelocationTarget, SectionIndex, SymbolIndex};
+
+        let merge_error = |e: object::read::Error| Error::ObjectMerge(e.to_string());
+
+        let mut merged: Option<Object> = None;
+        let mut merged_architecture = None;
+        let mut sections: HashMap<(Vec<u8>, Vec<u8>), SectionId> = HashMap::new();
+        let mut undefined: HashMap<Vec<u8>, SymbolId> = HashMap::new();
+        let mut manifest = Vec::new();
+        let mut modules = Vec::new();
+        let mut stripped = HashMap::new();
+
+        for (prefix, obj) in objects {
+            let prefixed = |name: &str| format!("{}{}", prefix, name);
+
+            let bytes = obj.bytes()?;
+            let file = object::File::parse(&bytes).map_err(merge_error)?;
+            if file.format() != BinaryFormat::Elf {
+                return Err(Error::Unsupported(format!(
+                    "merging {:?} objects",
+                    file.format()
+                )));
+            }
+            let architecture = *merged_architecture.get_or_insert(file.architecture());
+            if architecture != file.architecture() {
+                return Err(Error::ObjectMerge(format!(
+                    "{} is for {:?}, but earlier objects are for {:?}",
+                    prefix,
+                    file.architecture(),
+                    architecture
+                )));
+            }
+            let out = merged.get_or_insert_with(|| {
+                Object::new(file.format(), file.architecture(), file.endianness())
+            });
+
+            // Append each section's contents to the merged section of the same name, recording
+            // where they start.
+            let mut section_map: HashMap<SectionIndex, (SectionId, u64)> = HashMap::new();
+            let mut section_names: HashMap<SectionIndex, String> = HashMap::new();
+            let mut section_offsets: HashMap<String, u64> = HashMap::new();
+            for section in file.sections() {
+                let name = section.name().map_err(merge_error)?;
+                // Symbol, string, and relocation tables are rebuilt by the writer.
+                if name.is_empty()
+                    || section.kind() == SectionKind::Metadata
+                    || section.kind() == SectionKind::Unknown
+                {
+                    continue;
+                }
+                let segment = section.segment_name().map_err(merge_error)?.unwrap_or("");
+                let kind = section.kind();
+                let key = (segment.as_bytes().to_vec(), name.as_bytes().to_vec());
+                let section_id = *sections
+                    .entry(key.clone())
+                    .or_insert_with(|| out.add_section(key.0, key.1, kind));
+                let offset = if kind == SectionKind::UninitializedData {
+                    out.append_section_bss(section_id, section.size(), section.align())
+                } else {
+                    let data = section.data().map_err(merge_error)?;
+                    out.append_section_data(section_id, data, section.align())
+                };
+                section_map.insert(section.index(), (section_id, offset));
+                section_names.insert(section.index(), name.to_owned());
+                section_offsets.insert(name.to_owned(), offset);
+            }
+
+            // Only relocations that add the target's address to their addend can be redirected
+            // from a stripped symbol to its section.
+            let mut unstrippable = HashSet::new();
+            for section in file.sections() {
+                for (_offset, reloc) in section.relocations() {
+                    let rewritable = matches!(
+                        reloc.kind(),
+                        RelocationKind::Absolute
+                            | RelocationKind::Relative
+                            | RelocationKind::PltRelative
+                    );
+                    if let RelocationTarget::Symbol(index) = reloc.target() {
+                        if !rewritable {
+                            unstrippable.insert(index);
+                        }
+                    }
+                }
+            }
+
+            // Symbols are remapped along with an addend correcting for where their section now
+            // starts, which is only nonzero for section symbols.
+            let mut symbol_map: HashMap<SymbolIndex, (SymbolId, i64)> = HashMap::new();
+            for symbol in file.symbols() {
+                let name = symbol.name().map_err(merge_error)?;
+                match symbol.section() {
+                    object::SymbolSection::Section(index) => {
+                        let (section_id, offset) = match section_map.get(&index) {
+                            Some(section) => *section,
+                            None => continue,
+                        };
+                        if symbol.kind() == SymbolKind::Section {
+                            let id = out.section_symbol(section_id);
+                            symbol_map.insert(symbol.index(), (id, offset as i64));
+                            continue;
+                        }
+                        if name.is_empty() {
+                            continue;
+                        }
+                        if symbol.scope() == SymbolScope::Compilation
+                            && strip.contains(name)
+                            && !unstrippable.contains(&symbol.index())
+                        {
+                            let section_offset = symbol.address() + offset;
+                            let id = out.section_symbol(section_id);
+                            symbol_map.insert(symbol.index(), (id, section_offset as i64));
+                            stripped.insert(
+                                prefixed(name),
+                                StrippedSymbol {
+                                    section: section_names[&index].clone(),
+                                    offset: section_offset,
+                                    size: symbol.size(),
+                                    kind: symbol.kind(),
+                                },
+                            );
+                            continue;
+                        }
+                        let id = out.add_symbol(write::Symbol {
+                            name: prefixed(name).into_bytes(),
+                            value: symbol.address() + offset,
+                            size: symbol.size(),
+                            kind: symbol.kind(),
+                            scope: symbol.scope(),
+                            weak: symbol.is_weak(),
+                            section: SymbolSection::Section(section_id),
+                            flags: SymbolFlags::None,
+                        });
+                        symbol_map.insert(symbol.index(), (id, 0));
+                    }
+                    object::SymbolSection::Undefined if !name.is_empty() => {
+                        let id = *undefined
+                            .entry(name.as_bytes().to_vec())
+                            .or_insert_with(|| {
+                                out.add_symbol(write::Symbol {
+                                    name: name.as_bytes().to_vec(),
+                                    value: 0,
+                                    size: 0,
+                                    kind: symbol.kind(),
+                                    scope: symbol.scope(),
+                                    weak: symbol.is_weak(),
+                                    section: SymbolSection::Undefined,
+                                    flags: SymbolFlags::None,
+                                })
+                            });
+                        symbol_map.insert(symbol.index(), (id, 0));
+                    }
+                    // File symbols and the like have no bearing on the merged object.
+                    _ => {}
+                }
+            }
+
+            for section in file.sections() {
+                let (section_id, offset) = match section_map.get(&section.index()) {
+                    Some(section) => *section,
+                    None => continue,
+                };
+                for (reloc_offset, reloc) in section.relocations() {
+                    if reloc.has_implicit_addend() {
+                        return Err(Error::ObjectMerge(format!(
+                            "relocation at {:#x} in {} has an implicit addend",
+                            reloc_offset, prefix
+                        )));
+                    }
+                    let (symbol, addend) = match reloc.target() {
+                        RelocationTarget::Symbol(index) => symbol_map.get(&index).copied(),
+                        RelocationTarget::Section(index) => {
+                            section_map.get(&index).map(|(section_id, offset)| {
+                                (out.section_symbol(*section_id), *offset as i64)
+                            })
+                        }
+                        _ => None,
+                    }
+                    .ok_or_else(|| {
+                        Error::ObjectMerge(format!(
+                            "relocation at {:#x} in {} has an unsupported target",
+                            reloc_offset, prefix
+                        ))
+                    })?;
+                    out.add_relocation(
+                        section_id,
+                        write::Relocation {
+                            offset: reloc_offset + offset,
+                            size: reloc.size(),
+                            kind: reloc.kind(),
+                            encoding: reloc.encoding(),
+                            symbol,
+                            addend: reloc.addend() + addend,
+                        },
+                    )
+                    .map_err(|source| {
+                        Error::ObjectArtifact(source, format!("relocating {}", prefix))
+                    })?;
+                }
+            }
+
+            manifest.extend(obj.manifest.into_iter().map(|record| {
+                ManifestRecord {
+                    symbol: prefixed(&record.symbol),
+                    code_len: record.code_len,
+                    traps: record
+                        .traps
+                        .map(|(trap_symbol, trap_len)| (prefixed(&trap_symbol), trap_len)),
+                    trap_table_version: record.trap_table_version,
+                    compile_nanos: record.compile_nanos,
+                }
+            }));
+            modules.extend(obj.modules.into_iter().map(|module| ModuleRecord {
+                symbol: prefixed(&module.symbol),
+                data_symbol: prefixed(&module.data_symbol),
+                data: module.data,
+            }));
+            stripped.extend(obj.stripped.into_iter().map(|(name, symbol)| {
+                let offset = section_offsets.get(&symbol.section).copied().unwrap_or(0);
+                (
+                    prefixed(&name),
+                    StrippedSymbol {
+                        offset: symbol.offset + offset,
+                        ..symbol
+                    },
+                )
+            }));
+        }
+
+        let object = merged.ok_or_else(|| Error::ObjectMerge("no objects to merge".to_owned()))?;
+        Ok(Self {
+            object,
+            manifest,
+            modules,
+            symbol_prefix: String::new(),
+            stripped,
+            elf_os_abi: None,
+            elf_flags: None,
+        })
+    }
+
+    pub(crate) fn with_contents(
+        mut self,
+        manifest: Vec<ManifestRecord>,
+        module_symbol: String,
+        module_data_symbol: String,
+        module_data: Vec<u8>,
+    ) -> Self {
+        self.manifest = manifest;
+        self.modules = vec![ModuleRecord {
+            symbol: module_symbol,
+            data_symbol: module_data_symbol,
+            data: module_data,
+        }];
+        self
+    }
+
+    /// Override the OS/ABI and machine flags in the ELF header when the object is written. The
+    /// object must be ELF.
+    pub(crate) fn with_elf_header(mut self, os_abi: Option<u8>, flags: Option<u32>) -> Self {
+        self.elf_os_abi = os_abi;
+        self.elf_flags = flags;
+        self
+    }
+
+    /// Pad the code section to `size` bytes with repetitions of `fill`, each aligned to its own
+    /// size. See `CompilerBuilder::pad_code_to`.
+    pub(crate) fn pad_code_to(&mut self, size: usize, fill: &[u8]) -> Result<(), Error> {
+        if size % fill.len() != 0 {
+            return Err(Error::Input(format!(
+                "code can only be padded to a multiple of {} bytes on this target",
+                fill.len()
+            )));
+        }
+        let text = self.object.section_id(StandardSection::Text);
+        // Appending nothing reports the section's current size.
+        let actual = self.object.append_section_data(text, &[], 1) as usize;
+        if actual > size {
+            return Err(Error::CodeSizeBudgetExceeded {
+                actual,
+                budget: size,
+            });
+        }
+        // Code ends on an instruction boundary, but round up in case anything after it doesn't;
+        // `append_section_data` fills the gap with zeroes.
+        let start = (actual + fill.len() - 1) / fill.len() * fill.len();
+        let padding = fill
+            .iter()
+            .copied()
+            .cycle()
+            .take(size - start)
+            .collect::<Vec<_>>();
+        self.object
+            .append_section_data(text, &padding, fill.len() as u64);
+        Ok(())
+    }
+
+    /// Check that the function manifest, trap tables, and module data that `Compiler::object_file`
+    /// recorded are what the object actually contains, reporting every inconsistency found in
+    /// `Error::InconsistentObject`.
+    ///
+    /// The serialized object is parsed, and each function and trap table symbol is checked for
+    /// its kind and size, each function manifest entry for the relocations to its code and trap
+    /// table, their lengths, and the compile time, and each module data symbol for its contents
+    /// and the module that refers to it. This is intended to catch bugs in `lucetc` before an
+    /// object reaches the runtime loader; an object produced by `Compiler::object_file` should
+    /// always pass, and an object with no module data never does.
+    pub fn self_check(&self) -> Result<(), Error> {
+        use object::read::{Object as _, ObjectSection as _, ObjectSymbol as _};
+
+        let read_error = |e: object::read::Error| Error::Input(e.to_string());
+
+        let bytes = self.bytes()?;
+        let file = object::File::parse(&bytes).map_err(read_error)?;
+        // Mach-O symbol names have a leading underscore that the names recorded here don't.
+        let macho = file.format() == BinaryFormat::MachO;
+        let unmangle = |name: &str| -> String {
+            let name = if macho {
+                name.strip_prefix('_').unwrap_or(name)
+            } else {
+                name
+            };
+            name.to_owned()
+        };
+
+        // Every named symbol in the object, and the defined ones along with their contents, which
+        // are only available for symbols in sections with data.
+        let mut names = HashSet::new();
+        let mut defined = HashMap::new();
+        for symbol in file.symbols() {
+            let name = unmangle(symbol.name().map_err(read_error)?);
+            if name.is_empty() || symbol.kind() == SymbolKind::Section {
+                continue;
+            }
+            names.insert(name.clone());
+            if let Some(index) = symbol.section_index() {
+                let section = file.section_by_index(index).map_err(read_error)?;
+                let start = (symbol.address() - section.address()) as usize;
+                let contents = section
+                    .data()
+                    .map_err(read_error)?
+                    .get(start..start + symbol.size() as usize);
+                defined.insert(
+                    name,
+                    ObjectSymbolInfo {
+                        kind: symbol.kind(),
+                        size: symbol.size(),
+                        contents,
+                    },
+                );
+            }
+        }
+        // Stripped symbols are checked as they were before they were removed, at the offset
+        // relocations to them now use.
+        for (name, symbol) in self.stripped.iter() {
+            let contents = match file.section_by_name(&symbol.section) {
+                Some(section) => section
+                    .data()
+                    .map_err(read_error)?
+                    .get(symbol.offset as usize..(symbol.offset + symbol.size) as usize),
+                None => None,
+            };
+            names.insert(name.clone());
+            defined.insert(
+                name.clone(),
+                ObjectSymbolInfo {
+                    kind: symbol.kind,
+                    size: symbol.size,
+                    contents,
+                },
+            );
+        }
+        let stripped_at = |section: &str, addend: i64| {
+            self.stripped
+                .iter()
+                .find(|(_name, symbol)| symbol.section == section && symbol.offset as i64 == addend)
+                .map(|(name, _symbol)| name.clone())
+        };
+        // Each relocation within a symbol, as the symbol it patches, its offset in that symbol, and
+        // its target.
+        let relocations = self
+            .relocations()?
+            .into_iter()
+            .filter_map(|reloc| {
+                let target = stripped_at(&reloc.target, reloc.addend)
+                    .unwrap_or_else(|| unmangle(&reloc.target));
+                Some((unmangle(&reloc.symbol?), reloc.offset, target))
+            })
+            .collect::<Vec<_>>();
+        let target_of = |symbol: &str, offset: u64| {
+            relocations
+                .iter()
+                .find(|(patched, at, _target)| patched == symbol && *at == offset)
+                .map(|(_patched, _at, target)| target.as_str())
+        };
+        let read_u64 = |symbol: &str, offset: u64| {
+            let contents = defined.get(symbol)?.contents?;
+            let bytes = contents.get(offset as usize..offset as usize + 8)?;
+            let mut value = [0; 8];
+            value.copy_from_slice(bytes);
+            Some(u64::from_le_bytes(value))
+        };
+
+        let mut problems = Vec::new();
+        if self.modules.is_empty() {
+            problems.push("object has no module".to_owned());
+        }
+
+        let spec_size = std::mem::size_of::<FunctionSpec>() as u64;
+        for record in self.manifest.iter() {
+            let has_code = record.code_len > 0 || record.traps.is_some();
+            if has_code {
+                check_symbol(
+                    &defined,
+                    &record.symbol,
+                    SymbolKind::Text,
+                    record.code_len as u64,
+                    &mut problems,
+                );
+            } else if !names.contains(record.symbol.as_str()) {
+                problems.push(format!("function {} is missing", record.symbol));
+            }
+
+            // The manifest entry for the function is the one whose code pointer is relocated to
+            // the function's symbol.
+            let entry = relocations.iter().find(|(patched, at, target)| {
+                *target == record.symbol
+                    && at % spec_size == 0
+                    && patched.ends_with(FUNCTION_MANIFEST_SYM)
+            });
+            let (manifest, entry) = match entry {
+                Some((manifest, at, _target)) => (manifest.as_str(), *at),
+                None => {
+                    problems.push(format!(
+                        "function {} has no function manifest entry",
+                        record.symbol
+                    ));
+                    continue;
+                }
+            };
+            if read_u64(manifest, entry + 8) != Some(record.code_len as u64) {
+                problems.push(format!(
+                    "manifest entry for function {} does not have code length {}",
+                    record.symbol, record.code_len
+                ));
+            }
+            let trap_target = target_of(manifest, entry + 16);
+            let trap_symbol = record.traps.as_ref().map(|(symbol, _len)| symbol.as_str());
+            let trap_len = record.traps.as_ref().map_or(0, |(_symbol, len)| *len);
+            if trap_target != trap_symbol {
+                problems.push(format!(
+                    "manifest entry for function {} refers to trap table {:?}",
+                    record.symbol, trap_target
+                ));
+            }
+            if read_u64(manifest, entry + 24) != Some(trap_len as u64) {
+                problems.push(format!(
+                    "manifest entry for function {} does not have {} trap sites",
+                    record.symbol, trap_len
+                ));
+            }
+            if read_u64(manifest, entry + 32) != Some(record.compile_nanos.unwrap_or(0)) {
+                problems.push(format!(
+                    "manifest entry for function {} does not have compile time {:?}",
+                    record.symbol, record.compile_nanos
+                ));
+            }
+
+            if let Some((trap_symbol, trap_len)) = record.traps.as_ref() {
+                if !has_code {
+                    problems.push(format!(
+                        "trap table {} refers to function {}, which has no code",
+                        trap_symbol, record.symbol
+                    ));
+                }
+                let header_size = trap_table_header_size(record.trap_table_version).unwrap_or(0);
+                let trap_size = (header_size + *trap_len * std::mem::size_of::<TrapSite>()) as u64;
+                check_symbol(
+                    &defined,
+                    trap_symbol,
+                    SymbolKind::Data,
+                    trap_size,
+                    &mut problems,
+                );
+            }
+        }
+
+        let module_data_offset = offset_of!(SerializedModule, module_data_ptr) as u64;
+        for module in self.modules.iter() {
+            check_symbol(
+                &defined,
+                &module.data_symbol,
+                SymbolKind::Data,
+                module.data.len() as u64,
+                &mut problems,
+            );
+            let contents = defined
+                .get(module.data_symbol.as_str())
+                .and_then(|symbol| symbol.contents);
+            match contents {
+                Some(contents) if contents == module.data.as_slice() => {
+                    if let Err(e) = ModuleData::deserialize(contents) {
+                        problems.push(format!("module data does not deserialize: {}", e));
+                    }
+                }
+                Some(_) => problems.push(format!(
+                    "module data symbol {} does not hold the serialized module data",
+                    module.data_symbol
+                )),
+                None => {}
+            }
+
+            // The module's `SerializedModule` points to its module data.
+            check_symbol(
+                &defined,
+                &module.symbol,
+                SymbolKind::Data,
+                std::mem::size_of::<SerializedModule>() as u64,
+                &mut problems,
+            );
+            if target_of(&module.symbol, module_data_offset) != Some(module.data_symbol.as_str()) {
+                problems.push(format!(
+                    "module {} does not refer to module data {}",
+                    module.symbol, module.data_symbol
+                ));
+            }
+            if read_u64(&module.symbol, module_data_offset + 8) != Some(module.data.len() as u64) {
+                problems.push(format!(
+                    "module {} does not have module data length {}",
+                    module.symbol,
+                    module.data.len()
+                ));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InconsistentObject(problems))
+        }
+    }
+
     pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
         let _ = path.as_ref().file_name().ok_or(|| {
             let message = format!("Path must be filename {:?}", path.as_ref());
             Error::Input(message);
         });
         let mut file = File::create(path)?;
-        let bytes = self
+        let bytes = self.bytes()?;
+        file.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Write a GNU ld linker script to `path` that lays out the object's sections one after
+    /// another from the address `base`: code first, then read-only data, data, and zeroed data.
+    ///
+    /// Every section is kept even if nothing refers to it, and the global symbols lucetc reserves,
+    /// such as the module symbol and the module data, are listed with `EXTERN` so the linker keeps
+    /// them too. The script only places the guest's sections, so it is a starting point for an
+    /// embedding's own script rather than a complete one. Only ELF objects are supported.
+    pub fn write_linker_script(&self, path: &Path, base: u64) -> Result<(), Error> {
+        use object::read::{Object as _, ObjectSection as _, ObjectSymbol as _};
+
+        let read_error = |e: object::read::Error| Error::Input(e.to_string());
+
+        let bytes = self.bytes()?;
+        let file = object::File::parse(&bytes).map_err(read_error)?;
+        if file.format() != BinaryFormat::Elf {
+            return Err(Error::Unsupported(format!(
+                "linker scripts for {:?} objects",
+                file.format()
+            )));
+        }
+
+        let mut reserved = [FUNCTION_MANIFEST_SYM, stack_probe::STACK_PROBE_SYM]
+            .iter()
+            .map(|symbol| format!("{}{}", self.symbol_prefix, symbol))
+            .collect::<Vec<_>>();
+        for module in self.modules.iter() {
+            reserved.push(module.symbol.clone());
+            reserved.push(module.data_symbol.clone());
+        }
+        let kept_symbols = reserved
+            .into_iter()
+            .filter(|name| {
+                file.symbols().any(|symbol| {
+                    symbol.name() == Ok(name.as_str())
+                        && symbol.is_global()
+                        && !symbol.is_undefined()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut sections = Vec::new();
+        for section in file.sections() {
+            let order = match section.kind() {
+                SectionKind::Text => 0,
+                SectionKind::ReadOnlyData | SectionKind::ReadOnlyString => 1,
+                SectionKind::Data => 2,
+                SectionKind::UninitializedData => 3,
+                _ => continue,
+            };
+            sections.push((order, section.name().map_err(read_error)?));
+        }
+        sections.sort_by_key(|(order, _)| *order);
+
+        let mut script = String::from("/* Generated by lucetc. */\n");
+        if !kept_symbols.is_empty() {
+            script.push_str(&format!("EXTERN({})\n", kept_symbols.join(" ")));
+        }
+        script.push_str("SECTIONS\n{\n");
+        script.push_str(&format!("  . = {:#x};\n", base));
+        for (_, name) in sections {
+            script.push_str(&format!("  {} : {{ KEEP(*({})) }}\n", name, name));
+        }
+        script.push_str("}\n");
+
+        let mut file = File::create(path)?;
+        file.write_all(script.as_bytes())?;
+        Ok(())
+    }
+
+    /// The address map of the function with the given symbol, or `None` if the object has no
+    /// address map for it. See `CompilerBuilder::emit_address_map`.
+    ///
+    /// The map is read from the serialized object, so this is relatively expensive.
+    pub fn address_map_for(&self, symbol: &str) -> Result<Option<Vec<AddressMapEntry>>, Error> {
+        let map = self.data_symbol_contents(&address_map_sym(symbol))?;
+        Ok(map.map(|map| AddressMapEntry::deserialize(&map)))
+    }
+
+    /// The basic blocks of the function with the given symbol, or `None` if the object has no
+    /// CFG map for it. See `CompilerBuilder::emit_cfg_map`.
+    ///
+    /// Like `address_map_for`, this reads the serialized object.
+    pub fn cfg_map_for(&self, symbol: &str) -> Result<Option<Vec<BasicBlock>>, Error> {
+        let map = self.data_symbol_contents(&cfg_map_sym(symbol))?;
+        Ok(map.map(|map| BasicBlock::deserialize(&map)))
+    }
+
+    /// The signatures that the object's indirect calls check their callees against, sorted by
+    /// id, or `None` if the object has no signature table. See `CompilerBuilder::emit_sig_table`.
+    ///
+    /// Like `address_map_for`, this reads the serialized object.
+    pub fn signature_table(&self) -> Result<Option<Vec<SignatureTableEntry>>, Error> {
+        match self.data_symbol_contents(SIGNATURE_TABLE_SYM)? {
+            Some(table) => SignatureTableEntry::deserialize(&table).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The number of trap sites in all of the object's functions, as recorded in the function
+    /// manifest. This includes the sites in the stack probe and, when trap tables are shared,
+    /// counts a shared table once for each function that uses it.
+    ///
+    /// Most trap sites in a typical module are heap bounds checks, so a high count can suggest a
+    /// heap configuration that lets more of them be elided.
+    pub fn trap_site_count(&self) -> usize {
+        self.manifest
+            .iter()
+            .filter_map(|record| record.traps.as_ref())
+            .map(|(_trap_symbol, trap_len)| trap_len)
+            .sum()
+    }
+
+    /// How long each function took to translate and compile, in nanoseconds, as recorded in its
+    /// function manifest entry, in manifest order. Empty unless the object was compiled with
+    /// `CompilerBuilder::record_compile_times`, and functions lucetc synthesizes rather than
+    /// compiles from wasm are left out.
+    ///
+    /// Only objects compiled by a single `Compiler` are read. Like `address_map_for`, this reads
+    /// the serialized object.
+    pub fn compile_times(&self) -> Result<Vec<(String, u64)>, Error> {
+        let manifest = match self.data_symbol_contents(FUNCTION_MANIFEST_SYM)? {
+            Some(manifest) => manifest,
+            None => return Ok(Vec::new()),
+        };
+        let spec_size = std::mem::size_of::<FunctionSpec>();
+        let entries = manifest.chunks_exact(spec_size);
+        let mut compile_times = Vec::new();
+        for (record, entry) in self.manifest.iter().zip(entries) {
+            // The compile time is the last field of the entry, and 0 if none was recorded.
+            let mut value = [0; 8];
+            value.copy_from_slice(&entry[spec_size - 8..]);
+            let compile_nanos = u64::from_le_bytes(value);
+            if compile_nanos != 0 {
+                compile_times.push((record.symbol.clone(), compile_nanos));
+            }
+        }
+        Ok(compile_times)
+    }
+
+    /// The layout of the coverage counters the object's code increments, in counter order, or
+    /// `None` if the object is not instrumented for coverage. See `CompilerBuilder::coverage`.
+    ///
+    /// Like `address_map_for`, this reads the serialized object.
+    pub fn coverage_layout(&self) -> Result<Option<Vec<CoverageCounter>>, Error> {
+        match self.metadata(COVERAGE_METADATA_KEY)? {
+            Some(layout) => CoverageCounter::deserialize(&layout).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Compute a build ID over the object's contents and add it as a GNU build ID note,
+    /// returning the ID. The object must be ELF. See `Compiler::object_file_split`.
+    pub(crate) fn add_build_id(&mut self) -> Result<Vec<u8>, Error> {
+        use object::read::Object as _;
+
+        let bytes = self.bytes()?;
+        let file = object::File::parse(&bytes).map_err(|e| Error::Input(e.to_string()))?;
+        let build_id = Sha256::digest(&bytes)[..BUILD_ID_SIZE].to_vec();
+        let note = build_id_note(&build_id, file.endianness());
+        let section = self.object.add_section(
+            vec![],
+            BUILD_ID_SECTION.as_bytes().to_vec(),
+            SectionKind::Note,
+        );
+        self.object.append_section_data(section, &note, 4);
+        Ok(build_id)
+    }
+
+    /// The object's build ID, or `None` if it has none. See `Compiler::object_file_split`.
+    ///
+    /// Like `address_map_for`, this reads the serialized object.
+    pub fn build_id(&self) -> Result<Option<Vec<u8>>, Error> {
+        use object::read::{Object as _, ObjectSection as _};
+
+        let read_error = |e: object::read::Error| Error::Input(e.to_string());
+
+        let bytes = self.bytes()?;
+        let file = object::File::parse(&bytes).map_err(read_error)?;
+        let section = match file.section_by_name(BUILD_ID_SECTION) {
+            Some(section) => section,
+            None => return Ok(None),
+        };
+        Ok(parse_build_id_note(
+            section.data().map_err(read_error)?,
+            file.endianness(),
+        ))
+    }
+
+    /// The custom metadata stored under `key`, or `None` if the object has none. See
+    /// `CompilerBuilder::custom_metadata`.
+    ///
+    /// Like `address_map_for`, this reads the serialized object.
+    pub fn metadata(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        self.data_symbol_contents(&custom_metadata_sym(key))
+    }
+
+    /// Every relocation in the object, in section order, with the symbol it patches and the one
+    /// it refers to.
+    ///
+    /// This is meant for diagnosing link failures: an undefined target that the linker can't
+    /// resolve is reported along with the function that refers to it. Like `address_map_for`,
+    /// this reads the serialized object.
+    pub fn relocations(&self) -> Result<Vec<RelocationEntry>, Error> {
+        use object::read::{Object as _, ObjectSection as _, ObjectSymbol as _};
+        use object::RelocationTarget;
+
+        let read_error = |e: object::read::Error| Error::Input(e.to_string());
+
+        let bytes = self.bytes()?;
+        let file = object::File::parse(&bytes).map_err(read_error)?;
+
+        let mut entries = Vec::new();
+        for section in file.sections() {
+            // The symbols defined in this section, as (start, end, name) ranges of the section.
+            let symbols = file
+                .symbols()
+                .filter(|symbol| {
+                    symbol.section_index() == Some(section.index())
+                        && symbol.kind() != SymbolKind::Section
+                })
+                .map(|symbol| {
+                    let start = symbol.address() - section.address();
+                    Ok((
+                        start,
+                        start + symbol.size(),
+                        symbol.name().map_err(read_error)?,
+                    ))
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+
+            for (offset, reloc) in section.relocations() {
+                let (target, target_undefined) = match reloc.target() {
+                    RelocationTarget::Symbol(index) => {
+                        let symbol = file.symbol_by_index(index).map_err(read_error)?;
+                        let name = if symbol.kind() == SymbolKind::Section {
+                            let index = symbol.section_index().ok_or_else(|| {
+                                Error::Input("section symbol has no section".to_owned())
+                            })?;
+                            file.section_by_index(index)
+                                .and_then(|section| section.name())
+                                .map_err(read_error)?
+                        } else {
+                            symbol.name().map_err(read_error)?
+                        };
+                        (name, symbol.is_undefined())
+                    }
+                    RelocationTarget::Section(index) => {
+                        let target_section = file.section_by_index(index).map_err(read_error)?;
+                        (target_section.name().map_err(read_error)?, false)
+                    }
+                    // Absolute relocations have no target.
+                    _ => ("", false),
+                };
+                let containing = symbols
+                    .iter()
+                    .find(|(start, end, _name)| *start <= offset && offset < *end);
+                entries.push(RelocationEntry {
+                    symbol: containing.map(|(_start, _end, name)| (*name).to_owned()),
+                    offset: containing.map_or(offset, |(start, _end, _name)| offset - start),
+                    target: target.to_owned(),
+                    target_undefined,
+                    kind: reloc.kind(),
+                    addend: reloc.addend(),
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Every data symbol the object defines, in symbol table order, with its role, size, and
+    /// linkage.
+    ///
+    /// The module data symbol is recognized even in objects produced by `ObjectFile::merge`, but
+    /// other symbols are only categorized in objects compiled by a single `Compiler`. Like
+    /// `address_map_for`, this reads the serialized object.
+    pub fn data_symbols(&self) -> Result<Vec<DataSymbolInfo>, Error> {
+        use object::read::{Object as _, ObjectSymbol as _};
+
+        let read_error = |e: object::read::Error| Error::Input(e.to_string());
+
+        let bytes = self.bytes()?;
+        let file = object::File::parse(&bytes).map_err(read_error)?;
+        file.symbols()
+            .filter(|symbol| symbol.kind() == SymbolKind::Data && !symbol.is_undefined())
+            .map(|symbol| {
+                let name = symbol.name().map_err(read_error)?;
+                let kind = if self.modules.iter().any(|module| module.symbol == name) {
+                    DataSymbolKind::Module
+                } else if self.modules.iter().any(|module| module.data_symbol == name) {
+                    DataSymbolKind::ModuleData
+                } else {
+                    match name.strip_prefix(self.symbol_prefix.as_str()) {
+                        Some(name) => DataSymbolKind::of(name),
+                        None => DataSymbolKind::Other,
+                    }
+                };
+                Ok(DataSymbolInfo {
+                    name: name.to_owned(),
+                    kind,
+                    size: symbol.size(),
+                    scope: symbol.scope(),
+                    weak: symbol.is_weak(),
+                })
+            })
+            .collect()
+    }
+
+    /// The contents of the data symbol `name`, with the object's symbol prefix, in the serialized
+    /// object, or `None` if there is no such symbol.
+    fn data_symbol_contents(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        use object::read::{Object as _, ObjectSection as _, ObjectSymbol as _};
+
+        let read_error = |e: object::read::Error| Error::Input(e.to_string());
+        let name = &format!("{}{}", self.symbol_prefix, name);
+
+        let bytes = self.bytes()?;
+        let file = object::File::parse(&bytes).map_err(read_error)?;
+        let data_symbol = match file.symbols().find(|s| s.name() == Ok(name)) {
+            Some(data_symbol) => data_symbol,
+            None => return Ok(None),
+        };
+        let section = match data_symbol.section_index() {
+            Some(index) => file.section_by_index(index).map_err(read_error)?,
+            None => return Ok(None),
+        };
+        let data = section.data().map_err(read_error)?;
+        let start = (data_symbol.address() - section.address()) as usize;
+        let end = start + data_symbol.size() as usize;
+        let contents = data
+            .get(start..end)
+            .ok_or_else(|| Error::Input(format!("{} is out of its section's bounds", name)))?;
+        Ok(Some(contents.to_vec()))
+    }
+
+    /// The width, in bits, of a pointer on the target the object was compiled for, as recorded
+    /// in its module data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the object has no module data, as is the case for an `ObjectFile` created
+    /// directly with `ObjectFile::new`.
+    pub fn pointer_width(&self) -> u8 {
+        let module = self.modules.first().expect("object file has module data");
+        ModuleData::deserialize(&module.data)
+            .expect("module data produced by lucetc deserializes")
+            .pointer_width()
+    }
+
+    /// The contents of the object file, as `write` would write them.
+    pub fn bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = self
             .object
             .write()
             .map_err(|source| Error::ObjectArtifact(source, "Write error".to_owned()))?;
-        file.write_all(&bytes)?;
-        Ok(())
+        self.patch_elf_header(&mut bytes);
+        Ok(bytes)
+    }
+
+    // The object writer always emits an OS/ABI of `ELFOSABI_NONE` and no flags, so overrides are
+    // patched into the header afterward.
+    fn patch_elf_header(&self, bytes: &mut [u8]) {
+        const EI_CLASS: usize = 4;
+        const EI_DATA: usize = 5;
+        const EI_OSABI: usize = 7;
+        const ELFCLASS64: u8 = 2;
+        const ELFDATA2MSB: u8 = 2;
+
+        if let Some(os_abi) = self.elf_os_abi {
+            bytes[EI_OSABI] = os_abi;
+        }
+        if let Some(flags) = self.elf_flags {
+            let e_flags = if bytes[EI_CLASS] == ELFCLASS64 {
+                48
+            } else {
+                36
+            };
+            let flags = if bytes[EI_DATA] == ELFDATA2MSB {
+                flags.to_be_bytes()
+            } else {
+                flags.to_le_bytes()
+            };
+            bytes[e_flags..e_flags + 4].copy_from_slice(&flags);
+        }
     }
 }
@@ -8,6 +8,7 @@ pub enum RuntimeFunc {
     MemSize,
     MemGrow,
     YieldAtBoundExpiration,
+    UnreachableHandler,
 }
 
 pub struct RuntimeFuncType {
@@ -72,4 +73,25 @@ impl Runtime {
         );
         Self { functions }
     }
+
+    /// Add the handler that `unreachable` calls with `UnreachableStrategy::CallHandler`. It is
+    /// only declared when it is used, so that other modules don't import it.
+    pub fn with_unreachable_handler(mut self, target: TargetFrontendConfig) -> Self {
+        self.functions.insert(
+            RuntimeFunc::UnreachableHandler,
+            RuntimeFuncType {
+                name: "lucet_vmctx_unreachable".to_owned(),
+                signature: Signature {
+                    params: vec![],
+                    returns: vec![],
+                    call_conv: target.default_call_conv,
+                },
+                wasm_func_type: WasmFuncType {
+                    params: vec![].into_boxed_slice(),
+                    returns: vec![].into_boxed_slice(),
+                },
+            },
+        );
+        self
+    }
 }
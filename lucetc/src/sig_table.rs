@@ -0,0 +1,134 @@
+use crate::error::Error;
+use crate::module::ModuleInfo;
+use byteorder::{ByteOrder, LittleEndian};
+use cranelift_wasm::wasmparser::{FunctionBody, Operator};
+use cranelift_wasm::{TypeIndex, WasmFuncType, WasmType};
+use lucet_module::UniqueSignatureIndex;
+use std::collections::BTreeMap;
+
+/// The symbol for the signature table, when it is emitted.
+pub(crate) const SIGNATURE_TABLE_SYM: &str = "lucet_signature_table";
+
+/// A signature that the module's indirect calls check their callees against, and the id the
+/// check compares.
+///
+/// A `call_indirect` traps with `BadSignature` unless the table entry it calls has this id, so
+/// the ids in the table entry and at the call site identify the actual and expected types.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignatureTableEntry {
+    pub id: UniqueSignatureIndex,
+    pub func_type: WasmFuncType,
+}
+
+impl SignatureTableEntry {
+    /// Serialize entries as they are laid out in the signature table: for each entry, its
+    /// little-endian `u32` id, then its parameters and its results, each as a little-endian
+    /// `u32` count followed by one byte per type in the wasm binary encoding of value types.
+    pub(crate) fn serialize(entries: &[Self]) -> Result<Box<[u8]>, Error> {
+        let mut bytes = Vec::new();
+        for entry in entries {
+            bytes.extend_from_slice(&entry.id.as_u32().to_le_bytes());
+            for types in [&entry.func_type.params, &entry.func_type.returns].iter() {
+                bytes.extend_from_slice(&(types.len() as u32).to_le_bytes());
+                for ty in types.iter() {
+                    bytes.push(encode_type(ty)?);
+                }
+            }
+        }
+        Ok(bytes.into_boxed_slice())
+    }
+
+    pub(crate) fn deserialize(bytes: &[u8]) -> Result<Vec<Self>, Error> {
+        let mut entries = Vec::new();
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let id = UniqueSignatureIndex::from_u32(LittleEndian::read_u32(take(&mut rest, 4)?));
+            let mut types = Vec::with_capacity(2);
+            for _ in 0..2 {
+                let count = LittleEndian::read_u32(take(&mut rest, 4)?) as usize;
+                types.push(
+                    take(&mut rest, count)?
+                        .iter()
+                        .map(|byte| decode_type(*byte))
+                        .collect::<Result<Vec<_>, _>>()?
+                        .into_boxed_slice(),
+                );
+            }
+            let returns = types.pop().expect("results were read");
+            let params = types.pop().expect("parameters were read");
+            entries.push(SignatureTableEntry {
+                id,
+                func_type: WasmFuncType { params, returns },
+            });
+        }
+        Ok(entries)
+    }
+}
+
+/// Split the first `len` bytes off of `rest`.
+fn take<'a>(rest: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if rest.len() < len {
+        return Err(Error::Input("signature table is truncated".to_owned()));
+    }
+    let (taken, tail) = (*rest).split_at(len);
+    *rest = tail;
+    Ok(taken)
+}
+
+/// Collect the signatures of the module's indirect calls, sorted by id and without duplicates.
+pub(crate) fn signature_table<'a>(
+    info: &ModuleInfo<'_>,
+    function_bodies: impl Iterator<Item = &'a FunctionBody<'a>>,
+) -> Result<Vec<SignatureTableEntry>, Error> {
+    let mut signatures = BTreeMap::new();
+    for body in function_bodies {
+        for op in body.get_operators_reader()? {
+            match op? {
+                Operator::CallIndirect { index, .. }
+                | Operator::ReturnCallIndirect { index, .. } => {
+                    let id = info.signature_mapping[TypeIndex::from_u32(index)];
+                    signatures
+                        .entry(id)
+                        .or_insert_with(|| info.signatures[id].1.clone());
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(signatures
+        .into_iter()
+        .map(|(id, func_type)| SignatureTableEntry { id, func_type })
+        .collect())
+}
+
+fn encode_type(ty: &WasmType) -> Result<u8, Error> {
+    match ty {
+        WasmType::I32 => Ok(0x7f),
+        WasmType::I64 => Ok(0x7e),
+        WasmType::F32 => Ok(0x7d),
+        WasmType::F64 => Ok(0x7c),
+        WasmType::V128 => Ok(0x7b),
+        WasmType::FuncRef => Ok(0x70),
+        WasmType::ExternRef => Ok(0x6f),
+        ty => Err(Error::Unsupported(format!(
+            "signature table cannot encode type {:?}",
+            ty
+        ))),
+    }
+}
+
+fn decode_type(byte: u8) -> Result<WasmType, Error> {
+    match byte {
+        0x7f => Ok(WasmType::I32),
+        0x7e => Ok(WasmType::I64),
+        0x7d => Ok(WasmType::F32),
+        0x7c => Ok(WasmType::F64),
+        0x7b => Ok(WasmType::V128),
+        0x70 => Ok(WasmType::FuncRef),
+        0x6f => Ok(WasmType::ExternRef),
+        byte => Err(Error::Input(format!(
+            "signature table has unknown value type {:#x}",
+            byte
+        ))),
+    }
+}
@@ -92,3 +92,12 @@ pub fn verify_source_code(
 pub fn sign_module(path: impl AsRef<Path>, sk: &SecretKey) -> Result<(), Error> {
     ModuleSignature::sign(path, sk).map_err(|e| e.into())
 }
+
+// Sign the compiled code of a module found through `module_symbol`
+pub fn sign_module_with_symbol(
+    path: impl AsRef<Path>,
+    sk: &SecretKey,
+    module_symbol: &str,
+) -> Result<(), Error> {
+    ModuleSignature::sign_with_symbol(path, sk, module_symbol).map_err(|e| e.into())
+}
@@ -96,3 +96,10 @@ pub fn owned_sparse_data_from_initializers<'a>(
     let o = OwnedSparseData::new(out)?;
     Ok(o)
 }
+
+/// Sparse data for a heap with no initializers, where every page is zeroed.
+pub fn owned_sparse_data_empty(heap: &HeapSpec) -> Result<OwnedSparseData, Error> {
+    assert_eq!(heap.initial_size as usize % PAGE_SIZE, 0);
+    let pages = heap.initial_size as usize / PAGE_SIZE;
+    Ok(OwnedSparseData::new(vec![None; pages])?)
+}
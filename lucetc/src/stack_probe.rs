@@ -7,6 +7,9 @@
 //! it's closed we are taking the approach of including the stack probe in every Lucet module, and
 //! adding custom entries for it into the trap table, so that stack overflows in the probe will be
 //! treated like any other guest trap.
+//!
+//! With `CompilerBuilder::external_stack_probe`, the probe is instead declared as an import, to be
+//! provided by the host.
 
 use crate::compiler::CodegenContext;
 use crate::decls::ModuleDecls;
@@ -57,12 +60,13 @@ pub fn trap_sites() -> Vec<TrapSite> {
 pub fn declare<'a>(
     decls: &mut ModuleDecls<'a>,
     codegen_context: &CodegenContext,
+    linkage: Linkage,
 ) -> Result<UniqueFuncIndex, Error> {
     Ok(decls
         .declare_new_function(
             codegen_context,
             STACK_PROBE_SYM.to_string(),
-            Linkage::Local,
+            linkage,
             WasmFuncType {
                 params: vec![].into_boxed_slice(),
                 returns: vec![WasmType::I32].into_boxed_slice(),
@@ -5,16 +5,57 @@ use crate::module::UniqueFuncIndex;
 use crate::pointer::NATIVE_POINTER_SIZE;
 use byteorder::{LittleEndian, WriteBytesExt};
 use cranelift_codegen::entity::EntityRef;
-use cranelift_module::{DataContext, DataId, Module as ClifModule};
+use cranelift_module::{DataContext, DataId, FuncId, Module as ClifModule};
 use cranelift_wasm::{TableElementType, TableIndex};
 use std::io::Cursor;
 
 /// This symbol will be used to reference the `tables` field in `Module` - a sequence of tables.
 /// At the moment it will either be one or no tables, but in the future may grow.
 pub const TABLE_SYM: &str = "lucet_tables";
+/// The prefix of the symbol for the entries of each table, which is followed by the table index.
+pub(crate) const TABLE_DATA_PREFIX: &str = "guest_table_";
 /// This is functionally the size of `&[TableEntry]`, but defined here because it may not
 /// necessarily have the same field ordering.
 pub const TABLE_REF_SIZE: usize = NATIVE_POINTER_SIZE * 2;
+/// The function that uninitialized table entries point to, when they are set to trap.
+pub const UNINIT_TABLE_ENTRY_SYM: &str = "lucet_uninit_table_entry";
+
+/// The type and size of a table in a module, as declared in the wasm binary.
+#[derive(Debug, Clone)]
+pub struct TableInfo<'a> {
+    pub index: TableIndex,
+    pub element_type: TableElementType,
+    pub minimum: u32,
+    pub maximum: Option<u32>,
+    /// The module and field names the table is imported from, or `None` if the module defines it.
+    pub import_name: Option<(&'a str, &'a str)>,
+    pub export_names: Vec<&'a str>,
+}
+
+impl<'a> TableInfo<'a> {
+    pub(crate) fn new(index: TableIndex, decl: TableDecl<'a>) -> Self {
+        Self {
+            index,
+            element_type: decl.table.ty,
+            minimum: decl.table.minimum,
+            maximum: decl.table.maximum,
+            import_name: decl.import_name,
+            export_names: decl.export_names,
+        }
+    }
+
+    pub fn is_imported(&self) -> bool {
+        self.import_name.is_some()
+    }
+}
+
+/// A table slot whose initial contents are replaced with a reference to a named function.
+#[derive(Debug, Clone)]
+pub(crate) struct TableOverride {
+    pub table_index: u32,
+    pub slot: u32,
+    pub func_symbol: String,
+}
 
 #[derive(Debug, Clone)]
 enum Elem {
@@ -64,18 +105,44 @@ fn table_elements(decl: &TableDecl<'_>) -> Result<Vec<Elem>, Error> {
 pub fn write_table_data(
     codegen_context: &CodegenContext,
     decls: &ModuleDecls<'_>,
+    uninit_entry: Option<FuncId>,
+    overrides: &[TableOverride],
 ) -> Result<(DataId, usize), Error> {
     let mut tables_vec = Cursor::new(Vec::new());
     let mut table_ctx = DataContext::new();
     let mut tables_count = 0;
 
+    // Only the first table is written out, so overrides can't refer to any other.
+    if let Some(table_override) = overrides
+        .iter()
+        .find(|o| o.table_index != 0 || decls.get_table(TableIndex::new(0)).is_err())
+    {
+        let message = format!("table override for {:?}", table_override);
+        return Err(Error::TableIndexError(message));
+    }
+
     if let Ok(table_decl) = decls.get_table(TableIndex::new(0)) {
         // Indirect calls are performed by looking up the callee function and type in a table that
         // is present in the same object file.
         // The table is an array of pairs of (type index, function pointer). Both elements in the
         // pair are the size of a pointer.
         // This function creates that table as a section in the object.
-        let elements = table_elements(&table_decl)?;
+        let mut elements = table_elements(&table_decl)?;
+        for table_override in overrides {
+            let elem = elements
+                .get_mut(table_override.slot as usize)
+                .ok_or_else(|| {
+                    let message = format!("table override for {:?}", table_override);
+                    Error::TableIndexError(message)
+                })?;
+            let func_index = decls
+                .get_func_index(&table_override.func_symbol)
+                .ok_or_else(|| {
+                    let message = format!("table override for {:?}", table_override);
+                    Error::FunctionIndexError(message)
+                })?;
+            *elem = Elem::Func(func_index);
+        }
 
         let mut table_data =
             Cursor::new(Vec::with_capacity(elements.len() * 2 * NATIVE_POINTER_SIZE));
@@ -113,8 +180,14 @@ pub fn write_table_data(
                     // First element is the signature index. These will always be 32 bits in wasm, so
                     // u64::max will always be out of bounds.
                     putelem(&mut table_data, <u64>::max_value());
-                    // Second element is the function pointer. No relocation here, it will always be
-                    // null.
+                    // Second element is the function pointer. This is null, unless uninitialized
+                    // entries are set to point at a function that traps.
+                    if let Some(uninit_entry) = uninit_entry {
+                        let funcref = table_data_ctx.import_function(uninit_entry.into());
+                        let position = table_data.position();
+                        assert!(position < <u32>::max_value() as u64);
+                        table_data_ctx.write_function_addr(position as u32, funcref);
+                    }
                     putelem(&mut table_data, 0);
                 }
             }
@@ -0,0 +1,72 @@
+use crate::compiler::{Compiler, CompilerBuilder, CpuFeatures};
+use crate::error::Error;
+use crate::link_so;
+use lucet_module::bindings::Bindings;
+use lucet_module::ValueType;
+use lucet_runtime::{DlModule, Limits, MmapRegion, Module, Region, Val};
+use std::sync::Arc;
+
+/// Compile a module for the host, load it in this process, and run one of its exports.
+///
+/// This is meant for tests and quick experiments, not production use: each call compiles and
+/// links the module from scratch, and runs the export on a fresh instance with default limits.
+/// The instance is dropped once the export returns, so host state cannot be carried between
+/// calls. Linking uses `ld` as `Lucetc::shared_object_file` does, honoring `LD` and `LDFLAGS`.
+///
+/// The export's return value, if any, is the only element of the returned vector.
+pub fn test_run(
+    wasm: &[u8],
+    bindings: &Bindings,
+    export: &str,
+    args: &[Val],
+) -> Result<Vec<Val>, Error> {
+    let builder = Compiler::builder().with_cpu_features(CpuFeatures::baseline());
+    test_run_with(&builder, wasm, bindings, export, args)
+}
+
+/// Like `test_run`, but compile the module with the options set in `builder`.
+///
+/// The builder's target must be the host.
+pub fn test_run_with(
+    builder: &CompilerBuilder,
+    wasm: &[u8],
+    bindings: &Bindings,
+    export: &str,
+    args: &[Val],
+) -> Result<Vec<Val>, Error> {
+    let compiler = builder.create(wasm, bindings)?;
+
+    let dir = tempfile::Builder::new().prefix("lucetc").tempdir()?;
+    let objpath = dir.path().join("test_run.o");
+    let sopath = dir.path().join("test_run.so");
+    compiler.object_file()?.write(&objpath)?;
+    link_so(
+        &objpath,
+        builder.target_ref(),
+        &sopath,
+        builder.target_version_ref(),
+    )?;
+
+    let module: Arc<dyn Module> =
+        DlModule::load_with_symbol(&sopath, &builder.prefixed_module_symbol())
+            .map_err(Error::TestRun)?;
+    let ret_ty = {
+        let func = module.get_export_func(export).map_err(Error::TestRun)?;
+        module.get_signature(func.id).ret_ty
+    };
+
+    let region = MmapRegion::create(1, &Limits::default()).map_err(Error::TestRun)?;
+    let mut inst = region.new_instance(module).map_err(Error::TestRun)?;
+    let retval = inst
+        .run(export, args)
+        .and_then(|res| res.returned())
+        .map_err(Error::TestRun)?;
+
+    Ok(match ret_ty {
+        None => vec![],
+        Some(ValueType::I32) => vec![Val::I32(retval.as_i32())],
+        Some(ValueType::I64) => vec![Val::I64(retval.as_i64())],
+        Some(ValueType::F32) => vec![Val::F32(retval.as_f32())],
+        Some(ValueType::F64) => vec![Val::F64(retval.as_f64())],
+    })
+}
@@ -1,18 +1,51 @@
 use cranelift_codegen::ir;
+use sha2::{Digest, Sha256};
+
+/// The prefix of the symbol for each function's trap table.
+pub(crate) const TRAP_TABLE_PREFIX: &str = "lucet_trap_table_";
+/// The prefix of the symbol for each shared trap table. Shared trap table symbols also start
+/// with `TRAP_TABLE_PREFIX`.
+pub(crate) const SHARED_TRAP_TABLE_PREFIX: &str = "lucet_trap_table_shared_";
 
 pub(crate) fn trap_sym_for_func(sym: &str) -> String {
-    return format!("lucet_trap_table_{}", sym);
+    return format!("{}{}", TRAP_TABLE_PREFIX, sym);
+}
+
+/// The symbol for a trap table shared by every function whose serialized trap table is `table`.
+/// Naming it after the contents keeps the symbol stable, however functions are scheduled. The
+/// name comes from a SHA-256 digest rather than `DefaultHasher`, whose output may change between
+/// Rust releases, so the same module gets the same symbols from any build of lucetc.
+pub(crate) fn shared_trap_sym(table: &[u8]) -> String {
+    let digest = Sha256::digest(table);
+    let hex = digest[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    format!("{}{}", SHARED_TRAP_TABLE_PREFIX, hex)
 }
 
+/// The code of the trap `memory.grow` raises with `CompilerBuilder::hard_memory_limit`. Host
+/// trap codes are at most `lucet_module::MAX_HOST_TRAP_CODE`, so the user code above them is set
+/// aside for it.
+pub(crate) const MEMORY_LIMIT_TRAP: ir::TrapCode =
+    ir::TrapCode::User(lucet_module::MAX_HOST_TRAP_CODE + 1);
+
+/// The code of the trap raised by the default definition of an import declared with
+/// `CompilerBuilder::weak_imports`, when the host doesn't provide the import.
+pub(crate) const MISSING_IMPORT_TRAP: ir::TrapCode =
+    ir::TrapCode::User(lucet_module::MAX_HOST_TRAP_CODE + 2);
+
 // Trapcodes can be thought of as a tuple of (type, subtype). Each are
 // represented as a 16-bit unsigned integer. These are packed into a u32
 // wherein the type occupies the low 16 bites and the subtype takes the
 // high bits.
 //
-// Not all types have subtypes. Currently, only the user User type has a
-// subtype.
+// Not all types have subtypes. Currently, only the User type has a
+// subtype, which is the host-defined code of a `lucet_module::TrapCode::Host`.
 pub(crate) fn translate_trapcode(code: ir::TrapCode) -> lucet_module::TrapCode {
     match code {
+        MEMORY_LIMIT_TRAP => lucet_module::TrapCode::MemoryLimitExceeded,
+        MISSING_IMPORT_TRAP => lucet_module::TrapCode::MissingImport,
         ir::TrapCode::StackOverflow => lucet_module::TrapCode::StackOverflow,
         ir::TrapCode::HeapOutOfBounds => lucet_module::TrapCode::HeapOutOfBounds,
         ir::TrapCode::IndirectCallToNull => lucet_module::TrapCode::IndirectCallToNull,
@@ -24,6 +57,6 @@ pub(crate) fn translate_trapcode(code: ir::TrapCode) -> lucet_module::TrapCode {
         ir::TrapCode::TableOutOfBounds => lucet_module::TrapCode::TableOutOfBounds,
         ir::TrapCode::UnreachableCodeReached => lucet_module::TrapCode::Unreachable,
         ir::TrapCode::HeapMisaligned => lucet_module::TrapCode::HeapMisaligned,
-        ir::TrapCode::User(_) => panic!("we should never emit a user trapcode"),
+        ir::TrapCode::User(code) => lucet_module::TrapCode::Host(code),
     }
 }
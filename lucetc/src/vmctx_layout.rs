@@ -0,0 +1,54 @@
+use crate::pointer::NATIVE_POINTER_SIZE;
+use lucet_module::InstanceRuntimeData;
+use memoffset::offset_of;
+
+/// Where generated code finds the instance's state relative to the vmctx pointer passed to every
+/// guest function. See `Compiler::vmcontext_layout`.
+///
+/// Offsets are in bytes from the vmctx, and are negative for fields below it. The base of memory 0
+/// is the vmctx itself, so it has no field. Heap bounds are static, given by the module's heap
+/// specs rather than read from the vmctx, and tables are reached through symbols in the object,
+/// so neither appears here either.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VmContextLayout {
+    /// The start of the `InstanceRuntimeData`, which lies just below the vmctx.
+    pub runtime_data: i32,
+    /// A pointer to the globals, each of which occupies `global_size` bytes, in global index
+    /// order.
+    pub globals_ptr: i32,
+    pub global_size: u32,
+    pub instruction_count_adj: i32,
+    pub instruction_count_bound: i32,
+    pub stack_limit: i32,
+    pub interrupt_flag: i32,
+    /// Set by a host function to have its trampoline trap once it returns. See
+    /// `Bindings::set_trap_code`.
+    pub host_trap: i32,
+    /// A pointer to the base of each memory after the first, in memory index order starting
+    /// from memory 1. These lie just below the `InstanceRuntimeData`.
+    pub memory_base_ptrs: Vec<i32>,
+}
+
+impl VmContextLayout {
+    /// The layout for a module with `memories` linear memories.
+    pub(crate) fn new(memories: usize) -> Self {
+        let runtime_data = -(std::mem::size_of::<InstanceRuntimeData>() as i32);
+        let field = |offset: usize| runtime_data + offset as i32;
+        Self {
+            runtime_data,
+            globals_ptr: field(offset_of!(InstanceRuntimeData, globals_ptr)),
+            global_size: NATIVE_POINTER_SIZE as u32,
+            instruction_count_adj: field(offset_of!(InstanceRuntimeData, instruction_count_adj)),
+            instruction_count_bound: field(offset_of!(
+                InstanceRuntimeData,
+                instruction_count_bound
+            )),
+            stack_limit: field(offset_of!(InstanceRuntimeData, stack_limit)),
+            interrupt_flag: field(offset_of!(InstanceRuntimeData, interrupt_flag)),
+            host_trap: field(offset_of!(InstanceRuntimeData, host_trap)),
+            memory_base_ptrs: (1..memories)
+                .map(|index| runtime_data - (index * NATIVE_POINTER_SIZE) as i32)
+                .collect(),
+        }
+    }
+}
@@ -35,12 +35,121 @@ pub fn test_bindings() -> Bindings {
     Bindings::env(imports)
 }
 
+/// A module with two memories. wabt can't assemble multi-memory modules, so this is written out
+/// by hand. It is equivalent to:
+///
+/// ```text
+/// (module
+///   (memory $a 1)
+///   (memory $b 2)
+///   (func $main (export "main")
+///     (i32.store $a (i32.const 0) (i32.const 1))
+///     (i32.store $b (i32.const 0) (i32.load $a (i32.const 0)))))
+/// ```
+fn multi_memory_module() -> Vec<u8> {
+    #[rustfmt::skip]
+    let m = vec![
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+        // type section: () -> ()
+        0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+        // function section
+        0x03, 0x02, 0x01, 0x00,
+        // memory section: two memories, of one and two pages
+        0x05, 0x05, 0x02, 0x00, 0x01, 0x00, 0x02,
+        // export section: "main"
+        0x07, 0x08, 0x01, 0x04, b'm', b'a', b'i', b'n', 0x00, 0x00,
+        // code section
+        0x0a, 0x16, 0x01, 0x14, 0x00,
+        0x41, 0x00, 0x41, 0x01, 0x36, 0x02, 0x00,
+        0x41, 0x00, 0x41, 0x00, 0x28, 0x02, 0x00, 0x36, 0x42, 0x01, 0x00,
+        0x0b,
+    ];
+    m
+}
+
+/// Read the trap table of the function named `symbol`.
+fn trap_table(object: &[u8], symbol: &str) -> Vec<u8> {
+    use object::{Object, ObjectSection, ObjectSymbol};
+    let file = object::File::parse(object).expect("parse object");
+    let name = format!("lucet_trap_table_{}", symbol);
+    let symbol = file
+        .symbols()
+        .find(|symbol| symbol.name() == Ok(name.as_str()))
+        .unwrap_or_else(|| panic!("symbol {} is defined", name));
+    let section = file
+        .section_by_index(symbol.section_index().expect("trap table is in a section"))
+        .expect("section exists");
+    let start = (symbol.address() - section.address()) as usize;
+    section.data().expect("section data")[start..][..symbol.size() as usize].to_vec()
+}
+
+/// Read the trap codes recorded in the trap table of the function named `symbol`.
+fn trap_codes(object: &[u8], symbol: &str) -> Vec<lucet_module::TrapCode> {
+    let table = trap_table(object, symbol);
+    // A function without trap sites may have an empty table, too short to hold a header.
+    if table.len() < 8 {
+        return Vec::new();
+    }
+    let mut first = [0; 8];
+    first.copy_from_slice(&table[..8]);
+    let header_size = lucet_module::trap_table_header_size(lucet_module::trap_table_version(first))
+        .expect("known trap table version");
+    // Each trap site is an offset followed by a packed trap code.
+    table[header_size..]
+        .chunks(8)
+        .map(|site| {
+            let code = u32::from_le_bytes([site[4], site[5], site[6], site[7]]);
+            lucet_module::TrapCode::from_u32(code).expect("valid trap code")
+        })
+        .collect()
+}
+
+/// Take the messages logged so far that start with `prefix`, installing a logger to capture them
+/// on first use. Tests running in parallel share the logger, so each only takes the messages
+/// about functions it compiles.
+fn take_logged(prefix: &str) -> Vec<String> {
+    use std::sync::{Mutex, Once};
+
+    struct CaptureLogger(Mutex<Vec<String>>);
+    impl log::Log for CaptureLogger {
+        fn enabled(&self, _metadata: &log::Metadata<'_>) -> bool {
+            true
+        }
+        fn log(&self, record: &log::Record<'_>) {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+        fn flush(&self) {}
+    }
+
+    static INSTALL: Once = Once::new();
+    static mut LOGGER: Option<&'static CaptureLogger> = None;
+    INSTALL.call_once(|| {
+        let logger: &'static CaptureLogger =
+            Box::leak(Box::new(CaptureLogger(Mutex::new(Vec::new()))));
+        log::set_logger(logger).expect("no other logger is installed");
+        log::set_max_level(log::LevelFilter::Info);
+        unsafe { LOGGER = Some(logger) };
+    });
+    // Only written once, by `call_once`, which has completed before this read.
+    let logger = unsafe { LOGGER }.expect("logger is installed");
+
+    let mut messages = logger.0.lock().unwrap();
+    let (taken, rest) = std::mem::take(&mut *messages)
+        .into_iter()
+        .partition(|message: &String| message.starts_with(prefix));
+    *messages = rest;
+    taken
+}
+
 mod module_data {
     /// Tests of the `ModuleData` generated by the lucetc Compiler
-    use super::load_wat_module;
+    use super::{load_wat_module, take_logged};
     use cranelift_codegen::isa::BackendVariant;
     use lucet_module::bindings::Bindings;
-    use lucetc::{Compiler, CpuFeatures, HeapSettings, OptLevel};
+    use lucetc::{
+        CallTarget, Compiler, CpuFeatures, DataSegmentKind, HeapSettings, MemoryIndex, OptLevel,
+        SymbolScheme, TableElementType, UniqueFuncIndex,
+    };
     use std::path::PathBuf;
     use target_lexicon::Triple;
 
@@ -224,6 +333,176 @@ mod module_data {
         */
     }
 
+    #[test]
+    fn icall_tables() {
+        let m = load_wat_module("icall");
+        let b = Bindings::empty();
+        let builder = Compiler::builder();
+        let c = builder.create(&m, &b).expect("compile icall");
+        let tables = c.tables();
+
+        assert_eq!(tables.len(), 1);
+        assert!(matches!(tables[0].element_type, TableElementType::Func));
+        assert_eq!(tables[0].minimum, 3);
+        assert_eq!(tables[0].maximum, Some(3));
+        assert!(!tables[0].is_imported());
+    }
+
+    #[test]
+    fn call_graph() {
+        let m = load_wat_module("call");
+        let b = Bindings::empty();
+        let builder = Compiler::builder();
+        let c = builder.create(&m, &b).expect("compile call");
+        let graph = c.call_graph().expect("build call graph");
+
+        let main = UniqueFuncIndex::from_u32(0);
+        let inc = UniqueFuncIndex::from_u32(1);
+        assert_eq!(graph.callees(main), Some(&[CallTarget::Func(inc)][..]));
+        assert_eq!(graph.callees(inc), Some(&[][..]));
+    }
+
+    #[test]
+    fn icall_call_graph() {
+        let m = load_wat_module("icall");
+        let b = Bindings::empty();
+        let builder = Compiler::builder();
+        let c = builder.create(&m, &b).expect("compile icall");
+        let graph = c.call_graph().expect("build call graph");
+
+        // `foo` may call either function in the table with the right type, but not `wrongtype`
+        assert_eq!(
+            graph.callees(UniqueFuncIndex::from_u32(0)),
+            Some(
+                &[
+                    CallTarget::Func(UniqueFuncIndex::from_u32(1)),
+                    CallTarget::Func(UniqueFuncIndex::from_u32(2)),
+                ][..]
+            )
+        );
+    }
+
+    #[test]
+    fn recursion_cycles() {
+        let m = wabt::wat2wasm(
+            "(module
+               (func $even (export \"even\") (param i32) (result i32)
+                 (if (result i32) (local.get 0)
+                   (then (call $odd (i32.sub (local.get 0) (i32.const 1))))
+                   (else (i32.const 1))))
+               (func $odd (param i32) (result i32)
+                 (if (result i32) (local.get 0)
+                   (then (call $even (i32.sub (local.get 0) (i32.const 1))))
+                   (else (i32.const 0))))
+               (func $loop (call $loop))
+               (func (export \"main\") (drop (call $even (i32.const 4)))))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = Bindings::empty();
+        Compiler::builder()
+            .create(&m, &b)
+            .expect("compile recursive module")
+            .object_file()
+            .expect("codegen recursive module");
+        assert!(take_logged("recursion among functions: ").is_empty());
+
+        let builder = Compiler::builder()
+            .with_symbol_scheme(lucetc::SymbolScheme::Index)
+            .with_report_recursion(true);
+        let c = builder.create(&m, &b).expect("compile recursive module");
+        let cycles = c.call_graph().expect("build call graph").recursion_cycles();
+        assert_eq!(
+            cycles,
+            vec![
+                vec![UniqueFuncIndex::from_u32(0), UniqueFuncIndex::from_u32(1)],
+                vec![UniqueFuncIndex::from_u32(2)],
+            ]
+        );
+        c.object_file().expect("codegen recursive module");
+        assert_eq!(
+            take_logged("recursion among functions: "),
+            vec![
+                "recursion among functions: guest_func_even, guest_func_1".to_owned(),
+                "recursion among functions: guest_func_2".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn data_segments() {
+        let m = load_wat_module("data_segment");
+        let b = Bindings::empty();
+        let builder = Compiler::builder();
+        let c = builder.create(&m, &b).expect("compile data_segment");
+        let segments = c.data_segments();
+
+        let offsets: Vec<usize> = segments
+            .iter()
+            .map(|segment| match segment.kind {
+                DataSegmentKind::Active {
+                    memory_index,
+                    base: None,
+                    offset,
+                } if memory_index == MemoryIndex::from_u32(0) => offset,
+                _ => panic!("unexpected data segment {:?}", segment),
+            })
+            .collect();
+        assert_eq!(offsets, vec![0, 0, 1]);
+        assert_eq!(segments[0].data, b"99999");
+        assert_eq!(segments[1].data, &[0xaa, 0xbb]);
+        assert_eq!(segments[2].data, &[0xcc, 0xdd]);
+    }
+
+    #[test]
+    fn memory_init_program() {
+        use lucetc::{GlobalIndex, InitOffset};
+        let m = wabt::wat2wasm(
+            "(module
+               (global $base (import \"env\" \"base\") i32)
+               (memory 1)
+               (data (i32.const 4) \"ab\")
+               (data (global.get $base) \"cd\"))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = Bindings::empty();
+        // Segments based on a global can't be applied to the heap's sparse data.
+        let builder = Compiler::builder().with_separate_data_segments(true);
+        let c = builder.create(&m, &b).expect("compile");
+
+        let program = c.memory_init_program();
+        assert_eq!(program.len(), 2);
+        assert!(program
+            .iter()
+            .all(|op| op.memory_index == MemoryIndex::from_u32(0)));
+        assert_eq!(program[0].offset, InitOffset::Constant(4));
+        assert_eq!(program[0].data, b"ab");
+        assert_eq!(
+            program[1].offset,
+            InitOffset::Global {
+                global_index: GlobalIndex::from_u32(0),
+                offset: 0,
+            }
+        );
+        assert_eq!(program[1].data, b"cd");
+    }
+
+    #[test]
+    fn separate_data_segments() {
+        let m = load_wat_module("data_segment");
+        let b = Bindings::empty();
+        let builder = Compiler::builder().with_separate_data_segments(true);
+        let c = builder.create(&m, &b).expect("compile data_segment");
+
+        let module_data = c.module_data().unwrap();
+        let initializer = module_data
+            .sparse_data()
+            .expect("module has a heap")
+            .pages();
+        assert!(initializer.iter().all(|page| page.is_none()));
+
+        let _obj = c.object_file().expect("codegen data_segment");
+    }
+
     #[test]
     fn icall_sparse() {
         let m = load_wat_module("icall_sparse");
@@ -282,6 +561,183 @@ mod module_data {
         }
     }
 
+    #[test]
+    fn globals_info() {
+        use lucetc::{GlobalDef, GlobalInitialValue, WasmType};
+        let m = load_wat_module("globals_definition");
+        let b = Bindings::empty();
+        let builder = Compiler::builder();
+        let c = builder.create(&m, &b).expect("compile globals_definition");
+        let globals = c.globals();
+
+        assert_eq!(globals.len(), 3);
+        assert!(globals.iter().all(|g| matches!(g.ty, WasmType::I32)));
+        assert!(globals[0].mutable);
+        assert_eq!(
+            globals[0].initial_value,
+            GlobalInitialValue::Const(GlobalDef::I32(4))
+        );
+        assert!(!globals[2].mutable);
+        assert_eq!(
+            globals[2].initial_value,
+            GlobalInitialValue::Const(GlobalDef::I32(6))
+        );
+        assert_eq!(globals[2].export_names, vec!["z"]);
+
+        let m = load_wat_module("globals_import");
+        let c = builder.create(&m, &b).expect("compile globals_import");
+        let globals = c.globals();
+        assert_eq!(globals.len(), 1);
+        assert_eq!(
+            globals[0].initial_value,
+            GlobalInitialValue::Import {
+                module: "env",
+                field: "x"
+            }
+        );
+    }
+
+    #[test]
+    fn override_global() {
+        use lucet_module::Global as GlobalVariant;
+        use lucetc::{Error, GlobalDef, GlobalInitialValue};
+        let m = load_wat_module("globals_definition");
+        let b = Bindings::empty();
+        let c = Compiler::builder()
+            .with_override_global(2, GlobalDef::I32(42))
+            .create(&m, &b)
+            .expect("compile globals_definition");
+        let module_data = c.module_data().unwrap();
+        let gspec = module_data.globals_spec();
+        assert_eq!(gspec[0].global(), &GlobalVariant::Def(GlobalDef::I32(4)));
+        assert_eq!(gspec[2].global(), &GlobalVariant::Def(GlobalDef::I32(42)));
+        assert_eq!(gspec[2].export_names(), &["z"]);
+        let globals = c.globals();
+        assert_eq!(
+            globals[0].initial_value,
+            GlobalInitialValue::Const(GlobalDef::I32(4))
+        );
+        assert_eq!(
+            globals[2].initial_value,
+            GlobalInitialValue::Const(GlobalDef::I32(42))
+        );
+
+        match Compiler::builder()
+            .with_override_global(0, GlobalDef::I64(42))
+            .create(&m, &b)
+        {
+            Err(Error::InvalidGlobalOverride { index: 0, .. }) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("override with the wrong type accepted"),
+        }
+        match Compiler::builder()
+            .with_override_global(3, GlobalDef::I32(42))
+            .create(&m, &b)
+        {
+            Err(Error::GlobalIndexError(_)) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("override of a missing global accepted"),
+        }
+
+        let m = load_wat_module("globals_import");
+        match Compiler::builder()
+            .with_override_global(0, GlobalDef::I32(42))
+            .create(&m, &b)
+        {
+            Err(Error::InvalidGlobalOverride { index: 0, .. }) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("override of an imported global accepted"),
+        }
+    }
+
+    #[test]
+    fn imports_info() {
+        use lucetc::WasmType;
+        let m = load_wat_module("import");
+        let b = Bindings::env(
+            [("inc".to_owned(), "host_inc".to_owned())]
+                .iter()
+                .cloned()
+                .collect(),
+        );
+        let builder = Compiler::builder();
+        let c = builder.create(&m, &b).expect("compile import");
+        let imports = c.imports();
+
+        assert_eq!(imports.len(), 1);
+        assert_eq!(imports[0].index, UniqueFuncIndex::from_u32(0));
+        assert_eq!((imports[0].module, imports[0].field), ("env", "inc"));
+        assert_eq!(imports[0].symbol, "host_inc");
+        assert!(imports[0].signature.params.is_empty());
+        assert!(matches!(&*imports[0].signature.returns, [WasmType::I32]));
+
+        let m = load_wat_module("fibonacci");
+        let c = builder.create(&m, &b).expect("compile fibonacci");
+        assert!(c.imports().is_empty());
+    }
+
+    #[test]
+    fn abi_fingerprint() {
+        let fingerprint = |wat: &str, b: &Bindings| {
+            let m = wabt::wat2wasm(wat).expect("convert module to wasm binary format");
+            Compiler::builder()
+                .create(&m, b)
+                .expect("compile")
+                .abi_fingerprint()
+        };
+        let b = super::test_bindings();
+        let renamed = Bindings::env(
+            [("inc".to_owned(), "host_inc".to_owned())]
+                .iter()
+                .cloned()
+                .collect(),
+        );
+
+        let base = fingerprint(
+            "(module
+               (func $inc (import \"env\" \"inc\") (result i32))
+               (func (export \"main\") (result i32) (call $inc)))",
+            &b,
+        );
+        // Binding the import to another symbol doesn't change the interface.
+        let rebound = fingerprint(
+            "(module
+               (func $inc (import \"env\" \"inc\") (result i32))
+               (func (export \"main\") (result i32) (call $inc)))",
+            &renamed,
+        );
+        assert_eq!(base, rebound);
+        // Neither does the body of an export.
+        let rewritten = fingerprint(
+            "(module
+               (func $inc (import \"env\" \"inc\") (result i32))
+               (func (export \"main\") (result i32) (i32.add (call $inc) (i32.const 1))))",
+            &b,
+        );
+        assert_eq!(base, rewritten);
+        // An export's signature does.
+        let widened = fingerprint(
+            "(module
+               (func $inc (import \"env\" \"inc\") (result i32))
+               (func (export \"main\") (result i64) (i64.extend_i32_u (call $inc))))",
+            &b,
+        );
+        assert_ne!(base, widened);
+
+        // Names can contain anything, but can't be made to look like several entries.
+        let two_exports = fingerprint(
+            "(module
+               (func (export \"main\") (export \"x\") (result i32) (i32.const 0)))",
+            &b,
+        );
+        let one_export = fingerprint(
+            "(module
+               (func (export \"main [] -> [I32]\\0aexport x\") (result i32) (i32.const 0)))",
+            &b,
+        );
+        assert_ne!(two_exports, one_export);
+    }
+
     #[test]
     fn heap_spec_import() {
         use lucet_module::HeapSpec;
@@ -305,6 +761,110 @@ mod module_data {
         );
     }
 
+    #[test]
+    fn heap_page_size() {
+        use lucet_module::HeapSpec;
+        use lucetc::Error;
+        let b = Bindings::empty();
+        let h = HeapSettings {
+            page_size: 4 * 1024,
+            ..HeapSettings::default()
+        };
+
+        let m =
+            wabt::wat2wasm("(module (memory 2 4))").expect("convert module to wasm binary format");
+        let c = Compiler::builder()
+            .with_heap_settings(h.clone())
+            .create(&m, &b)
+            .expect("compile with 4KiB pages");
+        assert_eq!(
+            c.module_data().unwrap().heap_spec(),
+            Some(&HeapSpec {
+                reserved_size: h.min_reserved_size,
+                guard_size: h.guard_size,
+                initial_size: 2 * 4 * 1024,
+                max_size: Some(4 * 4 * 1024),
+            })
+        );
+        c.object_file().expect("codegen with 4KiB pages");
+
+        // The runtime grows memories in 64KiB pages.
+        let m = wabt::wat2wasm(
+            "(module (memory 1)
+               (func (export \"grow\") (result i32) (memory.grow (i32.const 1))))",
+        )
+        .expect("convert module to wasm binary format");
+        match Compiler::builder()
+            .with_heap_settings(h.clone())
+            .create(&m, &b)
+        {
+            Err(Error::Unsupported(_)) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("memory.grow accepted with 4KiB pages"),
+        }
+
+        for page_size in &[3 * 1024, 1024, 128 * 1024] {
+            let h = HeapSettings {
+                page_size: *page_size,
+                ..HeapSettings::default()
+            };
+            match Compiler::builder().with_heap_settings(h).create(&m, &b) {
+                Err(Error::MemorySpecs(_)) => {}
+                Err(e) => panic!("unexpected error: {}", e),
+                Ok(_) => panic!("page size {} accepted", page_size),
+            }
+        }
+    }
+
+    #[test]
+    fn auto_heap_settings() {
+        use lucet_module::HeapSpec;
+        let b = Bindings::empty();
+        let h = HeapSettings::default();
+        for (wat, reserved_size) in &[
+            // reserve exactly the declared maximum
+            ("(module (memory 1 4))", 4 * 64 * 1024),
+            // with no maximum, reserve the most the heap settings allow
+            ("(module (memory 1))", h.max_reserved_size),
+        ] {
+            let m = wabt::wat2wasm(wat).expect("convert module to wasm binary format");
+            let c = Compiler::builder()
+                .with_heap_settings(h.clone())
+                .with_auto_heap_settings(true)
+                .create(&m, &b)
+                .expect("compile");
+            let module_data = c.module_data().unwrap();
+            let heap_spec: &HeapSpec = module_data.heap_spec().expect("heap spec");
+            assert_eq!(heap_spec.reserved_size, *reserved_size);
+            assert_eq!(heap_spec.guard_size, h.guard_size);
+        }
+    }
+
+    #[test]
+    fn on_heap_layout() {
+        use lucetc::ResolvedHeapLayout;
+        use std::sync::{Arc, Mutex};
+        let m = load_wat_module("heap_spec_import");
+        let b = Bindings::empty();
+        let h = HeapSettings::default();
+        let layouts = Arc::new(Mutex::new(Vec::new()));
+        let layouts_ref = layouts.clone();
+        let builder = Compiler::builder()
+            .with_heap_settings(h.clone())
+            .with_on_heap_layout(Box::new(move |layout: &ResolvedHeapLayout| {
+                layouts_ref.lock().unwrap().push(layout.clone())
+            }));
+        let c = builder.create(&m, &b).expect("compiling heap_spec_import");
+
+        let layouts = layouts.lock().unwrap();
+        assert_eq!(layouts.len(), 1);
+        assert_eq!(layouts[0].settings, h);
+        assert_eq!(
+            layouts[0].heaps.iter().collect::<Vec<_>>(),
+            vec![c.module_data().unwrap().heap_spec().unwrap()]
+        );
+    }
+
     #[test]
     fn heap_spec_definition() {
         use lucet_module::HeapSpec;
@@ -402,11 +962,109 @@ mod module_data {
             Some("func_name_0")
         )
     }
+
+    #[test]
+    fn symbol_scheme() {
+        let m = load_wat_module("names_local");
+        let b = super::test_bindings();
+
+        let c = Compiler::builder()
+            .with_symbol_scheme(SymbolScheme::Index)
+            .create(&m, &b)
+            .expect("compile names_local");
+        let mdata = c.module_data().unwrap();
+        assert_eq!(
+            mdata.function_info().get(0).unwrap().name,
+            Some("guest_func_0")
+        );
+
+        let c = Compiler::builder()
+            .with_symbol_scheme(SymbolScheme::Qualified {
+                module: "names".to_owned(),
+            })
+            .create(&m, &b)
+            .expect("compile names_local");
+        let mdata = c.module_data().unwrap();
+        assert_eq!(
+            mdata.function_info().get(0).unwrap().name,
+            Some("names__func_name_0")
+        );
+        c.object_file().expect("object file with qualified symbols");
+    }
+
+    #[test]
+    fn frontend_config() {
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        let c = Compiler::builder()
+            .create(&m, &b)
+            .expect("compile fibonacci");
+        assert_eq!(c.frontend_config().pointer_bytes(), 8);
+    }
+
+    #[test]
+    fn estimate_peak_memory() {
+        let b = super::test_bindings();
+        // A module whose `main` adds up `adds` constants, with `data` bytes of data segment and
+        // `extra` more functions that do nothing.
+        let estimate = |adds: usize, data: usize, extra: usize| {
+            let m = wabt::wat2wasm(format!(
+                "(module
+                   (memory 1)
+                   (data (i32.const 0) \"{}\")
+                   (func (export \"main\") (result i32) (i32.const 0) {})
+                   {})",
+                "a".repeat(data),
+                "(i32.const 1) (i32.add) ".repeat(adds),
+                "(func) ".repeat(extra),
+            ))
+            .expect("convert module to wasm binary format");
+            Compiler::builder()
+                .create(&m, &b)
+                .expect("compile")
+                .estimate_peak_memory()
+        };
+
+        // Larger function bodies need more IR and produce more code.
+        assert!(estimate(10, 0, 0) < estimate(100, 0, 0));
+        assert!(estimate(100, 0, 0) < estimate(1000, 0, 0));
+        // Every function costs something, even an empty one.
+        assert!(estimate(10, 0, 0) < estimate(10, 0, 10));
+        // Data segments are counted twice, and nothing else about them.
+        assert_eq!(estimate(10, 4096, 0) - estimate(10, 0, 0), 2 * 4096);
+    }
+
+    #[test]
+    fn diff_manifests() {
+        use lucet_module::FunctionSpec;
+        use lucetc::SizeDelta;
+
+        let spec = |len| FunctionSpec::new(0, len, 0, 0);
+        let old = vec![
+            ("guest_func_a".to_owned(), spec(100)),
+            ("guest_func_b".to_owned(), spec(200)),
+            ("guest_func_c".to_owned(), spec(300)),
+        ];
+        let new = vec![
+            ("guest_func_b".to_owned(), spec(250)),
+            ("guest_func_c".to_owned(), spec(300)),
+            ("guest_func_d".to_owned(), spec(50)),
+        ];
+
+        let diff = lucetc::diff_manifests(&old, &new);
+        assert_eq!(diff.added, vec![("guest_func_d".to_owned(), 50)]);
+        assert_eq!(diff.removed, vec![("guest_func_a".to_owned(), 100)]);
+        assert_eq!(diff.sizes.len(), 2);
+        let changed: Vec<&SizeDelta> = diff.changed().collect();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].symbol, "guest_func_b");
+        assert_eq!(changed[0].delta(), 50);
+    }
 }
 
 mod compile {
     // Tests for compilation completion
-    use super::load_wat_module;
+    use super::{load_wat_module, take_logged, trap_codes, trap_table};
     use lucetc::Compiler;
     fn run_compile_test(file: &str) {
         let m = load_wat_module(file);
@@ -447,46 +1105,2755 @@ mod compile {
     compile_test!(grow_memory);
     compile_test!(unreachable_code);
     compile_test!(start_section);
-}
-
-mod validate {
-    use super::load_wat_module;
-    use cranelift_codegen::isa::BackendVariant;
-    use lucetc::{Compiler, CpuFeatures, HeapSettings, OptLevel, Validator, WasiMode};
-    use target_lexicon::Triple;
 
     #[test]
-    fn validate_arith() {
-        let m = load_wat_module("arith");
+    fn trap_uninit_table() {
+        let m = load_wat_module("icall_sparse");
         let b = super::test_bindings();
-
-        // Empty witx: arith module has no imports
-        let v = Validator::builder()
-            .parse_witx("")
-            .expect("empty witx validates")
-            .build();
-        let builder = Compiler::builder().with_validator(Some(v));
-        let c = builder.create(&m, &b).expect("compile");
-        let _obj = c.object_file().expect("codegen");
+        let builder = Compiler::builder().with_trap_uninit_table(true);
+        let c = builder.create(&m, &b).expect("compile icall_sparse");
+        let _obj = c.object_file().expect("codegen icall_sparse");
     }
 
     #[test]
-    fn validate_import() {
-        let m = load_wat_module("import");
+    fn codegen_check() {
+        use lucetc::{codegen_check, Error};
         let b = super::test_bindings();
+        let builder =
+            Compiler::builder().with_codegen_check(Box::new(codegen_check::no_indirect_calls));
 
-        let witx = "
-            (module $env
-              (@interface func (export \"inc\")
-                (result $r s32)))";
-        let v = Validator::builder()
-            .parse_witx(witx)
-            .expect("witx validates")
-            .build();
+        let m = load_wat_module("call");
+        let c = builder.create(&m, &b).expect("compile call");
+        let _obj = c.object_file().expect("codegen call");
 
-        let builder = Compiler::builder().with_validator(Some(v));
-        let c = builder.create(&m, &b).expect("compile");
-        let _obj = c.object_file().expect("codegen");
+        let m = load_wat_module("icall");
+        let c = builder.create(&m, &b).expect("compile icall");
+        match c.object_file() {
+            Err(Error::CodegenCheckFailed { symbol, .. }) => assert_eq!(symbol, "guest_func_foo"),
+            Err(e) => panic!("expected CodegenCheckFailed error, got {:?}", e),
+            Ok(_) => panic!("expected the indirect call in icall to fail the check"),
+        }
+    }
+
+    #[test]
+    fn bake_memory_image() {
+        use lucetc::Error;
+        let b = super::test_bindings();
+        let builder = Compiler::builder().with_bake_memory_image(true);
+
+        let m = load_wat_module("heap_image");
+        let c = builder.create(&m, &b).expect("compile heap_image");
+        let _obj = c.object_file().expect("codegen heap_image");
+
+        // data_segment.wat has segments that overwrite each other
+        let m = load_wat_module("data_segment");
+        let c = builder.create(&m, &b).expect("compile data_segment");
+        match c.object_file() {
+            Err(Error::OverlappingDataSegments { offset }) => assert_eq!(offset, 0),
+            Err(e) => panic!("expected OverlappingDataSegments error, got {:?}", e),
+            Ok(_) => panic!("overlapping data segments should not be baked"),
+        }
+    }
+
+    #[test]
+    fn export_all_functions() {
+        use lucetc::Error;
+        use object::{Object, ObjectSymbol};
+        let m = load_wat_module("icall");
+        let b = super::test_bindings();
+        let names = [
+            "guest_func_foo",
+            "guest_func_1",
+            "guest_func_2",
+            "guest_func_3",
+        ];
+        let symbols = |export_all_functions| {
+            let obj = Compiler::builder()
+                .with_export_all_functions(export_all_functions)
+                .create(&m, &b)
+                .expect("compile icall")
+                .object_file()
+                .expect("codegen icall");
+            let bytes = obj.bytes().expect("object bytes");
+            let file = object::File::parse(&bytes).expect("parse object");
+            names
+                .iter()
+                .map(|name| {
+                    let symbol = file
+                        .symbols()
+                        .find(|symbol| symbol.name() == Ok(name))
+                        .unwrap_or_else(|| panic!("symbol {} is defined", name));
+                    symbol.is_global()
+                })
+                .collect::<Vec<_>>()
+        };
+        // Only the wasm export is global by default, and every function is with the option.
+        assert_eq!(symbols(false), vec![true, false, false, false]);
+        assert_eq!(symbols(true), vec![true, true, true, true]);
+
+        // The symbol made up for the second function is the one the first is exported with.
+        let m = wabt::wat2wasm(
+            "(module
+               (func (export \"1\") (result i32) (i32.const 1))
+               (func (result i32) (i32.const 2)))",
+        )
+        .expect("convert module to wasm binary format");
+        match Compiler::builder()
+            .with_export_all_functions(true)
+            .create(&m, &b)
+        {
+            Err(Error::DuplicateFunctionSymbol { symbol }) => assert_eq!(symbol, "guest_func_1"),
+            Err(e) => panic!("expected DuplicateFunctionSymbol error, got {:?}", e),
+            Ok(_) => panic!("functions with the same symbol should not compile"),
+        }
+        // Symbols are only checked for duplicates when every function is exported.
+        if let Err(Error::DuplicateFunctionSymbol { symbol }) = Compiler::builder().create(&m, &b) {
+            panic!("duplicate symbol {} rejected by default", symbol);
+        }
+    }
+
+    #[test]
+    fn multi_memory() {
+        let m = super::multi_memory_module();
+        let b = super::test_bindings();
+
+        let builder = Compiler::builder();
+        assert!(builder.create(&m, &b).is_err());
+
+        let builder = Compiler::builder().with_allow_multi_memory(true);
+        let c = builder.create(&m, &b).expect("compile multi_memory");
+        // The module data records that the module uses more than one memory.
+        assert!(c.required_features().wasm.multi_memory);
+        let _obj = c.object_file().expect("codegen multi_memory");
+
+        // `main` accesses both memories, each through a heap of its own.
+        let c = builder.create(&m, &b).expect("compile multi_memory");
+        let dir = tempfile::tempdir().expect("create temporary directory");
+        c.cranelift_funcs()
+            .expect("translate functions")
+            .write_per_function(dir.path())
+            .expect("write functions");
+        let clif = std::fs::read_to_string(dir.path().join("guest_func_main.clif"))
+            .expect("read guest_func_main.clif");
+        assert!(clif.contains("heap0 = "), "{}", clif);
+        assert!(clif.contains("heap1 = "), "{}", clif);
+    }
+
+    #[test]
+    fn unsupported_feature() {
+        use lucetc::WasmFeature;
+        // As in `multi_memory_module`, written out by hand. It is equivalent to:
+        //
+        // (module
+        //   (memory $a 1)
+        //   (memory $b 2)
+        //   (func $main (export "main")
+        //     (drop (memory.size $b))))
+        #[rustfmt::skip]
+        let m: Vec<u8> = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+            // type section: () -> ()
+            0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            // function section
+            0x03, 0x02, 0x01, 0x00,
+            // memory section: two memories, of one and two pages
+            0x05, 0x05, 0x02, 0x00, 0x01, 0x00, 0x02,
+            // export section: "main"
+            0x07, 0x08, 0x01, 0x04, b'm', b'a', b'i', b'n', 0x00, 0x00,
+            // code section
+            0x0a, 0x07, 0x01, 0x05, 0x00,
+            0x3f, 0x01, 0x1a,
+            0x0b,
+        ];
+        let b = super::test_bindings();
+
+        let builder = Compiler::builder().with_allow_multi_memory(true);
+        let err = builder
+            .create(&m, &b)
+            .and_then(|c| c.object_file())
+            .err()
+            .expect("memory.size on memory 1 is unsupported");
+        assert_eq!(err.unsupported_feature(), Some(WasmFeature::MultiMemory));
+
+        let builder = builder.with_reject_wasm_feature(WasmFeature::MultiMemory);
+        let err = builder
+            .create(&m, &b)
+            .err()
+            .expect("multi-memory module fails validation");
+        assert_eq!(err.unsupported_feature(), None);
+    }
+
+    #[test]
+    fn incremental() {
+        use std::sync::Arc;
+        let module = |third_result: i32| {
+            wabt::wat2wasm(format!(
+                "(module
+                   (func $one (export \"one\") (result i32) (i32.const 1))
+                   (func $two (export \"two\") (result i32) (i32.add (call $one) (call $one)))
+                   (func $three (export \"three\") (result i32) (i32.const {})))",
+                third_result
+            ))
+            .expect("convert module to wasm binary format")
+        };
+        let b = super::test_bindings();
+        let full_compile = |m: &[u8]| {
+            Compiler::builder()
+                .create(m, &b)
+                .expect("compile")
+                .object_file()
+                .expect("codegen")
+                .bytes()
+                .expect("object bytes")
+        };
+
+        // Nothing can be reused in the first compilation.
+        let m = module(3);
+        let (obj, state) = Compiler::builder()
+            .create(&m, &b)
+            .expect("compile")
+            .object_file_incremental()
+            .expect("codegen");
+        assert_eq!(state.len(), 3);
+        assert_eq!(state.reused(), 0);
+        assert_eq!(obj.bytes().expect("object bytes"), full_compile(&m));
+
+        // Recompiling the same module reuses every function.
+        let state = Arc::new(state);
+        let (obj, next_state) = Compiler::builder()
+            .with_prev_state(state.clone())
+            .create(&m, &b)
+            .expect("compile")
+            .object_file_incremental()
+            .expect("incremental codegen");
+        assert_eq!(next_state.len(), 3);
+        assert_eq!(next_state.reused(), 3);
+        assert_eq!(obj.bytes().expect("object bytes"), full_compile(&m));
+
+        // Changing one function only translates that function again.
+        let changed = module(4);
+        let (obj, next_state) = Compiler::builder()
+            .with_prev_state(state.clone())
+            .create(&changed, &b)
+            .expect("compile")
+            .object_file_incremental()
+            .expect("incremental codegen");
+        assert_eq!(next_state.reused(), 2);
+        assert_eq!(obj.bytes().expect("object bytes"), full_compile(&changed));
+
+        // Options baked into the IR invalidate every function.
+        let (_obj, next_state) = Compiler::builder()
+            .with_prev_state(state)
+            .with_count_instructions(true)
+            .create(&m, &b)
+            .expect("compile")
+            .object_file_incremental()
+            .expect("incremental codegen");
+        assert_eq!(next_state.reused(), 0);
+    }
+
+    #[test]
+    fn report_unbounded_loops() {
+        let m = wabt::wat2wasm(
+            "(module (func (export \"unbounded_loop\") (param i32)
+               (loop (br_if 0 (local.tee 0 (i32.sub (local.get 0) (i32.const 1)))))))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+        let report = |count_instructions, report_unbounded_loops| {
+            Compiler::builder()
+                .with_count_instructions(count_instructions)
+                .with_report_unbounded_loops(report_unbounded_loops)
+                .create(&m, &b)
+                .expect("compile")
+                .object_file()
+                .expect("codegen");
+            take_logged("guest_func_unbounded_loop ")
+        };
+
+        assert!(report(false, false).is_empty());
+        assert_eq!(
+            report(false, true),
+            vec![
+                "guest_func_unbounded_loop contains 1 loop(s) that never check the instruction \
+                 count bound"
+                    .to_owned()
+            ]
+        );
+        // The loop's back-edge checks the bound when instructions are counted.
+        assert!(report(true, true).is_empty());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn elf_header() {
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        let c = Compiler::builder()
+            .with_elf_os_abi(0xff)
+            .with_elf_flags(0x1234_5678)
+            .create(&m, &b)
+            .expect("compile fibonacci");
+        let bytes = c.object_file().unwrap().bytes().unwrap();
+        assert_eq!(&bytes[..4], b"\x7fELF");
+        assert_eq!(bytes[7], 0xff);
+        assert_eq!(&bytes[48..52], &0x1234_5678u32.to_le_bytes());
+    }
+
+    #[test]
+    fn forbid_stack_probe() {
+        use lucetc::Error;
+        let b = super::test_bindings();
+
+        let small = load_wat_module("fibonacci");
+        let c = Compiler::builder()
+            .with_forbid_stack_probe(true)
+            .create(&small, &b)
+            .expect("compile fibonacci");
+        c.object_file().expect("fibonacci has no stack probe");
+
+        // Keep enough values live at once that they spill into a frame larger than a page.
+        let values = 1024;
+        let mut body = String::new();
+        for i in 0..values {
+            body.push_str(&format!("(i64.add (local.get 0) (i64.const {}))\n", i));
+        }
+        for _ in 1..values {
+            body.push_str("i64.add\n");
+        }
+        let wat = format!(
+            "(module (func (export \"main\") (param i64) (result i64)\n{}))",
+            body
+        );
+        let large = wabt::wat2wasm(wat).expect("convert module to wasm binary format");
+
+        let c = Compiler::builder()
+            .with_forbid_stack_probe(true)
+            .create(&large, &b)
+            .expect("compile large frame");
+        match c.object_file() {
+            Err(Error::StackProbeRequired { .. }) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("large frame compiled without a stack probe"),
+        }
+    }
+
+    #[test]
+    fn max_stack_size() {
+        use lucetc::Error;
+        let b = super::test_bindings();
+
+        let small = load_wat_module("fibonacci");
+        Compiler::builder()
+            .with_max_stack_size(1024)
+            .create(&small, &b)
+            .expect("compile fibonacci")
+            .object_file()
+            .expect("fibonacci's frame fits");
+
+        // Keep enough values live at once that they spill into a frame larger than a page.
+        let values = 1024;
+        let mut body = String::new();
+        for i in 0..values {
+            body.push_str(&format!("(i64.add (local.get 0) (i64.const {}))\n", i));
+        }
+        for _ in 1..values {
+            body.push_str("i64.add\n");
+        }
+        let wat = format!(
+            "(module (func (export \"main\") (param i64) (result i64)\n{}))",
+            body
+        );
+        let large = wabt::wat2wasm(wat).expect("convert module to wasm binary format");
+
+        Compiler::builder()
+            .with_max_stack_size(1024 * 1024)
+            .create(&large, &b)
+            .expect("compile large frame")
+            .object_file()
+            .expect("large frame fits in a larger stack");
+        match Compiler::builder()
+            .with_max_stack_size(1024)
+            .create(&large, &b)
+            .expect("compile large frame")
+            .object_file()
+        {
+            Err(Error::FrameExceedsStack { symbol, frame_size }) => {
+                assert_eq!(symbol, "guest_func_main");
+                assert!(frame_size > 1024);
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("frame larger than the stack accepted"),
+        }
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn spectre_mitigation() {
+        use lucetc::HeapSettings;
+        let m = wabt::wat2wasm(
+            "(module
+               (memory 1)
+               (func (export \"load\") (param i32) (result i32)
+                 (i32.load (local.get 0))))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+        let code = |builder: lucetc::CompilerBuilder| {
+            builder
+                .create(&m, &b)
+                .expect("compile")
+                .compiled_functions()
+                .expect("codegen")
+                .into_iter()
+                .map(|f| f.code)
+                .collect::<Vec<_>>()
+        };
+
+        // With the default heap settings, no access is bounds checked, so there is nothing to
+        // mitigate.
+        assert_eq!(
+            code(Compiler::builder().with_spectre_mitigation(true)),
+            code(Compiler::builder().with_spectre_mitigation(false))
+        );
+
+        // Without a guard region, every access is bounds checked, and the mitigation adds a
+        // conditional move to the check.
+        let heap_settings = HeapSettings {
+            guard_size: 0,
+            ..HeapSettings::default()
+        };
+        let checked = |spectre_mitigation: bool| {
+            code(
+                Compiler::builder()
+                    .with_heap_settings(heap_settings.clone())
+                    .with_spectre_mitigation(spectre_mitigation),
+            )
+        };
+        let mitigated = checked(true);
+        assert_eq!(
+            mitigated,
+            code(Compiler::builder().with_heap_settings(heap_settings.clone()))
+        );
+        let unmitigated = checked(false);
+        assert!(mitigated[0].len() > unmitigated[0].len());
+        // cmov
+        assert!(mitigated[0]
+            .windows(2)
+            .any(|w| w[0] == 0x0f && (w[1] & 0xf0) == 0x40));
+    }
+
+    #[test]
+    fn preserve_frame_pointer() {
+        use lucetc::Error;
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        let code = |builder: lucetc::CompilerBuilder| {
+            builder
+                .create(&m, &b)
+                .expect("compile fibonacci")
+                .compiled_functions()
+                .expect("codegen fibonacci")
+                .into_iter()
+                .map(|f| f.code)
+                .collect::<Vec<_>>()
+        };
+        let preserved = code(Compiler::builder().with_preserve_frame_pointer(true));
+        assert_eq!(preserved, code(Compiler::builder()));
+        if cfg!(target_arch = "x86_64") {
+            // push %rbp; mov %rsp, %rbp
+            assert!(preserved
+                .iter()
+                .all(|code| code.starts_with(&[0x55, 0x48, 0x89, 0xe5])));
+        }
+        // No Cranelift setting omits the frame pointer, so the option can't take effect yet.
+        match Compiler::builder()
+            .with_preserve_frame_pointer(false)
+            .create(&m, &b)
+        {
+            Err(Error::Unsupported(_)) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("frame pointer omission accepted without Cranelift support"),
+        }
+    }
+
+    #[test]
+    fn exact_div_traps() {
+        use lucet_module::TrapCode;
+        let m = wabt::wat2wasm(
+            "(module
+               (func (export \"i32_div_s\") (param i32 i32) (result i32)
+                 (i32.div_s (local.get 0) (local.get 1)))
+               (func (export \"i32_div_u\") (param i32 i32) (result i32)
+                 (i32.div_u (local.get 0) (local.get 1)))
+               (func (export \"i64_div_s\") (param i64 i64) (result i64)
+                 (i64.div_s (local.get 0) (local.get 1)))
+               (func (export \"i64_rem_u\") (param i64 i64) (result i64)
+                 (i64.rem_u (local.get 0) (local.get 1))))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+        let obj = Compiler::builder()
+            .with_exact_div_traps(true)
+            .create(&m, &b)
+            .expect("compile")
+            .object_file()
+            .expect("codegen");
+        obj.self_check().expect("object is consistent");
+
+        let bytes = obj.bytes().expect("object bytes");
+        let trap_codes = |func: &str| trap_codes(&bytes, &format!("guest_func_{}", func));
+
+        // Signed division traps with either code, depending on the operands.
+        for func in &["i32_div_s", "i64_div_s"] {
+            let codes = trap_codes(func);
+            assert!(
+                codes.contains(&TrapCode::IntegerDivByZero)
+                    && codes.contains(&TrapCode::IntegerOverflow),
+                "{}: {:?}",
+                func,
+                codes
+            );
+        }
+        // Unsigned division and remainder can only trap on a zero divisor.
+        for func in &["i32_div_u", "i64_rem_u"] {
+            let codes = trap_codes(func);
+            assert!(
+                !codes.is_empty() && codes.iter().all(|c| *c == TrapCode::IntegerDivByZero),
+                "{}: {:?}",
+                func,
+                codes
+            );
+        }
+    }
+
+    #[test]
+    fn interrupt_checks() {
+        use lucet_module::TrapCode;
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        for count_instructions in &[false, true] {
+            let codes = |interrupt_checks: Option<u32>| {
+                let mut builder = Compiler::builder().with_count_instructions(*count_instructions);
+                if let Some(period) = interrupt_checks {
+                    builder.interrupt_checks(period);
+                }
+                let obj = builder
+                    .create(&m, &b)
+                    .expect("compile fibonacci")
+                    .object_file()
+                    .expect("codegen fibonacci");
+                obj.self_check().expect("object is consistent");
+                trap_codes(&obj.bytes().expect("object bytes"), "guest_func_main")
+            };
+            assert!(!codes(None).contains(&TrapCode::Interrupt));
+            for period in &[1, 1000] {
+                assert!(codes(Some(*period)).contains(&TrapCode::Interrupt));
+            }
+        }
+    }
+
+    #[test]
+    fn unreachable_strategy() {
+        use lucet_module::TrapCode;
+        use lucetc::UnreachableStrategy;
+        let m = wabt::wat2wasm(
+            "(module (func (export \"main\") (param i32)
+               (if (local.get 0) (then unreachable))))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+        let object_file = |strategy: Option<UnreachableStrategy>| {
+            let mut builder = Compiler::builder();
+            if let Some(strategy) = strategy {
+                builder.unreachable_strategy(strategy);
+            }
+            let c = builder.create(&m, &b).expect("compile");
+            let symbols = c.required_runtime_symbols();
+            let obj = c.object_file().expect("codegen");
+            obj.self_check().expect("object is consistent");
+            (symbols, obj)
+        };
+        let calls_handler = |obj: &lucetc::ObjectFile| {
+            obj.relocations()
+                .expect("read relocations")
+                .iter()
+                .any(|reloc| {
+                    reloc.symbol.as_deref() == Some("guest_func_main")
+                        && reloc.target == "lucet_vmctx_unreachable"
+                })
+        };
+
+        // By default, and with `Trap`, `unreachable` is only a trap instruction.
+        for strategy in &[None, Some(UnreachableStrategy::Trap)] {
+            let (symbols, obj) = object_file(*strategy);
+            assert!(!symbols.contains(&"lucet_vmctx_unreachable".to_owned()));
+            assert!(!calls_handler(&obj));
+            let bytes = obj.bytes().expect("object bytes");
+            assert!(trap_codes(&bytes, "guest_func_main").contains(&TrapCode::Unreachable));
+        }
+
+        // With `CallHandler`, the handler is called, and the trap remains in case it returns.
+        let (symbols, obj) = object_file(Some(UnreachableStrategy::CallHandler));
+        assert_eq!(symbols, vec!["lucet_vmctx_unreachable".to_owned()]);
+        assert!(calls_handler(&obj));
+        let bytes = obj.bytes().expect("object bytes");
+        assert!(trap_codes(&bytes, "guest_func_main").contains(&TrapCode::Unreachable));
+
+        // The strategy is baked into translated IR, so changing it between incremental
+        // compilations translates every function again, and the handler call appears.
+        let (_obj, state) = Compiler::builder()
+            .create(&m, &b)
+            .expect("compile")
+            .object_file_incremental()
+            .expect("codegen");
+        let (obj, state) = Compiler::builder()
+            .with_prev_state(std::sync::Arc::new(state))
+            .with_unreachable_strategy(UnreachableStrategy::CallHandler)
+            .create(&m, &b)
+            .expect("compile")
+            .object_file_incremental()
+            .expect("incremental codegen");
+        assert_eq!(state.reused(), 0);
+        assert!(calls_handler(&obj));
+    }
+
+    #[test]
+    fn trap_table_version() {
+        use lucet_module::{TrapCode, TRAP_TABLE_VERSION, TRAP_TABLE_VERSION_UNVERSIONED};
+        use lucetc::Error;
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        let table = |version: Option<u8>| {
+            let mut builder = Compiler::builder().with_interrupt_checks(1);
+            if let Some(version) = version {
+                builder.trap_table_version(version);
+            }
+            let obj = builder
+                .create(&m, &b)
+                .expect("compile fibonacci")
+                .object_file()
+                .expect("codegen fibonacci");
+            obj.self_check().expect("object is consistent");
+            let bytes = obj.bytes().expect("object bytes");
+            assert!(trap_codes(&bytes, "guest_func_main").contains(&TrapCode::Interrupt));
+            trap_table(&bytes, "guest_func_main")
+        };
+
+        let current = table(None);
+        let mut first = [0; 8];
+        first.copy_from_slice(&current[..8]);
+        assert_eq!(lucet_module::trap_table_version(first), TRAP_TABLE_VERSION);
+        assert_eq!(current, table(Some(TRAP_TABLE_VERSION)));
+
+        // The unversioned table holds the same sites without the header.
+        let unversioned = table(Some(TRAP_TABLE_VERSION_UNVERSIONED));
+        assert_eq!(unversioned[..], current[8..]);
+
+        match Compiler::builder()
+            .with_trap_table_version(TRAP_TABLE_VERSION + 1)
+            .create(&m, &b)
+        {
+            Err(Error::Unsupported(_)) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("unknown trap table version accepted"),
+        }
+    }
+
+    #[test]
+    fn host_trap_code() {
+        use lucet_module::{TrapCode, TRAP_TABLE_VERSION_UNVERSIONED};
+        use lucetc::Error;
+        let m = load_wat_module("import");
+        let mut b = super::test_bindings();
+        b.set_trap_code("env", "inc", 7);
+
+        // The trampoline traps with the binding's code when the host asks it to.
+        let obj = Compiler::builder()
+            .create(&m, &b)
+            .expect("compile import")
+            .object_file()
+            .expect("codegen import");
+        obj.self_check().expect("object is consistent");
+        let bytes = obj.bytes().expect("object bytes");
+        assert!(trap_codes(&bytes, "trampoline_inc").contains(&TrapCode::Host(7)));
+
+        // Without a trap code, the trampoline doesn't check.
+        let obj = Compiler::builder()
+            .create(&m, &super::test_bindings())
+            .expect("compile import")
+            .object_file()
+            .expect("codegen import");
+        let bytes = obj.bytes().expect("object bytes");
+        assert!(!trap_codes(&bytes, "trampoline_inc")
+            .iter()
+            .any(|code| matches!(code, TrapCode::Host(_))));
+
+        // Runtimes that read unversioned trap tables don't know host codes.
+        match Compiler::builder()
+            .with_trap_table_version(TRAP_TABLE_VERSION_UNVERSIONED)
+            .create(&m, &b)
+            .expect("compile import")
+            .object_file()
+        {
+            Err(Error::Unsupported(_)) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("host code written to an unversioned trap table"),
+        }
+    }
+
+    #[test]
+    fn memory_limit_trap_code() {
+        use lucet_module::{TrapCode, MAX_HOST_TRAP_CODE};
+        use lucetc::Error;
+        let m = wabt::wat2wasm(
+            "(module (memory 1)
+               (func $inc (import \"env\" \"inc\") (result i32))
+               (func (export \"main\") (param i32) (result i32)
+                 (drop (call $inc))
+                 (memory.grow (local.get 0))))",
+        )
+        .expect("convert module to wasm binary format");
+
+        // Host code 0 and the memory limit trap don't share a code.
+        let mut b = super::test_bindings();
+        b.set_trap_code("env", "inc", 0);
+        let bytes = Compiler::builder()
+            .with_hard_memory_limit(Some(4))
+            .create(&m, &b)
+            .expect("compile")
+            .object_file()
+            .expect("codegen")
+            .bytes()
+            .expect("object bytes");
+        let main_codes = trap_codes(&bytes, "guest_func_main");
+        assert!(main_codes.contains(&TrapCode::MemoryLimitExceeded));
+        assert!(!main_codes.contains(&TrapCode::Host(0)));
+        let trampoline_codes = trap_codes(&bytes, "trampoline_inc");
+        assert!(trampoline_codes.contains(&TrapCode::Host(0)));
+        assert!(!trampoline_codes.contains(&TrapCode::MemoryLimitExceeded));
+
+        // Codes above the host range are reserved.
+        let mut b = super::test_bindings();
+        b.set_trap_code("env", "inc", MAX_HOST_TRAP_CODE + 1);
+        match Compiler::builder().create(&m, &b) {
+            Err(Error::Unsupported(_)) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("reserved trap code accepted"),
+        }
+    }
+
+    #[test]
+    fn trap_site_count() {
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        let count = |interrupt_checks: bool| {
+            let mut builder = Compiler::builder();
+            if interrupt_checks {
+                builder.interrupt_checks(1);
+            }
+            let obj = builder
+                .create(&m, &b)
+                .expect("compile fibonacci")
+                .object_file()
+                .expect("codegen fibonacci");
+            let bytes = obj.bytes().expect("object bytes");
+            assert!(obj.trap_site_count() >= trap_codes(&bytes, "guest_func_main").len());
+            obj.trap_site_count()
+        };
+        assert!(count(true) > count(false));
+    }
+
+    #[test]
+    fn cancellation_token() {
+        use lucetc::Error;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+
+        let token = Arc::new(AtomicBool::new(false));
+        let c = Compiler::builder()
+            .with_cancellation_token(token.clone())
+            .create(&m, &b)
+            .expect("compile fibonacci");
+        c.object_file().expect("codegen without cancellation");
+
+        let c = Compiler::builder()
+            .with_cancellation_token(token.clone())
+            .create(&m, &b)
+            .expect("compile fibonacci");
+        token.store(true, Ordering::Relaxed);
+        match c.object_file() {
+            Err(Error::Cancelled) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("cancelled compilation produced an object"),
+        }
+    }
+
+    #[test]
+    fn canonicalization_applied() {
+        let b = super::test_bindings();
+        let integer = load_wat_module("fibonacci");
+        let float = wabt::wat2wasm(
+            "(module (func (export \"main\") (param f64) (result f64) (f64.sqrt (local.get 0))))",
+        )
+        .expect("convert module to wasm binary format");
+        for (module, has_float_ops) in &[(integer, false), (float, true)] {
+            for canonicalize_nans in &[false, true] {
+                let c = Compiler::builder()
+                    .with_canonicalize_nans(*canonicalize_nans)
+                    .create(module, &b)
+                    .expect("compile");
+                assert_eq!(
+                    c.canonicalization_applied(),
+                    *canonicalize_nans && *has_float_ops
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn feature_usage() {
+        use lucet_module::ModuleFeatures;
+        use lucetc::{CpuFeatures, SpecificFeature, UniqueFuncIndex};
+        let m = wabt::wat2wasm(
+            "(module
+               (func (export \"count\") (param i32) (result i32) (i32.popcnt (local.get 0)))
+               (func (export \"add\") (param i32) (result i32) (i32.add (local.get 0) (local.get 0))))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+
+        let mut cpu_features = CpuFeatures::baseline();
+        cpu_features.set(SpecificFeature::Popcnt, true);
+        let c = Compiler::builder()
+            .with_cpu_features(cpu_features)
+            .create(&m, &b)
+            .expect("compile");
+        let usage = c.feature_usage();
+        assert_eq!(usage.len(), 2);
+        assert!(ModuleFeatures::from(&usage[&UniqueFuncIndex::from_u32(0)]).popcnt);
+        assert!(!ModuleFeatures::from(&usage[&UniqueFuncIndex::from_u32(1)]).popcnt);
+
+        // Without POPCNT enabled, the backend does not use it.
+        let c = Compiler::builder()
+            .with_cpu_features(CpuFeatures::baseline())
+            .create(&m, &b)
+            .expect("compile");
+        assert!(c
+            .feature_usage()
+            .values()
+            .all(|features| !ModuleFeatures::from(features).popcnt));
+    }
+
+    #[test]
+    fn required_features() {
+        use lucet_module::{ModuleData, WasmProposals};
+        use lucetc::{CpuFeatures, SpecificFeature};
+        // Written out by hand, as wabt doesn't accept multi-value or bulk memory operations. It
+        // is equivalent to:
+        //
+        // (module
+        //   (memory 1)
+        //   (func (param i32) (result i32 i32)
+        //     (local.get 0) (i32.popcnt (local.get 0)))
+        //   (func
+        //     (memory.fill (i32.const 0) (i32.const 0) (i32.const 0))))
+        #[rustfmt::skip]
+        let m: Vec<u8> = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+            // type section: (i32) -> (i32, i32), () -> ()
+            0x01, 0x0a, 0x02, 0x60, 0x01, 0x7f, 0x02, 0x7f, 0x7f, 0x60, 0x00, 0x00,
+            // function section
+            0x03, 0x03, 0x02, 0x00, 0x01,
+            // memory section: one memory of one page
+            0x05, 0x03, 0x01, 0x00, 0x01,
+            // code section
+            0x0a, 0x15, 0x02,
+            0x07, 0x00, 0x20, 0x00, 0x20, 0x00, 0x69, 0x0b,
+            0x0b, 0x00, 0x41, 0x00, 0x41, 0x00, 0x41, 0x00, 0xfc, 0x0b, 0x00, 0x0b,
+        ];
+        let b = super::test_bindings();
+
+        let mut cpu_features = CpuFeatures::baseline();
+        cpu_features.set(SpecificFeature::Popcnt, true);
+        cpu_features.set(SpecificFeature::BMI1, true);
+        let c = Compiler::builder()
+            .with_cpu_features(cpu_features)
+            .create(&m, &b)
+            .expect("compile");
+        let required = c.required_features();
+        assert_eq!(
+            required.wasm,
+            WasmProposals {
+                multi_value: true,
+                bulk_memory: true,
+                ..WasmProposals::default()
+            }
+        );
+        assert!(required.cpu.popcnt);
+        // BMI1 is enabled, but nothing uses it.
+        assert!(!required.cpu.bmi1);
+
+        // The requirements are recorded in the module data for the runtime to check.
+        let module_data = c.module_data().expect("module data").serialize().unwrap();
+        let module_data = ModuleData::deserialize(&module_data).unwrap();
+        assert_eq!(module_data.required_features().wasm, required.wasm);
+        assert!(module_data.required_features().cpu.popcnt);
+
+        let m = load_wat_module("fibonacci");
+        let required = Compiler::builder()
+            .create(&m, &b)
+            .expect("compile fibonacci")
+            .required_features();
+        assert_eq!(required.wasm, WasmProposals::default());
+        assert!(!required.cpu.popcnt);
+    }
+
+    #[test]
+    fn required_runtime_symbols() {
+        use lucetc::{CpuFeatures, SpecificFeature};
+        let m = wabt::wat2wasm(
+            "(module (memory 1)
+               (func (export \"grow\") (param i32) (result i32) (memory.grow (local.get 0)))
+               (func (export \"floor\") (param f32) (result f32) (f32.floor (local.get 0))))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+
+        // Without SSE4.1, rounding is a call to libm.
+        let c = Compiler::builder()
+            .with_cpu_features(CpuFeatures::baseline())
+            .create(&m, &b)
+            .expect("compile");
+        assert_eq!(
+            c.required_runtime_symbols(),
+            vec!["floorf".to_owned(), "lucet_vmctx_grow_memory".to_owned()]
+        );
+
+        let mut cpu_features = CpuFeatures::baseline();
+        cpu_features.set(SpecificFeature::SSE41, true);
+        let c = Compiler::builder()
+            .with_cpu_features(cpu_features)
+            .with_external_stack_probe(true)
+            .create(&m, &b)
+            .expect("compile");
+        assert_eq!(
+            c.required_runtime_symbols(),
+            vec![
+                "lucet_probestack".to_owned(),
+                "lucet_vmctx_grow_memory".to_owned()
+            ]
+        );
+    }
+
+    #[test]
+    fn deterministic_libcalls() {
+        use lucetc::CpuFeatures;
+        let m = wabt::wat2wasm(
+            "(module
+               (func (export \"floor\") (param f32) (result f32) (f32.floor (local.get 0)))
+               (func (export \"nearest\") (param f64) (result f64) (f64.nearest (local.get 0))))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+        let c = Compiler::builder()
+            .with_cpu_features(CpuFeatures::baseline())
+            .with_deterministic_libcalls(true)
+            .create(&m, &b)
+            .expect("compile");
+        assert_eq!(
+            c.required_runtime_symbols(),
+            vec![
+                "lucet_libcall_floorf".to_owned(),
+                "lucet_libcall_nearbyint".to_owned()
+            ]
+        );
+        let obj = c.object_file().expect("codegen");
+        obj.self_check().expect("object is consistent");
+        let targets = obj
+            .relocations()
+            .expect("read relocations")
+            .into_iter()
+            .map(|reloc| reloc.target)
+            .collect::<Vec<_>>();
+        assert!(targets.iter().any(|t| t == "lucet_libcall_floorf"));
+        assert!(targets.iter().any(|t| t == "lucet_libcall_nearbyint"));
+        assert!(!targets.iter().any(|t| t == "floorf" || t == "nearbyint"));
+    }
+
+    #[test]
+    fn allow_simd() {
+        use lucet_module::ModuleFeatures;
+        use lucetc::{CpuFeatures, Error, SpecificFeature};
+        let mut wat_features = wabt::Features::new();
+        wat_features.enable_simd();
+        let m = wabt::wat2wasm_with_features(
+            "(module (func (export \"double\") (param i32) (result i32)
+               (i32x4.extract_lane 0
+                 (i32x4.add (i32x4.splat (local.get 0)) (i32x4.splat (local.get 0))))))",
+            wat_features,
+        )
+        .expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+
+        let mut simd_features = CpuFeatures::baseline();
+        simd_features.set(SpecificFeature::SSSE3, true);
+        simd_features.set(SpecificFeature::SSE41, true);
+        match Compiler::builder()
+            .with_cpu_features(simd_features.clone())
+            .create(&m, &b)
+        {
+            Err(Error::ClifWasmValidation(_)) | Err(Error::WebAssemblyValidation { .. }) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("SIMD accepted without allow_simd"),
+        }
+
+        match Compiler::builder()
+            .with_cpu_features(CpuFeatures::baseline())
+            .with_allow_simd(true)
+            .create(&m, &b)
+        {
+            Err(Error::SimdCpuFeatures { missing }) => assert_eq!(
+                missing,
+                vec![SpecificFeature::SSSE3, SpecificFeature::SSE41]
+            ),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("SIMD accepted without SSSE3 and SSE4.1"),
+        }
+
+        let c = Compiler::builder()
+            .with_cpu_features(simd_features)
+            .with_allow_simd(true)
+            .create(&m, &b)
+            .expect("compile SIMD module");
+        let features = *c.module_data().expect("module data").features();
+        assert!(features.ssse3 && features.sse41);
+        assert!(c
+            .feature_usage()
+            .values()
+            .all(|features| ModuleFeatures::from(features).sse41));
+        let _obj = c.object_file().expect("codegen SIMD module");
+    }
+
+    #[test]
+    fn function_manifest_ordered() {
+        use lucet_module::bindings::Bindings;
+        use lucetc::FuncIndex;
+        let m = wabt::wat2wasm(
+            "(module
+               (import \"env\" \"first\" (func))
+               (import \"env\" \"second\" (func))
+               (func (export \"main\") (call 0) (call 1)))",
+        )
+        .expect("convert module to wasm binary format");
+        // Both imports are bound to the same host function, so they share a manifest entry.
+        let imports = [
+            ("first".to_owned(), "host_func".to_owned()),
+            ("second".to_owned(), "host_func".to_owned()),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let b = Bindings::env(imports);
+        let c = Compiler::builder().create(&m, &b).expect("compile");
+        let entries = c.function_manifest_ordered();
+
+        let wasm_entries = entries
+            .iter()
+            .filter_map(|entry| entry.wasm_index.map(|index| (index, entry)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            wasm_entries
+                .iter()
+                .map(|(index, entry)| (*index, entry.symbol.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (FuncIndex::from_u32(0), "host_func"),
+                (FuncIndex::from_u32(1), "host_func"),
+                (FuncIndex::from_u32(2), "guest_func_main"),
+            ]
+        );
+        assert_eq!(
+            wasm_entries[0].1.manifest_index,
+            wasm_entries[1].1.manifest_index
+        );
+        assert_ne!(
+            wasm_entries[1].1.manifest_index,
+            wasm_entries[2].1.manifest_index
+        );
+
+        // Runtime functions follow, without a wasm index.
+        assert!(entries[3..].iter().all(|entry| entry.wasm_index.is_none()));
+        assert!(entries
+            .iter()
+            .any(|entry| entry.symbol == "lucet_vmctx_grow_memory"));
+    }
+
+    #[test]
+    fn relocations() {
+        let m = load_wat_module("import");
+        let b = super::test_bindings();
+        let c = Compiler::builder().create(&m, &b).expect("compile import");
+        let obj = c.object_file().expect("codegen import");
+        let relocs = obj.relocations().expect("relocations");
+
+        // The call to the import is the only undefined symbol `main` refers to.
+        let undefined = relocs
+            .iter()
+            .filter(|reloc| reloc.symbol.as_deref() == Some("guest_func_main"))
+            .filter(|reloc| reloc.target_undefined)
+            .collect::<Vec<_>>();
+        assert_eq!(undefined.len(), 1);
+        assert_eq!(undefined[0].target, "inc");
+
+        // The function manifest refers to `main`, which is defined.
+        assert!(relocs.iter().any(|reloc| reloc.symbol.as_deref()
+            == Some("lucet_function_manifest")
+            && reloc.target == "guest_func_main"
+            && !reloc.target_undefined));
+    }
+
+    #[test]
+    fn data_symbols() {
+        use lucetc::{DataSymbolKind, SymbolScope};
+        let m = wabt::wat2wasm(
+            "(module
+               (memory 1)
+               (table 1 funcref)
+               (elem (i32.const 0) 0)
+               (func (export \"main\") (param i32) (result i32)
+                 (i32.load (local.get 0))))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+        let obj = Compiler::builder()
+            .with_emit_address_map(true)
+            .with_custom_metadata("git_sha".to_owned(), b"abcdef".to_vec())
+            .create(&m, &b)
+            .expect("compile")
+            .object_file()
+            .expect("codegen");
+        let symbols = obj.data_symbols().expect("data symbols");
+        let find = |kind: DataSymbolKind| {
+            symbols
+                .iter()
+                .find(|symbol| symbol.kind == kind)
+                .unwrap_or_else(|| panic!("no {:?} symbol", kind))
+        };
+
+        let module = find(DataSymbolKind::Module);
+        assert_eq!(module.name, "lucet_module");
+        assert_eq!(module.scope, SymbolScope::Dynamic);
+        assert_eq!(
+            find(DataSymbolKind::ModuleData).scope,
+            SymbolScope::Compilation
+        );
+        find(DataSymbolKind::FunctionManifest);
+        find(DataSymbolKind::Tables);
+        find(DataSymbolKind::TableData { index: 0 });
+        // The load can trap, so `main` has a trap table.
+        let trap_table = find(DataSymbolKind::TrapTable {
+            func: "guest_func_main".to_owned(),
+        });
+        assert!(trap_table.size > 0);
+        find(DataSymbolKind::AddressMap {
+            func: "guest_func_main".to_owned(),
+        });
+        let custom = find(DataSymbolKind::Custom {
+            key: "git_sha".to_owned(),
+        });
+        assert_eq!(custom.size, 6);
+        assert!(symbols.iter().all(|symbol| !symbol.weak));
+        assert!(!symbols
+            .iter()
+            .any(|symbol| symbol.kind == DataSymbolKind::Other));
+    }
+
+    #[test]
+    fn write_clif_per_function() {
+        let m = wabt::wat2wasm(
+            "(module
+               (func (export \"first\") (result i32) (i32.const 1))
+               (func (export \"second\") (result i32) (call 0)))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+        let c = Compiler::builder().create(&m, &b).expect("compile");
+        let dir = tempfile::tempdir().expect("create temporary directory");
+        c.cranelift_funcs()
+            .expect("translate functions")
+            .write_per_function(dir.path())
+            .expect("write functions");
+
+        let mut files = std::fs::read_dir(dir.path())
+            .expect("read directory")
+            .map(|entry| entry.expect("directory entry").file_name())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(
+            files,
+            vec!["guest_func_first.clif", "guest_func_second.clif"]
+        );
+        let second = std::fs::read_to_string(dir.path().join("guest_func_second.clif"))
+            .expect("read guest_func_second.clif");
+        assert!(second.starts_with("function "));
+        assert!(second.contains("call "));
+    }
+
+    #[test]
+    fn vmcontext_layout() {
+        use lucet_module::InstanceRuntimeData;
+        let b = super::test_bindings();
+
+        let m = load_wat_module("fibonacci");
+        let c = Compiler::builder()
+            .create(&m, &b)
+            .expect("compile fibonacci");
+        let layout = c.vmcontext_layout();
+        let runtime_data_size = std::mem::size_of::<InstanceRuntimeData>() as i32;
+        assert_eq!(layout.runtime_data, -runtime_data_size);
+        for offset in &[
+            layout.globals_ptr,
+            layout.instruction_count_adj,
+            layout.instruction_count_bound,
+            layout.stack_limit,
+            layout.interrupt_flag,
+            layout.host_trap,
+        ] {
+            assert!(*offset >= layout.runtime_data && *offset < 0);
+        }
+        assert_eq!(layout.global_size, 8);
+        assert!(layout.memory_base_ptrs.is_empty());
+
+        #[rustfmt::skip]
+        let m = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+            // memory section: two memories, of one and two pages
+            0x05, 0x05, 0x02, 0x00, 0x01, 0x00, 0x02,
+        ];
+        let c = Compiler::builder()
+            .with_allow_multi_memory(true)
+            .create(&m, &b)
+            .expect("compile multi-memory module");
+        assert_eq!(
+            c.vmcontext_layout().memory_base_ptrs,
+            vec![-runtime_data_size - 8]
+        );
+    }
+
+    #[test]
+    fn start_function() {
+        use lucetc::{FuncIndex, StartFunctionInfo};
+        let b = super::test_bindings();
+
+        let m = load_wat_module("start_section");
+        let c = Compiler::builder()
+            .create(&m, &b)
+            .expect("compile start_section");
+        assert_eq!(
+            c.start_function(),
+            Some(StartFunctionInfo {
+                wasm_index: FuncIndex::from_u32(1),
+                symbol: "start_1".to_owned(),
+                imported: false,
+            })
+        );
+
+        let m = wabt::wat2wasm("(module (import \"env\" \"inc\" (func)) (start 0))")
+            .expect("convert module to wasm binary format");
+        let c = Compiler::builder().create(&m, &b).expect("compile");
+        assert_eq!(
+            c.start_function(),
+            Some(StartFunctionInfo {
+                wasm_index: FuncIndex::from_u32(0),
+                symbol: "inc".to_owned(),
+                imported: true,
+            })
+        );
+
+        let m = load_wat_module("locals");
+        let c = Compiler::builder().create(&m, &b).expect("compile locals");
+        assert_eq!(c.start_function(), None);
+    }
+
+    #[test]
+    fn eliminated_traps() {
+        use lucetc::UniqueFuncIndex;
+        // The store and division after `unreachable` are never translated, so they can't trap.
+        let m = wabt::wat2wasm(
+            "(module
+               (memory 1)
+               (func (export \"live\") (param i32 i32) (result i32)
+                 (i32.div_u (local.get 0) (local.get 1)))
+               (func (export \"dead\") (result i32)
+                 (unreachable)
+                 (i32.store (i32.const 0) (i32.const 1))
+                 (i32.div_u (i32.const 1) (i32.const 0))))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+        let c = Compiler::builder().create(&m, &b).expect("compile");
+        let eliminated = c.eliminated_traps().expect("eliminated traps");
+        assert_eq!(eliminated.len(), 2);
+        assert!(eliminated[&UniqueFuncIndex::from_u32(0)].is_empty());
+        let dead = &eliminated[&UniqueFuncIndex::from_u32(1)];
+        let operators = dead
+            .iter()
+            .map(|trap| trap.operator.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(operators, ["I32Store", "I32DivU"]);
+
+        // The analysis leaves the compiler able to produce its object.
+        c.object_file().expect("codegen");
+    }
+
+    #[test]
+    fn invalid_table_initializer() {
+        // A module whose element segment puts function 5 in the table, when it only has one.
+        let m: &[u8] = &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // preamble
+            0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // type section: () -> ()
+            0x03, 0x02, 0x01, 0x00, // function section: one function of type 0
+            0x04, 0x04, 0x01, 0x70, 0x00, 0x01, // table section: one funcref table of size 1
+            0x09, 0x07, 0x01, 0x00, 0x41, 0x00, 0x0b, 0x01, 0x05, // element segment: [5] at 0
+            0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b, // code section: an empty body
+        ];
+        let b = super::test_bindings();
+        // The validator checks element segments before they are declared, so the table is never
+        // written from an index that doesn't name a function.
+        match Compiler::builder().create(m, &b) {
+            Err(Error::WebAssemblyValidation { message, .. }) => {
+                assert!(message.contains("unknown function"), "{}", message)
+            }
+            Err(e) => panic!("expected WebAssemblyValidation error, got {:?}", e),
+            Ok(_) => panic!("element segment with a missing function accepted"),
+        }
+    }
+
+    #[test]
+    fn report_const_fold() {
+        use lucetc::OptLevel;
+
+        let m = wabt::wat2wasm(
+            "(module (func (export \"const_fold\") (result i32)
+               (i32.add (i32.mul (i32.const 6) (i32.const 7)) (i32.const 0))))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+        let report = |opt_level: OptLevel| {
+            Compiler::builder()
+                .with_opt_level(opt_level)
+                .with_report_const_fold(true)
+                .create(&m, &b)
+                .expect("compile")
+                .object_file()
+                .expect("codegen");
+            take_logged("guest_func_const_fold:")
+        };
+
+        // Cranelift doesn't fold constants without optimizing.
+        assert!(report(OptLevel::None).is_empty());
+
+        for opt_level in &[OptLevel::Speed, OptLevel::SpeedAndSize] {
+            let messages = report(*opt_level);
+            assert_eq!(messages.len(), 1, "one report: {:?}", messages);
+            let counts = messages[0]
+                .trim_start_matches("guest_func_const_fold: constant folding reduced ")
+                .split(" instructions to ")
+                .map(|count| count.parse::<usize>().expect("instruction count"))
+                .collect::<Vec<_>>();
+            match counts.as_slice() {
+                [before, after] => {
+                    assert!(after < before);
+                    assert!((before - after) * 10 >= *before);
+                }
+                _ => panic!("unexpected report: {}", messages[0]),
+            }
+        }
+    }
+
+    #[test]
+    fn function_bodies() {
+        use lucetc::UniqueFuncIndex;
+        let m = load_wat_module("import_many");
+        let b = super::test_bindings();
+        let c = Compiler::builder()
+            .create(&m, &b)
+            .expect("compile import_many");
+        let bodies = c.function_bodies().collect::<Vec<_>>();
+        assert!(!bodies.is_empty());
+        assert!(bodies.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        for (func_index, code, offset) in bodies {
+            assert!(
+                func_index >= UniqueFuncIndex::from_u32(4),
+                "imports have no body"
+            );
+            assert_eq!(&m[offset..offset + code.len()], code);
+        }
+    }
+
+    #[test]
+    fn max_module_bytes() {
+        use lucetc::Error;
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        match Compiler::builder()
+            .with_max_module_bytes(m.len() - 1)
+            .create(&m, &b)
+        {
+            Err(Error::ModuleTooLarge { size, limit }) => {
+                assert_eq!(size, m.len());
+                assert_eq!(limit, m.len() - 1);
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("oversized module accepted"),
+        }
+
+        // Not even a wasm binary, but it is rejected before it is parsed.
+        match Compiler::builder()
+            .with_max_module_bytes(4)
+            .validate_only(b"not a wasm module")
+        {
+            Err(Error::ModuleTooLarge { .. }) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("oversized module accepted"),
+        }
+
+        Compiler::builder()
+            .with_max_module_bytes(m.len())
+            .create(&m, &b)
+            .expect("module at the limit compiles");
+    }
+
+    #[test]
+    fn max_total_code_size() {
+        use lucetc::Error;
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        let c = Compiler::builder()
+            .with_max_total_code_size(1024 * 1024)
+            .create(&m, &b)
+            .expect("compile fibonacci");
+        assert!(c.complexity_estimate() > 0);
+        c.object_file().expect("codegen fibonacci within budget");
+
+        let c = Compiler::builder()
+            .with_max_total_code_size(16)
+            .create(&m, &b)
+            .expect("compile fibonacci");
+        match c.object_file() {
+            Err(Error::CodeSizeBudgetExceeded { actual, budget }) => {
+                assert_eq!(budget, 16);
+                assert!(actual > budget);
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("code size budget not enforced"),
+        }
+    }
+
+    #[test]
+    fn pad_code_to() {
+        use lucetc::Error;
+        use object::{Object, ObjectSection, SectionKind};
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        let obj = Compiler::builder()
+            .with_pad_code_to(64 * 1024)
+            .create(&m, &b)
+            .expect("compile fibonacci")
+            .object_file()
+            .expect("codegen fibonacci");
+        obj.self_check().expect("object is consistent");
+        let bytes = obj.bytes().expect("object bytes");
+        let file = object::File::parse(&bytes).expect("parse object");
+        let text = file
+            .sections()
+            .find(|section| section.kind() == SectionKind::Text)
+            .expect("object has a code section");
+        assert_eq!(text.size(), 64 * 1024);
+        if cfg!(target_arch = "x86_64") {
+            let data = text.data().expect("code section data");
+            assert_eq!(data.last(), Some(&0xcc));
+        }
+
+        let c = Compiler::builder()
+            .with_pad_code_to(16)
+            .create(&m, &b)
+            .expect("compile fibonacci");
+        match c.object_file() {
+            Err(Error::CodeSizeBudgetExceeded { actual, budget }) => {
+                assert_eq!(budget, 16);
+                assert!(actual > budget);
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("code larger than its padded size accepted"),
+        }
+
+        // aarch64 instructions are 4 bytes, so its code can't be padded to any other multiple.
+        let c = Compiler::builder()
+            .with_pad_code_to(64 * 1024 + 2)
+            .create(&m, &b)
+            .expect("compile fibonacci");
+        match c.object_file() {
+            Err(Error::Input(_)) if cfg!(target_arch = "aarch64") => {}
+            Ok(_) if cfg!(target_arch = "x86_64") => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("unaligned padded size accepted"),
+        }
+
+        match Compiler::builder()
+            .with_pad_code_to(64 * 1024)
+            .with_function_sections(true)
+            .create(&m, &b)
+        {
+            Err(Error::Input(_)) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("padding accepted with function sections"),
+        }
+    }
+
+    #[test]
+    fn forbid_float() {
+        use lucetc::Error;
+        // Neither function has a float in its signature, but one converts through one.
+        let m = wabt::wat2wasm(
+            "(module
+               (func (export \"ints\") (param i32) (result i32) (i32.add (local.get 0) (i32.const 1)))
+               (func (export \"conv\") (param i32) (result i32)
+                 (i32.reinterpret_f32 (f32.convert_i32_s (local.get 0)))))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+
+        Compiler::builder()
+            .create(&m, &b)
+            .expect("compile without forbid_float");
+        match Compiler::builder().with_forbid_float(true).create(&m, &b) {
+            Err(Error::FloatNotAllowed { symbol, offset }) => {
+                assert_eq!(symbol, "guest_func_conv");
+                assert!(offset > 0);
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("float use accepted"),
+        }
+
+        // An indirect call can return a float even when no function in the module does.
+        let m = wabt::wat2wasm(
+            "(module
+               (type $f (func (result f64)))
+               (table 1 anyfunc)
+               (func (export \"icall\") (param i32)
+                 (drop (call_indirect (type $f) (local.get 0)))))",
+        )
+        .expect("convert module to wasm binary format");
+        match Compiler::builder().with_forbid_float(true).create(&m, &b) {
+            Err(Error::FloatNotAllowed { symbol, .. }) => assert_eq!(symbol, "guest_func_icall"),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("float-returning indirect call accepted"),
+        }
+
+        let m = load_wat_module("fibonacci");
+        Compiler::builder()
+            .with_forbid_float(true)
+            .create(&m, &b)
+            .expect("compile float-free module")
+            .object_file()
+            .expect("codegen float-free module");
+    }
+
+    #[test]
+    fn max_locals_per_function() {
+        use lucetc::Error;
+        let m = wabt::wat2wasm(
+            "(module
+               (func (export \"f\") (param i32) (local i32 i64) (local f32)))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+        let compile = |limit: u32| {
+            Compiler::builder()
+                .with_max_locals_per_function(limit)
+                .create(&m, &b)
+                .expect("compile")
+                .object_file()
+        };
+
+        compile(3).expect("codegen within the limit");
+        match compile(2) {
+            Err(Error::TooManyLocals {
+                symbol,
+                count,
+                limit,
+            }) => {
+                assert_eq!(symbol, "guest_func_f");
+                assert_eq!((count, limit), (3, 2));
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("local limit not enforced"),
+        }
+    }
+
+    #[test]
+    fn expect_export() {
+        use lucetc::{Error, WasmType};
+        let m = load_wat_module("locals");
+        let b = super::test_bindings();
+        Compiler::builder()
+            .with_expect_export("main".to_owned(), vec![], vec![WasmType::I32])
+            .create(&m, &b)
+            .expect("compile locals");
+
+        match Compiler::builder()
+            .with_expect_export("main".to_owned(), vec![WasmType::I32], vec![WasmType::I32])
+            .create(&m, &b)
+        {
+            Err(Error::ExportSignatureMismatch { name, .. }) => assert_eq!(name, "main"),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("mismatched export signature accepted"),
+        }
+
+        match Compiler::builder()
+            .with_expect_export("main".to_owned(), vec![], vec![])
+            .with_expect_export("run".to_owned(), vec![], vec![])
+            .create(&m, &b)
+        {
+            Err(Error::ExportExpectations(errors)) => match errors.as_slice() {
+                [Error::ExportSignatureMismatch { .. }, Error::MissingExport { name }] => {
+                    assert_eq!(name, "run")
+                }
+                errors => panic!("unexpected errors: {:?}", errors),
+            },
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("unmet export expectations accepted"),
+        }
+    }
+
+    #[test]
+    fn dedup_trap_tables() {
+        // Many small functions with the same trapping division have identical trap tables.
+        let mut wat = String::from("(module");
+        for i in 0..64 {
+            wat.push_str(&format!(
+                " (func (export \"div_{}\") (param i32 i32) (result i32)
+                   (i32.div_s (local.get 0) (local.get 1)))",
+                i
+            ));
+        }
+        wat.push_str(")");
+        let m = wabt::wat2wasm(wat).expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+
+        let object_size = |dedup_trap_tables: bool| {
+            let obj = Compiler::builder()
+                .with_dedup_trap_tables(dedup_trap_tables)
+                .create(&m, &b)
+                .expect("compile")
+                .object_file()
+                .expect("codegen");
+            obj.self_check().expect("object is consistent");
+            obj.bytes().expect("object bytes").len()
+        };
+        let (separate, shared) = (object_size(false), object_size(true));
+        assert!(
+            shared < separate,
+            "shared trap tables take {} bytes, separate tables {}",
+            shared,
+            separate
+        );
+    }
+
+    #[test]
+    fn emit_address_map() {
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        let object_file = |emit_address_map: bool| {
+            Compiler::builder()
+                .with_emit_address_map(emit_address_map)
+                .create(&m, &b)
+                .expect("compile fibonacci")
+                .object_file()
+                .expect("codegen fibonacci")
+        };
+
+        let obj = object_file(true);
+        obj.self_check().expect("object is consistent");
+        let map = obj
+            .address_map_for("guest_func_main")
+            .expect("read address map")
+            .expect("main has an address map");
+        assert!(!map.is_empty());
+        for entry in map.iter() {
+            assert!(entry.code_start < entry.code_end, "{:?}", entry);
+            assert!((entry.wasm_offset as usize) < m.len(), "{:?}", entry);
+        }
+        assert!(map.windows(2).all(|w| w[0].code_end <= w[1].code_start));
+
+        let obj = object_file(false);
+        assert_eq!(obj.address_map_for("guest_func_main").unwrap(), None);
+    }
+
+    #[test]
+    fn object_file_split() {
+        use object::{Object, ObjectSymbol};
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        let (obj, debug_obj) = Compiler::builder()
+            .with_emit_address_map(true)
+            .create(&m, &b)
+            .expect("compile fibonacci")
+            .object_file_split()
+            .expect("codegen fibonacci");
+
+        obj.self_check().expect("production object is consistent");
+        assert_eq!(obj.address_map_for("guest_func_main").unwrap(), None);
+        let build_id = obj
+            .build_id()
+            .expect("read build id")
+            .expect("production object has a build id");
+        assert_eq!(build_id.len(), 20);
+        assert_eq!(debug_obj.build_id(), build_id.as_slice());
+
+        let main = debug_obj
+            .functions()
+            .iter()
+            .find(|func| func.symbol == "guest_func_main")
+            .expect("debug object describes main");
+        assert_eq!(main.name.as_deref(), Some("main"));
+        let map = debug_obj
+            .address_map_for("guest_func_main")
+            .expect("main has an address map");
+        assert!(!map.is_empty());
+        assert!(map
+            .iter()
+            .all(|entry| (entry.wasm_offset as usize) < m.len()));
+
+        let bytes = debug_obj.bytes().expect("write debug object");
+        let file = object::File::parse(&bytes).expect("parse debug object");
+        assert!(file
+            .symbols()
+            .any(|symbol| symbol.name() == Ok("lucet_address_map_guest_func_main")));
+        assert!(file.section_by_name(".note.gnu.build-id").is_some());
+
+        // Unchanged code gets the same build id.
+        let (obj2, _debug_obj2) = Compiler::builder()
+            .with_emit_address_map(true)
+            .create(&m, &b)
+            .expect("compile fibonacci")
+            .object_file_split()
+            .expect("codegen fibonacci");
+        assert_eq!(obj2.build_id().unwrap(), Some(build_id));
+    }
+
+    #[test]
+    fn object_file_split_strips_local_functions() {
+        use object::{Object, ObjectSymbol};
+        let m = wabt::Wat2Wasm::new()
+            .write_debug_names(true)
+            .convert(
+                "(module
+                   (memory 1)
+                   (table 1 anyfunc)
+                   (elem (i32.const 0) $load)
+                   (func $load (param i32) (result i32) (i32.load (local.get 0)))
+                   (func (export \"main\") (param i32) (result i32) (call $load (local.get 0))))",
+            )
+            .expect("convert module to wasm binary format")
+            .as_ref()
+            .to_owned();
+        let b = super::test_bindings();
+        let (obj, debug_obj) = Compiler::builder()
+            .create(&m, &b)
+            .expect("compile")
+            .object_file_split()
+            .expect("codegen");
+
+        // `load` is called directly and through the table, and its trap table is in the manifest,
+        // but neither it nor its trap table keep a symbol.
+        obj.self_check().expect("production object is consistent");
+        let bytes = obj.bytes().expect("write production object");
+        let file = object::File::parse(&*bytes).expect("parse production object");
+        let names = file
+            .symbols()
+            .filter_map(|symbol| symbol.name().ok().map(str::to_owned))
+            .collect::<Vec<_>>();
+        assert!(
+            !names.iter().any(|name| name.contains("load_0")),
+            "{:?}",
+            names
+        );
+        assert!(names.iter().any(|name| name == "guest_func_main"));
+
+        let load = debug_obj
+            .functions()
+            .iter()
+            .find(|func| func.symbol == "load_0")
+            .expect("debug object describes load");
+        assert_eq!(load.name.as_deref(), Some("load"));
+        assert!(load.code_len > 0);
+        let debug_bytes = debug_obj.bytes().expect("write debug object");
+        let debug_file = object::File::parse(&*debug_bytes).expect("parse debug object");
+        let symbol = debug_file
+            .symbols()
+            .find(|symbol| symbol.name() == Ok("load_0"))
+            .expect("debug object has load's symbol");
+        assert_eq!(symbol.address(), load.code_offset);
+        assert_eq!(symbol.size(), load.code_len);
+    }
+
+    #[test]
+    fn coverage() {
+        use lucetc::{CoverageCounter, CoverageMode};
+        use object::{Object, ObjectSymbol};
+        let m = wabt::wat2wasm(
+            "(module
+               (func $a (export \"a\") (param i32) (result i32)
+                 (if (result i32) (local.get 0)
+                   (then (i32.const 1))
+                   (else (i32.const 2))))
+               (func (export \"b\")))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+        let object_file = |mode: Option<CoverageMode>| {
+            let mut builder = Compiler::builder();
+            if let Some(mode) = mode {
+                builder.coverage(mode);
+            }
+            builder
+                .create(&m, &b)
+                .expect("compile")
+                .object_file()
+                .expect("codegen")
+        };
+        let counter = |symbol: &str, wasm_offset: Option<u32>| CoverageCounter {
+            symbol: symbol.to_owned(),
+            wasm_offset,
+        };
+
+        let obj = object_file(Some(CoverageMode::FunctionEntry));
+        obj.self_check().expect("object is consistent");
+        assert_eq!(
+            obj.coverage_layout().expect("read coverage layout"),
+            Some(vec![
+                counter("guest_func_a", None),
+                counter("guest_func_b", None)
+            ])
+        );
+        let bytes = obj.bytes().expect("write object");
+        let file = object::File::parse(&bytes).expect("parse object");
+        assert!(file
+            .symbols()
+            .any(|symbol| symbol.is_undefined() && symbol.name() == Ok("lucet_coverage_counters")));
+
+        // The `if`, `else`, and `end` each start a block.
+        let layout = object_file(Some(CoverageMode::BasicBlock))
+            .coverage_layout()
+            .expect("read coverage layout")
+            .expect("object has a coverage layout");
+        let symbols = layout
+            .iter()
+            .map(|counter| counter.symbol.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            symbols,
+            vec![
+                "guest_func_a",
+                "guest_func_a",
+                "guest_func_a",
+                "guest_func_a",
+                "guest_func_b"
+            ]
+        );
+        assert_eq!(layout[0].wasm_offset, None);
+        let offsets = layout[1..4]
+            .iter()
+            .map(|counter| counter.wasm_offset.expect("block counter has an offset"))
+            .collect::<Vec<_>>();
+        assert!(offsets.windows(2).all(|w| w[0] < w[1]));
+        assert!(offsets.iter().all(|offset| (*offset as usize) < m.len()));
+
+        assert_eq!(object_file(None).coverage_layout().unwrap(), None);
+    }
+
+    #[test]
+    fn compiled_functions() {
+        let m = wabt::wat2wasm(
+            "(module
+               (func $a (export \"a\") (param i32) (result i32)
+                 (i32.div_s (local.get 0) (local.get 0)))
+               (func (export \"b\") (result i32) (call $a (i32.const 1))))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+        let funcs = Compiler::builder()
+            .create(&m, &b)
+            .expect("compile")
+            .compiled_functions()
+            .expect("codegen");
+
+        let symbols = funcs.iter().map(|f| f.symbol.as_str()).collect::<Vec<_>>();
+        assert_eq!(symbols, vec!["guest_func_a", "guest_func_b"]);
+        assert!(funcs.iter().all(|f| !f.code.is_empty()));
+        assert!(!funcs[0].traps.is_empty());
+        assert!(funcs[0]
+            .traps
+            .iter()
+            .all(|trap| (trap.offset as usize) < funcs[0].code.len()));
+        assert!(funcs[1]
+            .relocs
+            .iter()
+            .any(|reloc| reloc.symbol == "guest_func_a"));
+    }
+
+    #[test]
+    fn function_opt_level() {
+        use lucetc::{FuncIndex, OptLevel};
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        let code = |builder: lucetc::CompilerBuilder| {
+            builder
+                .create(&m, &b)
+                .expect("compile fibonacci")
+                .compiled_functions()
+                .expect("codegen fibonacci")
+                .into_iter()
+                .map(|f| f.code)
+                .collect::<Vec<_>>()
+        };
+
+        let per_function = code(
+            Compiler::builder().with_function_opt_level(Box::new(|_: FuncIndex| OptLevel::None)),
+        );
+        let global = code(Compiler::builder().with_opt_level(OptLevel::None));
+        assert_eq!(per_function, global);
+
+        Compiler::builder()
+            .with_function_opt_level(Box::new(|ix: FuncIndex| {
+                if ix.as_u32() % 2 == 0 {
+                    OptLevel::None
+                } else {
+                    OptLevel::Speed
+                }
+            }))
+            .create(&m, &b)
+            .expect("compile fibonacci")
+            .object_file()
+            .expect("codegen fibonacci")
+            .self_check()
+            .expect("object is consistent");
+    }
+
+    #[test]
+    fn function_sections() {
+        use object::{Object, ObjectSection, ObjectSymbol};
+        let m = wabt::wat2wasm(
+            "(module
+               (func (export \"a\") (result i32) (i32.const 1))
+               (func (export \"b\") (result i32) (call 0)))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+        let obj = Compiler::builder()
+            .with_function_sections(true)
+            .create(&m, &b)
+            .expect("compile")
+            .object_file()
+            .expect("codegen");
+        obj.self_check().expect("object is consistent");
+
+        let bytes = obj.bytes().expect("object bytes");
+        let file = object::File::parse(&bytes).expect("parse object");
+        let section_name = |name: &str| {
+            let symbol = file
+                .symbols()
+                .find(|symbol| symbol.name() == Ok(name))
+                .unwrap_or_else(|| panic!("symbol {} is defined", name));
+            let index = symbol.section_index().expect("symbol is in a section");
+            let section = file.section_by_index(index).expect("section exists");
+            section.name().expect("section name").to_owned()
+        };
+        let (a, b) = (section_name("guest_func_a"), section_name("guest_func_b"));
+        assert_ne!(a, b);
+        assert!(a.starts_with(".text"), "a is in {}", a);
+        assert!(b.starts_with(".text"), "b is in {}", b);
+    }
+
+    #[test]
+    fn emit_cfg_map() {
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        let object_file = |emit_cfg_map: bool| {
+            Compiler::builder()
+                .with_emit_cfg_map(emit_cfg_map)
+                .create(&m, &b)
+                .expect("compile fibonacci")
+                .object_file()
+                .expect("codegen fibonacci")
+        };
+
+        let obj = object_file(true);
+        obj.self_check().expect("object is consistent");
+        let blocks = obj
+            .cfg_map_for("guest_func_main")
+            .expect("read CFG map")
+            .expect("main has a CFG map");
+        // fibonacci's main has a loop, so it can't be a single block.
+        assert!(blocks.len() > 1);
+        assert_eq!(blocks[0].code_start, 0);
+        assert!(blocks.iter().all(|block| block.code_start < block.code_end));
+        assert!(blocks.windows(2).all(|w| w[0].code_end <= w[1].code_start));
+
+        let obj = object_file(false);
+        assert_eq!(obj.cfg_map_for("guest_func_main").unwrap(), None);
+    }
+
+    #[test]
+    fn emit_sig_table() {
+        use lucetc::{SignatureTableEntry, WasmType};
+        let m = wabt::wat2wasm(
+            "(module
+               (type $unary (func (param i32) (result i32)))
+               (type $sink (func (param f64)))
+               (type $unary_again (func (param i32) (result i32)))
+               (type $unused (func (param i64)))
+               (table 1 funcref)
+               (func (export \"a\") (param i32) (result i32)
+                 (call_indirect (type $unary) (local.get 0) (i32.const 0)))
+               (func (export \"b\") (param i32) (result i32)
+                 (call_indirect (type $unary_again) (local.get 0) (i32.const 0)))
+               (func (export \"c\") (param f64)
+                 (call_indirect (type $sink) (local.get 0) (i32.const 0))))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+        let object_file = |emit_sig_table: bool| {
+            Compiler::builder()
+                .with_emit_sig_table(emit_sig_table)
+                .create(&m, &b)
+                .expect("compile")
+                .object_file()
+                .expect("codegen")
+        };
+
+        let obj = object_file(true);
+        obj.self_check().expect("object is consistent");
+        let table = obj
+            .signature_table()
+            .expect("read signature table")
+            .expect("object has a signature table");
+        // Structurally equal types share an id, and types no indirect call uses are left out.
+        let types = table
+            .iter()
+            .map(|SignatureTableEntry { func_type, .. }| {
+                (func_type.params.to_vec(), func_type.returns.to_vec())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            vec![
+                (vec![WasmType::I32], vec![WasmType::I32]),
+                (vec![WasmType::F64], vec![]),
+            ]
+        );
+        assert!(table[0].id < table[1].id);
+
+        let obj = object_file(false);
+        assert_eq!(obj.signature_table().unwrap(), None);
+    }
+
+    #[test]
+    fn function_order() {
+        use object::{Object, ObjectSymbol};
+        let m = wabt::wat2wasm(
+            "(module
+               (func (export \"a\") (result i32) (i32.const 1))
+               (func (export \"b\") (result i32) (i32.const 2))
+               (func (export \"c\") (result i32) (i32.const 3)))",
+        )
+        .expect("convert module to wasm binary format");
+        let b = super::test_bindings();
+        let obj = Compiler::builder()
+            .with_function_order(vec!["guest_func_c".to_owned(), "guest_func_a".to_owned()])
+            .create(&m, &b)
+            .expect("compile")
+            .object_file()
+            .expect("codegen");
+        obj.self_check().expect("object is consistent");
+
+        let bytes = obj.bytes().expect("object bytes");
+        let file = object::File::parse(&bytes).expect("parse object");
+        let address = |name: &str| {
+            file.symbols()
+                .find(|symbol| symbol.name() == Ok(name))
+                .unwrap_or_else(|| panic!("symbol {} is defined", name))
+                .address()
+        };
+        let (a, b, c) = (
+            address("guest_func_a"),
+            address("guest_func_b"),
+            address("guest_func_c"),
+        );
+        assert!(
+            c < a && a < b,
+            "functions laid out at a={} b={} c={}",
+            a,
+            b,
+            c
+        );
+    }
+
+    #[test]
+    fn validate_only() {
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        let builder = Compiler::builder();
+        let validated = builder.validate_only(&m).expect("validate fibonacci");
+        let c = builder
+            .create_with_state(validated, &b)
+            .expect("create compiler for fibonacci");
+        c.object_file().expect("codegen fibonacci");
+
+        // Function bodies are only validated as they are compiled, but a truncated module fails
+        // to parse.
+        assert!(builder.validate_only(&m[..m.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn external_stack_probe() {
+        use object::{Object, ObjectSymbol};
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        for external_stack_probe in &[false, true] {
+            let obj = Compiler::builder()
+                .with_external_stack_probe(*external_stack_probe)
+                .create(&m, &b)
+                .expect("compile fibonacci")
+                .object_file()
+                .expect("codegen fibonacci");
+            obj.self_check().expect("object is consistent");
+
+            let bytes = obj.bytes().expect("object bytes");
+            let file = object::File::parse(&bytes).expect("parse object");
+            let probes = file
+                .symbols()
+                .filter(|symbol| symbol.name() == Ok("lucet_probestack"))
+                .collect::<Vec<_>>();
+            assert!(!probes.is_empty(), "the probe symbol is referenced");
+            assert_eq!(
+                probes.iter().any(|symbol| !symbol.is_undefined()),
+                !external_stack_probe
+            );
+        }
+    }
+
+    #[test]
+    fn import_filter() {
+        use cranelift_codegen::ir::{types, ArgumentPurpose, Signature};
+        use lucetc::Error;
+        let b = super::test_bindings();
+        let m = wabt::wat2wasm(
+            "(module
+               (import \"env\" \"inc\" (func))
+               (import \"env\" \"imp_0\" (func (param i64) (result i32))))",
+        )
+        .expect("convert module to wasm binary format");
+        // Reject imports that take a 64-bit value, such as a raw host pointer.
+        let no_i64_params = |_module: &str, _field: &str, signature: &Signature| {
+            !signature.params.iter().any(|param| {
+                param.value_type == types::I64 && param.purpose == ArgumentPurpose::Normal
+            })
+        };
+
+        Compiler::builder()
+            .with_import_filter(Box::new(
+                |_module: &str, _field: &str, _signature: &Signature| true,
+            ))
+            .create(&m, &b)
+            .expect("compile with accepting filter");
+        match Compiler::builder()
+            .with_import_filter(Box::new(no_i64_params))
+            .create(&m, &b)
+        {
+            Err(Error::ImportRejected { module, field }) => {
+                assert_eq!(module, "env");
+                assert_eq!(field, "imp_0");
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("rejected import accepted"),
+        }
+    }
+
+    #[test]
+    fn allowed_import_modules() {
+        use lucetc::Error;
+        let b = super::test_bindings();
+        let allowed = |modules: &[&str]| {
+            modules
+                .iter()
+                .map(|module| module.to_string())
+                .collect::<std::collections::HashSet<_>>()
+        };
+
+        let m = load_wat_module("import");
+        Compiler::builder()
+            .with_allowed_import_modules(allowed(&["env", "wasi_snapshot_preview1"]))
+            .create(&m, &b)
+            .expect("compile import");
+
+        let m = wabt::wat2wasm(
+            "(module
+               (import \"env\" \"inc\" (func))
+               (import \"host\" \"g\" (global i32))
+               (import \"other\" \"f\" (func))
+               (import \"host\" \"h\" (func)))",
+        )
+        .expect("convert module to wasm binary format");
+        match Compiler::builder()
+            .with_allowed_import_modules(allowed(&["env", "other"]))
+            .create(&m, &b)
+        {
+            Err(Error::DisallowedImportModule { module }) => assert_eq!(module, "host"),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("disallowed import module accepted"),
+        }
+        match Compiler::builder()
+            .with_allowed_import_modules(allowed(&["env"]))
+            .create(&m, &b)
+        {
+            Err(Error::ImportModuleViolations(errors)) => {
+                let modules = errors
+                    .iter()
+                    .map(|e| match e {
+                        Error::DisallowedImportModule { module } => module.as_str(),
+                        e => panic!("unexpected error: {}", e),
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(modules, ["other", "host"]);
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("disallowed import modules accepted"),
+        }
+    }
+
+    #[test]
+    fn write_linker_script() {
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        let obj = Compiler::builder()
+            .create(&m, &b)
+            .expect("compile fibonacci")
+            .object_file()
+            .expect("codegen fibonacci");
+        let dir = tempfile::tempdir().expect("create temporary directory");
+        let path = dir.path().join("guest.ld");
+        obj.write_linker_script(&path, 0x40_0000)
+            .expect("write linker script");
+        let script = std::fs::read_to_string(&path).expect("read linker script");
+
+        // The module data and function manifest are local symbols, kept through their sections.
+        assert!(script.contains("EXTERN(lucet_module)"));
+        assert!(script.contains(". = 0x400000;"));
+        assert!(script.contains(".text : { KEEP(*(.text)) }"));
+        let text = script.find(".text :").expect("code section is placed");
+        let data = script.find(".data :").expect("data section is placed");
+        assert!(text < data);
+    }
+
+    #[test]
+    fn custom_metadata() {
+        use lucetc::Error;
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        let obj = Compiler::builder()
+            .with_custom_metadata("build_id".to_owned(), b"1234".to_vec())
+            .with_custom_metadata("git_sha".to_owned(), b"old".to_vec())
+            .with_custom_metadata("git_sha".to_owned(), b"abcdef".to_vec())
+            .create(&m, &b)
+            .expect("compile fibonacci")
+            .object_file()
+            .expect("codegen fibonacci");
+        obj.self_check().expect("object is consistent");
+        assert_eq!(
+            obj.metadata("build_id").expect("read metadata"),
+            Some(b"1234".to_vec())
+        );
+        assert_eq!(
+            obj.metadata("git_sha").expect("read metadata"),
+            Some(b"abcdef".to_vec())
+        );
+        assert_eq!(obj.metadata("signature").expect("read metadata"), None);
+
+        match Compiler::builder()
+            .with_custom_metadata("build id".to_owned(), vec![])
+            .create(&m, &b)
+        {
+            Err(Error::Input(_)) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("invalid metadata key accepted"),
+        }
+    }
+
+    #[test]
+    fn record_compile_times() {
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        let obj = Compiler::builder()
+            .with_record_compile_times(true)
+            .create(&m, &b)
+            .expect("compile fibonacci")
+            .object_file()
+            .expect("codegen fibonacci");
+        obj.self_check().expect("object is consistent");
+        // The times are read back from the manifest entries of wasm functions, and none is 0,
+        // which would mean no time was recorded.
+        let compile_times = obj.compile_times().expect("read compile times");
+        let symbols = compile_times
+            .iter()
+            .map(|(symbol, _)| symbol.as_str())
+            .collect::<Vec<_>>();
+        assert!(symbols.contains(&"guest_func_main"));
+        assert!(!symbols.contains(&"lucet_probestack"));
+        assert!(compile_times
+            .iter()
+            .all(|(_, compile_nanos)| *compile_nanos > 0));
+
+        let obj = Compiler::builder()
+            .create(&m, &b)
+            .expect("compile fibonacci")
+            .object_file()
+            .expect("codegen fibonacci");
+        obj.self_check().expect("object is consistent");
+        assert!(obj.compile_times().expect("read compile times").is_empty());
+    }
+
+    #[test]
+    fn weak_imports() {
+        use lucetc::Error;
+        use object::{Object, ObjectSymbol};
+        let m = load_wat_module("import");
+        let b = super::test_bindings();
+        let weak = |import: &str| {
+            Compiler::builder()
+                .with_weak_imports([import.to_owned()].iter().cloned().collect())
+                .create(&m, &b)
+        };
+
+        let obj = weak("env::inc")
+            .expect("compile import")
+            .object_file()
+            .expect("codegen import");
+        obj.self_check().expect("object is consistent");
+        let bytes = obj.bytes().expect("object bytes");
+        let file = object::File::parse(&bytes).expect("parse object");
+        let inc = file
+            .symbols()
+            .find(|symbol| symbol.name() == Ok("inc"))
+            .expect("import symbol is present");
+        assert!(inc.is_weak());
+        assert!(!inc.is_undefined());
+        // The default definition traps as soon as it is called.
+        assert_eq!(
+            trap_codes(&bytes, "inc"),
+            vec![lucet_module::TrapCode::MissingImport]
+        );
+
+        match weak("env::dec") {
+            Err(Error::Input(_)) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("weak import missing from the module accepted"),
+        }
+    }
+
+    #[test]
+    fn duplicate_export() {
+        use lucetc::Error;
+        // A module exporting its only function as `f` twice, which wat2wasm won't produce.
+        let m = [
+            &b"\0asm\x01\0\0\0"[..],
+            // type section: [] -> []
+            &[0x01, 0x04, 0x01, 0x60, 0x00, 0x00],
+            // function section
+            &[0x03, 0x02, 0x01, 0x00],
+            // export section: function 0 as `f`, twice
+            &[
+                0x07, 0x09, 0x02, 0x01, b'f', 0x00, 0x00, 0x01, b'f', 0x00, 0x00,
+            ],
+            // code section
+            &[0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b],
+        ]
+        .concat();
+        let b = super::test_bindings();
+        match Compiler::builder().create(&m, &b) {
+            Err(Error::DuplicateExport { name }) => assert_eq!(name, "f"),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("duplicate export accepted"),
+        }
+    }
+
+    #[test]
+    fn table_override() {
+        use lucetc::Error;
+        let m = load_wat_module("icall");
+        let b = super::test_bindings();
+        // Each table entry is a (signature, function pointer) pair, and the pointer of slot `slot`
+        // is relocated to the function in it.
+        let slot_functions = |obj: &lucetc::ObjectFile| {
+            let relocs = obj.relocations().expect("relocations");
+            (0..3)
+                .map(|slot| {
+                    relocs
+                        .iter()
+                        .find(|reloc| {
+                            reloc.symbol.as_deref() == Some("guest_table_0")
+                                && reloc.offset == slot * 16 + 8
+                        })
+                        .map(|reloc| reloc.target.clone())
+                })
+                .collect::<Vec<_>>()
+        };
+        let original = Compiler::builder()
+            .create(&m, &b)
+            .expect("compile icall")
+            .object_file()
+            .expect("codegen icall");
+        let original = slot_functions(&original);
+        assert!(original.iter().all(Option::is_some));
+        assert_ne!(original[2].as_deref(), Some("guest_func_foo"));
+
+        let obj = Compiler::builder()
+            .with_table_override(0, 2, "guest_func_foo".to_owned())
+            .create(&m, &b)
+            .expect("compile icall")
+            .object_file()
+            .expect("codegen icall");
+        obj.self_check().expect("object is consistent");
+        let overridden = slot_functions(&obj);
+        assert_eq!(overridden[..2], original[..2]);
+        assert_eq!(overridden[2].as_deref(), Some("guest_func_foo"));
+
+        for (table_index, slot, symbol) in &[(0, 3, "guest_func_foo"), (1, 0, "guest_func_foo")] {
+            let c = Compiler::builder()
+                .with_table_override(*table_index, *slot, symbol.to_string())
+                .create(&m, &b)
+                .expect("compile icall");
+            match c.object_file() {
+                Err(Error::TableIndexError(_)) => {}
+                Err(e) => panic!("unexpected error: {}", e),
+                Ok(_) => panic!("override of a nonexistent slot should fail"),
+            }
+        }
+
+        let c = Compiler::builder()
+            .with_table_override(0, 0, "no_such_function".to_owned())
+            .create(&m, &b)
+            .expect("compile icall");
+        match c.object_file() {
+            Err(Error::FunctionIndexError(_)) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("override with a nonexistent function should fail"),
+        }
+    }
+
+    #[test]
+    fn split_debug_info() {
+        use object::{Object, ObjectSection};
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        let obj = Compiler::builder()
+            .with_split_debug_info(None)
+            .create(&m, &b)
+            .expect("compile fibonacci")
+            .object_file()
+            .expect("codegen fibonacci");
+        assert_eq!(obj.build_id().unwrap(), None);
+
+        let dir = tempfile::tempdir().expect("create temporary directory");
+        let path = dir.path().join("fibonacci.debug");
+        let obj = Compiler::builder()
+            .with_split_debug_info(Some(path.clone()))
+            .create(&m, &b)
+            .expect("compile fibonacci")
+            .object_file()
+            .expect("codegen fibonacci");
+        obj.self_check().expect("production object is consistent");
+        let build_id = obj
+            .build_id()
+            .expect("read build id")
+            .expect("production object has a build id");
+
+        let bytes = std::fs::read(&path).expect("read debug object");
+        let file = object::File::parse(&*bytes).expect("parse debug object");
+        let note = file
+            .section_by_name(".note.gnu.build-id")
+            .expect("debug object has a build id")
+            .data()
+            .expect("read build id note")
+            .to_vec();
+        assert!(note.ends_with(&build_id));
+    }
+
+    #[test]
+    fn self_check() {
+        // The check reads the serialized object, so try options that change how it is laid out.
+        let builders = vec![
+            Compiler::builder(),
+            Compiler::builder().with_dedup_trap_tables(true),
+            Compiler::builder().with_function_sections(true),
+            Compiler::builder().with_symbol_prefix("guest_a_".to_owned()),
+        ];
+        for file in &["icall", "import", "data_segment"] {
+            let m = load_wat_module(file);
+            let b = super::test_bindings();
+            for builder in builders.iter() {
+                let c = builder.create(&m, &b).expect("compile");
+                let obj = c.object_file().expect("codegen");
+                obj.self_check().expect("object is self-consistent");
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn merge() {
+        use lucetc::ObjectFile;
+        let b = super::test_bindings();
+        let objects = ["fibonacci", "import"]
+            .iter()
+            .map(|file| {
+                let m = load_wat_module(file);
+                let c = Compiler::builder().create(&m, &b).expect("compile");
+                (file.to_string(), c.object_file().expect("codegen"))
+            })
+            .collect();
+        let merged = ObjectFile::merge(objects).expect("merge objects");
+        merged
+            .self_check()
+            .expect("merged object is self-consistent");
+        merged.bytes().expect("write merged object");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn symbol_prefix() {
+        use object::{Object, ObjectSymbol};
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        let dir = tempfile::tempdir().expect("create temporary directory");
+        let mut paths = vec![];
+        for prefix in ["guest_a_", "guest_b_"].iter() {
+            let obj = Compiler::builder()
+                .with_symbol_prefix(prefix.to_string())
+                .create(&m, &b)
+                .expect("compile fibonacci")
+                .object_file()
+                .expect("codegen fibonacci");
+            obj.self_check()
+                .expect("prefixed object is self-consistent");
+            let path = dir.path().join(format!("{}fibonacci.o", prefix));
+            obj.write(&path).expect("write object");
+            paths.push(path);
+        }
+
+        // Unprefixed, both guests would define `lucet_module` and `guest_func_main`.
+        let linked = dir.path().join("guests.o");
+        let status = std::process::Command::new("ld")
+            .arg("-r")
+            .args(&paths)
+            .arg("-o")
+            .arg(&linked)
+            .status()
+            .expect("run ld");
+        assert!(status.success());
+
+        let bytes = std::fs::read(&linked).expect("read linked object");
+        let file = object::File::parse(&bytes).expect("parse object");
+        let defined = file
+            .symbols()
+            .filter(|symbol| !symbol.is_undefined())
+            .filter_map(|symbol| symbol.name().ok().map(str::to_owned))
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<_>>();
+        for prefix in ["guest_a_", "guest_b_"].iter() {
+            assert!(defined.contains(&format!("{}lucet_module", prefix)));
+            assert!(defined.contains(&format!("{}guest_func_main", prefix)));
+        }
+        assert!(!defined.iter().any(|name| name == "lucet_module"));
+    }
+
+    #[test]
+    fn config_diff() {
+        use lucetc::{CpuFeatures, SpecificFeature, TargetCpu};
+        use std::collections::HashMap;
+        let base = Compiler::builder().with_cpu_features(CpuFeatures::baseline());
+        assert!(base.diff(&base).is_empty());
+
+        let mut heap_settings = HeapSettings::default();
+        heap_settings.guard_size = 0;
+        let other = Compiler::builder()
+            .with_cpu_features(CpuFeatures::baseline())
+            .with_opt_level(OptLevel::None)
+            .with_heap_settings(heap_settings)
+            .with_count_instructions(true);
+        let settings = base
+            .diff(&other)
+            .into_iter()
+            .map(|difference| difference.setting)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            settings,
+            vec![
+                "opt_level",
+                "heap_settings.guard_size",
+                "count_instructions"
+            ]
+        );
+        let difference = &base.diff(&other)[2];
+        assert_eq!(difference.to_string(), "count_instructions: false vs. true");
+
+        // CPU features are compared by what they enable, however they were chosen.
+        let mut features = CpuFeatures::baseline();
+        for feature in [
+            SpecificFeature::SSE3,
+            SpecificFeature::SSSE3,
+            SpecificFeature::SSE41,
+            SpecificFeature::SSE42,
+            SpecificFeature::Popcnt,
+        ]
+        .iter()
+        {
+            features.set(*feature, true);
+        }
+        let nehalem = Compiler::builder()
+            .with_cpu_features(CpuFeatures::new(TargetCpu::Nehalem, HashMap::new()));
+        let individual = Compiler::builder().with_cpu_features(features);
+        assert!(nehalem.diff(&individual).is_empty());
+        assert_eq!(base.diff(&nehalem)[0].setting, "cpu_features");
+    }
+
+    #[test]
+    fn pointer_width() {
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        let c = Compiler::builder()
+            .create(&m, &b)
+            .expect("compile fibonacci");
+        assert_eq!(c.module_data().expect("module data").pointer_width(), 64);
+        let obj = c.object_file().expect("codegen fibonacci");
+        assert_eq!(obj.pointer_width(), 64);
+    }
+
+    #[test]
+    fn reserved_symbol_conflict() {
+        use lucet_module::bindings::Bindings;
+        use lucetc::Error;
+        let expect_conflict = |result: Result<Compiler<'_>, Error>, expected: &str| match result {
+            Err(Error::ReservedSymbolConflict { symbol }) => assert_eq!(symbol, expected),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("reserved symbol {} accepted", expected),
+        };
+
+        let m = load_wat_module("import");
+        for symbol in &[
+            "lucet_probestack",
+            "lucet_tables",
+            "lucet_data_segments",
+            "lucet_coverage_counters",
+            "guest_table_0",
+            "lucet_trap_table_guest_func_main",
+            "lucet_meta_key",
+        ] {
+            let imports = [("inc".to_owned(), symbol.to_string())]
+                .iter()
+                .cloned()
+                .collect();
+            let b = Bindings::env(imports);
+            expect_conflict(Compiler::builder().create(&m, &b), symbol);
+        }
+
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        expect_conflict(
+            Compiler::builder()
+                .with_module_symbol("guest_func_main".to_owned())
+                .create(&m, &b),
+            "guest_func_main",
+        );
+    }
+
+    #[test]
+    fn module_symbol() {
+        use lucetc::{DataSymbolKind, SymbolScope};
+        let m = load_wat_module("data_segment");
+        let b = super::test_bindings();
+        let c = Compiler::builder()
+            .with_module_symbol("guest_a_module".to_owned())
+            .create(&m, &b)
+            .expect("compile data_segment");
+        let obj = c.object_file().expect("codegen data_segment");
+        obj.self_check().expect("object is self-consistent");
+
+        // The renamed module is the only global module symbol, and its data is named after it.
+        let symbols = obj.data_symbols().expect("data symbols");
+        let module = |kind: DataSymbolKind| {
+            let symbols = symbols
+                .iter()
+                .filter(|symbol| symbol.kind == kind)
+                .collect::<Vec<_>>();
+            assert_eq!(symbols.len(), 1, "{:?} symbols", kind);
+            (symbols[0].name.as_str(), symbols[0].scope)
+        };
+        assert_eq!(
+            module(DataSymbolKind::Module),
+            ("guest_a_module", SymbolScope::Dynamic)
+        );
+        assert_eq!(
+            module(DataSymbolKind::ModuleData),
+            ("guest_a_module_data", SymbolScope::Compilation)
+        );
+        assert!(!symbols.iter().any(|symbol| symbol.name == "lucet_module"));
+    }
+}
+
+mod validate {
+    use super::load_wat_module;
+    use cranelift_codegen::isa::BackendVariant;
+    use lucetc::{Compiler, CpuFeatures, HeapSettings, OptLevel, Validator, WasiMode};
+    use target_lexicon::Triple;
+
+    #[test]
+    fn validate_arith() {
+        let m = load_wat_module("arith");
+        let b = super::test_bindings();
+
+        // Empty witx: arith module has no imports
+        let v = Validator::builder()
+            .parse_witx("")
+            .expect("empty witx validates")
+            .build();
+        let builder = Compiler::builder().with_validator(Some(v));
+        let c = builder.create(&m, &b).expect("compile");
+        let _obj = c.object_file().expect("codegen");
+    }
+
+    #[test]
+    fn validate_import() {
+        let m = load_wat_module("import");
+        let b = super::test_bindings();
+
+        let witx = "
+            (module $env
+              (@interface func (export \"inc\")
+                (result $r s32)))";
+        let v = Validator::builder()
+            .parse_witx(witx)
+            .expect("witx validates")
+            .build();
+
+        let builder = Compiler::builder().with_validator(Some(v));
+        let c = builder.create(&m, &b).expect("compile");
+        let _obj = c.object_file().expect("codegen");
     }
 
     #[test]
@@ -735,4 +4102,50 @@ mod validate {
             e => panic!("expected FunctionTranslation error, got {:?}", e),
         }
     }
+
+    #[test]
+    fn validation_progress() {
+        use std::sync::{Arc, Mutex};
+
+        let m = load_wat_module("fibonacci");
+        let b = super::test_bindings();
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let reports_ref = reports.clone();
+        let builder =
+            Compiler::builder().with_validation_progress(Box::new(move |position, total| {
+                reports_ref.lock().unwrap().push((position, total))
+            }));
+        let _c = builder.create(&m, &b).expect("compile fibonacci");
+
+        let reports = reports.lock().unwrap();
+        assert!(reports.iter().all(|(_, total)| *total == m.len()));
+        assert!(reports.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        assert_eq!(reports.last(), Some(&(m.len(), m.len())));
+    }
+}
+
+#[cfg(feature = "testing")]
+mod test_run {
+    use super::load_wat_module;
+    use lucetc::{test_run, Error, Val};
+
+    #[test]
+    fn run_export() {
+        let m = load_wat_module("locals");
+        let b = super::test_bindings();
+        match test_run(&m, &b, "main", &[]).expect("run locals").as_slice() {
+            [Val::I32(v)] => assert_eq!(*v, 74),
+            rets => panic!("unexpected return values: {:?}", rets),
+        }
+    }
+
+    #[test]
+    fn run_missing_export() {
+        let m = load_wat_module("locals");
+        let b = super::test_bindings();
+        match test_run(&m, &b, "not_an_export", &[]) {
+            Err(Error::TestRun(_)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
 }
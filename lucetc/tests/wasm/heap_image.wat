@@ -0,0 +1,8 @@
+(module
+  (memory 1)
+  (func $main (export "main")
+    (drop (i32.load (i32.const 0)))
+  )
+  (data (i32.const 0) "\01\02\03\04")
+  (data (i32.const 4096) "\05\06")
+)